@@ -0,0 +1,175 @@
+//! `#[derive(McpClient)]`: a typed async client for a `Subcommand` enum
+//!
+//! Generates `<Enum>Client` with one method per variant not marked
+//! `#[mcp(skip)]`. Each method takes the variant's fields as parameters and
+//! sends them as the tool's JSON arguments, keyed the way the server maps clap
+//! arguments.
+
+use crate::tools::{parse_variant_attrs, rename, rename_all_rule, tool_name};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Attribute, Data, DeriveInput, Fields, LitStr};
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let client_name = format_ident!("{}Client", name);
+
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            name,
+            "McpClient can only be derived for Subcommand enums",
+        ));
+    };
+
+    let rename_all = rename_all_rule(&input.attrs)?;
+    let mut methods = Vec::new();
+    for variant in &data.variants {
+        if parse_variant_attrs(&variant.attrs)?.skip {
+            continue;
+        }
+        let tool = tool_name(variant, &rename_all)?;
+        let method = format_ident!(
+            "{}",
+            rename(&variant.ident, "snake_case")
+                .map_err(|msg| syn::Error::new_spanned(&variant.ident, msg))?
+        );
+        let docs = variant.attrs.iter().filter(|a| a.path().is_ident("doc"));
+
+        let mut params = Vec::new();
+        let mut inserts = Vec::new();
+        match &variant.fields {
+            Fields::Named(fields) => {
+                for field in &fields.named {
+                    let ident = field.ident.as_ref().expect("named field");
+                    let ty = &field.ty;
+                    if has_flag(&field.attrs, "arg", "skip")? {
+                        continue;
+                    }
+                    params.push(quote!(#ident: #ty));
+                    if has_flag(&field.attrs, "command", "flatten")? {
+                        inserts.push(merge_object(quote!(#ident)));
+                    } else {
+                        let key = arg_id(&field.attrs)?
+                            .map(|lit| lit.value())
+                            .unwrap_or_else(|| ident.to_string());
+                        inserts.push(quote! {
+                            let value = clap_mcp::__private::serde_json::to_value(&#ident)?;
+                            if !value.is_null() {
+                                arguments.insert(#key.to_string(), value);
+                            }
+                        });
+                    }
+                }
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                params.push(quote!(args: #ty));
+                inserts.push(merge_object(quote!(args)));
+            }
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "McpClient supports named fields or a single Args struct",
+                ))
+            }
+            Fields::Unit => {}
+        }
+
+        methods.push(quote! {
+            #(#docs)*
+            pub async fn #method(
+                &self,
+                #(#params),*
+            ) -> ::std::result::Result<
+                clap_mcp::client::CallToolResult,
+                ::std::boxed::Box<dyn ::std::error::Error>,
+            > {
+                #[allow(unused_mut)]
+                let mut arguments = clap_mcp::__private::serde_json::Map::new();
+                #(#inserts)*
+                self.client
+                    .call_tool(
+                        #tool,
+                        ::core::option::Option::Some(
+                            clap_mcp::__private::serde_json::Value::Object(arguments),
+                        ),
+                    )
+                    .await
+            }
+        });
+    }
+
+    let doc = format!("Typed MCP client for the tools of [`{}`]", name);
+    Ok(quote! {
+        #[doc = #doc]
+        #vis struct #client_name {
+            client: clap_mcp::client::McpClient,
+        }
+
+        impl #client_name {
+            /// Wrap an already connected client
+            pub fn from_client(client: clap_mcp::client::McpClient) -> Self {
+                Self { client }
+            }
+
+            /// Connect to a server's SSE endpoint at `addr` (`host:port`)
+            pub async fn connect(
+                addr: &str,
+            ) -> ::std::result::Result<Self, ::std::boxed::Box<dyn ::std::error::Error>> {
+                ::std::result::Result::Ok(Self {
+                    client: clap_mcp::client::McpClient::connect(addr).await?,
+                })
+            }
+
+            /// The underlying untyped client
+            pub fn client(&self) -> &clap_mcp::client::McpClient {
+                &self.client
+            }
+
+            #(#methods)*
+        }
+    })
+}
+
+/// Serialize a value to an object and merge its entries into the arguments
+fn merge_object(value: TokenStream) -> TokenStream {
+    quote! {
+        match clap_mcp::__private::serde_json::to_value(&#value)? {
+            clap_mcp::__private::serde_json::Value::Object(map) => {
+                arguments.extend(map.into_iter().filter(|(_, v)| !v.is_null()));
+            }
+            _ => return ::std::result::Result::Err("arguments must serialize to an object".into()),
+        }
+    }
+}
+
+/// Whether `#[<attr>(..., flag, ...)]` is present
+pub(crate) fn has_flag(attrs: &[Attribute], attr_name: &str, flag: &str) -> syn::Result<bool> {
+    let mut found = false;
+    for attr in attrs.iter().filter(|a| a.path().is_ident(attr_name)) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(flag) {
+                found = true;
+            }
+            crate::skip_meta_value(&meta)
+        })?;
+    }
+    Ok(found)
+}
+
+/// Explicit `#[arg(id = "...")]` of a field
+pub(crate) fn arg_id(attrs: &[Attribute]) -> syn::Result<Option<LitStr>> {
+    let mut found = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("arg")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") && meta.input.peek(syn::Token![=]) {
+                found = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                crate::skip_meta_value(&meta)
+            }
+        })?;
+    }
+    Ok(found)
+}
@@ -1,13 +1,16 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Type};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, Ident, LitStr, Type};
+
+mod client;
+mod schema;
+mod tools;
 
 #[proc_macro_derive(McpMode, attributes(mcp))]
 pub fn derive_mcp_mode(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
     let name = &input.ident;
-    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
 
     // Find the field marked with #[mcp(mode_flag)]
     let mode_flag_field = find_mode_flag_field(&input.data);
@@ -15,15 +18,32 @@ pub fn derive_mcp_mode(input: TokenStream) -> TokenStream {
     // Find the subcommand field
     let subcommand_field = find_subcommand_field(&input.data);
 
+    // Flattened options marked #[mcp(global)], passed to every tool
+    let global_type = match find_global_field(&input.data) {
+        Ok(ty) => ty,
+        Err(err) => return err.into_compile_error().into(),
+    };
+
+    // Struct-level #[mcp(title = "...", install_config, ...)] settings
+    let struct_attrs = match parse_struct_attrs(&input.attrs) {
+        Ok(attrs) => attrs,
+        Err(err) => return err.into_compile_error().into(),
+    };
+
+    if let Some(flag) = &struct_attrs.transport_flag {
+        if let Err(err) = check_field_exists(&input.data, flag) {
+            return err.into_compile_error().into();
+        }
+    }
+
     let expanded = match (mode_flag_field, subcommand_field) {
-        (Some(flag_field), Some((cmd_field, cmd_type))) => generate_mcp_impl(
+        (Some(flag_field), Some((_cmd_field, cmd_type))) => generate_mcp_impl(
             name,
-            &impl_generics,
-            &ty_generics,
-            &where_clause,
+            &input.generics,
             flag_field,
-            cmd_field,
             cmd_type,
+            global_type,
+            struct_attrs,
         ),
         _ => {
             return syn::Error::new_spanned(
@@ -38,6 +58,133 @@ pub fn derive_mcp_mode(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// Collect `#[mcp(...)]` settings from the variants of a `Subcommand` enum
+#[proc_macro_derive(McpTools, attributes(mcp))]
+pub fn derive_mcp_tools(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    tools::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generate the tool schemas of a `Subcommand` enum at compile time
+#[proc_macro_derive(McpSchema, attributes(mcp))]
+pub fn derive_mcp_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    schema::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generate a typed async client (`<Enum>Client`) for a `Subcommand` enum
+#[proc_macro_derive(McpClient, attributes(mcp))]
+pub fn derive_mcp_client(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    client::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Consume the value of a nested meta item we are not interested in
+///
+/// Handles `key`, `key = expr` and `key(...)` forms so foreign attributes such
+/// as `#[command(...)]` can be scanned without understanding every option.
+pub(crate) fn skip_meta_value(meta: &syn::meta::ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        let _: syn::Expr = meta.value()?.parse()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        let content;
+        syn::parenthesized!(content in meta.input);
+        // Left unread, the tokens would be reported as unexpected
+        let _: proc_macro2::TokenStream = content.parse()?;
+    }
+    Ok(())
+}
+
+/// Settings parsed from struct-level `#[mcp(...)]` attributes
+#[derive(Default)]
+struct StructAttrs {
+    /// Server name; the clap command name when unset
+    name: Option<LitStr>,
+    /// Server version; the clap command version, then the crate's, when unset
+    version: Option<LitStr>,
+    instructions: Option<LitStr>,
+    title: Option<LitStr>,
+    website: Option<LitStr>,
+    icons: Vec<LitStr>,
+    contact: Option<LitStr>,
+    /// Generate `handle_mcp_install_config()`
+    install_config: bool,
+    /// Generate `handle_mcp_replay()`
+    replay: bool,
+    /// Generate `handle_mcp_call()`
+    call: bool,
+    /// Generate `handle_mcp_schema()`
+    export_schema: bool,
+    /// Field choosing the transport `run_mcp()` serves on
+    transport_flag: Option<LitStr>,
+    /// What `run_mcp_server()` does with calls, without a handler
+    missing_handler: Option<proc_macro2::TokenStream>,
+}
+
+fn parse_struct_attrs(attrs: &[Attribute]) -> syn::Result<StructAttrs> {
+    let mut parsed = StructAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("mcp")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                parsed.name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("version") {
+                parsed.version = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("instructions") {
+                parsed.instructions = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("title") {
+                parsed.title = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("website") {
+                parsed.website = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("icon") {
+                parsed.icons.push(meta.value()?.parse()?);
+            } else if meta.path.is_ident("contact") {
+                parsed.contact = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("install_config") {
+                parsed.install_config = true;
+            } else if meta.path.is_ident("replay") {
+                parsed.replay = true;
+            } else if meta.path.is_ident("call") {
+                parsed.call = true;
+            } else if meta.path.is_ident("export_schema") {
+                parsed.export_schema = true;
+            } else if meta.path.is_ident("transport_flag") {
+                parsed.transport_flag = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("missing_handler") {
+                let behavior: LitStr = meta.value()?.parse()?;
+                let variant = match behavior.value().as_str() {
+                    "error" => quote!(Error),
+                    "exec" => quote!(Exec),
+                    "dry_run" => quote!(DryRun),
+                    _ => {
+                        return Err(syn::Error::new(
+                            behavior.span(),
+                            "expected \"error\", \"exec\" or \"dry_run\"",
+                        ))
+                    }
+                };
+                parsed.missing_handler = Some(variant);
+            } else {
+                return Err(meta.error("unknown mcp attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(parsed)
+}
+
+fn option_string(value: &Option<LitStr>) -> proc_macro2::TokenStream {
+    match value {
+        Some(lit) => quote!(::core::option::Option::Some(#lit.to_string())),
+        None => quote!(::core::option::Option::None),
+    }
+}
+
 fn find_mode_flag_field(data: &Data) -> Option<Ident> {
     if let Data::Struct(data_struct) = data {
         if let Fields::Named(fields) = &data_struct.fields {
@@ -57,6 +204,53 @@ fn find_mode_flag_field(data: &Data) -> Option<Ident> {
     None
 }
 
+/// Type of the field marked `#[mcp(global)]`, which must be flattened `Args`
+fn find_global_field(data: &Data) -> syn::Result<Option<Type>> {
+    let Data::Struct(data_struct) = data else {
+        return Ok(None);
+    };
+    for field in data_struct.fields.iter() {
+        let mut global = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("mcp")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("global") {
+                    global = true;
+                } else {
+                    skip_meta_value(&meta)?;
+                }
+                Ok(())
+            })?;
+        }
+        if !global {
+            continue;
+        }
+        if !client::has_flag(&field.attrs, "command", "flatten")? {
+            return Err(syn::Error::new_spanned(
+                field,
+                "#[mcp(global)] must be on a #[command(flatten)] field of an Args struct",
+            ));
+        }
+        return Ok(Some(field.ty.clone()));
+    }
+    Ok(None)
+}
+
+/// Fail unless the struct has a named field called `name`
+fn check_field_exists(data: &Data, name: &LitStr) -> syn::Result<()> {
+    let found = matches!(data, Data::Struct(data) if data
+        .fields
+        .iter()
+        .any(|field| field.ident.as_ref().is_some_and(|ident| *ident == name.value())));
+    if found {
+        Ok(())
+    } else {
+        Err(syn::Error::new_spanned(
+            name,
+            format!("no field named `{}` to choose the transport", name.value()),
+        ))
+    }
+}
+
 fn find_subcommand_field(data: &Data) -> Option<(Ident, Type)> {
     if let Data::Struct(data_struct) = data {
         if let Fields::Named(fields) = &data_struct.fields {
@@ -103,26 +297,274 @@ fn find_subcommand_field(data: &Data) -> Option<(Ident, Type)> {
     None
 }
 
+/// Whether `ty` mentions a type parameter of `generics`
+pub(crate) fn mentions_type_param(ty: &Type, generics: &syn::Generics) -> bool {
+    fn mentions(tokens: proc_macro2::TokenStream, params: &[&Ident]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            proc_macro2::TokenTree::Ident(ident) => params.contains(&&ident),
+            proc_macro2::TokenTree::Group(group) => mentions(group.stream(), params),
+            _ => false,
+        })
+    }
+    let params: Vec<&Ident> = generics.type_params().map(|param| &param.ident).collect();
+    !params.is_empty() && mentions(quote!(#ty), &params)
+}
+
 fn generate_mcp_impl(
     name: &Ident,
-    impl_generics: &syn::ImplGenerics,
-    ty_generics: &syn::TypeGenerics,
-    where_clause: &Option<&syn::WhereClause>,
+    generics: &syn::Generics,
     mode_flag: Ident,
-    _subcommand_field: Ident,
     subcommand_type: Type,
+    global_type: Option<Type>,
+    struct_attrs: StructAttrs,
 ) -> proc_macro2::TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let title = option_string(&struct_attrs.title);
+    let website = option_string(&struct_attrs.website);
+    let contact = option_string(&struct_attrs.contact);
+    let icons = &struct_attrs.icons;
+    let mode_flag_id = mode_flag.to_string();
+
+    let with_globals = global_type
+        .as_ref()
+        .map(|ty| quote!(.with_global_args::<#ty>()));
+
+    // What serving needs of the subcommand and global types, which generic
+    // structs need not require of their parameters
+    let global_bounds = global_type.as_ref().map(|ty| {
+        quote! {
+            #ty: clap_mcp::__private::clap::Args
+                + ::core::marker::Send
+                + ::core::marker::Sync
+                + 'static,
+        }
+    });
+    // Whether the enum derives `McpTools` (and `McpSchema`) is only known
+    // once the struct's parameters are, too late for the probes
+    let generic_subcommand = mentions_type_param(&subcommand_type, generics);
+    let tools_bound = generic_subcommand.then(|| quote!(+ clap_mcp::McpTools));
+    let server_bounds = quote! {
+        where
+            #subcommand_type: clap_mcp::__private::clap::Subcommand
+                + ::core::clone::Clone
+                + ::core::marker::Send
+                + ::core::marker::Sync
+                + 'static
+                #tools_bound,
+            #global_bounds
+    };
+    let (tool_attributes, static_tools) = if generic_subcommand {
+        (
+            quote!(<#subcommand_type as clap_mcp::McpTools>::tool_attributes()),
+            quote!(<#subcommand_type as clap_mcp::McpTools>::static_tools()),
+        )
+    } else {
+        (
+            quote! {{
+                #[allow(unused_imports)]
+                use clap_mcp::__private::{ViaDefault as _, ViaMcpTools as _};
+                #[allow(clippy::needless_borrow)]
+                (&clap_mcp::__private::AttributesProbe::<#subcommand_type>::new()).tool_attributes()
+            }},
+            quote! {{
+                #[allow(unused_imports)]
+                use clap_mcp::__private::{ViaMcpSchema as _, ViaRuntimeSchema as _};
+                #[allow(clippy::needless_borrow)]
+                (&clap_mcp::__private::SchemaProbe::<#subcommand_type>::new()).static_tools()
+            }},
+        )
+    };
+
+    let install_config = struct_attrs.install_config.then(|| {
+        quote! {
+            /// Run `mcp install-config --client <client>` if that is the invoked command
+            ///
+            /// Call before regular parsing; returns `None` for any other invocation.
+            pub fn handle_mcp_install_config() -> Option<Result<(), Box<dyn std::error::Error>>> {
+                clap_mcp::install::handle_from_args(std::env::args_os(), || {
+                    let cmd = <Self as clap_mcp::__private::clap::CommandFactory>::command();
+                    clap_mcp::install::ServerEntry::current_exe(
+                        cmd.get_name(),
+                        clap_mcp::registry::mode_flag_args(&cmd, #mode_flag_id),
+                    )
+                })
+            }
+        }
+    });
+
+    let call = struct_attrs.call.then(|| {
+        quote! {
+            /// Run `mcp call <server> [tool]` if that is the invoked command
+            ///
+            /// Call before regular parsing; returns `None` for any other invocation.
+            pub fn handle_mcp_call() -> Option<Result<(), Box<dyn std::error::Error>>> {
+                clap_mcp::call::handle_from_args(std::env::args_os())
+            }
+        }
+    });
+
+    let with_missing_handler = struct_attrs
+        .missing_handler
+        .as_ref()
+        .map(|variant| quote!(.with_missing_handler(clap_mcp::exec::MissingHandler::#variant)));
+    let server_name = match &struct_attrs.name {
+        Some(name) => quote!(#name),
+        None => quote!(cmd.get_name().to_string()),
+    };
+    let server_version = match &struct_attrs.version {
+        Some(version) => quote!(#version),
+        None => quote!(cmd
+            .get_version()
+            .unwrap_or(env!("CARGO_PKG_VERSION"))
+            .to_string()),
+    };
+    let with_instructions = struct_attrs
+        .instructions
+        .as_ref()
+        .map(|instructions| quote!(.with_instructions(#instructions)));
+    let new_server = quote! {
+        {
+            let attributes = #tool_attributes;
+            // Unused when the name and version are both configured
+            #[allow(unused_variables)]
+            let cmd = <Self as clap_mcp::__private::clap::CommandFactory>::command();
+            let server = McpServer::<#subcommand_type>::new()
+                .with_name(#server_name)
+                .with_version(#server_version)
+                #with_instructions
+                .with_tool_attributes(attributes)
+                .with_metadata(Self::mcp_server_metadata())
+                #with_globals
+                #with_missing_handler;
+            match #static_tools {
+                ::core::option::Option::Some(tools) => server.with_static_schemas(tools),
+                ::core::option::Option::None => server,
+            }
+        }
+    };
+
+    let transport = match &struct_attrs.transport_flag {
+        Some(flag) => {
+            let field = Ident::new(&flag.value(), flag.span());
+            quote!(clap_mcp::TransportSetting::mcp_transport(&self.#field)?)
+        }
+        None => quote!(clap_mcp::McpTransport::Stdio),
+    };
+
+    let replay = struct_attrs.replay.then(|| {
+        quote! {
+            /// Replay the recorded call in `--mcp-replay <file>` if the flag is given
+            ///
+            /// Prints the parsed command line and the result. Call before regular
+            /// parsing; returns `None` when the flag is absent.
+            pub fn handle_mcp_replay(
+                handler: impl Fn(#subcommand_type) -> Result<String, String> + Send + Sync + 'static
+            ) -> Option<Result<(), Box<dyn std::error::Error>>>
+            #server_bounds
+            {
+                clap_mcp::replay::handle_from_args(std::env::args_os(), |entry| {
+                    use clap_mcp::McpServer;
+
+                    let server = #new_server.with_handler(Box::new(handler));
+                    let runtime = clap_mcp::__private::runtime()?;
+                    runtime.block_on(server.replay(&entry))
+                })
+            }
+        }
+    });
+
+    let export_schema = struct_attrs.export_schema.then(|| {
+        quote! {
+            /// Print the tool catalog for `--mcp-schema[=json-schema|openapi]` if
+            /// the flag is given
+            ///
+            /// Call before regular parsing; returns `None` when the flag is absent.
+            pub fn handle_mcp_schema() -> Option<Result<(), Box<dyn std::error::Error>>>
+            #server_bounds
+            {
+                clap_mcp::export::handle_from_args(std::env::args_os(), |format| {
+                    use clap_mcp::McpServer;
+
+                    let server = #new_server;
+                    let runtime = clap_mcp::__private::runtime()?;
+                    runtime.block_on(server.export_schema(format))
+                })
+            }
+        }
+    });
+
     quote! {
         impl #impl_generics #name #ty_generics #where_clause {
-            pub fn run_mcp_server(&self) -> Result<(), Box<dyn std::error::Error>> {
+            /// Server information declared with struct-level `#[mcp(...)]` attributes
+            pub fn mcp_server_metadata() -> clap_mcp::ServerMetadata {
+                clap_mcp::ServerMetadata {
+                    title: #title,
+                    website_url: #website,
+                    icons: ::std::vec![#(clap_mcp::Icon::new(#icons)),*],
+                    contact: #contact,
+                }
+            }
+
+            /// Registry `server.json` manifest built from the clap command and server metadata
+            ///
+            /// Set the namespaced registry name with `.name(...)` before publishing.
+            pub fn mcp_registry_manifest() -> clap_mcp::registry::ServerManifest {
+                let cmd = <Self as clap_mcp::__private::clap::CommandFactory>::command();
+                clap_mcp::registry::ServerManifest::from_command(&cmd)
+                    .metadata(Self::mcp_server_metadata())
+                    .run_args(clap_mcp::registry::mode_flag_args(&cmd, #mode_flag_id))
+            }
+
+            /// The server the `run_mcp*` methods start, before a handler is set
+            ///
+            /// Carries the `#[mcp(...)]` settings of the struct and its subcommands.
+            pub fn mcp_server() -> clap_mcp::McpServer<#subcommand_type>
+            #server_bounds
+            {
+                use clap_mcp::McpServer;
+
+                #new_server
+            }
+
+            #install_config
+
+            #call
+
+            #replay
+
+            #export_schema
+
+            /// Serve the tools with `handler`, over HTTP if the
+            /// `#[mcp(transport_flag)]` field asks for it and stdio otherwise
+            pub fn run_mcp(
+                &self,
+                handler: impl Fn(#subcommand_type) -> Result<String, String> + Send + Sync + 'static
+            ) -> Result<(), Box<dyn std::error::Error>>
+            #server_bounds
+            {
+                use clap_mcp::McpServer;
+
+                if !self.#mode_flag {
+                    return Err("MCP mode not enabled".into());
+                }
+
+                let transport = #transport;
+                let server = #new_server.with_handler(Box::new(handler));
+                let runtime = clap_mcp::__private::runtime()?;
+                runtime.block_on(server.serve(transport))
+            }
+
+            pub fn run_mcp_server(&self) -> Result<(), Box<dyn std::error::Error>>
+            #server_bounds
+            {
                 use clap_mcp::{McpServer, McpTransport};
 
                 if !self.#mode_flag {
                     return Err("MCP mode not enabled".into());
                 }
 
-                let server = McpServer::<#subcommand_type>::new();
-                let runtime = tokio::runtime::Runtime::new()?;
+                let server = #new_server;
+                let runtime = clap_mcp::__private::runtime()?;
                 runtime.block_on(server.serve_stdio())?;
 
                 Ok(())
@@ -131,30 +573,33 @@ fn generate_mcp_impl(
             pub fn run_mcp_server_with_handler(
                 &self,
                 handler: impl Fn(#subcommand_type) -> Result<String, String> + Send + Sync + 'static
-            ) -> Result<(), Box<dyn std::error::Error>> {
+            ) -> Result<(), Box<dyn std::error::Error>>
+            #server_bounds
+            {
                 use clap_mcp::{McpServer, McpTransport};
 
                 if !self.#mode_flag {
                     return Err("MCP mode not enabled".into());
                 }
 
-                let server = McpServer::<#subcommand_type>::new()
-                    .with_handler(Box::new(handler));
-                let runtime = tokio::runtime::Runtime::new()?;
+                let server = #new_server.with_handler(Box::new(handler));
+                let runtime = clap_mcp::__private::runtime()?;
                 runtime.block_on(server.serve_stdio())?;
 
                 Ok(())
             }
 
-            pub fn run_mcp_server_http(&self, addr: std::net::SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+            pub fn run_mcp_server_http(&self, addr: std::net::SocketAddr) -> Result<(), Box<dyn std::error::Error>>
+            #server_bounds
+            {
                 use clap_mcp::{McpServer, McpTransport};
 
                 if !self.#mode_flag {
                     return Err("MCP mode not enabled".into());
                 }
 
-                let server = McpServer::<#subcommand_type>::new();
-                let runtime = tokio::runtime::Runtime::new()?;
+                let server = #new_server;
+                let runtime = clap_mcp::__private::runtime()?;
                 runtime.block_on(server.serve_http(addr))?;
 
                 Ok(())
@@ -164,16 +609,17 @@ fn generate_mcp_impl(
                 &self,
                 addr: std::net::SocketAddr,
                 handler: impl Fn(#subcommand_type) -> Result<String, String> + Send + Sync + 'static
-            ) -> Result<(), Box<dyn std::error::Error>> {
+            ) -> Result<(), Box<dyn std::error::Error>>
+            #server_bounds
+            {
                 use clap_mcp::{McpServer, McpTransport};
 
                 if !self.#mode_flag {
                     return Err("MCP mode not enabled".into());
                 }
 
-                let server = McpServer::<#subcommand_type>::new()
-                    .with_handler(Box::new(handler));
-                let runtime = tokio::runtime::Runtime::new()?;
+                let server = #new_server.with_handler(Box::new(handler));
+                let runtime = clap_mcp::__private::runtime()?;
                 runtime.block_on(server.serve_http(addr))?;
 
                 Ok(())
@@ -0,0 +1,325 @@
+//! `#[derive(McpSchema)]`: tool schemas computed at compile time
+//!
+//! Builds the same input schemas the server derives from clap at runtime, but
+//! from the enum's syntax, and emits them as `static` JSON strings. Argument
+//! types come from the Rust field types, so they are more precise than the
+//! runtime introspection, which cannot see value parsers.
+//!
+//! A tuple variant wrapping an `Args` struct takes the schema the struct's
+//! own `#[derive(McpSchema)]` generates (`clap_mcp::schema::McpArgsSchema`).
+
+use crate::client::{arg_id, has_flag};
+use crate::tools::{
+    args_type, command_str_value, parse_arg_schemas, parse_secret_refs, parse_sticky_args,
+    parse_variant_attrs, rename_all_rule, str_value, tool_name, ArgSchema,
+};
+use proc_macro2::TokenStream;
+use quote::quote;
+use serde_json::{json, Map, Value};
+use syn::{Attribute, Data, DeriveInput, Fields, GenericArgument, PathArguments, Type};
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        Data::Struct(data) => {
+            let (properties, required) = fields_schema(&data.fields)?;
+            let input_schema = object_schema(properties, required);
+            return Ok(quote! {
+                impl #impl_generics clap_mcp::schema::McpArgsSchema for #name #ty_generics #where_clause {
+                    const INPUT_SCHEMA: &'static str = #input_schema;
+                }
+            });
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "McpSchema can only be derived for Subcommand enums and Args structs",
+            ))
+        }
+    };
+
+    let rename_all = rename_all_rule(&input.attrs)?;
+    let mut tools = Vec::new();
+    for variant in &data.variants {
+        let variant_attrs = parse_variant_attrs(&variant.attrs)?;
+        if variant_attrs.skip {
+            continue;
+        }
+        let tool = tool_name(variant, &rename_all)?;
+        let description = match command_str_value(&variant.attrs, "about")? {
+            Some(about) => about.value(),
+            None => doc_summary(&variant.attrs).unwrap_or_default(),
+        };
+
+        let input_schema = match &variant.fields {
+            // Shared by every variant wrapping the struct, so `_vars` is left
+            // for the server to add
+            Fields::Unnamed(_) => match args_type(variant) {
+                Some(ty) => quote!(<#ty as clap_mcp::schema::McpArgsSchema>::INPUT_SCHEMA),
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        variant,
+                        "McpSchema supports named fields or a single Args struct",
+                    ))
+                }
+            },
+            fields => {
+                let (mut properties, required) = fields_schema(fields)?;
+                if variant_attrs.interpolate {
+                    // Mirrors `clap_mcp::vars::vars_schema`
+                    properties.insert(
+                        "_vars".to_string(),
+                        json!({
+                            "type": "object",
+                            "description": "Session variables to remember, referenced in string arguments as ${name}",
+                            "additionalProperties": { "type": ["string", "number", "boolean", "null"] },
+                        }),
+                    );
+                }
+                let input_schema = object_schema(properties, required);
+                quote!(#input_schema)
+            }
+        };
+        tools.push(quote! {
+            clap_mcp::schema::StaticTool {
+                name: #tool,
+                description: #description,
+                input_schema: #input_schema,
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl #impl_generics clap_mcp::schema::McpSchema for #name #ty_generics #where_clause {
+            const TOOLS: &'static [clap_mcp::schema::StaticTool] = &[#(#tools),*];
+        }
+    })
+}
+
+/// The input schema of a tool taking `properties`, as a JSON string
+fn object_schema(properties: Map<String, Value>, required: Vec<String>) -> String {
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+    .to_string()
+}
+
+/// Argument schemas of the named fields of a variant or `Args` struct, and
+/// the ids of the required ones
+fn fields_schema(fields: &Fields) -> syn::Result<(Map<String, Value>, Vec<String>)> {
+    let secret_refs = parse_secret_refs(fields)?;
+    let sticky_args = parse_sticky_args(fields)?;
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    let mut position = 0;
+    let Fields::Named(named) = fields else {
+        return Ok((properties, required));
+    };
+    for field in &named.named {
+        if has_flag(&field.attrs, "command", "flatten")? {
+            return Err(syn::Error::new_spanned(
+                field,
+                "McpSchema cannot see into flattened arguments; use the runtime schemas",
+            ));
+        }
+        if has_flag(&field.attrs, "arg", "skip")? || has_flag(&field.attrs, "arg", "hide")? {
+            continue;
+        }
+
+        let id = arg_id(&field.attrs)?
+            .map(|lit| lit.value())
+            .unwrap_or_else(|| field.ident.as_ref().expect("named field").to_string());
+        let (mut schema, optional) = field_schema(&field.ty);
+        let help = match str_value(&field.attrs, "arg", "help")? {
+            Some(help) => Some(help.value()),
+            None => doc_summary(&field.attrs),
+        };
+        if let Some(help) = help {
+            schema["description"] = json!(help);
+        }
+        // Mirrors `clap_mcp::schema::command_properties`; whether the variable
+        // is set is only known when the server runs
+        let env = match str_value(&field.attrs, "arg", "env")? {
+            Some(env) => Some(env.value()),
+            None => has_flag(&field.attrs, "arg", "env")?.then(|| {
+                field
+                    .ident
+                    .as_ref()
+                    .expect("named field")
+                    .to_string()
+                    .to_uppercase()
+            }),
+        };
+        if let Some(env) = env {
+            let note = format!("[env: {}]", env);
+            schema["description"] = json!(match schema["description"].as_str() {
+                Some(help) => format!("{} {}", help, note),
+                None => note,
+            });
+            schema["x-env"] = json!(env);
+        }
+        if secret_refs.contains(&id) {
+            // Mirrors the runtime schema in `clap_mcp::secrets`
+            schema["x-secret-ref"] = json!(true);
+        }
+        if sticky_args.contains(&id) {
+            schema["x-sticky"] = json!(true);
+        }
+        let is_positional =
+            !has_flag(&field.attrs, "arg", "long")? && !has_flag(&field.attrs, "arg", "short")?;
+        if is_positional {
+            schema["x-positional"] = json!(true);
+            schema["x-position"] = json!(position);
+            position += 1;
+        }
+
+        // Only literal defaults can be known without evaluating code
+        if let Some(default) = str_value(&field.attrs, "arg", "default_value")? {
+            let default = default.value();
+            match (schema["type"].as_str(), schema["items"]["type"].as_str()) {
+                (Some("boolean"), _) => {}
+                (Some("array"), Some(ty)) => schema["default"] = json!([typed_value(&default, ty)]),
+                (Some(ty), _) => schema["default"] = typed_value(&default, ty),
+                _ => {}
+            }
+        }
+
+        let has_default = ["default_value", "default_value_t", "default_values_t"]
+            .iter()
+            .try_fold(false, |found, key| {
+                Ok::<_, syn::Error>(found || has_flag(&field.attrs, "arg", key)?)
+            })?;
+        if has_flag(&field.attrs, "arg", "required")? || (!optional && !has_default) {
+            required.push(id.clone());
+        }
+        properties.insert(id, schema);
+    }
+
+    for (id, schema) in parse_arg_schemas(fields)? {
+        let schema = match schema {
+            ArgSchema::Json(schema) => schema,
+            ArgSchema::ValueType(ty) => {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    "McpSchema cannot evaluate `json_schema` at compile time; use `schema` or the runtime schemas",
+                ))
+            }
+        };
+        let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(&schema.value()) else {
+            return Err(syn::Error::new_spanned(
+                schema,
+                "schema must be a JSON object",
+            ));
+        };
+        if let Some(Value::Object(generated)) = properties.get_mut(&id) {
+            generated.extend(fields);
+        }
+    }
+    Ok((properties, required))
+}
+
+/// JSON schema of a field and whether clap treats it as optional
+///
+/// `bool` flags, `Option<T>` and `Vec<T>` are never required. `Vec<T>` takes
+/// several values, so it is an `array` of `T`.
+fn field_schema(ty: &Type) -> (Value, bool) {
+    match outer(ty) {
+        Some((name, Some(inner))) if name == "Vec" => (
+            json!({ "type": "array", "items": field_schema(inner).0 }),
+            true,
+        ),
+        Some((name, Some(inner))) if name == "Option" => (field_schema(inner).0, true),
+        _ => {
+            let (ty, optional) = field_type(ty);
+            (json!({ "type": ty }), optional)
+        }
+    }
+}
+
+/// JSON type of a single-valued field and whether clap treats it as optional
+fn field_type(ty: &Type) -> (&'static str, bool) {
+    let Some((name, inner)) = outer(ty) else {
+        return ("string", false);
+    };
+    match (name.as_str(), inner) {
+        ("bool", _) => ("boolean", true),
+        ("Option", Some(inner)) => (field_type(inner).0, true),
+        (
+            "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+            | "usize",
+            _,
+        ) => ("integer", false),
+        ("f32" | "f64", _) => ("number", false),
+        _ => ("string", false),
+    }
+}
+
+/// `raw` as a JSON value of type `ty`, or a string if it does not parse as
+/// one; mirrors `clap_mcp::schema::typed_value`
+fn typed_value(raw: &str, ty: &str) -> Value {
+    let parsed = match ty {
+        "integer" => raw
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| raw.parse::<u64>().map(Value::from))
+            .ok(),
+        "number" => raw.parse::<f64>().ok().map(Value::from),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| Value::from(raw))
+}
+
+/// Last path segment of a type and its first generic type argument
+fn outer(ty: &Type) -> Option<(String, Option<&Type>)> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let inner = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        }),
+        _ => None,
+    };
+    Some((segment.ident.to_string(), inner))
+}
+
+/// The short help clap derives from doc comments: the first paragraph, with
+/// its lines joined and a single trailing period removed
+fn doc_summary(attrs: &[Attribute]) -> Option<String> {
+    let mut lines = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("doc")) {
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(doc),
+                    ..
+                }),
+            ..
+        }) = &attr.meta
+        {
+            for line in doc.value().split('\n') {
+                lines.push(line.strip_prefix(' ').unwrap_or(line).trim().to_string());
+            }
+        }
+    }
+    let paragraph: Vec<String> = lines
+        .into_iter()
+        .skip_while(|line| line.is_empty())
+        .take_while(|line| !line.is_empty())
+        .collect();
+    if paragraph.is_empty() {
+        return None;
+    }
+    let mut summary = paragraph.join(" ");
+    if summary.ends_with('.') && !summary.ends_with("..") {
+        summary.pop();
+    }
+    Some(summary)
+}
@@ -0,0 +1,726 @@
+//! `#[derive(McpTools)]`: per-variant MCP settings for a `Subcommand` enum
+//!
+//! The derive also rejects variants the server cannot expose as tools, so
+//! they fail the build instead of surfacing as broken tools at runtime.
+//!
+//! On an `Args` struct wrapped by tuple variants (`Build(BuildArgs)`), it
+//! collects the `#[mcp(...)]` settings of the struct's fields instead, which
+//! the enum's derive merges into the attributes of every such variant.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Data, DeriveInput, Fields, Ident, LitStr, Variant};
+
+/// MCP settings parsed from the `#[mcp(...)]` attributes of one variant
+#[derive(Default)]
+pub(crate) struct VariantAttrs {
+    output_mime: Option<LitStr>,
+    /// `${var}` interpolation in string arguments
+    pub interpolate: bool,
+    /// `#[mcp(skip)]`: hidden from MCP clients
+    pub skip: bool,
+    /// `#[mcp(read_only)]`, `#[mcp(destructive)]`, `#[mcp(idempotent)]`:
+    /// annotation hints for clients
+    read_only: bool,
+    destructive: bool,
+    idempotent: bool,
+    /// Per-argument schema overrides from `#[mcp(...)]` on fields
+    arg_schemas: Vec<(String, ArgSchema)>,
+    /// Arguments marked `#[mcp(secret_ref)]`
+    secret_refs: Vec<String>,
+    /// `#[mcp(timeout_secs = N)]`: how long a call may run
+    timeout_secs: Option<syn::LitInt>,
+    /// Arguments marked `#[mcp(sticky)]`
+    sticky_args: Vec<String>,
+    /// Arguments marked `#[mcp(secret)]`
+    secret_args: Vec<String>,
+    /// `#[mcp(renamed_from = "...")]`: former tool names still accepted
+    renamed_from: Vec<LitStr>,
+    /// `#[mcp(rename = "...")]` on fields: argument ids and listed names
+    arg_renames: Vec<(String, LitStr)>,
+    /// `#[mcp(alias = "...")]` on fields: aliases and argument ids
+    arg_aliases: Vec<(LitStr, String)>,
+    /// `#[mcp(serialize_by = "...")]`: argument whose value calls run
+    /// serially by
+    serialize_by: Option<LitStr>,
+    /// `#[arg(requires = "...")]` on fields: argument ids and the ids they
+    /// require
+    arg_requires: Vec<(String, Vec<LitStr>)>,
+}
+
+impl VariantAttrs {
+    fn is_empty(&self) -> bool {
+        self.output_mime.is_none()
+            && !self.interpolate
+            && !self.skip
+            && !self.read_only
+            && !self.destructive
+            && !self.idempotent
+            && self.arg_schemas.is_empty()
+            && self.secret_refs.is_empty()
+            && self.timeout_secs.is_none()
+            && self.sticky_args.is_empty()
+            && self.renamed_from.is_empty()
+            && self.secret_args.is_empty()
+            && self.arg_renames.is_empty()
+            && self.arg_aliases.is_empty()
+            && self.serialize_by.is_none()
+            && self.arg_requires.is_empty()
+    }
+}
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        Data::Struct(data) => return expand_args(&input, &data.fields),
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "McpTools can only be derived for Subcommand enums and Args structs",
+            ))
+        }
+    };
+
+    let rename_all = rename_all_rule(&input.attrs)?;
+
+    let mut errors: Option<syn::Error> = None;
+    for variant in &data.variants {
+        // Skipped variants are never exposed, so they need no representation
+        if parse_variant_attrs(&variant.attrs)?.skip {
+            continue;
+        }
+        if let Err(e) = check_representable(variant) {
+            match &mut errors {
+                Some(errors) => errors.combine(e),
+                None => errors = Some(e),
+            }
+        }
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+
+    let mut entries = Vec::new();
+    for variant in &data.variants {
+        let mut attrs = parse_variant_attrs(&variant.attrs)?;
+        attrs.arg_schemas = parse_arg_schemas(&variant.fields)?;
+        attrs.secret_refs = parse_secret_refs(&variant.fields)?;
+        attrs.sticky_args = parse_sticky_args(&variant.fields)?;
+        attrs.secret_args = parse_secret_args(&variant.fields)?;
+        attrs.arg_renames = parse_arg_renames(&variant.fields)?;
+        attrs.arg_aliases = parse_arg_aliases(&variant.fields)?;
+        attrs.arg_requires = parse_arg_requires(&variant.fields)?;
+        check_serialize_by(&variant.fields, &attrs)?;
+        // The settings of a wrapped Args struct are only known at runtime
+        let args_type = args_type(variant);
+        if attrs.is_empty() && args_type.is_none() {
+            continue;
+        }
+
+        let tool_name = tool_name(variant, &rename_all)?;
+
+        let output_mime = crate::option_string(&attrs.output_mime);
+        let serialize_by = crate::option_string(&attrs.serialize_by);
+        let interpolate = attrs.interpolate;
+        let skip = attrs.skip;
+        let read_only = attrs.read_only;
+        let destructive = attrs.destructive;
+        let idempotent = attrs.idempotent;
+        let secret_refs = &attrs.secret_refs;
+        let sticky_args = &attrs.sticky_args;
+        let renamed_from = &attrs.renamed_from;
+        let secret_args = &attrs.secret_args;
+        let timeout = match &attrs.timeout_secs {
+            Some(secs) => quote!(::core::option::Option::Some(
+                ::std::time::Duration::from_secs(#secs)
+            )),
+            None => quote!(::core::option::Option::None),
+        };
+        let arg_schemas = arg_schema_entries(&attrs.arg_schemas);
+        let arg_renames = arg_name_entries(&attrs.arg_renames);
+        let arg_aliases = arg_alias_entries(&attrs.arg_aliases);
+        let arg_requires = arg_requires_entries(&attrs.arg_requires);
+        let with_args = args_type.map(|ty| {
+            // The probe cannot see through the enum's own type parameters
+            if crate::mentions_type_param(ty, &input.generics) {
+                generics
+                    .make_where_clause()
+                    .predicates
+                    .push(syn::parse_quote!(#ty: clap_mcp::McpArgs));
+                return quote! {
+                    .with_arg_attributes(<#ty as clap_mcp::McpArgs>::arg_attributes())
+                };
+            }
+            quote! {
+                .with_arg_attributes({
+                    #[allow(unused_imports)]
+                    use clap_mcp::__private::{ViaMcpArgs as _, ViaPlainArgs as _};
+                    #[allow(clippy::needless_borrow)]
+                    (&clap_mcp::__private::ArgsProbe::<#ty>::new()).arg_attributes()
+                })
+            }
+        });
+        entries.push(quote! {
+            clap_mcp::ToolAttributes {
+                name: #tool_name.to_string(),
+                output_mime: #output_mime,
+                interpolate: #interpolate,
+                skip: #skip,
+                read_only: #read_only,
+                destructive: #destructive,
+                idempotent: #idempotent,
+                arg_schemas: [#(#arg_schemas),*].into_iter().collect(),
+                secret_refs: [#(#secret_refs.to_string()),*].into_iter().collect(),
+                timeout: #timeout,
+                sticky_args: [#(#sticky_args.to_string()),*].into_iter().collect(),
+                renamed_from: [#(#renamed_from.to_string()),*].into_iter().collect(),
+                secret_args: [#(#secret_args.to_string()),*].into_iter().collect(),
+                arg_renames: [#(#arg_renames),*].into_iter().collect(),
+                arg_aliases: [#(#arg_aliases),*].into_iter().collect(),
+                arg_requires: [#(#arg_requires),*].into_iter().collect(),
+                serialize_by: #serialize_by,
+                ..::core::default::Default::default()
+            }
+            #with_args
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics clap_mcp::McpTools for #name #ty_generics #where_clause {
+            #[allow(clippy::needless_update)]
+            fn tool_attributes() -> ::std::vec::Vec<clap_mcp::ToolAttributes> {
+                ::std::vec![#(#entries),*]
+            }
+
+            fn static_tools() -> ::core::option::Option<&'static [clap_mcp::schema::StaticTool]> {
+                #[allow(unused_imports)]
+                use clap_mcp::__private::{ViaMcpSchema as _, ViaRuntimeSchema as _};
+                #[allow(clippy::needless_borrow)]
+                (&clap_mcp::__private::SchemaProbe::<Self>::new()).static_tools()
+            }
+        }
+    })
+}
+
+/// `McpArgs` for an `Args` struct: the `#[mcp(...)]` settings of its fields
+fn expand_args(input: &DeriveInput, fields: &Fields) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    check_fields(fields)?;
+
+    let arg_schemas = arg_schema_entries(&parse_arg_schemas(fields)?);
+    let secret_refs = parse_secret_refs(fields)?;
+    let sticky_args = parse_sticky_args(fields)?;
+    let secret_args = parse_secret_args(fields)?;
+    let arg_renames = arg_name_entries(&parse_arg_renames(fields)?);
+    let arg_aliases = arg_alias_entries(&parse_arg_aliases(fields)?);
+    let arg_requires = arg_requires_entries(&parse_arg_requires(fields)?);
+    Ok(quote! {
+        impl #impl_generics clap_mcp::McpArgs for #name #ty_generics #where_clause {
+            fn arg_attributes() -> clap_mcp::ToolAttributes {
+                clap_mcp::ToolAttributes {
+                    arg_schemas: [#(#arg_schemas),*].into_iter().collect(),
+                    secret_refs: [#(#secret_refs.to_string()),*].into_iter().collect(),
+                    sticky_args: [#(#sticky_args.to_string()),*].into_iter().collect(),
+                    secret_args: [#(#secret_args.to_string()),*].into_iter().collect(),
+                    arg_renames: [#(#arg_renames),*].into_iter().collect(),
+                    arg_aliases: [#(#arg_aliases),*].into_iter().collect(),
+                    arg_requires: [#(#arg_requires),*].into_iter().collect(),
+                    ..::core::default::Default::default()
+                }
+            }
+        }
+    })
+}
+
+/// `(id, schema)` pairs building `ToolAttributes::arg_schemas`
+fn arg_schema_entries(arg_schemas: &[(String, ArgSchema)]) -> Vec<TokenStream> {
+    arg_schemas
+        .iter()
+        .map(|(id, schema)| {
+            let value = match schema {
+                ArgSchema::Json(json) => quote! {
+                    clap_mcp::__private::serde_json::from_str(#json)
+                        .expect("schema validated by #[derive(McpTools)]")
+                },
+                ArgSchema::ValueType(ty) => quote! {
+                    clap_mcp::schema::schema_for::<#ty>()
+                },
+            };
+            quote! { (#id.to_string(), #value) }
+        })
+        .collect()
+}
+
+/// `(id, name)` pairs building `ToolAttributes::arg_renames`
+fn arg_name_entries(arg_renames: &[(String, LitStr)]) -> Vec<TokenStream> {
+    arg_renames
+        .iter()
+        .map(|(id, name)| quote! { (#id.to_string(), #name.to_string()) })
+        .collect()
+}
+
+/// `(alias, id)` pairs building `ToolAttributes::arg_aliases`
+fn arg_alias_entries(arg_aliases: &[(LitStr, String)]) -> Vec<TokenStream> {
+    arg_aliases
+        .iter()
+        .map(|(alias, id)| quote! { (#alias.to_string(), #id.to_string()) })
+        .collect()
+}
+
+/// `(id, required ids)` pairs building `ToolAttributes::arg_requires`
+fn arg_requires_entries(arg_requires: &[(String, Vec<LitStr>)]) -> Vec<TokenStream> {
+    arg_requires
+        .iter()
+        .map(|(id, requires)| {
+            quote! { (#id.to_string(), [#(#requires.to_string()),*].into_iter().collect()) }
+        })
+        .collect()
+}
+
+/// The `Args` struct of a tuple variant like `Build(BuildArgs)`
+pub(crate) fn args_type(variant: &Variant) -> Option<&syn::Type> {
+    match &variant.fields {
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Some(&fields.unnamed[0].ty),
+        _ => None,
+    }
+}
+
+/// The enum's `#[command(rename_all = "...")]` rule, kebab-case by default
+pub(crate) fn rename_all_rule(attrs: &[Attribute]) -> syn::Result<String> {
+    Ok(command_str_value(attrs, "rename_all")?
+        .map(|lit| lit.value())
+        .unwrap_or_else(|| "kebab-case".to_string()))
+}
+
+/// Subcommand (and therefore tool) name clap derives for a variant
+pub(crate) fn tool_name(variant: &Variant, rename_all: &str) -> syn::Result<String> {
+    match command_str_value(&variant.attrs, "name")? {
+        Some(lit) => Ok(lit.value()),
+        None => rename(&variant.ident, rename_all)
+            .map_err(|msg| syn::Error::new_spanned(&variant.ident, msg)),
+    }
+}
+
+pub(crate) fn parse_variant_attrs(attrs: &[Attribute]) -> syn::Result<VariantAttrs> {
+    let mut parsed = VariantAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("mcp")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("output_mime") {
+                parsed.output_mime = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("interpolate") {
+                parsed.interpolate = true;
+                Ok(())
+            } else if meta.path.is_ident("skip") {
+                parsed.skip = true;
+                Ok(())
+            } else if meta.path.is_ident("read_only") {
+                parsed.read_only = true;
+                Ok(())
+            } else if meta.path.is_ident("destructive") {
+                parsed.destructive = true;
+                Ok(())
+            } else if meta.path.is_ident("idempotent") {
+                parsed.idempotent = true;
+                Ok(())
+            } else if meta.path.is_ident("timeout_secs") {
+                let secs: syn::LitInt = meta.value()?.parse()?;
+                secs.base10_parse::<u64>()?;
+                parsed.timeout_secs = Some(secs);
+                Ok(())
+            } else if meta.path.is_ident("renamed_from") {
+                parsed.renamed_from.push(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("serialize_by") {
+                parsed.serialize_by = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown mcp attribute"))
+            }
+        })?;
+        if parsed.read_only && parsed.destructive {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "a tool cannot be both read_only and destructive",
+            ));
+        }
+    }
+    Ok(parsed)
+}
+
+/// Reject a `serialize_by` argument the variant's fields do not declare
+///
+/// Fields of a wrapped `Args` struct are not known here, so tuple variants
+/// are not checked.
+fn check_serialize_by(fields: &Fields, attrs: &VariantAttrs) -> syn::Result<()> {
+    let (Some(arg), Fields::Named(named)) = (&attrs.serialize_by, fields) else {
+        return Ok(());
+    };
+    let mut names = Vec::new();
+    for field in &named.named {
+        let id = crate::client::arg_id(&field.attrs)?
+            .map(|lit| lit.value())
+            .unwrap_or_else(|| field.ident.as_ref().expect("named field").to_string());
+        names.push(id);
+    }
+    names.extend(attrs.arg_renames.iter().map(|(_, name)| name.value()));
+    names.extend(attrs.arg_aliases.iter().map(|(alias, _)| alias.value()));
+    if names.contains(&arg.value()) {
+        return Ok(());
+    }
+    Err(syn::Error::new_spanned(
+        arg,
+        format!(
+            "serialize_by names no argument of this tool: `{}`",
+            arg.value()
+        ),
+    ))
+}
+
+/// Reject variants that have no MCP tool representation
+///
+/// External subcommands have no name or schema to list, nested subcommands
+/// would need tools of their own, and `OsString` arguments accept non-UTF-8
+/// values that JSON tool arguments cannot carry.
+fn check_representable(variant: &Variant) -> syn::Result<()> {
+    if crate::client::has_flag(&variant.attrs, "command", "external_subcommand")? {
+        return Err(syn::Error::new_spanned(
+            &variant.ident,
+            "external subcommands cannot be exposed as MCP tools",
+        ));
+    }
+    check_fields(&variant.fields)
+}
+
+/// Reject nested subcommands and `OsString` arguments among `fields`
+fn check_fields(fields: &Fields) -> syn::Result<()> {
+    for field in fields.iter() {
+        if crate::client::has_flag(&field.attrs, "command", "subcommand")? {
+            return Err(syn::Error::new_spanned(
+                field,
+                "nested subcommands cannot be exposed as MCP tools; flatten them into the top-level enum",
+            ));
+        }
+        if mentions_os_string(&field.ty) {
+            return Err(syn::Error::new_spanned(
+                &field.ty,
+                "OsString arguments cannot be sent over MCP (tool arguments are UTF-8 JSON); use String or PathBuf",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `ty` is `OsString` or wraps it (`Vec<OsString>`, `Option<OsString>`)
+fn mentions_os_string(ty: &syn::Type) -> bool {
+    let syn::Type::Path(path) = ty else {
+        return false;
+    };
+    path.path.segments.iter().any(|segment| {
+        segment.ident == "OsString" || match &segment.arguments {
+            syn::PathArguments::AngleBracketed(args) => args.args.iter().any(
+                |arg| matches!(arg, syn::GenericArgument::Type(inner) if mentions_os_string(inner)),
+            ),
+            _ => false,
+        }
+    })
+}
+
+/// Schema override of one argument
+pub(crate) enum ArgSchema {
+    /// `#[mcp(schema = "...")]`: literal JSON, validated here
+    Json(LitStr),
+    /// `#[mcp(json_schema)]`: the field type's `schemars::JsonSchema` impl
+    ValueType(Box<syn::Type>),
+}
+
+/// MCP setting of one argument
+pub(crate) enum ArgAttr {
+    Schema(ArgSchema),
+    /// `#[mcp(secret_ref)]`: the value names a server-side secret
+    SecretRef,
+    /// `#[mcp(sticky)]`: omitted values default to the session's last one
+    Sticky,
+    /// `#[mcp(secret)]`: the value is sensitive and kept out of error messages
+    Secret,
+    /// `#[mcp(rename = "...")]`: the name the argument is listed and called by
+    Rename(LitStr),
+    /// `#[mcp(alias = "...")]`: a further name calls may use
+    Alias(LitStr),
+}
+
+/// `#[mcp(...)]` settings on the fields of a variant or `Args` struct, keyed
+/// by argument id
+pub(crate) fn parse_arg_attrs(fields: &Fields) -> syn::Result<Vec<(String, ArgAttr)>> {
+    let Fields::Named(fields) = fields else {
+        return Ok(Vec::new());
+    };
+    let mut attrs = Vec::new();
+    for field in &fields.named {
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("mcp")) {
+            attr.parse_nested_meta(|meta| {
+                let parsed = if meta.path.is_ident("schema") {
+                    let schema: LitStr = meta.value()?.parse()?;
+                    match serde_json::from_str::<serde_json::Value>(&schema.value()) {
+                        Ok(serde_json::Value::Object(_)) => {}
+                        Ok(_) => {
+                            return Err(syn::Error::new_spanned(
+                                &schema,
+                                "schema must be a JSON object",
+                            ))
+                        }
+                        Err(e) => {
+                            return Err(syn::Error::new_spanned(
+                                &schema,
+                                format!("invalid schema JSON: {}", e),
+                            ))
+                        }
+                    }
+                    ArgAttr::Schema(ArgSchema::Json(schema))
+                } else if meta.path.is_ident("json_schema") {
+                    ArgAttr::Schema(ArgSchema::ValueType(Box::new(
+                        value_type(&field.ty).clone(),
+                    )))
+                } else if meta.path.is_ident("secret_ref") {
+                    ArgAttr::SecretRef
+                } else if meta.path.is_ident("sticky") {
+                    ArgAttr::Sticky
+                } else if meta.path.is_ident("secret") {
+                    ArgAttr::Secret
+                } else if meta.path.is_ident("rename") {
+                    ArgAttr::Rename(meta.value()?.parse()?)
+                } else if meta.path.is_ident("alias") {
+                    ArgAttr::Alias(meta.value()?.parse()?)
+                } else {
+                    return Err(meta.error("unknown mcp argument attribute"));
+                };
+                let id = crate::client::arg_id(&field.attrs)?
+                    .map(|lit| lit.value())
+                    .unwrap_or_else(|| field.ident.as_ref().expect("named field").to_string());
+                attrs.push((id, parsed));
+                Ok(())
+            })?;
+        }
+    }
+    Ok(attrs)
+}
+
+/// `#[mcp(schema = "...")]` and `#[mcp(json_schema)]` on the fields of a variant
+pub(crate) fn parse_arg_schemas(fields: &Fields) -> syn::Result<Vec<(String, ArgSchema)>> {
+    Ok(parse_arg_attrs(fields)?
+        .into_iter()
+        .filter_map(|(id, attr)| match attr {
+            ArgAttr::Schema(schema) => Some((id, schema)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Ids of the `#[mcp(secret_ref)]` arguments of a variant
+pub(crate) fn parse_secret_refs(fields: &Fields) -> syn::Result<Vec<String>> {
+    Ok(parse_arg_attrs(fields)?
+        .into_iter()
+        .filter_map(|(id, attr)| matches!(attr, ArgAttr::SecretRef).then_some(id))
+        .collect())
+}
+
+/// Arguments marked `#[mcp(sticky)]`
+pub(crate) fn parse_sticky_args(fields: &Fields) -> syn::Result<Vec<String>> {
+    Ok(parse_arg_attrs(fields)?
+        .into_iter()
+        .filter_map(|(id, attr)| matches!(attr, ArgAttr::Sticky).then_some(id))
+        .collect())
+}
+
+/// `#[arg(requires = "...")]` on the fields of a variant, keyed by argument
+/// id
+///
+/// clap keeps them private once the command is built, so they are passed
+/// along for the schema's `dependentRequired`. Only literal ids are seen.
+fn parse_arg_requires(fields: &Fields) -> syn::Result<Vec<(String, Vec<LitStr>)>> {
+    let Fields::Named(fields) = fields else {
+        return Ok(Vec::new());
+    };
+    let mut arg_requires = Vec::new();
+    for field in &fields.named {
+        let mut requires = Vec::new();
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("arg")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("requires") && meta.input.peek(syn::Token![=]) {
+                    let expr: syn::Expr = meta.value()?.parse()?;
+                    if let syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(lit),
+                        ..
+                    }) = expr
+                    {
+                        requires.push(lit);
+                    }
+                    Ok(())
+                } else {
+                    crate::skip_meta_value(&meta)
+                }
+            })?;
+        }
+        if !requires.is_empty() {
+            let id = crate::client::arg_id(&field.attrs)?
+                .map(|lit| lit.value())
+                .unwrap_or_else(|| field.ident.as_ref().expect("named field").to_string());
+            arg_requires.push((id, requires));
+        }
+    }
+    Ok(arg_requires)
+}
+
+/// `#[mcp(rename = "...")]` on the fields of a variant, keyed by argument id
+///
+/// Rejects an argument renamed twice.
+fn parse_arg_renames(fields: &Fields) -> syn::Result<Vec<(String, LitStr)>> {
+    let mut renames: Vec<(String, LitStr)> = Vec::new();
+    for (id, attr) in parse_arg_attrs(fields)? {
+        if let ArgAttr::Rename(name) = attr {
+            if renames.iter().any(|(renamed, _)| *renamed == id) {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    format!("argument `{}` is renamed more than once", id),
+                ));
+            }
+            renames.push((id, name));
+        }
+    }
+    Ok(renames)
+}
+
+/// `#[mcp(alias = "...")]` on the fields of a variant, with the ids they
+/// stand for
+fn parse_arg_aliases(fields: &Fields) -> syn::Result<Vec<(LitStr, String)>> {
+    Ok(parse_arg_attrs(fields)?
+        .into_iter()
+        .filter_map(|(id, attr)| match attr {
+            ArgAttr::Alias(alias) => Some((alias, id)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Arguments marked `#[mcp(secret)]`
+fn parse_secret_args(fields: &Fields) -> syn::Result<Vec<String>> {
+    Ok(parse_arg_attrs(fields)?
+        .into_iter()
+        .filter_map(|(id, attr)| matches!(attr, ArgAttr::Secret).then_some(id))
+        .collect())
+}
+
+/// `T` for an `Option<T>` field, the field type otherwise
+fn value_type(ty: &syn::Type) -> &syn::Type {
+    if let syn::Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if segment.ident == "Option" {
+                if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+                    if let Some(syn::GenericArgument::Type(inner)) = args.args.first() {
+                        return inner;
+                    }
+                }
+            }
+        }
+    }
+    ty
+}
+
+/// Find a `key = "value"` setting inside `#[command(...)]` attributes
+pub(crate) fn command_str_value(attrs: &[Attribute], key: &str) -> syn::Result<Option<LitStr>> {
+    str_value(attrs, "command", key)
+}
+
+/// Find a `key = "value"` setting inside `#[<attr_name>(...)]` attributes
+pub(crate) fn str_value(
+    attrs: &[Attribute],
+    attr_name: &str,
+    key: &str,
+) -> syn::Result<Option<LitStr>> {
+    let mut found = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident(attr_name)) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) && meta.input.peek(syn::Token![=]) {
+                let expr: syn::Expr = meta.value()?.parse()?;
+                if let syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(lit),
+                    ..
+                }) = expr
+                {
+                    found = Some(lit);
+                }
+                Ok(())
+            } else {
+                crate::skip_meta_value(&meta)
+            }
+        })?;
+    }
+    Ok(found)
+}
+
+/// Apply clap's `rename_all` convention to a variant name
+pub(crate) fn rename(ident: &Ident, rule: &str) -> Result<String, String> {
+    let ident = ident.to_string();
+    let words = split_words(&ident);
+    let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    let capitalized: Vec<String> = lower
+        .iter()
+        .map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+
+    Ok(match rule {
+        "kebab-case" | "kebab" => lower.join("-"),
+        "snake_case" | "snake" => lower.join("_"),
+        "SCREAMING_SNAKE_CASE" | "SCREAMING-SNAKE-CASE" => lower.join("_").to_uppercase(),
+        "lower" | "lowercase" => lower.join(""),
+        "UPPER" | "UPPERCASE" => lower.join("").to_uppercase(),
+        "PascalCase" | "UpperCamelCase" => capitalized.join(""),
+        "camelCase" | "lowerCamelCase" => {
+            let mut out = lower.first().cloned().unwrap_or_default();
+            out.extend(capitalized.iter().skip(1).cloned());
+            out
+        }
+        "verbatim" | "Verbatim" => ident,
+        other => return Err(format!("unsupported rename_all rule `{}`", other)),
+    })
+}
+
+/// Split an identifier into words the way `heck` does for clap
+fn split_words(ident: &str) -> Vec<String> {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut words = Vec::new();
+    let mut current = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' || c == '-' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let boundary = c.is_uppercase()
+            && !current.is_empty()
+            && (chars[i - 1].is_lowercase()
+                || chars[i - 1].is_ascii_digit()
+                || chars.get(i + 1).is_some_and(|n| n.is_lowercase()));
+        if boundary {
+            words.push(std::mem::take(&mut current));
+        }
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
@@ -0,0 +1,451 @@
+//! Conversion of tool-call arguments into command-line tokens
+//!
+//! Tokens are handed to clap (or to a child process) as a vector, never
+//! through a shell, so values such as Windows paths, UNC paths, or strings with
+//! spaces and quotes are passed through verbatim. The quoting helpers here are
+//! only needed when an argv has to be shown or logged as a single string.
+//!
+//! CLIs with unusual flag grammars can register an [`ArgEncoder`] for an
+//! argument to take over how its value is turned into tokens.
+
+use clap::{Arg, ArgAction, Command};
+use rmcp::model::JsonObject;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Custom conversion of one argument's JSON value into CLI tokens
+///
+/// Registered per tool and argument with `McpServer::with_arg_encoder`. Plain
+/// functions and closures with the same signature implement it.
+pub trait ArgEncoder: Send + Sync {
+    /// Tokens for `value`
+    ///
+    /// `flag` is the option clap expects (`--filter` or `-f`), or `None` for a
+    /// positional argument, whose tokens are placed at its position.
+    fn encode(&self, flag: Option<&str>, value: &Value) -> Vec<String>;
+}
+
+impl<F> ArgEncoder for F
+where
+    F: Fn(Option<&str>, &Value) -> Vec<String> + Send + Sync,
+{
+    fn encode(&self, flag: Option<&str>, value: &Value) -> Vec<String> {
+        self(flag, value)
+    }
+}
+
+/// Encodes a JSON object as one `--flag key=value` pair per entry
+///
+/// For options declared with `ArgAction::Append`, e.g. `--filter key=value`
+/// given once per filter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyValuePairs;
+
+impl ArgEncoder for KeyValuePairs {
+    fn encode(&self, flag: Option<&str>, value: &Value) -> Vec<String> {
+        let Value::Object(entries) = value else {
+            let token = value_to_token(value.clone());
+            return flag
+                .map(|f| f.to_string())
+                .into_iter()
+                .chain([token])
+                .collect();
+        };
+        entries
+            .iter()
+            .flat_map(|(key, value)| {
+                let pair = format!("{}={}", key, value_to_token(value.clone()));
+                flag.map(|f| f.to_string()).into_iter().chain([pair])
+            })
+            .collect()
+    }
+}
+
+/// Encoders for the arguments of one tool, keyed by argument id
+pub(crate) type ArgEncoders = HashMap<String, Arc<dyn ArgEncoder>>;
+
+/// Build the tokens that follow the subcommand name for one tool call
+///
+/// Named arguments are emitted as `--long=value` so values that start with a
+/// dash (negative numbers, `-` for stdin, option-like strings) are never
+/// mistaken for flags. Arrays repeat the option once per element, except for
+/// options that take a list per occurrence and cannot repeat (`num_args(1..)`
+/// without `ArgAction::Append`): those get the flag once, followed by the
+/// elements. Counted flags are repeated as often as the number given.
+/// Positional arguments follow in declaration order, preceded by `--` when
+/// any of them starts with a dash or follows such a list. Arguments with an
+/// entry in `encoders` are converted by it instead.
+pub(crate) fn build_argv(
+    subcommand: Option<&Command>,
+    arguments: JsonObject,
+    encoders: Option<&ArgEncoders>,
+) -> Vec<String> {
+    let mut named = Vec::new();
+    let mut positionals: Vec<(usize, Vec<String>)> = Vec::new();
+    // Whether an option's value list could swallow the positionals
+    let mut value_list = false;
+
+    for (key, value) in arguments {
+        let arg =
+            subcommand.and_then(|cmd| cmd.get_arguments().find(|a| a.get_id().as_str() == key));
+        let encoder = encoders.and_then(|encoders| encoders.get(&key));
+
+        if let Some(arg) = arg.filter(|a| a.is_positional()) {
+            let position = subcommand
+                .into_iter()
+                .flat_map(|cmd| cmd.get_positionals())
+                .position(|p| p.get_id() == arg.get_id())
+                .unwrap_or(usize::MAX);
+            let tokens = match (encoder, value) {
+                (Some(encoder), value) => encoder.encode(None, &value),
+                (None, Value::Array(items)) => items.into_iter().map(value_to_token).collect(),
+                (None, value) => vec![value_to_token(value)],
+            };
+            positionals.push((position, tokens));
+            continue;
+        }
+
+        let flag = match arg {
+            Some(arg) => match (arg.get_long(), arg.get_short()) {
+                (Some(long), _) => format!("--{}", long),
+                (None, Some(short)) => format!("-{}", short),
+                (None, None) => format!("--{}", key),
+            },
+            None => format!("--{}", key),
+        };
+
+        if let Some(encoder) = encoder {
+            named.extend(encoder.encode(Some(&flag), &value));
+            continue;
+        }
+
+        let action = arg.map(|arg| arg.get_action());
+        match value {
+            // Options parsing a bool take it as a value; flags are present or absent
+            Value::Bool(b) if action.is_some_and(|a| a.takes_values()) => {
+                named.push(format!("{}={}", flag, b))
+            }
+            Value::Bool(true) => named.push(flag),
+            Value::Bool(false) => {}
+            Value::Number(n) if matches!(action, Some(ArgAction::Count)) => {
+                let count = n.as_u64().unwrap_or(0) as usize;
+                named.extend(std::iter::repeat_n(flag, count));
+            }
+            // Options taking several values at once get them all after one flag
+            Value::Array(items) if arg.is_some_and(takes_value_list) => {
+                named.push(flag);
+                named.extend(items.into_iter().map(value_to_token));
+                value_list = true;
+            }
+            // One occurrence per element for multi-value options
+            Value::Array(items) => named.extend(
+                items
+                    .into_iter()
+                    .map(|item| format!("{}={}", flag, value_to_token(item))),
+            ),
+            value => named.push(format!("{}={}", flag, value_to_token(value))),
+        }
+    }
+
+    positionals.sort_by_key(|(position, _)| *position);
+
+    let mut args = named;
+    let dash = positionals
+        .iter()
+        .flat_map(|(_, tokens)| tokens)
+        .any(|v| v.starts_with('-'));
+    if dash || (value_list && !positionals.is_empty()) {
+        args.push("--".to_string());
+    }
+    args.extend(positionals.into_iter().flat_map(|(_, tokens)| tokens));
+    args
+}
+
+/// Whether `arg` takes several values per occurrence and cannot be repeated,
+/// like `--range 1 5` or `--files a b c` with `num_args(1..)`
+fn takes_value_list(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Set)
+        && arg
+            .get_num_args()
+            .is_some_and(|range| range.max_values() > 1)
+}
+
+fn value_to_token(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Quote a single argument for a POSIX shell
+pub fn quote_posix_arg(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,+@%".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Quote a single argument following the Windows C runtime parsing rules
+///
+/// Backslashes are literal unless they precede a double quote, so only runs of
+/// backslashes before an embedded quote or the closing quote are doubled. This
+/// keeps `C:\Program Files\` and UNC paths like `\\server\share` intact.
+pub fn quote_windows_arg(arg: &str) -> String {
+    let needs_quotes = arg.is_empty() || arg.contains([' ', '\t', '\n', '\x0b', '"']);
+    if !needs_quotes {
+        return arg.to_string();
+    }
+
+    let mut out = String::with_capacity(arg.len() + 2);
+    out.push('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                out.push_str(&"\\".repeat(backslashes * 2 + 1));
+                out.push('"');
+                backslashes = 0;
+            }
+            c => {
+                out.push_str(&"\\".repeat(backslashes));
+                out.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    out.push_str(&"\\".repeat(backslashes * 2));
+    out.push('"');
+    out
+}
+
+/// Render an argv as a single command line using the host platform's quoting
+pub fn format_command_line<S: AsRef<str>>(args: &[S]) -> String {
+    let quote = if cfg!(windows) {
+        quote_windows_arg
+    } else {
+        quote_posix_arg
+    };
+    args.iter()
+        .map(|a| quote(a.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap()
+    }
+
+    fn copy_command() -> Command {
+        Command::new("copy")
+            .arg(Arg::new("source").required(true))
+            .arg(Arg::new("dest").required(true))
+            .arg(
+                Arg::new("dry_run")
+                    .long("dry-run")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("offset").short('o'))
+    }
+
+    #[test]
+    fn test_build_argv_typed_values() {
+        let mut cmd = Command::new("build")
+            .arg(
+                Arg::new("feature")
+                    .long("feature")
+                    .action(ArgAction::Append),
+            )
+            .arg(Arg::new("verbose").short('v').action(ArgAction::Count))
+            .arg(
+                Arg::new("strip")
+                    .long("strip")
+                    .value_parser(clap::value_parser!(bool)),
+            )
+            .arg(Arg::new("files").num_args(1..));
+        cmd.build();
+        let argv = build_argv(
+            Some(&cmd),
+            object(json!({
+                "feature": ["a", "b"],
+                "verbose": 2,
+                "strip": false,
+                "files": ["x.rs", "y.rs"],
+            })),
+            None,
+        );
+        assert_eq!(
+            argv,
+            vec![
+                "--feature=a",
+                "--feature=b",
+                "--strip=false",
+                "-v",
+                "-v",
+                "x.rs",
+                "y.rs",
+            ]
+        );
+        let matches = cmd
+            .try_get_matches_from(std::iter::once("build".to_string()).chain(argv))
+            .unwrap();
+        assert_eq!(matches.get_count("verbose"), 2);
+        assert_eq!(matches.get_one::<bool>("strip"), Some(&false));
+        assert_eq!(matches.get_many::<String>("files").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_build_argv_value_lists() {
+        let mut cmd = Command::new("scan")
+            .arg(Arg::new("ports").long("ports").num_args(1..))
+            .arg(Arg::new("range").long("range").num_args(2))
+            .arg(
+                Arg::new("exclude")
+                    .long("exclude")
+                    .num_args(1..)
+                    .action(ArgAction::Append),
+            )
+            .arg(Arg::new("host"));
+        cmd.build();
+        let argv = build_argv(
+            Some(&cmd),
+            object(json!({
+                "ports": [80, 443],
+                "range": [1, 5],
+                "exclude": ["a", "b"],
+                "host": "example.com",
+            })),
+            None,
+        );
+        assert_eq!(
+            argv,
+            vec![
+                "--exclude=a",
+                "--exclude=b",
+                "--ports",
+                "80",
+                "443",
+                "--range",
+                "1",
+                "5",
+                "--",
+                "example.com",
+            ]
+        );
+        let matches = cmd
+            .try_get_matches_from(std::iter::once("scan".to_string()).chain(argv))
+            .unwrap();
+        let values = |id: &str| {
+            matches
+                .get_many::<String>(id)
+                .unwrap()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(values("ports"), ["80", "443"]);
+        assert_eq!(values("range"), ["1", "5"]);
+        assert_eq!(values("exclude"), ["a", "b"]);
+        assert_eq!(values("host"), ["example.com"]);
+    }
+
+    #[test]
+    fn test_build_argv_passes_paths_verbatim() {
+        let cmd = copy_command();
+        let argv = build_argv(
+            Some(&cmd),
+            object(json!({
+                "dest": r"\\server\share\out dir\",
+                "source": r"C:\Program Files\app\data.txt",
+                "dry_run": true,
+            })),
+            None,
+        );
+        assert_eq!(
+            argv,
+            vec![
+                "--dry-run",
+                r"C:\Program Files\app\data.txt",
+                r"\\server\share\out dir\",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_argv_dash_values() {
+        let cmd = copy_command();
+        let argv = build_argv(
+            Some(&cmd),
+            object(json!({ "source": "-", "dest": "out", "offset": -5, "dry_run": false })),
+            None,
+        );
+        assert_eq!(argv, vec!["-o=-5", "--", "-", "out"]);
+    }
+
+    #[test]
+    fn test_build_argv_custom_encoders() {
+        let cmd = Command::new("query")
+            .arg(Arg::new("filter").long("filter").action(ArgAction::Append))
+            .arg(Arg::new("columns").num_args(1..));
+        let mut encoders = ArgEncoders::new();
+        encoders.insert("filter".to_string(), Arc::new(KeyValuePairs));
+        let split = |_: Option<&str>, value: &Value| -> Vec<String> {
+            value
+                .as_str()
+                .unwrap_or_default()
+                .split(',')
+                .map(str::to_string)
+                .collect()
+        };
+        encoders.insert("columns".to_string(), Arc::new(split));
+
+        let argv = build_argv(
+            Some(&cmd),
+            object(json!({
+                "filter": { "env": "prod", "tier": 2 },
+                "columns": "name,age",
+            })),
+            Some(&encoders),
+        );
+        assert_eq!(
+            argv,
+            vec!["--filter", "env=prod", "--filter", "tier=2", "name", "age"]
+        );
+    }
+
+    #[test]
+    fn test_quote_windows_arg() {
+        assert_eq!(quote_windows_arg(r"C:\temp\file.txt"), r"C:\temp\file.txt");
+        assert_eq!(
+            quote_windows_arg(r"C:\Program Files\"),
+            r#""C:\Program Files\\""#
+        );
+        assert_eq!(
+            quote_windows_arg(r"\\server\share\my file"),
+            r#""\\server\share\my file""#
+        );
+        assert_eq!(quote_windows_arg(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_windows_arg(r#"a\"b"#), r#""a\\\"b""#);
+        assert_eq!(quote_windows_arg(""), r#""""#);
+    }
+
+    #[test]
+    fn test_quote_posix_arg() {
+        assert_eq!(quote_posix_arg("--name=value"), "--name=value");
+        assert_eq!(quote_posix_arg("two words"), "'two words'");
+        assert_eq!(quote_posix_arg("it's"), r"'it'\''s'");
+        assert_eq!(quote_posix_arg(""), "''");
+    }
+}
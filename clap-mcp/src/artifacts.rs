@@ -0,0 +1,354 @@
+//! A directory for the files agents make a server produce
+//!
+//! Reports, logs and exports written on an agent's behalf pile up wherever
+//! each handler happened to put them. [`Artifacts`] gives them one
+//! configurable home, keeps it within a size and age budget, and serves its
+//! files as resources:
+//!
+//! ```ignore
+//! let artifacts = Artifacts::new("/var/lib/mytool/artifacts")
+//!     .max_size(512 * 1024 * 1024)
+//!     .max_age(Duration::from_secs(7 * 24 * 3600));
+//! let store = artifacts.clone();
+//! McpServer::<Commands>::new()
+//!     .with_artifacts(artifacts)
+//!     .with_handler(Box::new(move |cmd| {
+//!         let path = store.write("report.csv", build_report(cmd)?)?;
+//!         Ok(format!("Report written to {}", path.display()))
+//!     }));
+//! ```
+//!
+//! Cleanup runs when the server is built and after every write: files older
+//! than the maximum age are removed, then the oldest ones until the directory
+//! fits in the maximum size. The file just written is always kept. Files
+//! handlers place in [`Artifacts::dir`] themselves are managed the same way.
+//!
+//! `McpServer::with_artifacts` also registers a read-only `list_artifacts`
+//! tool, so operators and agents can see where the files live and how much
+//! room they take. A subcommand of the same name takes precedence.
+
+use crate::resources::{file_uri, guess_mime_type, read_file, ResourceProvider};
+use rmcp::model::{
+    object, AnnotateAble, RawResource, Resource, ResourceContents, Tool, ToolAnnotations,
+};
+use serde_json::{json, Value};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Name of the registered tool
+pub const LIST_ARTIFACTS_TOOL: &str = "list_artifacts";
+
+/// A file in the artifacts directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl Artifact {
+    fn age(&self) -> Duration {
+        self.modified.elapsed().unwrap_or_default()
+    }
+}
+
+/// The artifacts directory and its limits; clones share the directory
+#[derive(Debug, Clone)]
+pub struct Artifacts {
+    dir: Arc<PathBuf>,
+    /// Largest total size of the files kept, in bytes
+    max_size: Option<u64>,
+    /// Oldest a file may get before it is removed
+    max_age: Option<Duration>,
+}
+
+impl Artifacts {
+    /// Artifacts kept in `dir`, created when first written to
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        Self {
+            dir: Arc::new(std::path::absolute(&dir).unwrap_or(dir)),
+            max_size: None,
+            max_age: None,
+        }
+    }
+
+    /// Remove the oldest files once the directory holds more than `bytes`
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Remove files older than `age`
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// The directory the artifacts live in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Write `contents` as the artifact `name`, replacing one of the same
+    /// name, and clean up; returns the file's path
+    ///
+    /// Only the last component of `name` is used, so artifacts cannot land
+    /// outside the directory.
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> io::Result<PathBuf> {
+        let file_name = Path::new(name)
+            .file_name()
+            .filter(|file_name| !file_name.is_empty())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid artifact name `{}`", name),
+                )
+            })?;
+        std::fs::create_dir_all(self.dir.as_path())?;
+        let path = self.dir.join(file_name);
+        std::fs::write(&path, contents)?;
+        self.clean(Some(&path))?;
+        Ok(path)
+    }
+
+    /// Every artifact, newest first
+    pub fn list(&self) -> io::Result<Vec<Artifact>> {
+        let entries = match std::fs::read_dir(self.dir.as_path()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut artifacts = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            artifacts.push(Artifact {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+        artifacts.sort_by(|a, b| b.modified.cmp(&a.modified).then(a.name.cmp(&b.name)));
+        Ok(artifacts)
+    }
+
+    /// Remove the files over the age and size limits; returns the removed
+    /// files
+    pub fn cleanup(&self) -> io::Result<Vec<Artifact>> {
+        self.clean(None)
+    }
+
+    /// Like [`cleanup`](Self::cleanup), never removing `keep`
+    fn clean(&self, keep: Option<&Path>) -> io::Result<Vec<Artifact>> {
+        if self.max_age.is_none() && self.max_size.is_none() {
+            return Ok(Vec::new());
+        }
+        let mut kept = Vec::new();
+        let mut removed = Vec::new();
+        for artifact in self.list()? {
+            let expired = self.max_age.is_some_and(|max_age| artifact.age() > max_age);
+            if expired && keep != Some(artifact.path.as_path()) {
+                removed.push(artifact);
+            } else {
+                kept.push(artifact);
+            }
+        }
+        if let Some(max_size) = self.max_size {
+            let mut total: u64 = kept.iter().map(|artifact| artifact.size).sum();
+            // Oldest last
+            while total > max_size {
+                let Some(oldest) = kept
+                    .iter()
+                    .rposition(|artifact| keep != Some(artifact.path.as_path()))
+                else {
+                    break;
+                };
+                let artifact = kept.remove(oldest);
+                total -= artifact.size;
+                removed.push(artifact);
+            }
+        }
+        for artifact in &removed {
+            match std::fs::remove_file(&artifact.path) {
+                Ok(()) => tracing::debug!("removed artifact {}", artifact.path.display()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// What the `list_artifacts` tool returns
+    pub(crate) fn report(&self) -> io::Result<Value> {
+        let artifacts = self.list()?;
+        let total: u64 = artifacts.iter().map(|artifact| artifact.size).sum();
+        let listed: Vec<Value> = artifacts
+            .iter()
+            .map(|artifact| {
+                json!({
+                    "name": artifact.name,
+                    "path": artifact.path,
+                    "uri": file_uri(&artifact.path),
+                    "size": artifact.size,
+                    "ageSecs": artifact.age().as_secs(),
+                })
+            })
+            .collect();
+        Ok(json!({
+            "dir": self.dir.as_path(),
+            "totalSize": total,
+            "maxSize": self.max_size,
+            "maxAgeSecs": self.max_age.map(|age| age.as_secs()),
+            "artifacts": listed,
+        }))
+    }
+}
+
+impl ResourceProvider for Artifacts {
+    fn list(&self) -> Vec<Resource> {
+        let artifacts = Artifacts::list(self).unwrap_or_else(|e| {
+            tracing::warn!("failed to list artifacts in {}: {}", self.dir.display(), e);
+            Vec::new()
+        });
+        artifacts
+            .into_iter()
+            .map(|artifact| {
+                RawResource {
+                    uri: file_uri(&artifact.path),
+                    mime_type: guess_mime_type(&artifact.path).map(str::to_string),
+                    name: artifact.name,
+                    description: None,
+                    size: u32::try_from(artifact.size).ok(),
+                }
+                .no_annotation()
+            })
+            .collect()
+    }
+
+    fn read(&self, uri: &str) -> Option<Result<ResourceContents, String>> {
+        let artifacts = Artifacts::list(self).ok()?;
+        let artifact = artifacts
+            .into_iter()
+            .find(|artifact| file_uri(&artifact.path) == uri)?;
+        let mime_type = guess_mime_type(&artifact.path).map(str::to_string);
+        Some(read_file(uri, &artifact.path, mime_type))
+    }
+}
+
+/// Tool definition listed when an artifacts directory is set
+pub(crate) fn list_artifacts_tool() -> Tool {
+    Tool {
+        name: LIST_ARTIFACTS_TOOL.into(),
+        description: Some(
+            "List the files kept in the artifacts directory, with their paths, sizes and ages"
+                .into(),
+        ),
+        input_schema: Arc::new(object(json!({ "type": "object", "properties": {} }))),
+        annotations: Some(ToolAnnotations::new().read_only(true)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "clap-mcp-artifacts-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Make the file at `path` look `age` old
+    fn age(path: &Path, age: Duration) {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn test_write_and_list() {
+        let dir = temp_dir("list");
+        let artifacts = Artifacts::new(&dir);
+        assert!(artifacts.list().unwrap().is_empty());
+
+        let path = artifacts.write("../report.csv", "a,b\n").unwrap();
+        assert_eq!(path, dir.join("report.csv"));
+        artifacts.write("log.txt", "done").unwrap();
+        age(&path, Duration::from_secs(60));
+        let names: Vec<_> = artifacts
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|artifact| artifact.name)
+            .collect();
+        assert_eq!(names, ["log.txt", "report.csv"]);
+        assert!(artifacts.write("..", "x").is_err());
+
+        let report = artifacts.report().unwrap();
+        assert_eq!(report["totalSize"], 8);
+        assert_eq!(report["artifacts"][1]["name"], "report.csv");
+        assert!(report["artifacts"][1]["ageSecs"].as_u64().unwrap() >= 60);
+
+        let uri = file_uri(&path);
+        assert_eq!(ResourceProvider::list(&artifacts).len(), 2);
+        let Some(Ok(ResourceContents::TextResourceContents {
+            text, mime_type, ..
+        })) = artifacts.read(&uri)
+        else {
+            panic!("artifact not served");
+        };
+        assert_eq!(
+            (text.as_str(), mime_type.as_deref()),
+            ("a,b\n", Some("text/csv"))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cleanup() {
+        let dir = temp_dir("cleanup");
+        let artifacts = Artifacts::new(&dir)
+            .max_size(10)
+            .max_age(Duration::from_secs(3600));
+        // Placed by a handler, so not cleaned up until the next write
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, secs) in [("old.txt", 7200), ("older.txt", 120), ("newer.txt", 60)] {
+            std::fs::write(dir.join(name), "1234").unwrap();
+            age(&dir.join(name), Duration::from_secs(secs));
+        }
+
+        // Expired first, then the oldest until the rest fits
+        let removed: Vec<_> = artifacts
+            .cleanup()
+            .unwrap()
+            .into_iter()
+            .map(|artifact| artifact.name)
+            .collect();
+        assert_eq!(removed, ["old.txt"]);
+        artifacts.write("big.txt", "12345678").unwrap();
+        let kept: Vec<_> = artifacts
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|artifact| artifact.name)
+            .collect();
+        assert_eq!(kept, ["big.txt"]);
+
+        // The file just written stays even over the limit
+        artifacts.write("huge.txt", "x".repeat(20)).unwrap();
+        assert_eq!(artifacts.list().unwrap()[0].name, "huge.txt");
+        assert_eq!(artifacts.list().unwrap().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! Bearer-token authentication for the HTTP transport
+//!
+//! Without it, anyone who can reach the port can call the tools. With an
+//! [`AuthProvider`] configured, requests to the SSE and message endpoints must
+//! carry an `Authorization: Bearer <token>` header it accepts, or get a 401:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new()
+//!     .with_auth_token(std::env::var("MCP_TOKEN")?)
+//!     .serve_http(addr)
+//!     .await?;
+//!
+//! // Or decide per token, e.g. against a token store
+//! McpServer::<Commands>::new().with_auth(|token: &str| tokens.is_valid(token));
+//! ```
+//!
+//! Calls are metered, limited and authorized as the principal the token
+//! authenticates (see [`AuthProvider::identify`]), never as the one a client
+//! names in `_meta.principal`. Map tokens to principals with [`Principals`]:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new()
+//!     .with_auth(Principals(|token: &str| tokens.owner(token)));
+//! ```
+//!
+//! Authentication applies to transports that carry credentials: serving over
+//! a transport that cannot check them fails instead of running unprotected.
+//! Stdio is exempt, since only the parent process can talk to it.
+
+use std::fmt;
+use std::sync::Arc;
+
+/// Principal of callers whose provider does not tell them apart
+pub const AUTHENTICATED: &str = "authenticated";
+
+/// Decides whether a bearer token grants access, and to whom
+pub trait AuthProvider: Send + Sync {
+    fn authorize(&self, token: &str) -> bool;
+
+    /// The principal `token` authenticates, `None` if it is not accepted
+    ///
+    /// Providers that only accept or reject tokens identify every caller as
+    /// [`AUTHENTICATED`]; override it to tell callers apart.
+    fn identify(&self, token: &str) -> Option<String> {
+        self.authorize(token).then(|| AUTHENTICATED.to_string())
+    }
+}
+
+impl<F: Fn(&str) -> bool + Send + Sync> AuthProvider for F {
+    fn authorize(&self, token: &str) -> bool {
+        self(token)
+    }
+}
+
+/// Accepts one fixed token
+#[derive(Clone)]
+pub struct BearerToken(String);
+
+impl BearerToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+}
+
+impl fmt::Debug for BearerToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BearerToken([redacted])")
+    }
+}
+
+impl AuthProvider for BearerToken {
+    fn authorize(&self, token: &str) -> bool {
+        constant_time_eq(self.0.as_bytes(), token.as_bytes())
+    }
+}
+
+/// Accepts the tokens a closure maps to a principal
+pub struct Principals<F>(pub F);
+
+impl<F: Fn(&str) -> Option<String> + Send + Sync> AuthProvider for Principals<F> {
+    fn authorize(&self, token: &str) -> bool {
+        self.identify(token).is_some()
+    }
+
+    fn identify(&self, token: &str) -> Option<String> {
+        (self.0)(token)
+    }
+}
+
+/// Accepts a token if any of the providers does, as the first one's principal
+impl AuthProvider for Vec<Arc<dyn AuthProvider>> {
+    fn authorize(&self, token: &str) -> bool {
+        self.iter().any(|provider| provider.authorize(token))
+    }
+
+    fn identify(&self, token: &str) -> Option<String> {
+        self.iter().find_map(|provider| provider.identify(token))
+    }
+}
+
+/// The principal a request's bearer token authenticated, kept in its
+/// extensions for the handler
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub(crate) struct Authenticated(pub(crate) String);
+
+/// The principal the bearer token of the HTTP request behind a call
+/// authenticated, if any
+#[cfg(feature = "http")]
+pub(crate) fn authenticated(extensions: &rmcp::model::Extensions) -> Option<&str> {
+    let parts = extensions.get::<axum::http::request::Parts>()?;
+    let Authenticated(principal) = parts.extensions.get::<Authenticated>()?;
+    Some(principal)
+}
+
+#[cfg(not(feature = "http"))]
+pub(crate) fn authenticated(_extensions: &rmcp::model::Extensions) -> Option<&str> {
+    None
+}
+
+/// Compare without stopping at the first difference, so response times do
+/// not reveal how much of a guessed token is right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The token of an `Authorization` header value using the Bearer scheme
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim())
+        .filter(|token| !token.is_empty())
+}
+
+/// Wrap `router` so requests without an accepted bearer token get a 401
+///
+/// Accepted requests carry the token's principal as [`Authenticated`].
+#[cfg(feature = "http")]
+pub(crate) fn require_auth(router: axum::Router, auth: Arc<dyn AuthProvider>) -> axum::Router {
+    use axum::http::{header, StatusCode};
+    use axum::response::IntoResponse;
+
+    router.layer(axum::middleware::from_fn(
+        move |mut request: axum::extract::Request, next: axum::middleware::Next| {
+            let principal = request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(bearer_token)
+                .and_then(|token| auth.identify(token));
+            async move {
+                match principal {
+                    Some(principal) => {
+                        request.extensions_mut().insert(Authenticated(principal));
+                        next.run(request).await
+                    }
+                    None => (
+                        StatusCode::UNAUTHORIZED,
+                        [(header::WWW_AUTHENTICATE, "Bearer")],
+                    )
+                        .into_response(),
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bearer_token() {
+        assert_eq!(bearer_token("Bearer abc"), Some("abc"));
+        assert_eq!(bearer_token("bearer  abc "), Some("abc"));
+        assert_eq!(bearer_token("Basic abc"), None);
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("abc"), None);
+    }
+
+    #[test]
+    fn test_providers() {
+        let token = BearerToken::new("s3cret");
+        assert!(token.authorize("s3cret"));
+        assert!(!token.authorize("s3cre"));
+        assert!(!token.authorize("s3cret!"));
+        assert_eq!(format!("{:?}", token), "BearerToken([redacted])");
+
+        let mut any: Vec<Arc<dyn AuthProvider>> = vec![
+            Arc::new(token),
+            Arc::new(|token: &str| token.starts_with("svc-")),
+        ];
+        assert!(any.authorize("s3cret"));
+        assert!(any.authorize("svc-deploy"));
+        assert!(!any.authorize("guest"));
+        assert_eq!(any.identify("svc-deploy").as_deref(), Some(AUTHENTICATED));
+
+        let principals = Principals(|token: &str| token.strip_prefix("user-").map(str::to_string));
+        assert_eq!(principals.identify("user-alice").as_deref(), Some("alice"));
+        assert!(principals.authorize("user-bob"));
+        assert!(!principals.authorize("alice"));
+        let any: Vec<Arc<dyn AuthProvider>> = vec![Arc::new(principals), any.remove(0)];
+        assert_eq!(any.identify("user-alice").as_deref(), Some("alice"));
+        assert_eq!(any.identify("s3cret").as_deref(), Some(AUTHENTICATED));
+        assert_eq!(any.identify("guest"), None);
+    }
+}
@@ -0,0 +1,241 @@
+//! Per-tool authorization of principals
+//!
+//! A [`ToolAuthorizer`] decides whether a principal may call a tool, before
+//! the call is metered or run. The principal is the one calls are metered
+//! under (see [`crate::metering`]). Closures deciding by principal and tool
+//! name are authorizers:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new()
+//!     .with_authorizer(|principal: &str, tool: &str| tool != "deploy" || principal == "ops");
+//! ```
+//!
+//! Implementing the trait gives access to the whole [`AuthorizationRequest`],
+//! including the call's arguments and the tool's annotations, and allows
+//! asynchronous decisions, e.g. by an OPA server with the `opa` feature (see
+//! [`crate::opa`]).
+//!
+//! Asking an external policy service (OPA, an internal RBAC API) on every
+//! call is slow for chatty agents. [`CachedAuthorizer`] remembers its
+//! decisions per principal and tool for a while, so it should only wrap
+//! authorizers that do not look at the arguments. Clones share the cache;
+//! keep one to read its stats or to invalidate entries when roles change:
+//!
+//! ```ignore
+//! let authorizer = CachedAuthorizer::new(opa_client, Duration::from_secs(60));
+//! McpServer::<Commands>::new().with_authorizer(authorizer.clone());
+//! // ... on a role change
+//! authorizer.invalidate_principal("alice");
+//! ```
+//!
+//! Denied calls fail with an `invalid_request` error.
+
+use rmcp::model::{JsonObject, ToolAnnotations};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A tool call awaiting authorization
+#[derive(Debug, Clone, Copy)]
+pub struct AuthorizationRequest<'a> {
+    pub principal: &'a str,
+    /// The tool's current name, also for calls using a former one
+    pub tool: &'a str,
+    /// Arguments as sent by the client, before variables and secrets are
+    /// substituted
+    pub arguments: &'a JsonObject,
+    /// Annotation hints declared with `#[mcp(read_only)]` and the like
+    pub annotations: Option<&'a ToolAnnotations>,
+}
+
+/// Whether an authorizer allows a call, once it has decided
+pub type Decision<'a> = Pin<Box<dyn Future<Output = bool> + Send + 'a>>;
+
+/// Decides whether a principal may make a tool call; closures taking the
+/// principal and the tool name implement it
+pub trait ToolAuthorizer: Send + Sync {
+    fn authorize<'a>(&'a self, request: &'a AuthorizationRequest<'a>) -> Decision<'a>;
+}
+
+impl<F: Fn(&str, &str) -> bool + Send + Sync> ToolAuthorizer for F {
+    fn authorize<'a>(&'a self, request: &'a AuthorizationRequest<'a>) -> Decision<'a> {
+        let allowed = self(request.principal, request.tool);
+        Box::pin(std::future::ready(allowed))
+    }
+}
+
+/// Counters of a [`CachedAuthorizer`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Decisions answered from the cache
+    pub hits: u64,
+    /// Decisions the wrapped authorizer was asked for
+    pub misses: u64,
+    /// Decisions cached right now, including expired ones not yet dropped
+    pub entries: usize,
+}
+
+/// A cached decision
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    allowed: bool,
+    expires: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+    entries: HashMap<(String, String), Entry>,
+    hits: u64,
+    misses: u64,
+}
+
+/// A [`ToolAuthorizer`] remembering the decisions of another for `ttl`;
+/// clones share the cache
+#[derive(Clone)]
+pub struct CachedAuthorizer {
+    inner: Arc<dyn ToolAuthorizer>,
+    ttl: Duration,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl CachedAuthorizer {
+    pub fn new(inner: impl ToolAuthorizer + 'static, ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            ttl,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Forget the decision for `principal` calling `tool`
+    pub fn invalidate(&self, principal: &str, tool: &str) {
+        let key = (principal.to_string(), tool.to_string());
+        self.cache.lock().unwrap().entries.remove(&key);
+    }
+
+    /// Forget every decision for `principal`, e.g. after a role change
+    pub fn invalidate_principal(&self, principal: &str) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.entries.retain(|(cached, _), _| cached != principal);
+    }
+
+    /// Forget every decision, e.g. after a policy update
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.lock().unwrap();
+        CacheStats {
+            hits: cache.hits,
+            misses: cache.misses,
+            entries: cache.entries.len(),
+        }
+    }
+
+    /// The cached decision for `key`, counting the hit or miss
+    fn cached(&self, key: &(String, String), now: Instant) -> Option<bool> {
+        let mut cache = self.cache.lock().unwrap();
+        match cache.entries.get(key).filter(|entry| entry.expires > now) {
+            Some(entry) => {
+                let allowed = entry.allowed;
+                cache.hits += 1;
+                Some(allowed)
+            }
+            None => {
+                cache.misses += 1;
+                None
+            }
+        }
+    }
+}
+
+impl ToolAuthorizer for CachedAuthorizer {
+    fn authorize<'a>(&'a self, request: &'a AuthorizationRequest<'a>) -> Decision<'a> {
+        Box::pin(async move {
+            let key = (request.principal.to_string(), request.tool.to_string());
+            let now = Instant::now();
+            if let Some(allowed) = self.cached(&key, now) {
+                return allowed;
+            }
+
+            // Not holding the lock, so a slow policy service does not hold up
+            // calls answered from the cache
+            let allowed = self.inner.authorize(request).await;
+            let mut cache = self.cache.lock().unwrap();
+            cache.entries.retain(|_, entry| entry.expires > now);
+            let expires = now + self.ttl;
+            cache.entries.insert(key, Entry { allowed, expires });
+            allowed
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// An authorizer letting only `ops` deploy, counting how often it is asked
+    fn counting() -> (Arc<AtomicUsize>, impl ToolAuthorizer) {
+        let asked = Arc::new(AtomicUsize::new(0));
+        let counter = asked.clone();
+        let authorizer = move |principal: &str, tool: &str| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            tool != "deploy" || principal == "ops"
+        };
+        (asked, authorizer)
+    }
+
+    async fn authorize(authorizer: &impl ToolAuthorizer, principal: &str, tool: &str) -> bool {
+        let arguments = JsonObject::new();
+        let request = AuthorizationRequest {
+            principal,
+            tool,
+            arguments: &arguments,
+            annotations: None,
+        };
+        authorizer.authorize(&request).await
+    }
+
+    #[tokio::test]
+    async fn test_cached_decisions() {
+        let (asked, inner) = counting();
+        let authorizer = CachedAuthorizer::new(inner, Duration::from_secs(60));
+        assert!(!authorize(&authorizer, "alice", "deploy").await);
+        assert!(!authorize(&authorizer, "alice", "deploy").await);
+        assert!(authorize(&authorizer, "ops", "deploy").await);
+        assert!(authorize(&authorizer, "alice", "status").await);
+        assert_eq!(asked.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            authorizer.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 3,
+                entries: 3
+            }
+        );
+
+        authorizer.invalidate("alice", "deploy");
+        assert!(!authorize(&authorizer, "alice", "deploy").await);
+        assert_eq!(asked.load(Ordering::SeqCst), 4);
+
+        authorizer.clone().invalidate_principal("alice");
+        assert_eq!(authorizer.stats().entries, 1);
+        authorizer.clear();
+        assert_eq!(authorizer.stats().entries, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_decisions_are_asked_again() {
+        let (asked, inner) = counting();
+        let authorizer = CachedAuthorizer::new(inner, Duration::ZERO);
+        assert!(authorize(&authorizer, "ops", "deploy").await);
+        assert!(authorize(&authorizer, "ops", "deploy").await);
+        assert_eq!(asked.load(Ordering::SeqCst), 2);
+        assert_eq!(authorizer.stats().hits, 0);
+        assert_eq!(authorizer.stats().entries, 1);
+    }
+}
@@ -0,0 +1,270 @@
+//! Interactive client for poking at MCP servers
+//!
+//! ```text
+//! clap-mcp-repl --sse 127.0.0.1:8080
+//! clap-mcp-repl -- ./target/debug/calculator --mcp
+//! ```
+//!
+//! At the prompt, `tools` lists the server's tools, `describe <tool>` shows a
+//! tool's input schema and `<tool> key=value ...` (or `<tool> {json}`) calls it.
+//! Tool names and argument names tab-complete.
+
+use clap::Parser;
+use clap_mcp::call::parse_arguments;
+use clap_mcp::client::McpClient;
+use rmcp::model::{CallToolResult, RawContent, Tool};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json::Value;
+
+const BUILTINS: &[&str] = &["tools", "describe", "help", "quit"];
+
+#[derive(Parser)]
+#[command(name = "clap-mcp-repl")]
+#[command(about = "Interactive client for debugging MCP servers")]
+struct Cli {
+    /// Connect to an SSE server at this address (e.g. 127.0.0.1:8080)
+    #[arg(long, conflicts_with = "command")]
+    sse: Option<String>,
+
+    /// Server command to spawn and talk to over stdio
+    #[arg(last = true, required_unless_present = "sse")]
+    command: Vec<String>,
+}
+
+/// Tab completion for tool names and `name=` arguments
+struct ReplHelper {
+    tools: Vec<Tool>,
+}
+
+impl ReplHelper {
+    fn tool(&self, name: &str) -> Option<&Tool> {
+        self.tools.iter().find(|tool| tool.name == name)
+    }
+
+    fn candidates(&self, line: &str) -> (usize, Vec<String>) {
+        let start = line.rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..];
+        let mut words = line[..start].split_whitespace();
+
+        let options: Vec<String> = match words.next() {
+            None => BUILTINS
+                .iter()
+                .map(|b| b.to_string())
+                .chain(self.tools.iter().map(|tool| tool.name.to_string()))
+                .collect(),
+            Some("describe") => self
+                .tools
+                .iter()
+                .map(|tool| tool.name.to_string())
+                .collect(),
+            Some(name) => {
+                let used: Vec<&str> = words
+                    .filter_map(|w| w.split_once('='))
+                    .map(|(k, _)| k)
+                    .collect();
+                self.tool(name)
+                    .and_then(|tool| tool.input_schema.get("properties"))
+                    .and_then(Value::as_object)
+                    .map(|props| {
+                        props
+                            .keys()
+                            .filter(|key| !used.contains(&key.as_str()))
+                            .map(|key| format!("{}=", key))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            }
+        };
+
+        let matches = options
+            .into_iter()
+            .filter(|o| o.starts_with(word))
+            .collect();
+        (start, matches)
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, matches) = self.candidates(&line[..pos]);
+        let pairs = matches
+            .into_iter()
+            .map(|m| Pair {
+                display: m.clone(),
+                replacement: m,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+fn print_result(result: &CallToolResult) {
+    if result.is_error == Some(true) {
+        println!("error:");
+    }
+    for content in &result.content {
+        match &content.raw {
+            RawContent::Text(text) => match serde_json::from_str::<Value>(&text.text) {
+                Ok(json @ (Value::Object(_) | Value::Array(_))) => {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&json).unwrap_or_default()
+                    )
+                }
+                _ => println!("{}", text.text),
+            },
+            other => println!(
+                "{}",
+                serde_json::to_string_pretty(other).unwrap_or_default()
+            ),
+        }
+    }
+}
+
+fn print_tools(tools: &[Tool]) {
+    let width = tools.iter().map(|tool| tool.name.len()).max().unwrap_or(0);
+    for tool in tools {
+        println!(
+            "  {:width$}  {}",
+            tool.name,
+            tool.description.as_deref().unwrap_or(""),
+            width = width
+        );
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let client = match &cli.sse {
+        Some(addr) => McpClient::connect(addr).await?,
+        None => McpClient::connect_stdio(&cli.command[0], &cli.command[1..]).await?,
+    };
+
+    let tools = client.list_tools().await?;
+    println!(
+        "connected: {} tools (type `help` for commands, Tab to complete)",
+        tools.len()
+    );
+
+    let mut editor: Editor<ReplHelper, _> = Editor::new()?;
+    editor.set_helper(Some(ReplHelper { tools }));
+
+    loop {
+        let line = match editor.readline("mcp> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            continue;
+        };
+        let _ = editor.add_history_entry(line.as_str());
+        let helper = editor.helper().expect("helper is set");
+
+        match command {
+            "quit" | "exit" => break,
+            "help" => {
+                println!("  tools                    list available tools");
+                println!("  describe <tool>          show a tool's input schema");
+                println!("  <tool> key=value ...     call a tool");
+                println!("  <tool> {{\"key\": ...}}     call a tool with JSON arguments");
+                println!("  quit                     exit");
+            }
+            "tools" => print_tools(&helper.tools),
+            "describe" => match args.first().and_then(|name| helper.tool(name)) {
+                Some(tool) => println!(
+                    "{}",
+                    serde_json::to_string_pretty(tool.input_schema.as_ref()).unwrap_or_default()
+                ),
+                None => println!("usage: describe <tool>"),
+            },
+            name => {
+                let arguments = match parse_arguments(helper.tool(name), args) {
+                    Ok(arguments) => arguments,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                match client.call_tool(name, Some(Value::Object(arguments))).await {
+                    Ok(result) => print_result(&result),
+                    Err(e) => println!("call failed: {}", e),
+                }
+            }
+        }
+    }
+
+    client.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn tool() -> Tool {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "a": { "type": "number" },
+                "b": { "type": "number" },
+                "label": { "type": "string" }
+            }
+        });
+        Tool::new(
+            "add",
+            "Add two numbers",
+            Arc::new(schema.as_object().unwrap().clone()),
+        )
+    }
+
+    #[test]
+    fn test_completion() {
+        let helper = ReplHelper {
+            tools: vec![tool()],
+        };
+        assert_eq!(helper.candidates("ad"), (0, vec!["add".to_string()]));
+        let (start, mut args) = helper.candidates("add a=1 ");
+        args.sort();
+        assert_eq!(start, 8);
+        assert_eq!(args, vec!["b=".to_string(), "label=".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_arguments() {
+        let tool = tool();
+        let args = parse_arguments(Some(&tool), &["a=1", "label=2"]).unwrap();
+        assert_eq!(args["a"], json!(1));
+        assert_eq!(args["label"], json!("2"));
+
+        let args = parse_arguments(Some(&tool), &[r#"{"a":"#, "2}"]).unwrap();
+        assert_eq!(args["a"], json!(2));
+        assert!(parse_arguments(Some(&tool), &["oops"]).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Re-exporting the tools of remote MCP servers
+//!
+//! A bridged server is connected to as a client when the local server starts.
+//! Its tools are listed next to the clap-derived ones under a prefix, and calls
+//! to prefixed names are forwarded to it unchanged.
+//!
+//! Bridging needs the `client` feature; without it [`Remotes`] is always empty.
+
+use rmcp::{model::*, Error as McpError};
+
+/// A remote server registered with `McpServer::bridge`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BridgeConfig {
+    /// SSE endpoint, e.g. `http://127.0.0.1:8080/sse`
+    pub url: String,
+    /// Prefix for re-exported tool names; derived from the remote's name if unset
+    pub prefix: Option<String>,
+}
+
+/// The connected bridged servers of one [`McpServer`](crate::McpServer)
+#[derive(Default)]
+pub(crate) struct Remotes {
+    #[cfg(feature = "client")]
+    servers: Vec<RemoteServer>,
+}
+
+#[cfg(feature = "client")]
+impl Remotes {
+    pub(crate) async fn connect(
+        configs: &[BridgeConfig],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut servers = Vec::with_capacity(configs.len());
+        for config in configs {
+            let server = RemoteServer::connect(config)
+                .await
+                .map_err(|e| format!("failed to bridge {}: {}", config.url, e))?;
+            servers.push(server);
+        }
+        Ok(Self { servers })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    /// Prefixed tools of every reachable remote
+    pub(crate) async fn list_tools(&self) -> Vec<Tool> {
+        let mut tools = Vec::new();
+        for server in &self.servers {
+            // An unreachable bridge should not hide the other tools
+            match server.list_tools().await {
+                Ok(remote_tools) => tools.extend(remote_tools),
+                Err(e) => tracing::warn!("failed to list bridged tools: {}", e),
+            }
+        }
+        tools
+    }
+
+    /// Whether a remote owns the prefixed `name`
+    pub(crate) fn owns(&self, name: &str) -> bool {
+        self.servers
+            .iter()
+            .any(|server| server.remote_name(name).is_some())
+    }
+
+    /// Forward a call to the remote owning the prefixed `name`, if any
+    pub(crate) async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Option<JsonObject>,
+    ) -> Option<Result<CallToolResult, McpError>> {
+        for server in &self.servers {
+            if let Some(remote_name) = server.remote_name(name) {
+                return Some(server.call_tool(remote_name, arguments).await);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(not(feature = "client"))]
+impl Remotes {
+    pub(crate) async fn connect(
+        configs: &[BridgeConfig],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        match configs.first() {
+            Some(config) => Err(format!(
+                "cannot bridge {}: clap-mcp was built without the `client` feature",
+                config.url
+            )
+            .into()),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        true
+    }
+
+    pub(crate) async fn list_tools(&self) -> Vec<Tool> {
+        Vec::new()
+    }
+
+    pub(crate) fn owns(&self, _name: &str) -> bool {
+        false
+    }
+
+    pub(crate) async fn call_tool(
+        &self,
+        _name: &str,
+        _arguments: Option<JsonObject>,
+    ) -> Option<Result<CallToolResult, McpError>> {
+        None
+    }
+}
+
+/// Live client connection to a bridged server
+#[cfg(feature = "client")]
+struct RemoteServer {
+    prefix: String,
+    client: rmcp::service::RunningService<rmcp::RoleClient, ClientInfo>,
+}
+
+#[cfg(feature = "client")]
+impl RemoteServer {
+    async fn connect(config: &BridgeConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        use rmcp::{transport::SseClientTransport, ServiceExt};
+
+        let transport = SseClientTransport::start(config.url.as_str()).await?;
+        let client_info = ClientInfo {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities: ClientCapabilities::default(),
+            client_info: Implementation {
+                name: "clap-mcp-bridge".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+        };
+        let client = client_info.serve(transport).await?;
+
+        let prefix = match &config.prefix {
+            Some(prefix) => prefix.clone(),
+            None => default_prefix(
+                client
+                    .peer_info()
+                    .map(|info| info.server_info.name.as_str()),
+            ),
+        };
+        Ok(Self { prefix, client })
+    }
+
+    /// The remote's tools, renamed with this bridge's prefix
+    async fn list_tools(&self) -> Result<Vec<Tool>, McpError> {
+        let tools = self.client.list_all_tools().await.map_err(to_mcp_error)?;
+        Ok(tools
+            .into_iter()
+            .map(|mut tool| {
+                tool.name = format!("{}{}", self.prefix, tool.name).into();
+                tool
+            })
+            .collect())
+    }
+
+    /// Remote tool name for a prefixed local name, if it belongs to this bridge
+    fn remote_name<'a>(&self, name: &'a str) -> Option<&'a str> {
+        name.strip_prefix(self.prefix.as_str())
+            .filter(|rest| !rest.is_empty())
+    }
+
+    async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Option<JsonObject>,
+    ) -> Result<CallToolResult, McpError> {
+        self.client
+            .call_tool(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments,
+            })
+            .await
+            .map_err(to_mcp_error)
+    }
+}
+
+/// `<server name>_`, reduced to characters valid in tool names
+#[cfg(feature = "client")]
+fn default_prefix(server_name: Option<&str>) -> String {
+    let name: String = server_name
+        .unwrap_or("remote")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}_", name.trim_matches('_'))
+}
+
+#[cfg(feature = "client")]
+fn to_mcp_error(error: rmcp::service::ServiceError) -> McpError {
+    match error {
+        rmcp::service::ServiceError::McpError(e) => e,
+        other => McpError::internal_error(format!("bridged server error: {}", other), None),
+    }
+}
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_prefix() {
+        assert_eq!(default_prefix(Some("github-server")), "github_server_");
+        assert_eq!(default_prefix(Some("@scope/tools")), "scope_tools_");
+        assert_eq!(default_prefix(None), "remote_");
+    }
+}
@@ -0,0 +1,269 @@
+//! Desktop extension (`.dxt`) packaging
+//!
+//! Desktop extensions are zip archives with a `manifest.json` at the root and
+//! the server binary alongside it. [`DesktopExtension`] derives the manifest
+//! from the registry [`ServerManifest`] and lays out the bundle directory, which
+//! `dxt pack` (or any zip tool) turns into the installable file:
+//!
+//! ```ignore
+//! DesktopExtension::new(Cli::mcp_registry_manifest())
+//!     .tools_from::<Commands>()
+//!     .author("Jane Doe")
+//!     .write_bundle("target/dxt", env!("CARGO_BIN_EXE_calculator"))?;
+//! ```
+
+use crate::metadata::McpTools;
+use crate::registry::ServerManifest;
+use clap::{Command, Subcommand};
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Manifest format version written to `dxt_version`
+pub const DXT_VERSION: &str = "0.1";
+
+/// Builder for a desktop extension manifest and bundle layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct DesktopExtension {
+    manifest: ServerManifest,
+    author: Option<String>,
+    tools: Vec<(String, Option<String>)>,
+    platforms: Vec<String>,
+}
+
+impl DesktopExtension {
+    /// Start from the registry manifest exported for the same server
+    pub fn new(manifest: ServerManifest) -> Self {
+        Self {
+            manifest,
+            author: None,
+            tools: Vec::new(),
+            platforms: vec![
+                "darwin".to_string(),
+                "linux".to_string(),
+                "win32".to_string(),
+            ],
+        }
+    }
+
+    pub fn author(mut self, name: impl Into<String>) -> Self {
+        self.author = Some(name.into());
+        self
+    }
+
+    /// List the subcommands of `T` as the tools the extension provides
+    ///
+    /// Hidden and `#[mcp(skip)]` subcommands are left out, as the server does.
+    pub fn tools_from<T: Subcommand + McpTools>(mut self) -> Self {
+        let skipped: Vec<String> = T::tool_attributes()
+            .into_iter()
+            .filter(|attrs| attrs.skip)
+            .map(|attrs| attrs.name)
+            .collect();
+        let cmd = T::augment_subcommands(Command::new("tools"));
+        self.tools = cmd
+            .get_subcommands()
+            .filter(|sub| !sub.is_hide_set())
+            .filter(|sub| !skipped.iter().any(|name| name == sub.get_name()))
+            .map(|sub| {
+                (
+                    sub.get_name().to_string(),
+                    sub.get_about().map(|about| about.to_string()),
+                )
+            })
+            .collect();
+        self
+    }
+
+    /// List tools from schemas generated by `#[derive(McpSchema)]`
+    ///
+    /// Like [`DesktopExtension::tools_from`], without building a `Command`.
+    pub fn tools_from_static(mut self, tools: &[crate::schema::StaticTool]) -> Self {
+        self.tools = tools
+            .iter()
+            .map(|tool| {
+                let description =
+                    (!tool.description.is_empty()).then(|| tool.description.to_string());
+                (tool.name.to_string(), description)
+            })
+            .collect();
+        self
+    }
+
+    /// Platforms the bundled binary runs on (`darwin`, `linux`, `win32`)
+    pub fn platforms(mut self, platforms: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.platforms = platforms.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Short, path-safe extension name
+    fn name(&self) -> &str {
+        let name = self.manifest.registry_name();
+        name.rsplit('/').next().unwrap_or(name)
+    }
+
+    /// Path of the server binary inside the bundle
+    pub fn entry_point(&self) -> String {
+        if self.platforms.iter().all(|p| p == "win32") {
+            format!("server/{}.exe", self.name())
+        } else {
+            format!("server/{}", self.name())
+        }
+    }
+
+    /// Render `manifest.json`
+    pub fn to_json(&self) -> Value {
+        let metadata = self.manifest.server_metadata();
+        let mut doc = Map::new();
+        doc.insert("dxt_version".to_string(), json!(DXT_VERSION));
+        doc.insert("name".to_string(), json!(self.name()));
+        if let Some(title) = &metadata.title {
+            doc.insert("display_name".to_string(), json!(title));
+        }
+        doc.insert(
+            "version".to_string(),
+            json!(self.manifest.package_version().unwrap_or("0.0.0")),
+        );
+        doc.insert(
+            "description".to_string(),
+            json!(self.manifest.package_description().unwrap_or_default()),
+        );
+        if let Some(author) = &self.author {
+            doc.insert("author".to_string(), json!({ "name": author }));
+        }
+        if let Some(website) = &metadata.website_url {
+            doc.insert("homepage".to_string(), json!(website));
+        }
+        if let Some(contact) = &metadata.contact {
+            doc.insert("support".to_string(), json!(contact));
+        }
+        if let Some(icon) = metadata.icons.first() {
+            doc.insert("icon".to_string(), json!(icon.src));
+        }
+
+        let entry_point = self.entry_point();
+        doc.insert(
+            "server".to_string(),
+            json!({
+                "type": "binary",
+                "entry_point": entry_point,
+                "mcp_config": {
+                    "command": format!("${{__dirname}}/{}", entry_point),
+                    "args": self.manifest.run_arguments(),
+                },
+            }),
+        );
+
+        if !self.tools.is_empty() {
+            let tools: Vec<Value> = self
+                .tools
+                .iter()
+                .map(|(name, description)| match description {
+                    Some(description) => json!({ "name": name, "description": description }),
+                    None => json!({ "name": name }),
+                })
+                .collect();
+            doc.insert("tools".to_string(), json!(tools));
+        }
+        doc.insert(
+            "compatibility".to_string(),
+            json!({ "platforms": self.platforms }),
+        );
+
+        Value::Object(doc)
+    }
+
+    /// Write `manifest.json` and copy the server binary into `dir`
+    pub fn write_bundle(&self, dir: impl AsRef<Path>, binary: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        let entry_point = dir.join(self.entry_point());
+        if let Some(parent) = entry_point.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(binary, &entry_point)?;
+
+        let manifest = serde_json::to_string_pretty(&self.to_json())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(dir.join("manifest.json"), manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{Icon, ServerMetadata};
+
+    #[derive(clap::Subcommand, crate::McpTools)]
+    enum Commands {
+        /// Add two numbers
+        Add,
+        #[command(hide = true)]
+        Internal,
+        /// Erase the memory
+        #[mcp(skip)]
+        Wipe,
+    }
+
+    fn command() -> Command {
+        Commands::augment_subcommands(
+            Command::new("calculator")
+                .version("1.2.0")
+                .about("A simple calculator"),
+        )
+    }
+
+    fn extension() -> DesktopExtension {
+        let manifest = ServerManifest::from_command(&command())
+            .name("io.github.example/calculator")
+            .metadata(ServerMetadata {
+                title: Some("Calculator".to_string()),
+                icons: vec![Icon::new("icon.png")],
+                ..Default::default()
+            })
+            .run_args(["--mcp"]);
+        DesktopExtension::new(manifest)
+            .tools_from::<Commands>()
+            .author("Example")
+    }
+
+    #[test]
+    fn test_manifest_json() {
+        let manifest = extension().to_json();
+        assert_eq!(manifest["dxt_version"], DXT_VERSION);
+        assert_eq!(manifest["name"], "calculator");
+        assert_eq!(manifest["display_name"], "Calculator");
+        assert_eq!(manifest["version"], "1.2.0");
+        assert_eq!(manifest["icon"], "icon.png");
+        assert_eq!(manifest["server"]["entry_point"], "server/calculator");
+        assert_eq!(
+            manifest["server"]["mcp_config"],
+            json!({ "command": "${__dirname}/server/calculator", "args": ["--mcp"] })
+        );
+        assert_eq!(
+            manifest["tools"],
+            json!([{ "name": "add", "description": "Add two numbers" }])
+        );
+    }
+
+    #[test]
+    fn test_write_bundle() {
+        let dir = std::env::temp_dir().join(format!("clap-mcp-dxt-{}", std::process::id()));
+        let binary = dir.join("calculator-bin");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&binary, b"binary").unwrap();
+
+        let bundle = dir.join("bundle");
+        extension().write_bundle(&bundle, &binary).unwrap();
+        assert_eq!(
+            fs::read(bundle.join("server/calculator")).unwrap(),
+            b"binary"
+        );
+        let manifest: Value =
+            serde_json::from_str(&fs::read_to_string(bundle.join("manifest.json")).unwrap())
+                .unwrap();
+        assert_eq!(manifest["name"], "calculator");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+//! `mcp call`: call tools on other MCP servers from the terminal
+//!
+//! Opt in with `#[mcp(call)]` on the `McpMode` parser and handle the command
+//! before regular argument parsing:
+//!
+//! ```ignore
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     if let Some(result) = Cli::handle_mcp_call() {
+//!         return result;
+//!     }
+//!     let cli = Cli::parse();
+//!     // ...
+//! }
+//! ```
+//!
+//! The same binary then scripts against any server an agent uses:
+//!
+//! ```text
+//! mytool mcp call 127.0.0.1:8080                      # list the tools
+//! mytool mcp call 127.0.0.1:8080 add --arg a=1 --arg b=2
+//! mytool mcp call https://tools.internal/sse deploy --arg env=prod --token s3cret
+//! mytool mcp call --stdio "other-tool --mcp" status --raw
+//! ```
+//!
+//! Text results are printed as they are. A result marked as an error is
+//! reported as an error, so the command exits with a failure status.
+
+use crate::client::McpClient;
+use clap::{Args, FromArgMatches};
+use rmcp::model::{CallToolResult, JsonObject, RawContent, ResourceContents, Tool};
+use serde_json::Value;
+use std::error::Error;
+use std::ffi::OsString;
+
+/// Arguments of the `mcp call` command
+#[derive(Debug, Clone, Default, Args)]
+pub struct CallArgs {
+    /// Server to call: `host:port` or an SSE URL, or with --stdio a command
+    pub server: String,
+    /// Tool to call; the server's tools are listed when omitted
+    pub tool: Option<String>,
+    /// Tool argument, repeatable; values are parsed as JSON unless the tool
+    /// takes a string. A single JSON object passes every argument at once
+    #[arg(long = "arg", value_name = "KEY=VALUE")]
+    pub args: Vec<String>,
+    /// Spawn SERVER (split on whitespace) and talk to it over stdio
+    #[arg(long)]
+    pub stdio: bool,
+    /// Bearer token to send to an SSE server
+    #[arg(long)]
+    pub token: Option<String>,
+    /// Print the whole result as JSON
+    #[arg(long)]
+    pub raw: bool,
+}
+
+/// Run `mcp call`, returning what to print
+///
+/// A result marked as an error becomes the `Err`.
+pub async fn call(args: &CallArgs) -> Result<String, Box<dyn Error>> {
+    let client = connect(args).await?;
+    let output = call_with(&client, args).await;
+    client.shutdown().await?;
+    output
+}
+
+async fn connect(args: &CallArgs) -> Result<McpClient, Box<dyn Error>> {
+    if args.stdio {
+        let mut words = args.server.split_whitespace().map(str::to_string);
+        let program = words.next().ok_or("no server command given")?;
+        return McpClient::connect_stdio(&program, &words.collect::<Vec<_>>()).await;
+    }
+    let url = if args.server.starts_with("http://") || args.server.starts_with("https://") {
+        args.server.clone()
+    } else {
+        format!("http://{}/sse", args.server)
+    };
+    let mut builder = McpClient::builder();
+    if let Some(token) = &args.token {
+        builder = builder.bearer_token(token);
+    }
+    builder.connect_url(&url).await
+}
+
+async fn call_with(client: &McpClient, args: &CallArgs) -> Result<String, Box<dyn Error>> {
+    let tools = client.list_tools().await?;
+    let Some(name) = &args.tool else {
+        return Ok(tool_list(&tools));
+    };
+    let tool = tools.iter().find(|tool| tool.name == *name);
+    let words: Vec<&str> = args.args.iter().map(String::as_str).collect();
+    let arguments = parse_arguments(tool, &words)?;
+    let result = client
+        .call_tool(name, Some(Value::Object(arguments)))
+        .await?;
+
+    let output = match args.raw {
+        true => serde_json::to_string_pretty(&result)?,
+        false => result_text(&result),
+    };
+    match result.is_error {
+        Some(true) => Err(output.into()),
+        _ => Ok(output),
+    }
+}
+
+/// Turn `key=value` pairs (or a single JSON object) into tool arguments
+///
+/// Values are parsed as JSON when the schema does not declare the property a
+/// string, so `count=3` sends a number and `name=3` a string for string args.
+pub fn parse_arguments(tool: Option<&Tool>, words: &[&str]) -> Result<JsonObject, String> {
+    let rest = words.join(" ");
+    if rest.trim_start().starts_with('{') {
+        return match serde_json::from_str(&rest) {
+            Ok(Value::Object(map)) => Ok(map),
+            Ok(_) => Err("arguments must be a JSON object".to_string()),
+            Err(e) => Err(format!("invalid JSON: {}", e)),
+        };
+    }
+
+    let properties = tool
+        .and_then(|tool| tool.input_schema.get("properties"))
+        .and_then(Value::as_object);
+    let mut arguments = JsonObject::new();
+    for word in words {
+        let (key, raw) = word
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value, got `{}`", word))?;
+        let is_string = properties
+            .and_then(|props| props.get(key))
+            .and_then(|prop| prop.get("type"))
+            .is_some_and(|ty| ty == "string");
+        let value = if is_string {
+            Value::String(raw.to_string())
+        } else {
+            serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+        };
+        arguments.insert(key.to_string(), value);
+    }
+    Ok(arguments)
+}
+
+/// Text blocks as they are, anything else as JSON, one block per line
+fn result_text(result: &CallToolResult) -> String {
+    let blocks: Vec<String> = result
+        .content
+        .iter()
+        .map(|content| match &content.raw {
+            RawContent::Text(text) => text.text.clone(),
+            RawContent::Resource(embedded) => match &embedded.resource {
+                ResourceContents::TextResourceContents { text, .. } => text.clone(),
+                other => serde_json::to_string_pretty(other).unwrap_or_default(),
+            },
+            other => serde_json::to_string_pretty(other).unwrap_or_default(),
+        })
+        .collect();
+    blocks.join("\n")
+}
+
+/// One `name  description` line per tool
+fn tool_list(tools: &[Tool]) -> String {
+    let width = tools.iter().map(|tool| tool.name.len()).max().unwrap_or(0);
+    tools
+        .iter()
+        .map(|tool| {
+            let line = format!(
+                "{:width$}  {}",
+                tool.name,
+                tool.description.as_deref().unwrap_or(""),
+                width = width
+            );
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Run `mcp call` if it is the command being invoked
+///
+/// `args` are the full process arguments including the binary name. Returns
+/// `None` when the invocation is something else, so regular parsing can go on.
+pub fn handle_from_args(
+    args: impl IntoIterator<Item = impl Into<OsString>>,
+) -> Option<Result<(), Box<dyn Error>>> {
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    if args.get(1).is_none_or(|arg| arg != "mcp") || args.get(2).is_none_or(|arg| arg != "call") {
+        return None;
+    }
+
+    let bin = args[0].clone();
+    let cmd = CallArgs::augment_args(clap::Command::new("call"));
+    let run = || -> Result<(), Box<dyn Error>> {
+        let matches =
+            cmd.try_get_matches_from(std::iter::once(bin).chain(args[3..].iter().cloned()))?;
+        let call_args = CallArgs::from_arg_matches(&matches)?;
+        let runtime = crate::__private::runtime()?;
+        println!("{}", runtime.block_on(call(&call_args))?);
+        Ok(())
+    };
+    Some(run())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_arguments() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "a": { "type": "number" }, "label": { "type": "string" } }
+        });
+        let tool = Tool::new("add", "", Arc::new(schema.as_object().unwrap().clone()));
+        let args = parse_arguments(Some(&tool), &["a=1", "label=2"]).unwrap();
+        assert_eq!(args["a"], json!(1));
+        assert_eq!(args["label"], json!("2"));
+        let args = parse_arguments(None, &[r#"{"a": [1, 2]}"#]).unwrap();
+        assert_eq!(args["a"], json!([1, 2]));
+        assert!(parse_arguments(None, &["oops"]).is_err());
+    }
+
+    #[test]
+    fn test_handle_from_args_ignores_other_commands() {
+        assert!(handle_from_args(["tool", "add", "1", "2"]).is_none());
+        assert!(handle_from_args(["tool", "mcp", "install-config"]).is_none());
+    }
+}
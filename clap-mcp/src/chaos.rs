@@ -0,0 +1,233 @@
+//! Fault injection for hardening MCP integrations (`chaos` feature)
+//!
+//! [`ChaosTransport`] wraps either side's transport and, with configurable
+//! probabilities, delays messages, drops notifications and resets the
+//! connection. Runs are reproducible: the same seed injects the same faults
+//! for the same message sequence.
+//!
+//! ```ignore
+//! let config = ChaosConfig::new(42)
+//!     .delay(0.2, Duration::from_millis(250))
+//!     .drop_notifications(0.1)
+//!     .reset(0.01);
+//! let handler = McpServer::<Commands>::new().with_handler(h).into_handler().await?;
+//! rmcp::serve_server(handler, chaos::wrap(rmcp::transport::stdio(), config)).await?;
+//! ```
+//!
+//! Meant for tests and staging; never enable it in production builds.
+
+use rmcp::model::JsonRpcMessage;
+use rmcp::service::{RxJsonRpcMessage, ServiceRole, TxJsonRpcMessage};
+use rmcp::transport::{IntoTransport, Transport};
+use std::future::Future;
+use std::time::Duration;
+
+/// Which faults to inject and how often
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosConfig {
+    seed: u64,
+    delay: Option<(f64, Duration)>,
+    drop_notifications: f64,
+    reset: f64,
+}
+
+impl ChaosConfig {
+    /// No faults yet; `seed` makes the injected faults reproducible
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            delay: None,
+            drop_notifications: 0.0,
+            reset: 0.0,
+        }
+    }
+
+    /// Hold each message back by `delay` with the given probability
+    pub fn delay(mut self, probability: f64, delay: Duration) -> Self {
+        self.delay = Some((probability, delay));
+        self
+    }
+
+    /// Silently drop notifications in either direction with the given probability
+    pub fn drop_notifications(mut self, probability: f64) -> Self {
+        self.drop_notifications = probability;
+        self
+    }
+
+    /// Reset the connection at each message with the given probability
+    ///
+    /// After a reset, sends fail with [`ChaosError::Reset`] and receiving
+    /// reports the connection as closed.
+    pub fn reset(mut self, probability: f64) -> Self {
+        self.reset = probability;
+        self
+    }
+}
+
+/// Error of a [`ChaosTransport`]
+#[derive(Debug)]
+pub enum ChaosError<E> {
+    /// The wrapped transport failed
+    Transport(E),
+    /// An injected connection reset
+    Reset,
+    /// I/O error raised by rmcp's service layer around the transport
+    Io(std::io::Error),
+}
+
+impl<E> From<std::io::Error> for ChaosError<E> {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for ChaosError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Transport(e) => e.fmt(f),
+            Self::Reset => f.write_str("connection reset (injected)"),
+            Self::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for ChaosError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(e) => Some(e),
+            Self::Reset => None,
+            Self::Io(e) => Some(e),
+        }
+    }
+}
+
+/// A transport that injects the faults of a [`ChaosConfig`]
+pub struct ChaosTransport<T> {
+    inner: T,
+    config: ChaosConfig,
+    rng: u64,
+    reset: bool,
+}
+
+/// Wrap any transport accepted by rmcp's `serve` functions
+pub fn wrap<R, E, A>(
+    transport: impl IntoTransport<R, E, A>,
+    config: ChaosConfig,
+) -> ChaosTransport<impl Transport<R, Error = E>>
+where
+    R: ServiceRole,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    ChaosTransport::new(transport.into_transport(), config)
+}
+
+impl<T> ChaosTransport<T> {
+    /// Wrap a transport
+    pub fn new(inner: T, config: ChaosConfig) -> Self {
+        Self {
+            inner,
+            // xorshift state must be non-zero
+            rng: config.seed | 1,
+            config,
+            reset: false,
+        }
+    }
+
+    /// Whether an event with `probability` happens this time
+    fn roll(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        ((self.rng >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn roll_delay(&mut self) -> Option<Duration> {
+        let (probability, delay) = self.config.delay?;
+        self.roll(probability).then_some(delay)
+    }
+
+    fn roll_reset(&mut self) -> bool {
+        if !self.reset && self.roll(self.config.reset) {
+            tracing::debug!("chaos: injecting connection reset");
+            self.reset = true;
+        }
+        self.reset
+    }
+
+    fn roll_drop<A, B, C>(&mut self, message: &JsonRpcMessage<A, B, C>) -> bool {
+        matches!(message, JsonRpcMessage::Notification(_))
+            && self.roll(self.config.drop_notifications)
+    }
+}
+
+impl<R, T> Transport<R> for ChaosTransport<T>
+where
+    R: ServiceRole,
+    T: Transport<R>,
+{
+    type Error = ChaosError<T::Error>;
+
+    fn send(
+        &mut self,
+        item: TxJsonRpcMessage<R>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let delay = self.roll_delay();
+        let reset = self.roll_reset();
+        let dropped = !reset && self.roll_drop(&item);
+        let send = (!reset && !dropped).then(|| self.inner.send(item));
+        async move {
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+            match send {
+                Some(send) => send.await.map_err(ChaosError::Transport),
+                None if reset => Err(ChaosError::Reset),
+                None => Ok(()),
+            }
+        }
+    }
+
+    async fn receive(&mut self) -> Option<RxJsonRpcMessage<R>> {
+        loop {
+            if self.reset {
+                return None;
+            }
+            let message = self.inner.receive().await?;
+            if self.roll_reset() {
+                return None;
+            }
+            if self.roll_drop(&message) {
+                continue;
+            }
+            if let Some(delay) = self.roll_delay() {
+                tokio::time::sleep(delay).await;
+            }
+            return Some(message);
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        self.inner.close().await.map_err(ChaosError::Transport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolls_are_reproducible() {
+        let mut a = ChaosTransport::new((), ChaosConfig::new(7));
+        let mut b = ChaosTransport::new((), ChaosConfig::new(7));
+        let rolls_a: Vec<bool> = (0..64).map(|_| a.roll(0.5)).collect();
+        let rolls_b: Vec<bool> = (0..64).map(|_| b.roll(0.5)).collect();
+        assert_eq!(rolls_a, rolls_b);
+        assert!(rolls_a.contains(&true) && rolls_a.contains(&false));
+
+        assert!(!a.roll(0.0));
+        assert!(a.roll(1.0));
+    }
+}
@@ -0,0 +1,367 @@
+//! Client for calling clap-mcp servers
+//!
+//! Besides raw `call_tool`, [`McpClient::call`] accepts a value of the same
+//! `Subcommand` enum the server was built from, so Rust programs can drive a
+//! remote instance of a CLI type-safely:
+//!
+//! ```ignore
+//! #[derive(Subcommand, Clone, Serialize)]
+//! enum Commands {
+//!     Add { a: f64, b: f64 },
+//! }
+//!
+//! let client = McpClient::connect("127.0.0.1:8080").await?;
+//! let result = client.call(&Commands::Add { a: 2.0, b: 3.0 }).await?;
+//! ```
+//!
+//! `#[derive(McpClient)]` on the enum generates a `CommandsClient` with one
+//! method per subcommand instead (`client.add(2.0, 3.0).await?`).
+//!
+//! Servers behind authentication or TLS are reached through
+//! [`McpClient::builder`]:
+//!
+//! ```ignore
+//! let client = McpClient::builder()
+//!     .bearer_token("s3cret")
+//!     .header("X-Tenant", "acme")
+//!     .root_certificate_pem(&std::fs::read("ca.pem")?)
+//!     .connect_url("https://tools.internal/sse")
+//!     .await?;
+//! ```
+
+use clap::Subcommand;
+use rmcp::{model::*, transport::SseClientTransport, RoleClient, ServiceExt};
+use serde::Serialize;
+use serde_json::Value;
+
+pub use rmcp::model::CallToolResult;
+
+/// An MCP client connected to a server over SSE or stdio
+pub struct McpClient {
+    client: rmcp::service::RunningService<RoleClient, ClientInfo>,
+}
+
+/// Settings of an SSE connection, made by [`McpClient::builder`]
+///
+/// Invalid headers and certificates are reported when connecting.
+#[derive(Debug, Default)]
+pub struct McpClientBuilder {
+    headers: reqwest::header::HeaderMap,
+    root_certificates: Vec<reqwest::Certificate>,
+    accept_invalid_certs: bool,
+    error: Option<String>,
+}
+
+impl McpClientBuilder {
+    /// Send `Authorization: Bearer <token>` with every request
+    pub fn bearer_token(self, token: &str) -> Self {
+        self.header(
+            reqwest::header::AUTHORIZATION.as_str(),
+            &format!("Bearer {}", token),
+        )
+    }
+
+    /// Send the header `name: value` with every request
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION};
+
+        let name = HeaderName::from_bytes(name.as_bytes());
+        let value = HeaderValue::from_str(value);
+        match (name, value) {
+            (Ok(name), Ok(mut value)) => {
+                value.set_sensitive(name == AUTHORIZATION);
+                self.headers.insert(name, value);
+            }
+            (Err(e), _) => self.fail(format!("invalid header name: {}", e)),
+            (_, Err(e)) => self.fail(format!("invalid value for header: {}", e)),
+        }
+        self
+    }
+
+    /// Trust the PEM-encoded CA certificate `pem` besides the system roots
+    pub fn root_certificate_pem(mut self, pem: &[u8]) -> Self {
+        match reqwest::Certificate::from_pem(pem) {
+            Ok(certificate) => self.root_certificates.push(certificate),
+            Err(e) => self.fail(format!("invalid root certificate: {}", e)),
+        }
+        self
+    }
+
+    /// Skip verifying the server's certificate, e.g. a self-signed one in
+    /// tests. Never use it against real servers.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    /// Connect to an MCP server at the given address
+    pub async fn connect(self, addr: &str) -> Result<McpClient, Box<dyn std::error::Error>> {
+        self.connect_url(&format!("http://{}/sse", addr)).await
+    }
+
+    /// Connect to an SSE endpoint given as a full URL
+    pub async fn connect_url(self, sse_url: &str) -> Result<McpClient, Box<dyn std::error::Error>> {
+        if let Some(error) = self.error {
+            return Err(error.into());
+        }
+        let mut http = reqwest::Client::builder()
+            .default_headers(self.headers)
+            .danger_accept_invalid_certs(self.accept_invalid_certs);
+        for certificate in self.root_certificates {
+            http = http.add_root_certificate(certificate);
+        }
+        let config = rmcp::transport::sse_client::SseClientConfig {
+            sse_endpoint: sse_url.into(),
+            ..Default::default()
+        };
+        let transport = SseClientTransport::start_with_client(http.build()?, config).await?;
+
+        let client = McpClient::client_info().serve(transport).await?;
+
+        Ok(McpClient { client })
+    }
+
+    /// Keep the first problem to report when connecting
+    fn fail(&mut self, error: String) {
+        self.error.get_or_insert(error);
+    }
+}
+
+impl McpClient {
+    /// Connect to an MCP server at the given address
+    pub async fn connect(addr: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::connect_url(&format!("http://{}/sse", addr)).await
+    }
+
+    /// Connect to an SSE endpoint given as a full URL
+    pub async fn connect_url(sse_url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let transport = SseClientTransport::start(sse_url).await?;
+
+        let client = Self::client_info().serve(transport).await?;
+
+        Ok(Self { client })
+    }
+
+    /// Connect to an SSE endpoint that requires a bearer token
+    pub async fn connect_url_with_token(
+        sse_url: &str,
+        token: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::builder()
+            .bearer_token(token)
+            .connect_url(sse_url)
+            .await
+    }
+
+    /// Connect over SSE with custom headers or TLS settings
+    pub fn builder() -> McpClientBuilder {
+        McpClientBuilder::default()
+    }
+
+    /// Spawn `program` with `args` and talk to it over stdio
+    pub async fn connect_stdio(
+        program: &str,
+        args: &[String],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut command = tokio::process::Command::new(program);
+        command.args(args);
+        let transport = rmcp::transport::TokioChildProcess::new(command)?;
+
+        let client = Self::client_info().serve(transport).await?;
+
+        Ok(Self { client })
+    }
+
+    /// Talk to a server over an already connected byte stream
+    pub(crate) async fn connect_io<R, W>(
+        reader: R,
+        writer: W,
+    ) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        R: tokio::io::AsyncRead + Send + Unpin + 'static,
+        W: tokio::io::AsyncWrite + Send + Unpin + 'static,
+    {
+        let client = Self::client_info().serve((reader, writer)).await?;
+
+        Ok(Self { client })
+    }
+
+    fn client_info() -> ClientInfo {
+        ClientInfo {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities: ClientCapabilities::default(),
+            client_info: Implementation {
+                name: "clap-mcp-client".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+        }
+    }
+
+    /// Handle for sending requests from other tasks
+    pub(crate) fn peer(&self) -> &rmcp::service::Peer<RoleClient> {
+        self.client.peer()
+    }
+
+    /// Server information sent during initialization
+    pub fn server_info(&self) -> Option<&ServerInfo> {
+        self.client.peer_info()
+    }
+
+    /// List all available tools
+    pub async fn list_tools(&self) -> Result<Vec<Tool>, Box<dyn std::error::Error>> {
+        let tools = self.client.list_all_tools().await?;
+        Ok(tools)
+    }
+
+    /// List the resources the server offers
+    pub async fn list_resources(&self) -> Result<Vec<Resource>, Box<dyn std::error::Error>> {
+        let resources = self.client.list_all_resources().await?;
+        Ok(resources)
+    }
+
+    /// Read the contents of the resource at `uri`
+    pub async fn read_resource(
+        &self,
+        uri: &str,
+    ) -> Result<Vec<ResourceContents>, Box<dyn std::error::Error>> {
+        let result = self
+            .client
+            .read_resource(ReadResourceRequestParam {
+                uri: uri.to_string(),
+            })
+            .await?;
+        Ok(result.contents)
+    }
+
+    /// Call a tool with optional arguments
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: Option<Value>,
+    ) -> Result<CallToolResult, Box<dyn std::error::Error>> {
+        let args = arguments.and_then(|v| v.as_object().cloned());
+        let result = self
+            .client
+            .call_tool(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments: args,
+            })
+            .await?;
+        Ok(result)
+    }
+
+    /// Call the tool for a subcommand value, with its fields as arguments
+    pub async fn call<T: Subcommand + Serialize>(
+        &self,
+        command: &T,
+    ) -> Result<CallToolResult, Box<dyn std::error::Error>> {
+        let (name, arguments) = tool_call(command)?;
+        self.call_tool(&name, Some(Value::Object(arguments))).await
+    }
+
+    /// Extract text content from a tool result
+    ///
+    /// Text carried in an embedded text resource (used for tools that declare
+    /// an output MIME type) is returned as well.
+    pub fn extract_text(result: &CallToolResult) -> Option<String> {
+        result
+            .content
+            .first()
+            .and_then(|content| match &content.raw {
+                RawContent::Text(text) => Some(text.text.clone()),
+                RawContent::Resource(embedded) => match &embedded.resource {
+                    ResourceContents::TextResourceContents { text, .. } => Some(text.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+    }
+
+    /// Shutdown the client
+    pub async fn shutdown(self) -> Result<(), Box<dyn std::error::Error>> {
+        self.client.cancel().await?;
+        Ok(())
+    }
+}
+
+/// Tool name and arguments for a subcommand value
+///
+/// The serde variant name is matched against the subcommand names ignoring
+/// case and separators (`ShowConfig` finds `show-config`), and fields become
+/// arguments keyed by field name, which is also clap's default argument id.
+/// `None` fields are left out.
+pub fn tool_call<T: Subcommand + Serialize>(command: &T) -> Result<(String, JsonObject), String> {
+    let (variant, fields) = match serde_json::to_value(command).map_err(|e| e.to_string())? {
+        Value::String(variant) => (variant, JsonObject::new()),
+        Value::Object(map) if map.len() == 1 => {
+            let (variant, fields) = map.into_iter().next().expect("one entry");
+            match fields {
+                Value::Object(fields) => (variant, fields),
+                Value::Null => (variant, JsonObject::new()),
+                _ => {
+                    return Err(format!(
+                        "variant `{}` must have named fields to map to tool arguments",
+                        variant
+                    ))
+                }
+            }
+        }
+        _ => return Err("subcommand must serialize as an externally tagged enum".to_string()),
+    };
+
+    let cmd = T::augment_subcommands(clap::Command::new("mcp"));
+    let name = cmd
+        .get_subcommands()
+        .map(|sub| sub.get_name())
+        .find(|name| normalize(name) == normalize(&variant))
+        .ok_or_else(|| format!("no subcommand matches variant `{}`", variant))?
+        .to_string();
+
+    let arguments = fields.into_iter().filter(|(_, v)| !v.is_null()).collect();
+    Ok((name, arguments))
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Subcommand, Clone, Serialize)]
+    enum Commands {
+        Add {
+            #[arg(long)]
+            a: i32,
+            #[arg(long)]
+            b: Option<i32>,
+        },
+        ShowConfig,
+        #[command(name = "ls")]
+        List {
+            path: String,
+        },
+    }
+
+    #[test]
+    fn test_tool_call_mapping() {
+        let (name, args) = tool_call(&Commands::Add { a: 1, b: None }).unwrap();
+        assert_eq!(name, "add");
+        assert_eq!(Value::Object(args), json!({ "a": 1 }));
+
+        let (name, args) = tool_call(&Commands::ShowConfig).unwrap();
+        assert_eq!(name, "show-config");
+        assert!(args.is_empty());
+
+        // Renamed subcommands can't be matched from the variant name
+        assert!(tool_call(&Commands::List {
+            path: ".".to_string()
+        })
+        .is_err());
+    }
+}
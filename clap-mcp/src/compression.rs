@@ -0,0 +1,669 @@
+//! Compression of HTTP responses
+//!
+//! With the `compression` feature, the SSE transport compresses responses for
+//! clients that accept it (`Accept-Encoding: gzip` or `deflate`). Tool lists,
+//! results and resource reads all reach clients as events on the `/sse`
+//! stream, so multi-megabyte catalogs and command logs shrink several times
+//! over. Every chunk is flushed as it is sent, so compression never holds a
+//! message back.
+//!
+//! The encoder is a small DEFLATE implementation using the fixed Huffman
+//! codes, which keeps the feature free of extra dependencies; its output is
+//! tested against the system's `gzip`. Responses that
+//! are already encoded, are not text or JSON, or are known to be shorter than
+//! [`MIN_SIZE`] bytes are sent as they are.
+
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use futures::StreamExt;
+
+/// Bodies shorter than this are not worth compressing
+pub const MIN_SIZE: u64 = 1024;
+
+/// Compress the responses of `router` for clients accepting it
+pub(crate) fn compress_responses(router: axum::Router) -> axum::Router {
+    router.layer(axum::middleware::from_fn(compress))
+}
+
+async fn compress(request: Request, next: Next) -> Response {
+    let encoding = Encoding::negotiate(request.headers());
+    let response = next.run(request).await;
+    match encoding {
+        Some(encoding) if worth_compressing(&response) => encode(response, encoding),
+        _ => response,
+    }
+}
+
+fn worth_compressing(response: &Response) -> bool {
+    let headers = response.headers();
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        return false;
+    }
+    let compressible = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|ty| ty.starts_with("text/") || ty.starts_with("application/json"));
+    let size = response.body().size_hint().exact();
+    compressible && size.is_none_or(|size| size >= MIN_SIZE)
+}
+
+fn encode(response: Response, encoding: Encoding) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let headers = &mut parts.headers;
+    headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.name()),
+    );
+    headers.remove(header::CONTENT_LENGTH);
+    headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+    let state = (body.into_data_stream(), Some(Encoder::new(encoding)));
+    let stream = futures::stream::unfold(state, |(mut data, encoder)| async move {
+        let mut encoder = encoder?;
+        match data.next().await {
+            Some(Ok(chunk)) => {
+                let compressed = Bytes::from(encoder.write(&chunk));
+                Some((Ok(compressed), (data, Some(encoder))))
+            }
+            Some(Err(e)) => Some((Err(e), (data, None))),
+            None => Some((Ok(Bytes::from(encoder.finish())), (data, None))),
+        }
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// A `Content-Encoding` the server can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Gzip,
+    /// DEFLATE in the zlib format
+    Deflate,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// The encoding `headers` prefer, gzip on a tie
+    fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        let (mut gzip, mut deflate) = (0.0, 0.0);
+        let accepted = headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for item in accepted {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+                gzip = quality;
+            } else if name.eq_ignore_ascii_case("deflate") {
+                deflate = quality;
+            }
+        }
+        if gzip > 0.0 && gzip >= deflate {
+            Some(Encoding::Gzip)
+        } else if deflate > 0.0 {
+            Some(Encoding::Deflate)
+        } else {
+            None
+        }
+    }
+}
+
+/// Compresses a body chunk by chunk in a gzip or zlib stream
+pub(crate) struct Encoder {
+    encoding: Encoding,
+    deflate: Deflate,
+    started: bool,
+    crc: u32,
+    adler: (u32, u32),
+    /// Input length modulo 2^32, for the gzip trailer
+    size: u32,
+}
+
+impl Encoder {
+    pub(crate) fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            deflate: Deflate::new(),
+            started: false,
+            crc: 0,
+            adler: (1, 0),
+            size: 0,
+        }
+    }
+
+    /// Compress `data`, flushed so the client can decode all of it
+    pub(crate) fn write(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = self.header();
+        self.crc = crc32(self.crc, data);
+        self.adler = adler32(self.adler, data);
+        self.size = self.size.wrapping_add(data.len() as u32);
+        self.deflate.compress(data, &mut out);
+        out
+    }
+
+    /// End the stream
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        let mut out = self.header();
+        self.deflate.finish(&mut out);
+        match self.encoding {
+            Encoding::Gzip => {
+                out.extend_from_slice(&self.crc.to_le_bytes());
+                out.extend_from_slice(&self.size.to_le_bytes());
+            }
+            Encoding::Deflate => {
+                let (a, b) = self.adler;
+                out.extend_from_slice(&((b << 16) | a).to_be_bytes());
+            }
+        }
+        out
+    }
+
+    /// The stream header, before the first output
+    fn header(&mut self) -> Vec<u8> {
+        if std::mem::replace(&mut self.started, true) {
+            return Vec::new();
+        }
+        match self.encoding {
+            // No name or timestamp, unknown OS
+            Encoding::Gzip => vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff],
+            // 32K window, fastest level
+            Encoding::Deflate => vec![0x78, 0x01],
+        }
+    }
+}
+
+/// How far back matches may reach
+const WINDOW: usize = 1 << 15;
+const HASH_SIZE: usize = 1 << 15;
+/// How many earlier positions to try per match
+const MAX_CHAIN: usize = 64;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+/// DEFLATE with fixed Huffman codes, matching across chunks
+struct Deflate {
+    /// Recent input; matches reach into earlier chunks
+    history: Vec<u8>,
+    /// Stream position of `history[0]`
+    start: usize,
+    /// Latest position + 1 of each 3-byte hash, 0 for none
+    head: Vec<usize>,
+    /// Previous position + 1 with the same hash, by position in the window
+    prev: Vec<usize>,
+    bits: BitWriter,
+}
+
+impl Deflate {
+    fn new() -> Self {
+        Self {
+            history: Vec::new(),
+            start: 0,
+            head: vec![0; HASH_SIZE],
+            prev: vec![0; WINDOW],
+            bits: BitWriter::default(),
+        }
+    }
+
+    /// Append a block holding `data` and a sync flush to `out`
+    fn compress(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        if data.is_empty() {
+            return;
+        }
+        if self.history.len() > 2 * WINDOW {
+            let dropped = self.history.len() - WINDOW;
+            self.history.drain(..dropped);
+            self.start += dropped;
+        }
+        let mut pos = self.start + self.history.len();
+        self.history.extend_from_slice(data);
+        let end = self.start + self.history.len();
+
+        // Not the last block, fixed codes
+        self.bits.write(0b010, 3);
+        while pos < end {
+            let (length, distance) = self.longest_match(pos, end);
+            if length >= MIN_MATCH {
+                self.bits.length(length);
+                self.bits.distance(distance);
+                for matched in pos..pos + length {
+                    self.insert(matched, end);
+                }
+                pos += length;
+            } else {
+                self.bits.symbol(u32::from(self.history[pos - self.start]));
+                self.insert(pos, end);
+                pos += 1;
+            }
+        }
+        self.bits.symbol(256);
+        // An empty stored block brings the output to a byte boundary
+        self.bits.write(0, 3);
+        self.bits.align();
+        self.bits.out.extend_from_slice(&[0, 0, 0xff, 0xff]);
+        out.append(&mut self.bits.out);
+    }
+
+    /// Append an empty last block to `out`
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        self.bits.write(0b011, 3);
+        self.bits.symbol(256);
+        self.bits.align();
+        out.append(&mut self.bits.out);
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = &self.history[pos - self.start..pos - self.start + MIN_MATCH];
+        let value =
+            (usize::from(bytes[0]) << 10) ^ (usize::from(bytes[1]) << 5) ^ usize::from(bytes[2]);
+        value & (HASH_SIZE - 1)
+    }
+
+    fn insert(&mut self, pos: usize, end: usize) {
+        if pos + MIN_MATCH > end {
+            return;
+        }
+        let hash = self.hash(pos);
+        self.prev[pos & (WINDOW - 1)] = self.head[hash];
+        self.head[hash] = pos + 1;
+    }
+
+    /// Length and distance of the longest earlier match of the input at `pos`
+    fn longest_match(&self, pos: usize, end: usize) -> (usize, usize) {
+        if pos + MIN_MATCH > end {
+            return (0, 0);
+        }
+        let max_length = MAX_MATCH.min(end - pos);
+        let current = &self.history[pos - self.start..pos - self.start + max_length];
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash(pos)];
+        for _ in 0..MAX_CHAIN {
+            let Some(earlier) = candidate.checked_sub(1) else {
+                break;
+            };
+            if earlier < self.start || pos - earlier > WINDOW {
+                break;
+            }
+            let from = &self.history[earlier - self.start..];
+            let length = current.iter().zip(from).take_while(|(a, b)| a == b).count();
+            if length > best.0 {
+                best = (length, pos - earlier);
+                if length == max_length {
+                    break;
+                }
+            }
+            let next = self.prev[earlier & (WINDOW - 1)];
+            // A slot reused by a later position ends the chain
+            if next > earlier {
+                break;
+            }
+            candidate = next;
+        }
+        best
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Writes bits least significant first, as DEFLATE packs them
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// The `count` low bits of `value`
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= u64::from(value) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// A Huffman code, which DEFLATE packs most significant bit first
+    fn code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+
+    /// A literal/length symbol in the fixed code
+    fn symbol(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn length(&mut self, length: usize) {
+        let index = LENGTH_BASE.partition_point(|&base| usize::from(base) <= length) - 1;
+        self.symbol(257 + index as u32);
+        let extra = length - usize::from(LENGTH_BASE[index]);
+        self.write(extra as u32, u32::from(LENGTH_EXTRA[index]));
+    }
+
+    fn distance(&mut self, distance: usize) {
+        let index = DISTANCE_BASE.partition_point(|&base| usize::from(base) <= distance) - 1;
+        self.code(index as u32, 5);
+        let extra = distance - usize::from(DISTANCE_BASE[index]);
+        self.write(extra as u32, u32::from(DISTANCE_EXTRA[index]));
+    }
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 of the gzip trailer, continued over `data`
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let crc = data.iter().fold(!crc, |crc, &byte| {
+        CRC_TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+/// Adler-32 sums of the zlib trailer, continued over `data`
+fn adler32((mut a, mut b): (u32, u32), data: &[u8]) -> (u32, u32) {
+    const MOD: u32 = 65521;
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads DEFLATE streams of fixed and stored blocks, the ones the encoder
+    /// writes
+    struct Inflater<'a> {
+        data: &'a [u8],
+        bit: usize,
+    }
+
+    impl Inflater<'_> {
+        fn bits(&mut self, count: u32) -> usize {
+            (0..count).fold(0, |value, i| {
+                let bit = (self.data[self.bit / 8] >> (self.bit % 8)) & 1;
+                self.bit += 1;
+                value | (usize::from(bit) << i)
+            })
+        }
+
+        /// A fixed-code symbol, read most significant bit first
+        fn symbol(&mut self) -> usize {
+            let mut code = 0;
+            for length in 1..=9 {
+                code = (code << 1) | self.bits(1);
+                match (length, code) {
+                    (7, 0..=0x17) => return code + 256,
+                    (8, 0x30..=0xbf) => return code - 0x30,
+                    (8, 0xc0..=0xc7) => return code - 0xc0 + 280,
+                    (9, 0x190..=0x1ff) => return code - 0x190 + 144,
+                    _ => {}
+                }
+            }
+            panic!("invalid fixed code");
+        }
+
+        fn inflate(mut self) -> (Vec<u8>, usize) {
+            let mut out = Vec::new();
+            loop {
+                let last = self.bits(1) == 1;
+                match self.bits(2) {
+                    0 => {
+                        self.bit = self.bit.div_ceil(8) * 8;
+                        let length = self.bits(16);
+                        assert_eq!(self.bits(16), !length & 0xffff);
+                        for _ in 0..length {
+                            out.push(self.bits(8) as u8);
+                        }
+                    }
+                    1 => loop {
+                        let symbol = self.symbol();
+                        if symbol < 256 {
+                            out.push(symbol as u8);
+                            continue;
+                        }
+                        if symbol == 256 {
+                            break;
+                        }
+                        let index = symbol - 257;
+                        let length = usize::from(LENGTH_BASE[index])
+                            + self.bits(u32::from(LENGTH_EXTRA[index]));
+                        let code = (0..5).fold(0, |code, _| (code << 1) | self.bits(1));
+                        let distance = usize::from(DISTANCE_BASE[code])
+                            + self.bits(u32::from(DISTANCE_EXTRA[code]));
+                        for _ in 0..length {
+                            out.push(out[out.len() - distance]);
+                        }
+                    },
+                    other => panic!("unexpected block type {}", other),
+                }
+                if last {
+                    return (out, self.bit.div_ceil(8));
+                }
+            }
+        }
+    }
+
+    fn inflate(data: &[u8]) -> (Vec<u8>, usize) {
+        Inflater { data, bit: 0 }.inflate()
+    }
+
+    fn encode_chunks(encoding: Encoding, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut encoder = Encoder::new(encoding);
+        let mut out = Vec::new();
+        for chunk in chunks {
+            out.extend(encoder.write(chunk));
+        }
+        out.extend(encoder.finish());
+        out
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf4_3926);
+        let (a, b) = adler32((1, 0), b"Wikipedia");
+        assert_eq!((b << 16) | a, 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        let event = br#"data: {"jsonrpc":"2.0","id":1,"result":{"tools":[{"name":"deploy"}]}}"#;
+        let catalog: Vec<u8> = event.repeat(200);
+        let chunks: Vec<&[u8]> = vec![&catalog[..5000], b"", &catalog[5000..], event];
+        let gzip = encode_chunks(Encoding::Gzip, &chunks);
+        let input = [&catalog[..], event].concat();
+        assert!(gzip.len() < input.len() / 10, "{} bytes", gzip.len());
+
+        assert_eq!(&gzip[..3], &[0x1f, 0x8b, 8]);
+        let (output, used) = inflate(&gzip[10..]);
+        assert_eq!(output, input);
+        let trailer = &gzip[10 + used..];
+        assert_eq!(trailer[..4], crc32(0, &input).to_le_bytes());
+        assert_eq!(trailer[4..], (input.len() as u32).to_le_bytes());
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        // Bytes of every value, long runs and matches reaching far back
+        let mut input: Vec<u8> = (0..=255).collect();
+        input.extend([b'a'; 1000]);
+        input.extend((0..40_000u32).map(|i| (i * 7 % 251) as u8));
+        input.extend_from_within(..300);
+        let zlib = encode_chunks(Encoding::Deflate, &[&input[..10], &input[10..]]);
+        assert_eq!((u32::from(zlib[0]) << 8 | u32::from(zlib[1])) % 31, 0);
+        let (output, used) = inflate(&zlib[2..]);
+        assert_eq!(output, input);
+        let (a, b) = adler32((1, 0), &input);
+        assert_eq!(zlib[2 + used..], ((b << 16) | a).to_be_bytes());
+    }
+
+    /// Decompress `gzip` with the system's `gzip`, an independent decoder
+    fn gunzip(gzip: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new("gzip")
+            .arg("-dc")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("gzip is installed");
+        child.stdin.take().unwrap().write_all(gzip).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "gzip rejected the stream");
+        output.stdout
+    }
+
+    #[test]
+    fn test_real_decoder() {
+        let event = br#"data: {"jsonrpc":"2.0","id":7,"result":{"content":[{"type":"text"}]}}"#;
+        let mut input: Vec<u8> = (0..=255).collect();
+        input.extend(event.repeat(300));
+        input.extend((0..70_000u32).map(|i| (i * 31 % 253) as u8));
+        input.extend_from_within(1000..5000);
+        let chunks: Vec<&[u8]> = vec![&input[..3], &input[3..20_000], b"", &input[20_000..]];
+
+        let gzip = encode_chunks(Encoding::Gzip, &chunks);
+        assert_eq!(gunzip(&gzip), input);
+
+        // Decode the zlib stream's DEFLATE data in a gzip container, with its
+        // Adler-32 checked separately
+        let zlib = encode_chunks(Encoding::Deflate, &chunks);
+        let (raw, trailer) = zlib[2..].split_at(zlib.len() - 6);
+        let mut rewrapped = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+        rewrapped.extend_from_slice(raw);
+        rewrapped.extend_from_slice(&crc32(0, &input).to_le_bytes());
+        rewrapped.extend_from_slice(&(input.len() as u32).to_le_bytes());
+        assert_eq!(gunzip(&rewrapped), input);
+        let (a, b) = adler32((1, 0), &input);
+        assert_eq!(trailer, ((b << 16) | a).to_be_bytes());
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let negotiate = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT_ENCODING, accept.parse().unwrap());
+            Encoding::negotiate(&headers)
+        };
+        assert_eq!(negotiate("gzip, deflate, br"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0, br"), None);
+        assert_eq!(negotiate("identity"), None);
+        assert_eq!(Encoding::negotiate(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_compressed_responses() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let big = "x".repeat(4096);
+        let router = axum::Router::new()
+            .route("/big", axum::routing::get(move || async move { big }))
+            .route("/small", axum::routing::get(|| async { "small" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, compress_responses(router)).await });
+
+        let get = |path: &'static str| async move {
+            let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: test\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n",
+                path
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await.unwrap();
+            response
+        };
+
+        let response = get("/big").await;
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&response[..split]).to_lowercase();
+        assert!(head.contains("content-encoding: gzip"), "{}", head);
+        assert!(head.contains("transfer-encoding: chunked"), "{}", head);
+        // Undo the chunked transfer encoding
+        let mut rest = &response[split + 4..];
+        let mut body = Vec::new();
+        loop {
+            let line = rest.windows(2).position(|w| w == b"\r\n").unwrap();
+            let size =
+                usize::from_str_radix(std::str::from_utf8(&rest[..line]).unwrap(), 16).unwrap();
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&rest[line + 2..line + 2 + size]);
+            rest = &rest[line + 2 + size + 2..];
+        }
+        assert_eq!(gunzip(&body), "x".repeat(4096).as_bytes());
+
+        let response = String::from_utf8(get("/small").await).unwrap();
+        assert!(!response.to_lowercase().contains("content-encoding"));
+        assert!(response.ends_with("small"));
+    }
+}
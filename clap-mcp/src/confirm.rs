@@ -0,0 +1,155 @@
+//! Confirmation of destructive tool calls
+//!
+//! With `McpServer::with_destructive_confirmation()`, calls to tools marked
+//! `#[mcp(destructive)]` do not run right away. The first call is answered
+//! with an error result showing the command it would run and a one-time
+//! token. The agent shows it to the user and, once they approve, repeats the
+//! call with the token as `_confirm`:
+//!
+//! ```json
+//! {"name": "deploy", "arguments": {"env": "prod", "_confirm": "3f9a0c2e41b7d865"}}
+//! ```
+//!
+//! A token confirms only the command it was issued for, once, within
+//! [`CONFIRMATION_TTL`] and in the same session; any other call gets a fresh
+//! token instead of running. `_parse_only` calls run nothing and need no
+//! confirmation.
+//!
+//! The token travels through the agent, which can repeat the call without
+//! asking anyone, so it is advisory only: it makes the agent stop and call
+//! twice. To have the user approve calls themselves, ask them outside the
+//! conversation with `McpServer::with_confirmation_handler` instead, e.g. in
+//! a desktop dialog or a chat message:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new().with_confirmation_handler(|tool, command| {
+//!     ask_user(&format!("Run `{}`?", command.join(" ")))
+//! });
+//! ```
+//!
+//! Destructive calls then wait until the handler answers and run only if it
+//! returns `true`; no token is issued and `_confirm` is not accepted.
+//!
+//! rmcp 0.2 cannot send `elicitation/create` yet, so clients declaring
+//! elicitation are asked through the result as well.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Argument carrying the token that confirms a call
+pub const CONFIRM_KEY: &str = "_confirm";
+
+/// How long a token stays valid
+pub const CONFIRMATION_TTL: Duration = Duration::from_secs(300);
+
+/// Function asking the user whether the tool named by its first argument may
+/// run the command line, secrets redacted, given as its second
+pub type Approver = dyn Fn(&str, &[String]) -> bool + Send + Sync + 'static;
+
+/// Schema of the `_confirm` argument
+pub(crate) fn confirm_schema() -> Value {
+    json!({
+        "type": "string",
+        "description": "Token confirming this destructive call, returned by the first call once the user approved it",
+    })
+}
+
+/// A command waiting for its confirmation
+struct Pending {
+    tool: String,
+    args: Vec<String>,
+    issued: Instant,
+}
+
+/// Tokens issued to one session for calls awaiting confirmation
+#[derive(Default)]
+pub(crate) struct Confirmations {
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl Confirmations {
+    /// A new token confirming `tool` run with the command line `args`
+    pub(crate) fn issue(&self, tool: &str, args: &[String]) -> String {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, call| call.issued.elapsed() < CONFIRMATION_TTL);
+        let token = new_token();
+        pending.insert(
+            token.clone(),
+            Pending {
+                tool: tool.to_string(),
+                args: args.to_vec(),
+                issued: Instant::now(),
+            },
+        );
+        token
+    }
+
+    /// Use up `token` if it confirms `tool` run with `args`
+    ///
+    /// A token given for another command stays valid for that one.
+    pub(crate) fn redeem(&self, token: &str, tool: &str, args: &[String]) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        let confirms = pending.get(token).is_some_and(|call| {
+            call.tool == tool && call.args == args && call.issued.elapsed() < CONFIRMATION_TTL
+        });
+        if confirms {
+            pending.remove(token);
+        }
+        confirms
+    }
+}
+
+/// A token that cannot be guessed from earlier ones
+fn new_token() -> String {
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write(crate::context::generate_id().as_bytes());
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(|token| token.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tokens_confirm_once() {
+        let confirmations = Confirmations::default();
+        let deploy = args(&["deploy", "--env", "prod"]);
+        let token = confirmations.issue("deploy", &deploy);
+        assert_eq!(token.len(), 16);
+        assert_ne!(token, confirmations.issue("deploy", &deploy));
+
+        // Other commands are not confirmed, and leave the token usable
+        assert!(!confirmations.redeem(&token, "deploy", &args(&["deploy", "--env", "dev"])));
+        assert!(!confirmations.redeem(&token, "destroy", &deploy));
+        assert!(!confirmations.redeem("guess", "deploy", &deploy));
+        assert!(confirmations.redeem(&token, "deploy", &deploy));
+        assert!(!confirmations.redeem(&token, "deploy", &deploy));
+    }
+
+    #[test]
+    fn test_tokens_expire() {
+        let confirmations = Confirmations::default();
+        let deploy = args(&["deploy"]);
+        let token = confirmations.issue("deploy", &deploy);
+        let expired = Instant::now().checked_sub(CONFIRMATION_TTL);
+        let Some(expired) = expired else {
+            // The clock started too recently to go back that far
+            return;
+        };
+        confirmations
+            .pending
+            .lock()
+            .unwrap()
+            .get_mut(&token)
+            .unwrap()
+            .issued = expired;
+        assert!(!confirmations.redeem(&token, "deploy", &deploy));
+    }
+}
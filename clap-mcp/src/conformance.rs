@@ -0,0 +1,499 @@
+//! Golden-transcript conformance checks against the MCP spec
+//!
+//! [`Conformance`] runs a matrix of protocol interactions (initialize for each
+//! protocol version, ping, listing and calling tools, cancellation and error
+//! responses) against a server handler. Each case talks raw JSON-RPC over a
+//! fresh in-memory connection and checks the responses against the shapes the
+//! spec requires, so regressions show up when rmcp or the protocol version is
+//! bumped rather than in a client.
+//!
+//! ```ignore
+//! let handler = McpServer::<Commands>::new().with_handler(h).into_handler().await?;
+//! let report = Conformance::new(handler)
+//!     .with_call("add", json!({ "a": 1, "b": 2 }))
+//!     .run()
+//!     .await;
+//! assert!(report.passed(), "{report}");
+//! ```
+
+use rmcp::handler::server::ServerHandler;
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines, ReadHalf};
+
+/// Protocol versions the initialize cases request
+pub const PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26"];
+
+/// Tool name the error cases assume the server does not have
+const UNKNOWN_TOOL: &str = "__conformance_unknown_tool__";
+
+/// Runs the conformance matrix against a server handler
+pub struct Conformance<S> {
+    handler: S,
+    call: Option<(String, Value)>,
+    timeout: Duration,
+}
+
+/// Outcome of one conformance case
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseResult {
+    /// Case name, e.g. `initialize/2024-11-05`
+    pub name: String,
+    /// Why the case failed, `None` if it passed
+    pub failure: Option<String>,
+    /// Lines exchanged, prefixed with `->` (sent) or `<-` (received)
+    pub transcript: Vec<String>,
+}
+
+/// Results of [`Conformance::run`]; `Display` lists failures with transcripts
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConformanceReport {
+    pub cases: Vec<CaseResult>,
+}
+
+impl ConformanceReport {
+    /// Whether every case passed
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(|case| case.failure.is_none())
+    }
+
+    /// Cases that failed
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.cases.iter().filter(|case| case.failure.is_some())
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failed = self.failures().count();
+        writeln!(
+            f,
+            "{} of {} conformance cases passed",
+            self.cases.len() - failed,
+            self.cases.len()
+        )?;
+        for case in self.failures() {
+            writeln!(
+                f,
+                "\n{}: {}",
+                case.name,
+                case.failure.as_deref().unwrap_or("")
+            )?;
+            for line in &case.transcript {
+                writeln!(f, "  {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: ServerHandler + Clone> Conformance<S> {
+    /// Conformance run against `handler`; each case gets its own clone
+    pub fn new(handler: S) -> Self {
+        Self {
+            handler,
+            call: None,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// A call that should succeed, exercised by the call and cancel cases
+    ///
+    /// Without one, only calls expected to fail are made, so running the
+    /// matrix has no side effects.
+    pub fn with_call(mut self, tool: impl Into<String>, arguments: Value) -> Self {
+        self.call = Some((tool.into(), arguments));
+        self
+    }
+
+    /// How long to wait for each response, 5 seconds by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run every case and collect the results
+    pub async fn run(&self) -> ConformanceReport {
+        let mut cases: Vec<(String, Case)> = PROTOCOL_VERSIONS
+            .iter()
+            .map(|version| (format!("initialize/{version}"), Case::Initialize(version)))
+            .collect();
+        cases.extend([
+            ("ping".to_string(), Case::Ping),
+            ("tools/list".to_string(), Case::ListTools),
+            ("tools/call/unknown-tool".to_string(), Case::UnknownTool),
+            ("cancel/unknown-request".to_string(), Case::CancelUnknown),
+            ("errors/method-not-found".to_string(), Case::MethodNotFound),
+        ]);
+        if self.call.is_some() {
+            cases.push(("tools/call".to_string(), Case::Call));
+            cases.push(("cancel/in-flight".to_string(), Case::CancelInFlight));
+        }
+
+        let mut report = ConformanceReport::default();
+        for (name, case) in cases {
+            let mut session = Session::new(self.handler.clone(), self.timeout);
+            let failure = self.run_case(&mut session, case).await.err();
+            report.cases.push(CaseResult {
+                name,
+                failure,
+                transcript: std::mem::take(&mut session.transcript),
+            });
+        }
+        report
+    }
+
+    async fn run_case(&self, session: &mut Session, case: Case) -> Result<(), String> {
+        if let Case::Initialize(version) = case {
+            return check_initialize(session, version).await;
+        }
+        session.initialize(PROTOCOL_VERSIONS[0]).await?;
+
+        match case {
+            Case::Initialize(_) => unreachable!("handled above"),
+            Case::Ping => {
+                let result = session.request("ping", None).await.and_then(into_result)?;
+                expect(result == json!({}), "ping result must be an empty object")
+            }
+            Case::ListTools => {
+                let result = session
+                    .request("tools/list", None)
+                    .await
+                    .and_then(into_result)?;
+                check_tools(&result)
+            }
+            Case::Call => {
+                let (tool, arguments) = self.call.clone().expect("only scheduled with a call");
+                let params = json!({ "name": tool, "arguments": arguments });
+                let result = session
+                    .request("tools/call", Some(params))
+                    .await
+                    .and_then(into_result)?;
+                check_call_result(&result)?;
+                expect(
+                    result.get("isError") != Some(&json!(true)),
+                    "the configured call returned isError: true",
+                )
+            }
+            Case::UnknownTool => {
+                let params = json!({ "name": UNKNOWN_TOOL, "arguments": {} });
+                let response = session.request("tools/call", Some(params)).await?;
+                // Either a protocol error or a tool result flagged as an error
+                match response.get("error") {
+                    Some(error) => check_error(error, None),
+                    None => {
+                        let result = into_result(response)?;
+                        check_call_result(&result)?;
+                        expect(
+                            result.get("isError") == Some(&json!(true)),
+                            "calling an unknown tool must fail",
+                        )
+                    }
+                }
+            }
+            Case::MethodNotFound => {
+                let response = session.request("conformance/unknown", None).await?;
+                let error = response
+                    .get("error")
+                    .ok_or("an unknown method must be answered with an error")?;
+                check_error(error, Some(-32601))
+            }
+            Case::CancelUnknown => {
+                let params = json!({ "requestId": 9999, "reason": "conformance" });
+                session
+                    .notify("notifications/cancelled", Some(params))
+                    .await?;
+                session.request("ping", None).await.and_then(into_result)?;
+                Ok(())
+            }
+            Case::CancelInFlight => {
+                let (tool, arguments) = self.call.clone().expect("only scheduled with a call");
+                let params = json!({ "name": tool, "arguments": arguments });
+                let id = session.send_request("tools/call", Some(params)).await?;
+                let params = json!({ "requestId": id, "reason": "conformance" });
+                session
+                    .notify("notifications/cancelled", Some(params))
+                    .await?;
+                // The call may have completed before the cancellation arrived;
+                // either way the session must stay usable.
+                session.request("ping", None).await.and_then(into_result)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    Initialize(&'static str),
+    Ping,
+    ListTools,
+    Call,
+    UnknownTool,
+    MethodNotFound,
+    CancelUnknown,
+    CancelInFlight,
+}
+
+/// A raw JSON-RPC connection to one served clone of the handler
+struct Session {
+    writer: tokio::io::WriteHalf<DuplexStream>,
+    reader: Lines<BufReader<ReadHalf<DuplexStream>>>,
+    transcript: Vec<String>,
+    next_id: u64,
+    timeout: Duration,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl Session {
+    fn new<S: ServerHandler>(handler: S, timeout: Duration) -> Self {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(async move {
+            let (read, write) = tokio::io::split(server);
+            if let Ok(service) = rmcp::serve_server(handler, (read, write)).await {
+                let _ = service.waiting().await;
+            }
+        });
+        let (read, writer) = tokio::io::split(client);
+        Self {
+            writer,
+            reader: BufReader::new(read).lines(),
+            transcript: Vec::new(),
+            next_id: 1,
+            timeout,
+            server,
+        }
+    }
+
+    /// Handshake with the given version and return the initialize response
+    async fn initialize(&mut self, version: &str) -> Result<Value, String> {
+        let params = json!({
+            "protocolVersion": version,
+            "capabilities": {},
+            "clientInfo": { "name": "clap-mcp-conformance", "version": env!("CARGO_PKG_VERSION") },
+        });
+        let response = self.request("initialize", Some(params)).await?;
+        self.notify("notifications/initialized", None).await?;
+        Ok(response)
+    }
+
+    async fn send(&mut self, message: Value) -> Result<(), String> {
+        let line = message.to_string();
+        self.transcript.push(format!("-> {line}"));
+        self.writer
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .map_err(|e| format!("connection closed: {e}"))
+    }
+
+    async fn send_request(&mut self, method: &str, params: Option<Value>) -> Result<u64, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut message = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+        self.send(message).await?;
+        Ok(id)
+    }
+
+    /// Send a request and wait for the response with its id
+    async fn request(&mut self, method: &str, params: Option<Value>) -> Result<Value, String> {
+        let id = self.send_request(method, params).await?;
+        loop {
+            let message = self.receive().await?;
+            if message.get("id") == Some(&json!(id)) {
+                return Ok(message);
+            }
+        }
+    }
+
+    async fn notify(&mut self, method: &str, params: Option<Value>) -> Result<(), String> {
+        let mut message = json!({ "jsonrpc": "2.0", "method": method });
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+        self.send(message).await
+    }
+
+    /// Next message from the server, checked against the JSON-RPC envelope
+    async fn receive(&mut self) -> Result<Value, String> {
+        let line = tokio::time::timeout(self.timeout, self.reader.next_line())
+            .await
+            .map_err(|_| format!("no response within {:?}", self.timeout))?
+            .map_err(|e| format!("connection failed: {e}"))?
+            .ok_or("connection closed")?;
+        self.transcript.push(format!("<- {line}"));
+        let message: Value =
+            serde_json::from_str(&line).map_err(|e| format!("invalid JSON from server: {e}"))?;
+        check_envelope(&message)?;
+        Ok(message)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+fn expect(condition: bool, failure: &str) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(failure.to_string())
+    }
+}
+
+/// The `result` of a response, or its error as the failure
+fn into_result(mut response: Value) -> Result<Value, String> {
+    if let Some(error) = response.get("error") {
+        return Err(format!("unexpected error response: {error}"));
+    }
+    response
+        .get_mut("result")
+        .map(Value::take)
+        .ok_or_else(|| "response has neither result nor error".to_string())
+}
+
+/// Every message is JSON-RPC 2.0; responses have exactly one of result/error
+fn check_envelope(message: &Value) -> Result<(), String> {
+    expect(
+        message.get("jsonrpc") == Some(&json!("2.0")),
+        "message must carry \"jsonrpc\": \"2.0\"",
+    )?;
+    if message.get("method").is_some() {
+        return expect(message["method"].is_string(), "method must be a string");
+    }
+    let id = message.get("id").ok_or("response must carry an id")?;
+    expect(
+        id.is_string() || id.is_i64() || id.is_u64() || id.is_null(),
+        "response id must be a string or an integer",
+    )?;
+    expect(
+        message.get("result").is_some() != message.get("error").is_some(),
+        "response must carry exactly one of result and error",
+    )
+}
+
+fn check_error(error: &Value, code: Option<i64>) -> Result<(), String> {
+    let actual = error
+        .get("code")
+        .and_then(Value::as_i64)
+        .ok_or("error code must be an integer")?;
+    expect(
+        error.get("message").is_some_and(Value::is_string),
+        "error message must be a string",
+    )?;
+    match code {
+        Some(code) if code != actual => Err(format!("expected error code {code}, got {actual}")),
+        _ => Ok(()),
+    }
+}
+
+async fn check_initialize(session: &mut Session, version: &str) -> Result<(), String> {
+    let result = session.initialize(version).await.and_then(into_result)?;
+    let negotiated = result
+        .get("protocolVersion")
+        .and_then(Value::as_str)
+        .ok_or("protocolVersion must be a string")?;
+    expect(
+        negotiated == version || PROTOCOL_VERSIONS.contains(&negotiated),
+        &format!("server negotiated unknown protocol version {negotiated}"),
+    )?;
+    expect(
+        result.get("capabilities").is_some_and(Value::is_object),
+        "capabilities must be an object",
+    )?;
+    let info = result.get("serverInfo").ok_or("serverInfo is required")?;
+    expect(
+        info.get("name").is_some_and(Value::is_string)
+            && info.get("version").is_some_and(Value::is_string),
+        "serverInfo needs string name and version",
+    )?;
+    // The session is usable once initialized
+    session.request("ping", None).await.and_then(into_result)?;
+    Ok(())
+}
+
+fn check_tools(result: &Value) -> Result<(), String> {
+    let tools = result
+        .get("tools")
+        .and_then(Value::as_array)
+        .ok_or("tools must be an array")?;
+    if let Some(cursor) = result.get("nextCursor") {
+        expect(cursor.is_string(), "nextCursor must be a string")?;
+    }
+    for tool in tools {
+        let name = tool
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or("tool name must be a string")?;
+        let fail = |what: &str| format!("tool {name}: {what}");
+        if let Some(description) = tool.get("description") {
+            expect(
+                description.is_string(),
+                &fail("description must be a string"),
+            )?;
+        }
+        let schema = tool
+            .get("inputSchema")
+            .ok_or_else(|| fail("inputSchema is required"))?;
+        expect(
+            schema.get("type") == Some(&json!("object")),
+            &fail("inputSchema must be of type object"),
+        )?;
+        let properties = match schema.get("properties") {
+            Some(Value::Object(properties)) => Some(properties),
+            Some(_) => return Err(fail("inputSchema properties must be an object")),
+            None => None,
+        };
+        if let Some(required) = schema.get("required") {
+            let required = required
+                .as_array()
+                .ok_or_else(|| fail("inputSchema required must be an array"))?;
+            for id in required {
+                let id = id
+                    .as_str()
+                    .ok_or_else(|| fail("required entries must be strings"))?;
+                expect(
+                    properties.is_some_and(|p| p.contains_key(id)),
+                    &fail(&format!("required argument {id} is not a property")),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_call_result(result: &Value) -> Result<(), String> {
+    let content = result
+        .get("content")
+        .and_then(Value::as_array)
+        .ok_or("call result content must be an array")?;
+    if let Some(is_error) = result.get("isError") {
+        expect(is_error.is_boolean(), "isError must be a boolean")?;
+    }
+    for item in content {
+        let kind = item
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or("content items need a string type")?;
+        let has_string = |key: &str| item.get(key).is_some_and(Value::is_string);
+        match kind {
+            "text" => expect(has_string("text"), "text content needs a string text")?,
+            "image" | "audio" => expect(
+                has_string("data") && has_string("mimeType"),
+                "image and audio content need string data and mimeType",
+            )?,
+            "resource" => expect(
+                item.get("resource")
+                    .is_some_and(|r| r.get("uri").is_some_and(Value::is_string)),
+                "embedded resources need a uri",
+            )?,
+            other => return Err(format!("unknown content type {other}")),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,278 @@
+//! Per-call context available to command handlers
+//!
+//! Every tool call gets a correlation ID, taken from the request's
+//! `_meta.correlationId` when the client sends one and generated otherwise.
+//! It is logged with the call, attached to protocol error data, and readable
+//! from inside the handler so it can be forwarded to backends:
+//!
+//! ```ignore
+//! fn execute(cmd: Commands) -> Result<String, String> {
+//!     let id = clap_mcp::context::correlation_id().unwrap_or_default();
+//!     backend.request().header("x-correlation-id", id).send()
+//! }
+//! ```
+//!
+//! It also carries the [`ClientFeatures`] the client declared when it
+//! connected, so features that need client support can fall back instead of
+//! failing the call:
+//!
+//! ```ignore
+//! if clap_mcp::context::client_features().sampling {
+//!     // ask the client's model to summarize
+//! } else {
+//!     // return the raw output
+//! }
+//! ```
+//!
+//! Output captured for a client should be plain: spinners, progress bars and
+//! colors only add control sequences to it. [`plain_output`] tells handlers
+//! shared with the CLI when to skip them, and [`color_enabled`] also honors
+//! `NO_COLOR` and `TERM=dumb` outside calls:
+//!
+//! ```ignore
+//! let progress = if clap_mcp::context::plain_output() {
+//!     ProgressBar::hidden()
+//! } else {
+//!     ProgressBar::new(total)
+//! };
+//! ```
+//!
+//! Programs run by `McpServer::with_exec` get [`PLAIN_OUTPUT_ENV`] instead.
+
+use crate::globals::GlobalValues;
+use rmcp::model::{ClientCapabilities, Meta};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// `_meta` key a client can use to supply its own correlation ID
+pub const CORRELATION_ID_META_KEY: &str = "correlationId";
+
+/// Environment of programs run by `McpServer::with_exec`: marks MCP mode
+/// (`CLAP_MCP`) and turns off color for tools following the common
+/// conventions
+pub const PLAIN_OUTPUT_ENV: &[(&str, &str)] = &[
+    ("CLAP_MCP", "1"),
+    ("NO_COLOR", "1"),
+    ("CLICOLOR", "0"),
+    ("TERM", "dumb"),
+];
+
+/// Variables forcing color even when output is not a terminal, removed from
+/// the environment of programs run by `McpServer::with_exec`
+pub const COLOR_FORCING_ENV: &[&str] = &["CLICOLOR_FORCE", "FORCE_COLOR"];
+
+/// The tool call currently being handled
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallContext {
+    /// ID tying this call's logs and errors together across systems
+    pub correlation_id: String,
+    /// Name of the tool being called
+    pub tool: String,
+    /// What the connected client supports
+    pub client: ClientFeatures,
+}
+
+/// Optional features the connected client declared during initialization
+///
+/// Everything is `false` when the client declared nothing, or when no call
+/// is being handled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientFeatures {
+    /// The server may request LLM completions (`sampling/createMessage`)
+    pub sampling: bool,
+    /// The server may list the client's roots (`roots/list`)
+    pub roots: bool,
+    /// The client notifies when its roots change
+    pub roots_list_changed: bool,
+    /// The server may ask the user for input (`elicitation/create`)
+    ///
+    /// rmcp 0.2 does not model this capability yet, so it is read from
+    /// `experimental.elicitation`.
+    pub elicitation: bool,
+    /// Names of the experimental capabilities the client declared
+    pub experimental: BTreeSet<String>,
+}
+
+impl ClientFeatures {
+    pub(crate) fn from_capabilities(capabilities: &ClientCapabilities) -> Self {
+        let experimental: BTreeSet<String> = capabilities
+            .experimental
+            .iter()
+            .flat_map(|experimental| experimental.keys().cloned())
+            .collect();
+        Self {
+            sampling: capabilities.sampling.is_some(),
+            roots: capabilities.roots.is_some(),
+            roots_list_changed: capabilities
+                .roots
+                .as_ref()
+                .and_then(|roots| roots.list_changed)
+                .unwrap_or(false),
+            elicitation: experimental.contains("elicitation"),
+            experimental,
+        }
+    }
+
+    /// Whether the client declared the experimental capability `name`
+    pub fn supports_experimental(&self, name: &str) -> bool {
+        self.experimental.contains(name)
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<CallContext>> = const { RefCell::new(None) };
+    static GLOBAL_ARGS: RefCell<Option<GlobalValues>> = const { RefCell::new(None) };
+}
+
+/// Context of the call being handled on this thread, if any
+///
+/// Only set while a [`CommandHandler`](crate::CommandHandler) runs.
+pub fn current() -> Option<CallContext> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Correlation ID of the call being handled on this thread, if any
+pub fn correlation_id() -> Option<String> {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map(|ctx| ctx.correlation_id.clone())
+    })
+}
+
+/// Features of the client making the call being handled on this thread
+///
+/// Outside a call nothing is supported, so callers degrade gracefully.
+pub fn client_features() -> ClientFeatures {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map(|ctx| ctx.client.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Whether a tool call is being handled on this thread, so output is captured
+/// for a client and should not contain spinners, progress bars or colors
+pub fn plain_output() -> bool {
+    CURRENT.with(|current| current.borrow().is_some())
+}
+
+/// Whether colored output is wanted: not while a tool call is handled, nor
+/// when `NO_COLOR` is set or `TERM` is `dumb`
+pub fn color_enabled() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let dumb = std::env::var_os("TERM").is_some_and(|term| term == "dumb");
+    !plain_output() && !no_color && !dumb
+}
+
+/// Global options of the call being handled on this thread (see
+/// [`crate::globals`])
+///
+/// `None` outside a call, when no global options are registered, or when `G`
+/// is not the registered type.
+pub fn global_args<G: Clone + 'static>() -> Option<G> {
+    GLOBAL_ARGS.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .and_then(|values| values.downcast_ref::<G>().cloned())
+    })
+}
+
+/// Run `f` with `context` as the current call context
+pub(crate) fn scope<R>(context: CallContext, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT.with(|current| current.replace(Some(context)));
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Run `f` with `values` as the current global options
+pub(crate) fn scope_global_args<R>(values: Option<GlobalValues>, f: impl FnOnce() -> R) -> R {
+    let previous = GLOBAL_ARGS.with(|current| current.replace(values));
+    let result = f();
+    GLOBAL_ARGS.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// The client-supplied correlation ID, or a freshly generated one
+pub(crate) fn correlation_id_from_meta(meta: &Meta) -> String {
+    match meta.0.get(CORRELATION_ID_META_KEY) {
+        Some(serde_json::Value::String(id)) if !id.is_empty() => id.clone(),
+        _ => generate_id(),
+    }
+}
+
+/// Unique-enough ID: start time, process ID and a per-process counter
+pub(crate) fn generate_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    format!(
+        "{:x}-{:x}-{:x}",
+        nanos,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_correlation_id_from_meta() {
+        let mut meta = Meta::new();
+        meta.0
+            .insert(CORRELATION_ID_META_KEY.to_string(), json!("abc-123"));
+        assert_eq!(correlation_id_from_meta(&meta), "abc-123");
+
+        let generated = correlation_id_from_meta(&Meta::new());
+        assert_ne!(generated, correlation_id_from_meta(&Meta::new()));
+    }
+
+    #[test]
+    fn test_scope_sets_and_restores_context() {
+        assert_eq!(correlation_id(), None);
+        let context = CallContext {
+            correlation_id: "outer".to_string(),
+            tool: "add".to_string(),
+            client: ClientFeatures::default(),
+        };
+        assert!(!plain_output());
+        let seen = scope(context.clone(), || (current(), correlation_id()));
+        assert_eq!(seen, (Some(context.clone()), Some("outer".to_string())));
+        assert_eq!(current(), None);
+        assert!(scope(context, || plain_output() && !color_enabled()));
+        assert!(!plain_output());
+    }
+
+    #[test]
+    fn test_client_features_from_capabilities() {
+        assert_eq!(
+            ClientFeatures::from_capabilities(&ClientCapabilities::default()),
+            ClientFeatures::default()
+        );
+
+        let capabilities: ClientCapabilities = serde_json::from_value(json!({
+            "sampling": {},
+            "roots": { "listChanged": true },
+            "experimental": { "elicitation": {}, "x-trace": {} },
+        }))
+        .unwrap();
+        let features = ClientFeatures::from_capabilities(&capabilities);
+        assert!(features.sampling && features.roots && features.roots_list_changed);
+        assert!(features.elicitation);
+        assert!(features.supports_experimental("x-trace"));
+        assert!(!features.supports_experimental("other"));
+
+        assert_eq!(client_features(), ClientFeatures::default());
+    }
+}
@@ -0,0 +1,266 @@
+//! Length limits on tool and argument descriptions
+//!
+//! Doc comments of a large CLI easily run to several paragraphs, which some
+//! clients truncate mid-sentence or reject outright. [`DescriptionLimits`]
+//! caps them when the tool list is built:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new()
+//!     .with_description_limits(DescriptionLimits::new().tool(1024).argument(256));
+//! ```
+//!
+//! A description over its limit keeps its first paragraph, cut at a word
+//! boundary if that is still too long, and points to the resource
+//! `help://<tool>`. That resource serves the full descriptions of the tool and
+//! its arguments, so nothing is lost for clients that read it. A warning
+//! lists the tools affected.
+
+use rmcp::model::{AnnotateAble, RawResource, Resource, ResourceContents, Tool};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// URI scheme of the full help of tools with shortened descriptions
+pub const HELP_SCHEME: &str = "help://";
+
+/// Longest descriptions to send, in characters; unlimited when unset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DescriptionLimits {
+    pub tool: Option<usize>,
+    pub argument: Option<usize>,
+}
+
+impl DescriptionLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit tool descriptions to `max` characters
+    pub fn tool(mut self, max: usize) -> Self {
+        self.tool = Some(max);
+        self
+    }
+
+    /// Limit argument descriptions to `max` characters
+    pub fn argument(mut self, max: usize) -> Self {
+        self.argument = Some(max);
+        self
+    }
+
+    /// Shorten the descriptions of `tools` over the limits, keeping the full
+    /// text of each tool affected in `help`
+    ///
+    /// Returns the names of the tools affected.
+    pub(crate) fn apply(&self, tools: &mut [Tool], help: &HelpResources) -> Vec<String> {
+        let mut affected = Vec::new();
+        for tool in tools {
+            let uri = format!("{}{}", HELP_SCHEME, tool.name);
+            let full = help_text(tool);
+            let mut shortened = false;
+
+            if let (Some(max), Some(description)) = (self.tool, &tool.description) {
+                let suffix = format!(" (see resource {})", uri);
+                if let Some(short) = truncate(description, max, &suffix) {
+                    tool.description = Some(short.into());
+                    shortened = true;
+                }
+            }
+            if let Some(max) = self.argument {
+                let mut input_schema = (*tool.input_schema).clone();
+                if let Some(Value::Object(properties)) = input_schema.get_mut("properties") {
+                    for property in properties.values_mut() {
+                        let Some(Value::String(description)) = property.get_mut("description")
+                        else {
+                            continue;
+                        };
+                        let suffix = format!(" (see {})", uri);
+                        if let Some(short) = truncate(description, max, &suffix) {
+                            *description = short;
+                            shortened = true;
+                        }
+                    }
+                }
+                tool.input_schema = Arc::new(input_schema);
+            }
+
+            if shortened {
+                help.insert(tool.name.to_string(), full);
+                affected.push(tool.name.to_string());
+            }
+        }
+        affected
+    }
+}
+
+/// The first paragraph of `text` ending in `suffix`, cut at a word boundary
+/// to fit in `max` characters; `None` when `text` fits as it is
+fn truncate(text: &str, max: usize, suffix: &str) -> Option<String> {
+    if text.chars().count() <= max {
+        return None;
+    }
+    let Some(budget) = max.checked_sub(suffix.chars().count()).filter(|&n| n > 0) else {
+        // No room to point at the help, just cut
+        return Some(text.chars().take(max).collect());
+    };
+    let first = text.split("\n\n").next().unwrap_or_default().trim_end();
+    if first.chars().count() <= budget {
+        return Some(format!("{}{}", first, suffix));
+    }
+    // Leave room for the ellipsis, and back up to the start of a word the
+    // limit falls in
+    let chars: Vec<char> = first.chars().collect();
+    let mut end = budget - 1;
+    if !chars[end].is_whitespace() {
+        if let Some(space) = chars[..end].iter().rposition(|c| c.is_whitespace()) {
+            end = space.max(1);
+        }
+    }
+    let cut: String = chars[..end].iter().collect();
+    Some(format!("{}…{}", cut.trim_end(), suffix))
+}
+
+/// The full description of `tool` and its arguments, as Markdown
+fn help_text(tool: &Tool) -> String {
+    let mut text = format!("# {}\n", tool.name);
+    if let Some(description) = tool.description.as_deref().filter(|d| !d.is_empty()) {
+        text.push('\n');
+        text.push_str(description);
+        text.push('\n');
+    }
+    let Some(Value::Object(properties)) = tool.input_schema.get("properties") else {
+        return text;
+    };
+    let described: Vec<_> = properties
+        .iter()
+        .filter_map(|(name, property)| Some((name, property.get("description")?.as_str()?)))
+        .collect();
+    if !described.is_empty() {
+        text.push_str("\n## Arguments\n");
+        for (name, description) in described {
+            text.push_str(&format!("\n- `{}`: {}", name, description));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// A [`ResourceProvider`](crate::resources::ResourceProvider) serving the full
+/// help of tools whose descriptions were shortened, as `help://<tool>`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct HelpResources {
+    texts: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl HelpResources {
+    fn insert(&self, tool: String, text: String) {
+        self.texts.lock().unwrap().insert(tool, text);
+    }
+}
+
+impl crate::resources::ResourceProvider for HelpResources {
+    fn list(&self) -> Vec<Resource> {
+        self.texts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(tool, text)| {
+                RawResource {
+                    uri: format!("{}{}", HELP_SCHEME, tool),
+                    name: format!("Help of {}", tool),
+                    description: Some(format!("Full descriptions of `{}`", tool)),
+                    mime_type: Some("text/markdown".to_string()),
+                    size: u32::try_from(text.len()).ok(),
+                }
+                .no_annotation()
+            })
+            .collect()
+    }
+
+    fn read(&self, uri: &str) -> Option<Result<ResourceContents, String>> {
+        let tool = uri.strip_prefix(HELP_SCHEME)?;
+        let text = self.texts.lock().unwrap().get(tool).cloned()?;
+        Some(Ok(ResourceContents::TextResourceContents {
+            uri: uri.to_string(),
+            mime_type: Some("text/markdown".to_string()),
+            text,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::ResourceProvider;
+    use serde_json::json;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("Short enough", 20, " (more)"), None);
+        assert_eq!(
+            truncate(
+                "Deploy the app.\n\nLong details follow here.",
+                30,
+                " (more)"
+            ),
+            Some("Deploy the app. (more)".to_string())
+        );
+        assert_eq!(
+            truncate("Deploy the application to every region", 30, " (more)"),
+            Some("Deploy the application… (more)".to_string())
+        );
+        // Without room for the suffix the text is cut as it is
+        assert_eq!(
+            truncate("Deploy everything", 5, " (more)"),
+            Some("Deplo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply_limits() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "env": { "type": "string", "description": "Environment to deploy to, one of dev, staging or prod" },
+                "force": { "type": "boolean", "description": "Skip checks" }
+            }
+        });
+        let mut tools = vec![
+            Tool::new(
+                "deploy",
+                "Deploy the app.\n\nRuns the migrations first, then rolls out one region at a time.",
+                Arc::new(schema.as_object().unwrap().clone()),
+            ),
+            Tool::new(
+                "status",
+                "Show status",
+                Arc::new(json!({ "type": "object" }).as_object().unwrap().clone()),
+            ),
+        ];
+        let help = HelpResources::default();
+        let affected = DescriptionLimits::new()
+            .tool(60)
+            .argument(40)
+            .apply(&mut tools, &help);
+        assert_eq!(affected, ["deploy"]);
+        assert_eq!(
+            tools[0].description.as_deref(),
+            Some("Deploy the app. (see resource help://deploy)")
+        );
+        let env = &tools[0].input_schema["properties"]["env"]["description"];
+        assert_eq!(env, "Environment to… (see help://deploy)");
+        assert_eq!(
+            tools[0].input_schema["properties"]["force"]["description"],
+            "Skip checks"
+        );
+
+        assert_eq!(help.list().len(), 1);
+        let Some(Ok(ResourceContents::TextResourceContents { text, .. })) =
+            help.read("help://deploy")
+        else {
+            panic!("no help for deploy");
+        };
+        assert!(text.contains("Runs the migrations first,"));
+        assert!(text.contains("- `env`: Environment to deploy to, one of dev, staging or prod"));
+        assert!(help.read("help://status").is_none());
+    }
+}
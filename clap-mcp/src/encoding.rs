@@ -0,0 +1,133 @@
+//! Decoding of captured subprocess output
+//!
+//! Child processes do not always write UTF-8: Windows tools frequently emit
+//! UTF-16LE or text in the active codepage. [`OutputEncoding`] describes what a
+//! process is expected to produce so captured bytes decode without replacement
+//! characters.
+
+/// Expected encoding of a child process's stdout/stderr
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OutputEncoding {
+    /// Honor a UTF-8 or UTF-16 byte order mark, otherwise decode as UTF-8
+    #[default]
+    Auto,
+    /// UTF-8, with invalid sequences replaced by U+FFFD
+    Utf8,
+    /// Little-endian UTF-16, as written by many Windows tools
+    Utf16Le,
+    /// Big-endian UTF-16
+    Utf16Be,
+    /// A WHATWG encoding label such as `"windows-1252"` or `"shift_jis"`
+    #[cfg(feature = "codepages")]
+    Label(String),
+}
+
+impl OutputEncoding {
+    /// Decode captured bytes into a string
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            OutputEncoding::Auto => match bytes {
+                [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+                [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+                [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+                _ => String::from_utf8_lossy(bytes).into_owned(),
+            },
+            OutputEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            OutputEncoding::Utf16Le => decode_utf16(
+                bytes.strip_prefix(&[0xFF, 0xFE]).unwrap_or(bytes),
+                u16::from_le_bytes,
+            ),
+            OutputEncoding::Utf16Be => decode_utf16(
+                bytes.strip_prefix(&[0xFE, 0xFF]).unwrap_or(bytes),
+                u16::from_be_bytes,
+            ),
+            #[cfg(feature = "codepages")]
+            OutputEncoding::Label(label) => {
+                match encoding_rs::Encoding::for_label(label.as_bytes()) {
+                    Some(encoding) => encoding.decode(bytes).0.into_owned(),
+                    None => String::from_utf8_lossy(bytes).into_owned(),
+                }
+            }
+        }
+    }
+
+    /// Parse an encoding name such as `utf-8`, `utf-16le` or `auto`
+    ///
+    /// With the `codepages` feature any WHATWG label (e.g. `windows-1252`,
+    /// `cp866`) is accepted as well.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "auto" => Some(OutputEncoding::Auto),
+            "utf-8" | "utf8" => Some(OutputEncoding::Utf8),
+            "utf-16le" | "utf16le" | "utf-16" | "unicode" => Some(OutputEncoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(OutputEncoding::Utf16Be),
+            #[cfg(feature = "codepages")]
+            _ if encoding_rs::Encoding::for_label(name.as_bytes()).is_some() => {
+                Some(OutputEncoding::Label(name.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| match pair {
+            [a, b] => from_bytes([*a, *b]),
+            // A dangling odd byte cannot form a code unit
+            _ => 0xFFFD,
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_decode_utf16le() {
+        let bytes = utf16le("Größe: 5 MB\r\n");
+        assert_eq!(OutputEncoding::Utf16Le.decode(&bytes), "Größe: 5 MB\r\n");
+
+        let mut with_bom = vec![0xFF, 0xFE];
+        with_bom.extend(utf16le("ok"));
+        assert_eq!(OutputEncoding::Utf16Le.decode(&with_bom), "ok");
+        assert_eq!(OutputEncoding::Auto.decode(&with_bom), "ok");
+    }
+
+    #[test]
+    fn test_decode_auto_defaults_to_utf8() {
+        assert_eq!(OutputEncoding::Auto.decode("héllo".as_bytes()), "héllo");
+        assert_eq!(
+            OutputEncoding::Auto.decode(&[0xEF, 0xBB, 0xBF, b'h', b'i']),
+            "hi"
+        );
+        assert_eq!(OutputEncoding::Utf8.decode(&[b'a', 0xFF]), "a\u{FFFD}");
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            OutputEncoding::from_name("UTF-16LE"),
+            Some(OutputEncoding::Utf16Le)
+        );
+        assert_eq!(
+            OutputEncoding::from_name("utf8"),
+            Some(OutputEncoding::Utf8)
+        );
+        assert_eq!(OutputEncoding::from_name("no-such-encoding"), None);
+    }
+
+    #[cfg(feature = "codepages")]
+    #[test]
+    fn test_decode_codepage() {
+        let encoding = OutputEncoding::from_name("windows-1252").unwrap();
+        assert_eq!(encoding.decode(&[0x80, b' ', 0xE9]), "€ é");
+    }
+}
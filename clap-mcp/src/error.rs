@@ -0,0 +1,232 @@
+//! Error conversion helpers for command handlers
+
+use std::error::Error;
+
+/// Render an error and its `source()` chain as a multi-line message
+///
+/// The format mirrors what `anyhow` prints for `{:?}` without a backtrace:
+///
+/// ```text
+/// failed to deploy
+///
+/// Caused by:
+///     0: failed to read manifest
+///     1: No such file or directory (os error 2)
+/// ```
+pub fn format_error_chain(error: &(dyn Error + 'static)) -> String {
+    format_chain(
+        error.to_string(),
+        std::iter::successors(error.source(), |e| (*e).source()),
+    )
+}
+
+fn format_chain<'a>(
+    head: String,
+    causes: impl Iterator<Item = &'a (dyn Error + 'static)>,
+) -> String {
+    let causes: Vec<String> = causes.map(|e| e.to_string()).collect();
+    if causes.is_empty() {
+        return head;
+    }
+
+    let mut out = head;
+    out.push_str("\n\nCaused by:");
+    for (i, cause) in causes.iter().enumerate() {
+        out.push_str(&format!("\n    {}: {}", i, cause));
+    }
+    out
+}
+
+/// A [`ToolError`](crate::output::ToolError) rendering the chain like
+/// [`format_error_chain`], with every message of it as `{"chain": [...]}` data
+#[cfg(any(feature = "anyhow", feature = "eyre"))]
+fn chain_error<'a>(
+    chain: impl Iterator<Item = &'a (dyn Error + 'static)>,
+) -> crate::output::ToolError {
+    let chain: Vec<&(dyn Error + 'static)> = chain.collect();
+    let messages: Vec<String> = chain.iter().map(|e| e.to_string()).collect();
+    let head = messages.first().cloned().unwrap_or_default();
+    let text = format_chain(head, chain.into_iter().skip(1));
+    crate::output::ToolError::new(text).with_data(serde_json::json!({ "chain": messages }))
+}
+
+/// Adapt a handler returning `anyhow::Result<String>` into a [`crate::CommandHandler`]
+///
+/// Errors are rendered with their full context chain instead of only the
+/// outermost message.
+#[cfg(feature = "anyhow")]
+pub fn anyhow_handler<T>(
+    handler: impl Fn(T) -> anyhow::Result<String> + Send + Sync + 'static,
+) -> crate::CommandHandler<T> {
+    Box::new(move |cmd| handler(cmd).map_err(|e| format_chain(e.to_string(), e.chain().skip(1))))
+}
+
+/// Adapt a handler returning `anyhow::Result<ToolOutput>` into a
+/// [`crate::StructuredHandler`]
+///
+/// Errors carry their context chain as data, for clients to inspect.
+#[cfg(feature = "anyhow")]
+pub fn anyhow_structured_handler<T>(
+    handler: impl Fn(T) -> anyhow::Result<crate::output::ToolOutput> + Send + Sync + 'static,
+) -> crate::StructuredHandler<T> {
+    Box::new(move |cmd| handler(cmd).map_err(|e| chain_error(e.chain())))
+}
+
+/// Adapt a handler returning `eyre::Result<String>` into a [`crate::CommandHandler`]
+///
+/// Errors are rendered with their full context chain instead of only the
+/// outermost message.
+#[cfg(feature = "eyre")]
+pub fn eyre_handler<T>(
+    handler: impl Fn(T) -> eyre::Result<String> + Send + Sync + 'static,
+) -> crate::CommandHandler<T> {
+    Box::new(move |cmd| handler(cmd).map_err(|e| format_chain(e.to_string(), e.chain().skip(1))))
+}
+
+/// Adapt a handler returning `eyre::Result<ToolOutput>` into a
+/// [`crate::StructuredHandler`]
+///
+/// Errors carry their context chain as data, for clients to inspect.
+#[cfg(feature = "eyre")]
+pub fn eyre_structured_handler<T>(
+    handler: impl Fn(T) -> eyre::Result<crate::output::ToolOutput> + Send + Sync + 'static,
+) -> crate::StructuredHandler<T> {
+    Box::new(move |cmd| handler(cmd).map_err(|e| chain_error(e.chain())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct Wrapped(&'static str, Option<Box<Wrapped>>);
+
+    impl fmt::Display for Wrapped {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl Error for Wrapped {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.1.as_deref().map(|e| e as &(dyn Error + 'static))
+        }
+    }
+
+    #[test]
+    fn test_format_error_chain() {
+        let err = Wrapped(
+            "failed to deploy",
+            Some(Box::new(Wrapped(
+                "failed to read manifest",
+                Some(Box::new(Wrapped("file not found", None))),
+            ))),
+        );
+
+        assert_eq!(
+            format_error_chain(&err),
+            "failed to deploy\n\nCaused by:\n    0: failed to read manifest\n    1: file not found"
+        );
+        assert_eq!(
+            format_error_chain(&Wrapped("plain", None)),
+            "plain".to_string()
+        );
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn test_anyhow_handler_preserves_context() {
+        use anyhow::Context;
+
+        let handler = anyhow_handler(|n: u32| {
+            let ratio = 100u32
+                .checked_div(n)
+                .ok_or_else(|| anyhow::anyhow!("division by zero"))
+                .context("failed to compute ratio")?;
+            Ok(ratio.to_string())
+        });
+
+        assert_eq!(handler(4), Ok("25".to_string()));
+        assert_eq!(
+            handler(0),
+            Err("failed to compute ratio\n\nCaused by:\n    0: division by zero".to_string())
+        );
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[test]
+    fn test_anyhow_structured_handler_lists_chain() {
+        use crate::output::ToolOutput;
+        use anyhow::Context;
+
+        let handler = anyhow_structured_handler(|n: u32| {
+            let ratio = 100u32
+                .checked_div(n)
+                .ok_or_else(|| anyhow::anyhow!("division by zero"))
+                .context("failed to compute ratio")?;
+            Ok(ToolOutput::Text(ratio.to_string()))
+        });
+
+        assert!(matches!(handler(4), Ok(ToolOutput::Text(text)) if text == "25"));
+        let error = handler(0).unwrap_err();
+        assert_eq!(
+            error.output,
+            ToolOutput::Text(
+                "failed to compute ratio\n\nCaused by:\n    0: division by zero".to_string()
+            )
+        );
+        assert_eq!(
+            error.data(),
+            Some(&serde_json::json!({ "chain": ["failed to compute ratio", "division by zero"] }))
+        );
+    }
+
+    #[cfg(feature = "eyre")]
+    #[test]
+    fn test_eyre_handler_preserves_context() {
+        use eyre::WrapErr;
+
+        let handler = eyre_handler(|n: u32| {
+            let ratio = 100u32
+                .checked_div(n)
+                .ok_or_else(|| eyre::eyre!("division by zero"))
+                .wrap_err("failed to compute ratio")?;
+            Ok(ratio.to_string())
+        });
+
+        assert_eq!(handler(4), Ok("25".to_string()));
+        assert_eq!(
+            handler(0),
+            Err("failed to compute ratio\n\nCaused by:\n    0: division by zero".to_string())
+        );
+    }
+
+    #[cfg(feature = "eyre")]
+    #[test]
+    fn test_eyre_structured_handler_lists_chain() {
+        use crate::output::ToolOutput;
+        use eyre::WrapErr;
+
+        let handler = eyre_structured_handler(|n: u32| {
+            let ratio = 100u32
+                .checked_div(n)
+                .ok_or_else(|| eyre::eyre!("division by zero"))
+                .wrap_err("failed to compute ratio")?;
+            Ok(ToolOutput::Text(ratio.to_string()))
+        });
+
+        assert!(matches!(handler(4), Ok(ToolOutput::Text(text)) if text == "25"));
+        let error = handler(0).unwrap_err();
+        assert_eq!(
+            error.output,
+            ToolOutput::Text(
+                "failed to compute ratio\n\nCaused by:\n    0: division by zero".to_string()
+            )
+        );
+        assert_eq!(
+            error.data(),
+            Some(&serde_json::json!({ "chain": ["failed to compute ratio", "division by zero"] }))
+        );
+    }
+}
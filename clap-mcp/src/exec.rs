@@ -0,0 +1,319 @@
+//! Running tool calls as invocations of an existing binary
+//!
+//! CLIs without a Rust handler, including ones you do not own, can be served
+//! by describing their interface as a `Subcommand` enum and letting
+//! `McpServer::with_exec` run the binary itself. Each call is parsed by clap
+//! as usual, then its command line is passed to the program:
+//!
+//! ```ignore
+//! #[derive(Subcommand, Clone)]
+//! enum Git {
+//!     /// Show the working tree status
+//!     Status {
+//!         #[arg(long)]
+//!         short: bool,
+//!     },
+//! }
+//!
+//! McpServer::<Git>::new()
+//!     .with_exec("git")
+//!     .with_exec_timeout(Duration::from_secs(30));
+//! ```
+//!
+//! `{"name": "status", "arguments": {"short": true}}` runs `git status
+//! --short`. The result holds stdout, then stderr if the program wrote any,
+//! then the exit status; a non-zero exit marks it as an error. Programs still
+//! running after the timeout are killed. Their output is decoded as UTF-8 or
+//! by its byte order mark, unless `McpServer::with_exec_encoding` names
+//! another [`OutputEncoding`].
+//!
+//! Programs run with `NO_COLOR=1`, `TERM=dumb` and `CLAP_MCP=1` set, so they
+//! leave out colors and spinners (see
+//! [`PLAIN_OUTPUT_ENV`](crate::context::PLAIN_OUTPUT_ENV)).
+//!
+//! A server built without a handler or exec program fails every call, unless
+//! [`MissingHandler`] says otherwise: `McpServer::with_missing_handler` can
+//! run the current executable with the call's command line, echo that command
+//! line without running anything, or pass it to a fallback function:
+//!
+//! ```ignore
+//! // `mytool --mcp` serves `mytool <subcommand> ...` invocations
+//! McpServer::<Commands>::new().with_missing_handler(MissingHandler::Exec);
+//! // {"tool": "add", "argv": ["add", "--a", "1", "--b", "2"], "dry_run": true}
+//! McpServer::<Commands>::new().with_missing_handler(MissingHandler::DryRun);
+//! ```
+
+use crate::context::{COLOR_FORCING_ENV, PLAIN_OUTPUT_ENV};
+use crate::encoding::OutputEncoding;
+use crate::messages::{Message, Messages};
+use crate::output::{ToolError, ToolOutput};
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Time a program may run when no timeout is configured
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Function taking the tool name and command line of calls no handler runs
+pub type FallbackHandler =
+    dyn Fn(&str, &[String]) -> Result<ToolOutput, ToolError> + Send + Sync + 'static;
+
+/// What calls do on a server without a handler or exec program
+#[derive(Clone, Default)]
+pub enum MissingHandler {
+    /// Fail with an error saying no handler was provided
+    #[default]
+    Error,
+    /// Run the current executable with the call's command line, as
+    /// `McpServer::with_exec` would
+    Exec,
+    /// Return the tool name and command line as JSON, without running anything
+    DryRun,
+    /// Pass the tool name and command line to a function
+    Fallback(Arc<FallbackHandler>),
+}
+
+impl MissingHandler {
+    /// Hand calls to `fallback`
+    pub fn fallback(
+        fallback: impl Fn(&str, &[String]) -> Result<ToolOutput, ToolError> + Send + Sync + 'static,
+    ) -> Self {
+        Self::Fallback(Arc::new(fallback))
+    }
+}
+
+/// The program tool calls run as
+#[derive(Debug, Clone)]
+pub(crate) struct Exec {
+    program: PathBuf,
+    pub(crate) timeout: Duration,
+    /// How the program's stdout and stderr are decoded
+    pub(crate) encoding: OutputEncoding,
+}
+
+/// How a run ended
+#[derive(Debug)]
+enum Exit {
+    Status(ExitStatus),
+    TimedOut,
+}
+
+/// Captured output of one run
+#[derive(Debug)]
+pub(crate) struct Execution {
+    stdout: String,
+    stderr: String,
+    exit: Exit,
+}
+
+impl Exec {
+    pub(crate) fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            timeout: DEFAULT_TIMEOUT,
+            encoding: OutputEncoding::default(),
+        }
+    }
+
+    pub(crate) fn program(&self) -> String {
+        self.program.display().to_string()
+    }
+
+    /// Run the program with `args`, killing it once the timeout elapses
+    pub(crate) fn run(&self, args: &[String]) -> std::io::Result<Execution> {
+        let mut command = Command::new(&self.program);
+        for var in COLOR_FORCING_ENV {
+            command.env_remove(var);
+        }
+        let mut child = command
+            .args(args)
+            .envs(PLAIN_OUTPUT_ENV.iter().copied())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // Drain both pipes while waiting so a chatty program cannot block
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+
+        let deadline = Instant::now() + self.timeout;
+        let exit = loop {
+            if let Some(status) = child.try_wait()? {
+                break Exit::Status(status);
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                child.wait()?;
+                break Exit::TimedOut;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+
+        Ok(Execution {
+            stdout: self.encoding.decode(&stdout.join().unwrap_or_default()),
+            stderr: self.encoding.decode(&stderr.join().unwrap_or_default()),
+            exit,
+        })
+    }
+}
+
+impl Execution {
+    /// Whether the program exited with status 0
+    pub(crate) fn success(&self) -> bool {
+        matches!(&self.exit, Exit::Status(status) if status.success())
+    }
+
+    /// Stdout, and the stderr and exit status blocks that follow it
+    pub(crate) fn into_outputs(
+        self,
+        timeout: Duration,
+        messages: &Messages,
+    ) -> (String, ToolOutput) {
+        let mut trailer = Vec::new();
+        if !self.stderr.is_empty() {
+            trailer.push(ToolOutput::Text(format!("stderr:\n{}", self.stderr)));
+        }
+        trailer.push(ToolOutput::Text(match self.exit {
+            Exit::Status(status) => status.to_string(),
+            Exit::TimedOut => messages.render(&Message::ExecTimedOut {
+                timeout_secs: timeout.as_secs(),
+            }),
+        }));
+        (self.stdout, ToolOutput::Mixed(trailer))
+    }
+}
+
+fn read_in_background(
+    pipe: Option<impl Read + Send + 'static>,
+) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str, timeout: Duration) -> Execution {
+        let mut exec = Exec::new("sh");
+        exec.timeout = timeout;
+        exec.run(&["-c".to_string(), script.to_string()]).unwrap()
+    }
+
+    #[test]
+    fn test_capture_output_and_status() {
+        let execution = sh("echo out; echo err >&2; exit 3", DEFAULT_TIMEOUT);
+        assert!(!execution.success());
+        assert_eq!(execution.stdout, "out\n");
+        assert_eq!(execution.stderr, "err\n");
+        assert!(matches!(execution.exit, Exit::Status(status) if status.code() == Some(3)));
+
+        assert!(sh("true", DEFAULT_TIMEOUT).success());
+        assert!(Exec::new("/nonexistent/clap-mcp-test").run(&[]).is_err());
+    }
+
+    #[test]
+    fn test_output_encoding() {
+        // "hé" in UTF-16LE, with and without a byte order mark
+        let script = |bom: &str| format!("printf '{}h\\000\\351\\000'", bom);
+        let execution = sh(&script("\\377\\376"), DEFAULT_TIMEOUT);
+        assert_eq!(execution.stdout, "hé");
+
+        let mut exec = Exec::new("sh");
+        exec.encoding = OutputEncoding::Utf16Le;
+        let execution = exec.run(&["-c".to_string(), script("")]).unwrap();
+        assert_eq!(execution.stdout, "hé");
+    }
+
+    #[test]
+    fn test_plain_output_env() {
+        let execution = sh(
+            "echo $CLAP_MCP $NO_COLOR $CLICOLOR $TERM ${CLICOLOR_FORCE:-unset}",
+            DEFAULT_TIMEOUT,
+        );
+        assert_eq!(execution.stdout, "1 1 0 dumb unset\n");
+    }
+
+    #[test]
+    fn test_timeout_kills_program() {
+        let started = Instant::now();
+        let execution = sh("echo started; exec sleep 10", Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(matches!(execution.exit, Exit::TimedOut));
+        assert!(!execution.success());
+        assert_eq!(execution.stdout, "started\n");
+
+        let (_, trailer) = execution.into_outputs(Duration::from_secs(30), &Messages::default());
+        let ToolOutput::Mixed(blocks) = trailer else {
+            panic!("expected mixed output");
+        };
+        assert!(matches!(
+            &blocks[..],
+            [ToolOutput::Text(text)] if text == "Command timed out after 30 seconds and was killed"
+        ));
+    }
+}
+
+#[cfg(all(test, windows))]
+mod windows_tests {
+    use super::*;
+    use crate::argv::{build_argv, format_command_line};
+    use clap::{Arg, ArgAction, Command};
+    use serde_json::json;
+
+    #[test]
+    fn test_paths_reach_the_program_verbatim() {
+        let cmd = Command::new("copy")
+            .arg(Arg::new("source").required(true))
+            .arg(Arg::new("dest").required(true))
+            .arg(
+                Arg::new("dry_run")
+                    .long("dry-run")
+                    .action(ArgAction::SetTrue),
+            );
+        let argv = build_argv(
+            Some(&cmd),
+            json!({
+                "source": r"C:\Program Files\x",
+                "dest": r"\\server\share\f",
+                "dry_run": true,
+            })
+            .as_object()
+            .cloned()
+            .unwrap(),
+            None,
+        );
+        assert_eq!(
+            argv,
+            ["--dry-run", r"C:\Program Files\x", r"\\server\share\f"]
+        );
+
+        // `echo` prints its command line as cmd.exe received it
+        let mut args = vec!["/c".to_string(), "echo".to_string()];
+        args.extend(argv.iter().cloned());
+        let execution = Exec::new("cmd").run(&args).unwrap();
+        assert!(execution.success());
+        assert_eq!(
+            execution.stdout.trim_end(),
+            r#"--dry-run "C:\Program Files\x" \\server\share\f"#
+        );
+        assert_eq!(execution.stdout.trim_end(), format_command_line(&argv));
+    }
+
+    #[test]
+    fn test_trailing_backslashes_are_quoted_like_the_runtime() {
+        let argv = vec![r"\\server\share\out dir\".to_string()];
+        let mut args = vec!["/c".to_string(), "echo".to_string()];
+        args.extend(argv.iter().cloned());
+        let execution = Exec::new("cmd").run(&args).unwrap();
+        assert_eq!(execution.stdout.trim_end(), r#""\\server\share\out dir\\""#);
+        assert_eq!(execution.stdout.trim_end(), format_command_line(&argv));
+    }
+}
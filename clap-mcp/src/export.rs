@@ -0,0 +1,243 @@
+//! Export of the tool catalog as a JSON Schema or OpenAPI document
+//!
+//! What an LLM sees of a server is its tool list. [`document`] turns that list
+//! into a single JSON document, to review, diff in CI, or hand to
+//! integrations that do not speak MCP:
+//!
+//! - [`SchemaFormat::JsonSchema`]: a JSON Schema of `tools/call` params, with
+//!   each tool's input schema under `$defs`
+//! - [`SchemaFormat::OpenApi`]: an OpenAPI 3.1 document with a
+//!   `POST /tools/{name}` operation per tool taking its arguments as the
+//!   request body
+//!
+//! `McpServer::export_schema` builds it from a server's settings. `McpMode`
+//! parsers marked `#[mcp(export_schema)]` get a `handle_mcp_schema` function
+//! printing it for `--mcp-schema` (or `--mcp-schema=openapi`):
+//!
+//! ```ignore
+//! if let Some(result) = Cli::handle_mcp_schema() {
+//!     return result;
+//! }
+//! ```
+//!
+//! Tools keep the order they are listed in, so exports of the same CLI are
+//! identical and diff cleanly.
+
+use rmcp::model::{Implementation, Tool};
+use serde_json::{json, Map, Value};
+use std::ffi::OsString;
+use std::str::FromStr;
+
+/// Flag printing the tool catalog
+pub const SCHEMA_FLAG: &str = "--mcp-schema";
+
+/// Shape of an exported catalog
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaFormat {
+    #[default]
+    JsonSchema,
+    OpenApi,
+}
+
+impl FromStr for SchemaFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json-schema" | "jsonschema" => Ok(Self::JsonSchema),
+            "openapi" => Ok(Self::OpenApi),
+            other => Err(format!(
+                "unknown schema format `{}`, expected `json-schema` or `openapi`",
+                other
+            )),
+        }
+    }
+}
+
+/// The catalog of `server`'s `tools` in `format`
+pub fn document(format: SchemaFormat, server: &Implementation, tools: &[Tool]) -> Value {
+    match format {
+        SchemaFormat::JsonSchema => json_schema(server, tools),
+        SchemaFormat::OpenApi => openapi(server, tools),
+    }
+}
+
+/// A tool's input schema with its description and annotations
+fn tool_schema(tool: &Tool) -> Value {
+    let mut schema = (*tool.input_schema).clone();
+    if let Some(description) = &tool.description {
+        schema.insert("description".to_string(), json!(description));
+    }
+    if let Some(annotations) = &tool.annotations {
+        schema.insert("x-mcp-annotations".to_string(), json!(annotations));
+    }
+    Value::Object(schema)
+}
+
+fn json_schema(server: &Implementation, tools: &[Tool]) -> Value {
+    let mut defs = Map::new();
+    let calls: Vec<Value> = tools
+        .iter()
+        .map(|tool| {
+            defs.insert(tool.name.to_string(), tool_schema(tool));
+            json!({
+                "type": "object",
+                "properties": {
+                    "name": { "const": tool.name },
+                    "arguments": { "$ref": format!("#/$defs/{}", tool.name) }
+                },
+                "required": ["name"]
+            })
+        })
+        .collect();
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": format!("{} tool calls", server.name),
+        "x-server": { "name": server.name, "version": server.version },
+        "oneOf": calls,
+        "$defs": defs
+    })
+}
+
+fn openapi(server: &Implementation, tools: &[Tool]) -> Value {
+    let mut paths = Map::new();
+    for tool in tools {
+        let mut operation = json!({
+            "operationId": tool.name,
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/json": { "schema": Value::Object((*tool.input_schema).clone()) }
+                }
+            },
+            "responses": {
+                "200": {
+                    "description": "The tool's result",
+                    "content": { "application/json": { "schema": { "type": "object" } } }
+                }
+            }
+        });
+        if let Some(description) = &tool.description {
+            operation["description"] = json!(description);
+        }
+        if let Some(annotations) = &tool.annotations {
+            operation["x-mcp-annotations"] = json!(annotations);
+        }
+        paths.insert(
+            format!("/tools/{}", tool.name),
+            json!({ "post": operation }),
+        );
+    }
+    json!({
+        "openapi": "3.1.0",
+        "info": { "title": server.name, "version": server.version },
+        "paths": paths
+    })
+}
+
+/// Print the catalog if `--mcp-schema` is present
+///
+/// `args` are the full process arguments including the binary name. Returns
+/// `None` when the flag is absent, so regular parsing can go on.
+pub fn handle_from_args(
+    args: impl IntoIterator<Item = impl Into<OsString>>,
+    export: impl FnOnce(SchemaFormat) -> Result<Value, Box<dyn std::error::Error>>,
+) -> Option<Result<(), Box<dyn std::error::Error>>> {
+    let format = schema_format(args.into_iter().map(Into::into))?;
+    let run = || -> Result<(), Box<dyn std::error::Error>> {
+        let document = export(format?)?;
+        println!("{}", serde_json::to_string_pretty(&document)?);
+        Ok(())
+    };
+    Some(run())
+}
+
+/// The format asked for with `--mcp-schema[=format]`
+fn schema_format(mut args: impl Iterator<Item = OsString>) -> Option<Result<SchemaFormat, String>> {
+    args.next();
+    args.find_map(|arg| {
+        let arg = arg.to_str()?;
+        if arg == SCHEMA_FLAG {
+            return Some(Ok(SchemaFormat::default()));
+        }
+        let format = arg.strip_prefix(SCHEMA_FLAG)?.strip_prefix('=')?;
+        Some(format.parse())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::ToolAnnotations;
+    use std::sync::Arc;
+
+    fn tools() -> Vec<Tool> {
+        let schema = json!({
+            "type": "object",
+            "properties": { "env": { "type": "string" } },
+            "required": ["env"]
+        });
+        let mut deploy = Tool::new(
+            "deploy",
+            "Deploy the app",
+            Arc::new(schema.as_object().unwrap().clone()),
+        );
+        deploy.annotations = Some(ToolAnnotations::new().destructive(true));
+        vec![deploy]
+    }
+
+    fn server() -> Implementation {
+        Implementation {
+            name: "mytool".to_string(),
+            version: "1.2.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let doc = document(SchemaFormat::JsonSchema, &server(), &tools());
+        assert_eq!(doc["oneOf"][0]["properties"]["name"]["const"], "deploy");
+        assert_eq!(
+            doc["oneOf"][0]["properties"]["arguments"]["$ref"],
+            "#/$defs/deploy"
+        );
+        let deploy = &doc["$defs"]["deploy"];
+        assert_eq!(deploy["required"], json!(["env"]));
+        assert_eq!(deploy["description"], "Deploy the app");
+        assert_eq!(deploy["x-mcp-annotations"]["destructiveHint"], true);
+        assert_eq!(doc["x-server"]["version"], "1.2.0");
+    }
+
+    #[test]
+    fn test_openapi() {
+        let doc = document(SchemaFormat::OpenApi, &server(), &tools());
+        assert_eq!(doc["info"]["title"], "mytool");
+        let deploy = &doc["paths"]["/tools/deploy"]["post"];
+        assert_eq!(deploy["operationId"], "deploy");
+        assert_eq!(
+            deploy["requestBody"]["content"]["application/json"]["schema"]["properties"]["env"]
+                ["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn test_schema_flag() {
+        let format = |args: &[&str]| schema_format(args.iter().map(OsString::from));
+        assert_eq!(format(&["tool", "add"]), None);
+        assert_eq!(
+            format(&["tool", "--mcp-schema"]),
+            Some(Ok(SchemaFormat::JsonSchema))
+        );
+        assert_eq!(
+            format(&["tool", "--mcp-schema=openapi"]),
+            Some(Ok(SchemaFormat::OpenApi))
+        );
+        assert!(matches!(
+            format(&["tool", "--mcp-schema=yaml"]),
+            Some(Err(_))
+        ));
+        // The binary name is not a flag
+        assert_eq!(format(&["--mcp-schema"]), None);
+    }
+}
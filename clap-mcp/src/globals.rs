@@ -0,0 +1,133 @@
+//! Top-level CLI options passed to every tool call
+//!
+//! Options on the parent `Parser` (e.g. `--config`, `--verbose`) belong to no
+//! subcommand, so tools would run without them. Move them into an `Args`
+//! struct flattened into the parser and register it; every tool then accepts
+//! them in a `global` object, and handlers read the parsed values:
+//!
+//! ```ignore
+//! #[derive(Args, Clone)]
+//! struct GlobalOpts {
+//!     #[arg(long, global = true)]
+//!     config: Option<PathBuf>,
+//!     #[arg(short, long, global = true)]
+//!     verbose: bool,
+//! }
+//!
+//! McpServer::<Commands>::new().with_global_args::<GlobalOpts>();
+//!
+//! fn execute(cmd: Commands) -> Result<String, String> {
+//!     let globals: GlobalOpts = clap_mcp::context::global_args().unwrap();
+//!     // ...
+//! }
+//! ```
+//!
+//! ```json
+//! {"name": "deploy", "arguments": {"target": "prod", "global": {"config": "ci.toml"}}}
+//! ```
+//!
+//! Options the call omits take their clap defaults, not the values the server
+//! was started with. `McpMode` registers a `#[command(flatten)]` field marked
+//! `#[mcp(global)]` automatically.
+
+use clap::{ArgMatches, Args, Command};
+use serde_json::{json, Value};
+use std::any::Any;
+use std::sync::Arc;
+
+/// Argument key carrying the global options of a call
+pub const GLOBAL_KEY: &str = "global";
+
+/// Parsed global options, as handed to the handler
+pub(crate) type GlobalValues = Arc<dyn Any + Send + Sync>;
+
+type Parser = dyn Fn(&ArgMatches) -> Result<GlobalValues, clap::Error> + Send + Sync;
+
+/// The global options registered with `McpServer::with_global_args`
+#[derive(Clone)]
+pub(crate) struct GlobalArgs {
+    command: Command,
+    parse: Arc<Parser>,
+}
+
+impl GlobalArgs {
+    pub(crate) fn new<G: Args + Send + Sync + 'static>() -> Self {
+        let mut command = G::augment_args(Command::new(GLOBAL_KEY));
+        command.build();
+        Self {
+            command,
+            parse: Arc::new(|matches| Ok(Arc::new(G::from_arg_matches(matches)?) as GlobalValues)),
+        }
+    }
+
+    /// Schema of the `global` argument listed for every tool
+    pub(crate) fn schema(&self) -> Value {
+        let (properties, required) = crate::schema::command_properties(&self.command, None);
+        json!({
+            "type": "object",
+            "description": "Options of the top-level command, applying to every tool",
+            "properties": properties,
+            "required": required,
+        })
+    }
+
+    /// Parse the `global` argument of a call; clap defaults fill what it omits
+    ///
+    /// Also returns the option tokens the values were parsed from.
+    pub(crate) fn parse(
+        &self,
+        global: Option<Value>,
+    ) -> Result<(GlobalValues, Vec<String>), String> {
+        let arguments = match global {
+            None => Default::default(),
+            Some(Value::Object(arguments)) => arguments,
+            Some(_) => return Err(format!("`{}` must be an object", GLOBAL_KEY)),
+        };
+        let argv = crate::argv::build_argv(Some(&self.command), arguments, None);
+        let matches = self
+            .command
+            .clone()
+            .try_get_matches_from(
+                std::iter::once(GLOBAL_KEY).chain(argv.iter().map(String::as_str)),
+            )
+            .map_err(|e| e.to_string())?;
+        let values = (self.parse)(&matches).map_err(|e| e.to_string())?;
+        Ok((values, argv))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(clap::Args, Clone, Debug, PartialEq)]
+    struct GlobalOpts {
+        /// Config file
+        #[arg(long, default_value = "app.toml")]
+        config: String,
+        #[arg(short, long)]
+        verbose: bool,
+    }
+
+    #[test]
+    fn test_parse_global_args() {
+        let globals = GlobalArgs::new::<GlobalOpts>();
+        let schema = globals.schema();
+        assert_eq!(schema["properties"]["config"]["description"], "Config file");
+        assert_eq!(schema["properties"]["verbose"]["type"], "boolean");
+
+        let (parsed, argv) = globals.parse(Some(json!({ "verbose": true }))).unwrap();
+        assert_eq!(argv, ["--verbose"]);
+        assert_eq!(
+            parsed.downcast_ref::<GlobalOpts>(),
+            Some(&GlobalOpts {
+                config: "app.toml".to_string(),
+                verbose: true,
+            })
+        );
+        let (defaults, _) = globals.parse(None).unwrap();
+        assert!(!defaults.downcast_ref::<GlobalOpts>().unwrap().verbose);
+        assert!(globals.parse(Some(json!({ "unknown": 1 }))).is_err());
+        assert!(globals.parse(Some(json!("verbose"))).is_err());
+    }
+}
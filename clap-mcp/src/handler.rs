@@ -0,0 +1,2236 @@
+//! The MCP request handler behind [`McpServer`](crate::McpServer)
+
+use crate::argv::{self, ArgEncoders};
+use crate::artifacts::{self, Artifacts};
+use crate::authz::{AuthorizationRequest, ToolAuthorizer};
+use crate::bridge::Remotes;
+use crate::confirm::{Approver, Confirmations, CONFIRM_KEY};
+use crate::context::{CallContext, ClientFeatures, CORRELATION_ID_META_KEY};
+use crate::descriptions::{DescriptionLimits, HelpResources};
+use crate::exec::{Exec, MissingHandler};
+use crate::globals::{GlobalArgs, GlobalValues, GLOBAL_KEY};
+use crate::logging::LogSink;
+use crate::messages::{Message, Messages};
+use crate::metadata::{ServerMetadata, ToolAttributes};
+use crate::metering::{Meter, Outcome, ToolCall};
+use crate::output::{ToolError, ToolOutput};
+use crate::policy::{
+    BusyAction, ConcurrencyLimit, LockGuard, LockProvider, PolicyDecision, SerialLocks, ToolPolicy,
+};
+use crate::quota::{Quotas, Remaining};
+use crate::replay::{AuditEntry, Replay};
+use crate::resources::{LastResults, ResourceProvider};
+use crate::sanitize::OutputSanitizer;
+use crate::secrets::{SecretArgs, SecretStore};
+use crate::stats::UsageStats;
+use crate::streaming::Sink;
+use crate::support::{self, SupportMatrix, SupportedFeatures};
+use crate::tools::{RuntimeTool, ToolRegistry};
+use crate::validate;
+use crate::vars::{SessionStore, SessionVars};
+use crate::workspace::WorkspaceDefaults;
+use crate::{CapabilitiesHook, CommandHandler, StructuredHandler};
+use clap::Subcommand;
+use rmcp::{
+    handler::server::ServerHandler,
+    model::*,
+    service::{NotificationContext, RequestContext, RoleServer},
+    Error as McpError,
+};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+
+/// Settings shared by every session served by one [`McpServer`](crate::McpServer)
+#[derive(Clone, Default)]
+pub(crate) struct ServerOptions {
+    pub sanitizer: OutputSanitizer,
+    pub tool_attributes: HashMap<String, ToolAttributes>,
+    pub metadata: ServerMetadata,
+    /// Name advertised in `serverInfo`; `clap-mcp-server` when unset
+    pub name: Option<String>,
+    /// Version advertised in `serverInfo`; this crate's version when unset
+    pub version: Option<String>,
+    /// Usage guidance sent to clients when they initialize
+    pub instructions: Option<String>,
+    pub capability_hooks: Vec<Arc<CapabilitiesHook>>,
+    /// Custom argv encoders keyed by tool name
+    pub arg_encoders: HashMap<String, ArgEncoders>,
+    /// Tools generated by `#[derive(McpSchema)]`, replacing introspection
+    pub static_tools: Option<Vec<Tool>>,
+    pub usage_stats: Option<UsageStats>,
+    /// Whether to register the `capabilities` tool
+    pub support_matrix: bool,
+    /// How many tools `tools/list` returns at once; all of them when unset
+    pub page_size: Option<usize>,
+    /// Whether to register the `validate_call` tool
+    pub validate_tool: bool,
+    /// Whether calls may ask to be parsed but not run with `_parse_only`
+    pub parse_only: bool,
+    /// Whether the handler streams its output (see [`crate::streaming`])
+    pub streaming: bool,
+    /// Whether calls to destructive tools wait for the user's confirmation
+    /// (see [`crate::confirm`])
+    pub confirm_destructive: bool,
+    /// Asks the user to approve destructive calls, instead of a token
+    pub approver: Option<Arc<Approver>>,
+    pub secrets: SecretStore,
+    pub messages: Messages,
+    pub policy: Option<Arc<dyn ToolPolicy>>,
+    pub meter: Option<Arc<dyn Meter>>,
+    /// Metering weights keyed by tool name; 1 when unset
+    pub tool_costs: HashMap<String, u64>,
+    /// Top-level options accepted by every tool under `global`
+    pub globals: Option<GlobalArgs>,
+    pub quotas: Option<Quotas>,
+    /// Whether results end with the quota left after the call
+    pub quota_report: bool,
+    pub resources: Vec<Arc<dyn ResourceProvider>>,
+    /// Keeps each tool's last successful result for the caller that got it
+    pub last_results: Option<LastResults>,
+    /// Program tool calls run as, instead of the handler
+    pub exec: Option<Exec>,
+    /// What calls do without a handler or exec program
+    pub missing_handler: MissingHandler,
+    /// How long a call may run unless its tool sets its own timeout
+    pub timeout: Option<Duration>,
+    /// Whether to wrap tool listing and calls in `tracing` spans
+    pub tracing: bool,
+    /// Whether to offer the `logging` capability, sending handler log events
+    /// to clients
+    pub logging: bool,
+    /// How many calls may run at once
+    pub concurrency: Option<ConcurrencyLimit>,
+    /// What happens to calls beyond the concurrency limit
+    pub busy_action: BusyAction,
+    /// Locks of the argument values `serialize_by` tools run serially by
+    pub serial_locks: SerialLocks,
+    /// Holds those locks instead, e.g. shared by several servers
+    pub lock_provider: Option<Arc<dyn LockProvider>>,
+    /// Whether HTTP clients authenticate, so calls are attributed to their
+    /// token's principal and never to the one named in `_meta`
+    pub authenticated: bool,
+    /// Which principals may call which tools
+    pub authorizer: Option<Arc<dyn ToolAuthorizer>>,
+    /// Tools exposed to clients; every tool when unset
+    pub allowed_tools: Option<HashSet<String>>,
+    /// Tools hidden from clients, even if allowed
+    pub denied_tools: HashSet<String>,
+    /// Tools registered while the server runs
+    pub runtime_tools: ToolRegistry,
+    /// Keeps session state across reconnects, by the token calls carry
+    pub session_store: Option<Arc<dyn SessionStore>>,
+    /// Arguments defaulting to paths in the client's workspace
+    pub workspace_defaults: Option<WorkspaceDefaults>,
+    pub description_limits: Option<DescriptionLimits>,
+    /// Full help of the tools whose descriptions the limits shortened
+    pub help: HelpResources,
+    /// Where agent-generated files are kept, listed by `list_artifacts`
+    pub artifacts: Option<Artifacts>,
+}
+
+/// The rmcp [`ServerHandler`] exposing a clap `Subcommand` enum as MCP tools
+///
+/// [`McpServer`](crate::McpServer) builds and serves one of these. Build it
+/// directly to mount the tools in your own rmcp service composition, e.g. with
+/// a custom transport:
+///
+/// ```ignore
+/// let handler = ClapMcpHandler::<Commands>::new(Box::new(execute));
+/// rmcp::serve_server(handler, my_transport).await?.waiting().await?;
+/// ```
+pub struct ClapMcpHandler<T> {
+    handler: Option<Arc<StructuredHandler<T>>>,
+    options: Arc<ServerOptions>,
+    remotes: Arc<Remotes>,
+    vars: Arc<SessionVars>,
+    /// Least severe level of the log messages the session's client gets
+    log_level: Arc<Mutex<LoggingLevel>>,
+    /// Destructive calls of the session awaiting confirmation
+    confirmations: Arc<Confirmations>,
+    catalog: Arc<OnceLock<Catalog>>,
+    _phantom: PhantomData<T>,
+}
+
+/// Level of the log messages sent until the client sets one
+const DEFAULT_LOG_LEVEL: LoggingLevel = LoggingLevel::Info;
+
+/// The clap commands and tool list of a handler, built on first use and
+/// shared by its sessions
+///
+/// Augmenting the subcommand enum and inferring schemas walks every
+/// subcommand, which CLIs with hundreds of them should not pay per call.
+struct Catalog {
+    /// The subcommand enum under a root command, for name inference
+    command: clap::Command,
+    /// Every subcommand, built, in declaration order
+    subcommands: Vec<clap::Command>,
+    /// The local tools listed to clients
+    tools: Vec<Tool>,
+    /// Whether the CLI sets `infer_subcommands`, probed once
+    infers_subcommands: bool,
+}
+
+impl Catalog {
+    /// The built subcommand called `name`
+    fn subcommand(&self, name: &str) -> Option<&clap::Command> {
+        self.subcommands
+            .iter()
+            .find(|subcommand| subcommand.get_name() == name)
+    }
+}
+
+/// Each clone serves one session, so clones start without session variables
+/// or pending confirmations, and at the default log level
+impl<T> Clone for ClapMcpHandler<T> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+            options: self.options.clone(),
+            remotes: self.remotes.clone(),
+            vars: Arc::default(),
+            log_level: Arc::new(Mutex::new(DEFAULT_LOG_LEVEL)),
+            confirmations: Arc::default(),
+            catalog: self.catalog.clone(),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: Subcommand + 'static> ClapMcpHandler<T> {
+    /// Handler running `handler` for every tool call, with default settings
+    ///
+    /// Use [`McpServer::into_handler`](crate::McpServer::into_handler) to get a
+    /// handler carrying the options configured on the server builder.
+    pub fn new(handler: CommandHandler<T>) -> Self {
+        Self::structured(crate::structured(handler))
+    }
+
+    /// Like [`ClapMcpHandler::new`], for a handler returning typed content
+    pub fn structured(handler: StructuredHandler<T>) -> Self {
+        Self::with_options(Some(handler), ServerOptions::default())
+    }
+}
+
+impl<T: Subcommand> ClapMcpHandler<T> {
+    pub(crate) fn with_options(
+        handler: Option<StructuredHandler<T>>,
+        options: ServerOptions,
+    ) -> Self {
+        Self {
+            handler: handler.map(Arc::new),
+            options: Arc::new(options),
+            remotes: Arc::new(Remotes::default()),
+            vars: Arc::default(),
+            log_level: Arc::new(Mutex::new(DEFAULT_LOG_LEVEL)),
+            confirmations: Arc::default(),
+            catalog: Arc::default(),
+            _phantom: PhantomData,
+        }
+    }
+
+    pub(crate) fn with_remotes(mut self, remotes: Remotes) -> Self {
+        self.remotes = Arc::new(remotes);
+        self
+    }
+}
+
+impl<T: Subcommand> ClapMcpHandler<T> {
+    /// The cached commands and tools, built on first use
+    fn catalog(&self) -> &Catalog {
+        self.catalog.get_or_init(|| {
+            let command = T::augment_subcommands(clap::Command::new("mcp"));
+            let subcommands: Vec<_> = command
+                .get_subcommands()
+                .map(|subcommand| {
+                    // Building fills in the defaults (e.g. how many values
+                    // each argument takes) that the schemas are inferred from
+                    let mut subcommand = subcommand.clone();
+                    subcommand.build();
+                    subcommand
+                })
+                .collect();
+
+            let mut tools = match &self.options.static_tools {
+                Some(tools) => tools
+                    .iter()
+                    .cloned()
+                    .map(|mut tool| {
+                        let attrs = self.options.tool_attributes.get(tool.name.as_ref());
+                        tool.annotations = attrs.and_then(ToolAttributes::annotations);
+                        let mut input_schema = (*tool.input_schema).clone();
+                        crate::schema::apply_env_fallbacks(&mut input_schema);
+                        if attrs.is_some_and(|attrs| attrs.interpolate) {
+                            // Schemas of tuple variants come from their Args
+                            // struct, which knows nothing of `_vars`
+                            if let Some(serde_json::Value::Object(properties)) =
+                                input_schema.get_mut("properties")
+                            {
+                                properties
+                                    .entry(crate::vars::VARS_KEY)
+                                    .or_insert_with(crate::vars::vars_schema);
+                            }
+                        }
+                        tool.input_schema = Arc::new(input_schema);
+                        tool
+                    })
+                    .collect(),
+                None => self.subcommand_tools(&subcommands),
+            };
+            // Constraints between arguments come from clap, for static
+            // schemas too
+            for tool in &mut tools {
+                let Some(subcommand) = subcommands
+                    .iter()
+                    .find(|subcommand| subcommand.get_name() == tool.name)
+                else {
+                    continue;
+                };
+                let attrs = self.options.tool_attributes.get(tool.name.as_ref());
+                let constraints = crate::schema::command_constraints(subcommand, attrs);
+                if !constraints.is_empty() {
+                    let mut input_schema = (*tool.input_schema).clone();
+                    input_schema.extend(constraints);
+                    tool.input_schema = Arc::new(input_schema);
+                }
+            }
+            if self.options.parse_only {
+                for tool in &mut tools {
+                    let mut input_schema = (*tool.input_schema).clone();
+                    if let Some(serde_json::Value::Object(properties)) =
+                        input_schema.get_mut("properties")
+                    {
+                        properties.insert(
+                            validate::PARSE_ONLY_KEY.to_string(),
+                            validate::parse_only_schema(),
+                        );
+                    }
+                    tool.input_schema = Arc::new(input_schema);
+                }
+            }
+            if self.options.confirm_destructive && self.options.approver.is_none() {
+                for tool in &mut tools {
+                    if !self.needs_confirmation(&tool.name) {
+                        continue;
+                    }
+                    let mut input_schema = (*tool.input_schema).clone();
+                    if let Some(serde_json::Value::Object(properties)) =
+                        input_schema.get_mut("properties")
+                    {
+                        properties
+                            .insert(CONFIRM_KEY.to_string(), crate::confirm::confirm_schema());
+                    }
+                    tool.input_schema = Arc::new(input_schema);
+                }
+            }
+            for tool in &mut tools {
+                if let Some(attrs) = self.options.tool_attributes.get(tool.name.as_ref()) {
+                    let mut input_schema = (*tool.input_schema).clone();
+                    attrs.rename_properties(&mut input_schema);
+                    tool.input_schema = Arc::new(input_schema);
+                }
+            }
+            if let Some(globals) = &self.options.globals {
+                let schema = globals.schema();
+                for tool in &mut tools {
+                    let mut input_schema = (*tool.input_schema).clone();
+                    if let Some(serde_json::Value::Object(properties)) =
+                        input_schema.get_mut("properties")
+                    {
+                        properties.insert(GLOBAL_KEY.to_string(), schema.clone());
+                    }
+                    tool.input_schema = Arc::new(input_schema);
+                }
+            }
+            if let Some(limits) = &self.options.description_limits {
+                let affected = limits.apply(&mut tools, &self.options.help);
+                if !affected.is_empty() {
+                    tracing::warn!(
+                        "shortened the descriptions of {} tools to the limits: {}",
+                        affected.len(),
+                        affected.join(", ")
+                    );
+                }
+            }
+
+            Catalog {
+                infers_subcommands: infers_subcommands(&command),
+                command,
+                subcommands,
+                tools,
+            }
+        })
+    }
+
+    /// The local tools listed to clients
+    pub(crate) fn tools(&self) -> &[Tool] {
+        &self.catalog().tools
+    }
+
+    /// Tools inferred from the subcommands by clap introspection
+    fn subcommand_tools(&self, subcommands: &[clap::Command]) -> Vec<Tool> {
+        let mut tools = Vec::new();
+
+        for subcommand in subcommands {
+            let name = subcommand.get_name().to_string();
+            let description = subcommand
+                .get_about()
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+
+            let attrs = self.options.tool_attributes.get(&name);
+            if attrs.is_some_and(|attrs| attrs.skip) {
+                continue;
+            }
+            let (mut properties, required) = crate::schema::command_properties(subcommand, attrs);
+
+            if attrs.is_some_and(|attrs| attrs.interpolate) {
+                properties.insert(
+                    crate::vars::VARS_KEY.to_string(),
+                    crate::vars::vars_schema(),
+                );
+            }
+
+            let input_schema = json!({
+                "type": "object",
+                "properties": properties,
+                "required": required
+            });
+
+            tools.push(Tool {
+                name: name.into(),
+                description: Some(description.into()),
+                input_schema: Arc::new(object(input_schema)),
+                annotations: attrs.and_then(ToolAttributes::annotations),
+            });
+        }
+
+        tools
+    }
+
+    /// Optional features enabled on this handler
+    pub(crate) fn support_matrix(&self) -> SupportMatrix {
+        SupportMatrix::new(SupportedFeatures {
+            bridge: !self.remotes.is_empty(),
+            static_schemas: self.options.static_tools.is_some(),
+            usage_stats: self.options.usage_stats.is_some(),
+            resources: !self.options.resources.is_empty(),
+            streaming: self.options.streaming,
+            ..Default::default()
+        })
+    }
+
+    /// Protocol error for rejected arguments, in the configured language
+    ///
+    /// clap quotes rejected values, which may be resolved secrets, so they
+    /// are redacted.
+    fn invalid_params(&self, message: &Message, data: &Option<serde_json::Value>) -> McpError {
+        let text = self.options.messages.render(message);
+        McpError::invalid_params(self.options.secrets.redact(&text), data.clone())
+    }
+
+    /// Content blocks for a handler's output, sanitized and with secrets redacted
+    ///
+    /// Text of tools with an `output_mime` travels as an embedded resource so
+    /// the MIME type reaches the client alongside the text.
+    fn contents(
+        &self,
+        output: ToolOutput,
+        tool_name: &str,
+        output_mime: Option<&str>,
+    ) -> Vec<Content> {
+        let secrets = &self.options.secrets;
+        match output {
+            ToolOutput::Text(text) => {
+                let text = secrets.redact(&self.options.sanitizer.sanitize(&text));
+                vec![match output_mime {
+                    Some(mime_type) => Content::resource(ResourceContents::TextResourceContents {
+                        uri: format!("clap-mcp://tools/{}/output", tool_name),
+                        mime_type: Some(mime_type.to_string()),
+                        text,
+                    }),
+                    None => Content::text(text),
+                }]
+            }
+            ToolOutput::Json(value) => vec![Content::text(secrets.redact(&value.to_string()))],
+            ToolOutput::Image { data, mime_type } => vec![Content::image(data, mime_type)],
+            ToolOutput::Resource {
+                uri,
+                mime_type,
+                text,
+            } => vec![Content::resource(ResourceContents::TextResourceContents {
+                uri,
+                mime_type,
+                text: secrets.redact(&text),
+            })],
+            ToolOutput::Blob {
+                uri,
+                mime_type,
+                data,
+            } => vec![Content::resource(ResourceContents::BlobResourceContents {
+                uri,
+                mime_type,
+                blob: data,
+            })],
+            ToolOutput::Content(mut contents) => {
+                for content in &mut contents {
+                    if let RawContent::Text(text) = &mut content.raw {
+                        text.text = secrets.redact(&self.options.sanitizer.sanitize(&text.text));
+                    }
+                }
+                contents
+            }
+            ToolOutput::Mixed(outputs) => outputs
+                .into_iter()
+                .flat_map(|output| self.contents(output, tool_name, output_mime))
+                .collect(),
+        }
+    }
+
+    /// Input schema listed for the local tool `name`
+    fn input_schema(&self, name: &str) -> Option<Arc<JsonObject>> {
+        self.tools()
+            .iter()
+            .find(|tool| tool.name == name)
+            .map(|tool| tool.input_schema.clone())
+    }
+
+    /// Whether the subcommand `name` is marked `#[mcp(skip)]` or not exposed
+    fn is_skipped(&self, name: &str) -> bool {
+        !self.is_exposed(name)
+            || self
+                .options
+                .tool_attributes
+                .get(name)
+                .is_some_and(|attrs| attrs.skip)
+    }
+
+    /// The subcommand `name` calls, following clap aliases and, when the CLI
+    /// enables `infer_subcommands`, abbreviations
+    ///
+    /// Ambiguous abbreviations return the candidates, leaving out tools
+    /// clients may not see.
+    fn infer_tool_name(&self, name: &str) -> Result<String, Vec<String>> {
+        let catalog = self.catalog();
+        infer_tool_name(
+            &catalog.command,
+            name,
+            catalog.infers_subcommands,
+            &|candidate| !self.is_skipped(candidate),
+        )
+    }
+
+    /// Whether the allowed and denied tools let clients see and call `name`
+    fn is_exposed(&self, name: &str) -> bool {
+        let allowed = self.options.allowed_tools.as_ref();
+        !self.options.denied_tools.contains(name)
+            && allowed.is_none_or(|allowed| allowed.contains(name))
+    }
+
+    /// Whether calls run as programs, which enforce their own timeouts
+    fn runs_programs(&self) -> bool {
+        self.options.exec.is_some()
+            || (self.handler.is_none()
+                && matches!(self.options.missing_handler, MissingHandler::Exec))
+    }
+
+    /// Whether calls to the tool `name` wait for the user's confirmation
+    fn needs_confirmation(&self, name: &str) -> bool {
+        self.options.confirm_destructive
+            && self
+                .options
+                .tool_attributes
+                .get(name)
+                .is_some_and(|attrs| attrs.destructive)
+    }
+
+    /// Whether `name` is the `validate_call` tool rather than a subcommand
+    fn is_validate_tool(&self, cmd: &clap::Command, name: &str) -> bool {
+        self.options.validate_tool
+            && name == validate::VALIDATE_TOOL
+            && cmd.find_subcommand(name).is_none()
+    }
+
+    /// Whether `name` is the `capabilities` tool rather than a subcommand
+    fn is_support_tool(&self, cmd: &clap::Command, name: &str) -> bool {
+        self.options.support_matrix
+            && name == support::SUPPORT_TOOL
+            && cmd.find_subcommand(name).is_none()
+    }
+
+    /// Whether `name` is the `list_artifacts` tool rather than a subcommand
+    fn is_artifacts_tool(&self, cmd: &clap::Command, name: &str) -> bool {
+        self.options.artifacts.is_some()
+            && name == artifacts::LIST_ARTIFACTS_TOOL
+            && cmd.find_subcommand(name).is_none()
+    }
+}
+
+/// A local tool call parsed into its subcommand, ready to run
+struct ParsedCall<T> {
+    tool_name: String,
+    subcommand: T,
+    global_args: Option<GlobalValues>,
+    /// Global options, subcommand and arguments, as passed to an exec program
+    args: Vec<String>,
+    secret_args: SecretArgs,
+    serial_key: Option<String>,
+    /// Receives the handler's log events, when the client gets them
+    log_sink: Option<LogSink>,
+    /// Receives the output of streaming handlers
+    stream: Option<Sink>,
+}
+
+/// A local tool call turned into the command line clap will parse
+struct PreparedCall {
+    tool_name: String,
+    /// Program name, subcommand and argument tokens
+    argv: Vec<String>,
+    global_args: Option<GlobalValues>,
+    /// Tokens of the global options, which clap parses separately
+    global_argv: Vec<String>,
+    /// Values of `#[mcp(secret)]` arguments, kept out of errors
+    secret_args: SecretArgs,
+    /// Lock key of the `serialize_by` argument, its id and JSON value, if
+    /// the tool has one
+    serial_key: Option<String>,
+}
+
+/// What an admitted call runs under: how long it may take, the client's
+/// cancellation, and the permits it holds
+struct Guard {
+    timeout: Option<Duration>,
+    ct: CancellationToken,
+    /// Its concurrency slot and `serialize_by` lock
+    permits: (Option<OwnedSemaphorePermit>, Option<LockGuard>),
+    error_data: Option<serde_json::Value>,
+}
+
+impl<T: Subcommand + Send + Sync + 'static> ClapMcpHandler<T> {
+    /// Run a tool call against the local subcommands or a bridged server
+    async fn dispatch(
+        &self,
+        request: CallToolRequestParam,
+        correlation_id: String,
+        client: ClientFeatures,
+        context: &RequestContext<RoleServer>,
+        ran: &AtomicBool,
+    ) -> Result<CallToolResult, McpError> {
+        let ct = context.ct.clone();
+        let mut tool_name = request.name.to_string();
+        let error_data = Some(json!({ CORRELATION_ID_META_KEY: correlation_id }));
+        let renamed = self.renamed_tool(&tool_name).map(|new| {
+            let old = std::mem::replace(&mut tool_name, new.to_string());
+            Message::ToolRenamed {
+                old,
+                new: new.to_string(),
+            }
+        });
+
+        // An alias calls the subcommand it stands for, so every per-tool
+        // setting applies under the subcommand's name
+        let cmd = &self.catalog().command;
+        if let Some(subcommand) = cmd.find_subcommand(&tool_name) {
+            if self.is_skipped(subcommand.get_name()) {
+                let message = Message::UnknownTool { tool: tool_name };
+                return Err(self.invalid_params(&message, &error_data));
+            }
+            tool_name = subcommand.get_name().to_string();
+        }
+        if !self.is_exposed(&tool_name) {
+            let message = Message::UnknownTool { tool: tool_name };
+            return Err(self.invalid_params(&message, &error_data));
+        }
+
+        let mut arguments = request.arguments.unwrap_or_default();
+        self.vars
+            .take_from(&mut arguments)
+            .map_err(|e| self.invalid_params(&e, &error_data))?;
+        if let Some(defaults) = &self.options.workspace_defaults {
+            if !self.vars.has_workspace() {
+                let root = match client.roots {
+                    true => crate::workspace::first_root(&context.peer).await,
+                    false => None,
+                };
+                self.vars.set_workspace(defaults.resolve(root.as_deref()));
+            }
+        }
+
+        if self.is_support_tool(cmd, &tool_name) {
+            let matrix = serde_json::to_string(&self.support_matrix())
+                .map_err(|e| McpError::internal_error(e.to_string(), error_data.clone()))?;
+            return Ok(CallToolResult::success(vec![Content::text(matrix)]));
+        }
+        if self.is_validate_tool(cmd, &tool_name) {
+            return self.validate_call(arguments, &error_data);
+        }
+        if let Some(artifacts) = &self.options.artifacts {
+            if self.is_artifacts_tool(cmd, &tool_name) {
+                return self.list_artifacts(artifacts, &error_data);
+            }
+        }
+        if cmd.find_subcommand(&tool_name).is_none() {
+            if let Some(runtime_tool) = self.options.runtime_tools.get(&tool_name) {
+                crate::validate::validate_arguments(
+                    &runtime_tool.tool.input_schema,
+                    &arguments,
+                    &|_| false,
+                )
+                .map_err(|e| self.invalid_params(&e, &error_data))?;
+                let guard = Guard {
+                    timeout: self.tool_timeout(&tool_name),
+                    permits: self
+                        .admit(&tool_name, None, &correlation_id, &ct, &error_data)
+                        .await?,
+                    ct,
+                    error_data,
+                };
+                let log_id = correlation_id.clone();
+                ran.store(true, Ordering::Relaxed);
+                return self
+                    .run_guarded(
+                        tool_name,
+                        move |handler| {
+                            handler.run_runtime_tool(
+                                runtime_tool,
+                                arguments,
+                                correlation_id,
+                                client,
+                            )
+                        },
+                        &log_id,
+                        guard,
+                    )
+                    .await;
+            }
+            if self.remotes.owns(&tool_name) {
+                let guard = Guard {
+                    timeout: self.tool_timeout(&tool_name),
+                    permits: self
+                        .admit(&tool_name, None, &correlation_id, &ct, &error_data)
+                        .await?,
+                    ct,
+                    error_data,
+                };
+                ran.store(true, Ordering::Relaxed);
+                return self
+                    .run_bridged(tool_name, arguments, &correlation_id, guard)
+                    .await;
+            }
+        }
+
+        let parse_only = self.options.parse_only
+            && arguments.remove(validate::PARSE_ONLY_KEY) == Some(serde_json::Value::Bool(true));
+        let confirm_token =
+            match self.options.confirm_destructive && self.options.approver.is_none() {
+                true => arguments.remove(CONFIRM_KEY),
+                false => None,
+            };
+        let call = self.prepare_call(tool_name, arguments, &self.vars, &error_data)?;
+        tracing::trace!(
+            %correlation_id,
+            tool = %call.tool_name,
+            argv = ?self.redact_argv(&call),
+            "parsing tool call"
+        );
+        if parse_only {
+            return self.parse_only(call, &error_data);
+        }
+        let mut call = self.parse_prepared(call, &error_data)?;
+        if self.needs_confirmation(&call.tool_name) {
+            if let Some(approver) = &self.options.approver {
+                if !self.approve(approver.clone(), &call, &correlation_id).await {
+                    let message = Message::ConfirmationDenied {
+                        tool: call.tool_name.clone(),
+                        command: self.confirmation_command(&call).join(" "),
+                    };
+                    return Ok(CallToolResult::error(vec![Content::text(
+                        self.options.messages.render(&message),
+                    )]));
+                }
+            } else {
+                let token = confirm_token.as_ref().and_then(|token| token.as_str());
+                let confirmed = token.is_some_and(|token| {
+                    self.confirmations
+                        .redeem(token, &call.tool_name, &call.args)
+                });
+                if !confirmed {
+                    return Ok(self.confirmation_request(&call, &correlation_id));
+                }
+            }
+        }
+        let log_sink = self.options.logging.then(|| {
+            let level = *self.log_level.lock().unwrap();
+            LogSink::new(context.peer.clone(), level)
+        });
+        call.log_sink = log_sink.clone();
+        let stream = match context.meta.get_progress_token() {
+            Some(token) => Sink::new(context.peer.clone(), token),
+            None => Sink::default(),
+        };
+        call.stream = Some(stream.clone());
+        let tool_name = call.tool_name.clone();
+        let permits = self
+            .admit(
+                &tool_name,
+                call.serial_key.as_deref(),
+                &correlation_id,
+                &ct,
+                &error_data,
+            )
+            .await?;
+        let guard = Guard {
+            // Exec programs are killed at the timeout instead
+            timeout: self
+                .tool_timeout(&tool_name)
+                .filter(|_| !self.runs_programs()),
+            ct,
+            permits,
+            error_data: error_data.clone(),
+        };
+        let run_id = correlation_id.clone();
+        ran.store(true, Ordering::Relaxed);
+        let result = self
+            .run_guarded(
+                tool_name.clone(),
+                move |handler| handler.run(call, run_id, client),
+                &correlation_id,
+                guard,
+            )
+            .await;
+        // Handlers given up on stop streaming
+        stream.close();
+        let mut result = result?;
+        // The client gets the call's log messages and progress before its
+        // result
+        if let Some(log_sink) = log_sink {
+            log_sink.flush().await;
+        }
+        stream.flush().await;
+        if let Some(renamed) = renamed {
+            tracing::warn!(%correlation_id, "{}", renamed);
+            let warning = self.options.messages.render(&renamed);
+            result.content.push(Content::text(warning));
+        }
+        if let Some(last_results) = &self.options.last_results {
+            last_results.record(&self.results_owner(context), &tool_name, &result);
+        }
+        Ok(result)
+    }
+
+    /// Answer a `list_artifacts` call, after cleaning up
+    fn list_artifacts(
+        &self,
+        artifacts: &Artifacts,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<CallToolResult, McpError> {
+        let report = artifacts.cleanup().and_then(|_| artifacts.report());
+        let report = report.map_err(|e| {
+            McpError::internal_error(
+                format!(
+                    "failed to list artifacts in {}: {}",
+                    artifacts.dir().display(),
+                    e
+                ),
+                error_data.clone(),
+            )
+        })?;
+        Ok(CallToolResult::success(vec![Content::text(
+            report.to_string(),
+        )]))
+    }
+
+    /// The answer holding a destructive call back until the user confirms
+    /// the command it would run
+    fn confirmation_request(&self, call: &ParsedCall<T>, correlation_id: &str) -> CallToolResult {
+        let token = self.confirmations.issue(&call.tool_name, &call.args);
+        tracing::info!(%correlation_id, tool = %call.tool_name, "awaiting confirmation");
+        let message = Message::ConfirmationRequired {
+            tool: call.tool_name.clone(),
+            command: self.confirmation_command(call).join(" "),
+            token,
+        };
+        CallToolResult::error(vec![Content::text(self.options.messages.render(&message))])
+    }
+
+    /// Ask `approver` whether the destructive call may run
+    ///
+    /// It may wait for the user, so it runs off the async runtime; one that
+    /// panics approves nothing.
+    async fn approve(
+        &self,
+        approver: Arc<Approver>,
+        call: &ParsedCall<T>,
+        correlation_id: &str,
+    ) -> bool {
+        let tool = call.tool_name.clone();
+        let command = self.confirmation_command(call);
+        tracing::info!(%correlation_id, %tool, "asking for approval");
+        #[cfg(target_family = "wasm")]
+        {
+            approver(&tool, &command)
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        {
+            tokio::task::spawn_blocking(move || approver(&tool, &command))
+                .await
+                .unwrap_or(false)
+        }
+    }
+
+    /// The command line a destructive call would run, as the user sees it
+    fn confirmation_command(&self, call: &ParsedCall<T>) -> Vec<String> {
+        call.args
+            .iter()
+            .map(|arg| call.secret_args.redact(&self.options.secrets.redact(arg)))
+            .collect()
+    }
+
+    /// Current name of the tool formerly called `name`, if it was renamed
+    ///
+    /// A subcommand actually named `name` takes precedence.
+    fn renamed_tool(&self, name: &str) -> Option<&str> {
+        if self.catalog().command.find_subcommand(name).is_some() {
+            return None;
+        }
+        self.options
+            .tool_attributes
+            .values()
+            .find(|attrs| attrs.renamed_from.contains(name))
+            .map(|attrs| attrs.name.as_str())
+    }
+
+    /// How long a call to `tool_name` may run
+    fn tool_timeout(&self, tool_name: &str) -> Option<Duration> {
+        self.options
+            .tool_attributes
+            .get(tool_name)
+            .and_then(|attrs| attrs.timeout)
+            .or(self.options.timeout)
+    }
+
+    /// Let a call to `tool_name` through the policy, then wait for its
+    /// `serialize_by` lock and a concurrency slot
+    ///
+    /// The lock is waited on first, so queued calls do not take up slots.
+    async fn admit(
+        &self,
+        tool_name: &str,
+        serial_key: Option<&str>,
+        correlation_id: &str,
+        ct: &CancellationToken,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<(Option<OwnedSemaphorePermit>, Option<LockGuard>), McpError> {
+        if let Some(policy) = &self.options.policy {
+            self.apply_policy(policy.as_ref(), tool_name, correlation_id, ct)
+                .await?;
+        }
+        let serial = match serial_key {
+            Some(key) => Some(self.acquire_serial(key, tool_name, ct, error_data).await?),
+            None => None,
+        };
+        let slot = match &self.options.concurrency {
+            Some(limit) => Some(self.acquire_slot(limit, tool_name, ct, error_data).await?),
+            None => None,
+        };
+        Ok((slot, serial))
+    }
+
+    /// Wait for a slot under the concurrency limit, or fail the call when
+    /// the server is busy
+    ///
+    /// The error data carries the `limit`.
+    async fn acquire_slot(
+        &self,
+        limit: &ConcurrencyLimit,
+        tool_name: &str,
+        ct: &CancellationToken,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<OwnedSemaphorePermit, McpError> {
+        let message = tokio::select! {
+            slot = limit.acquire(self.options.busy_action) => match slot {
+                Some(slot) => return Ok(slot),
+                None => Message::ServerBusy { limit: limit.limit },
+            },
+            _ = ct.cancelled() => Message::ToolCancelled {
+                tool: tool_name.to_string(),
+            },
+        };
+        let mut data = error_data.clone().unwrap_or_else(|| json!({}));
+        data["limit"] = json!(limit.limit);
+        Err(McpError::invalid_request(
+            self.options.messages.render(&message),
+            Some(data),
+        ))
+    }
+
+    /// Wait for the calls giving the same value to the tool's `serialize_by`
+    /// argument, or fail the call when the client cancels it first or the
+    /// lock cannot be taken
+    async fn acquire_serial(
+        &self,
+        key: &str,
+        tool_name: &str,
+        ct: &CancellationToken,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<LockGuard, McpError> {
+        let locks: &dyn LockProvider = match &self.options.lock_provider {
+            Some(provider) => provider.as_ref(),
+            None => &self.options.serial_locks,
+        };
+        tokio::select! {
+            guard = locks.lock(key) => guard.map_err(|e| {
+                McpError::internal_error(
+                    format!("failed to lock `{}` for tool `{}`: {}", key, tool_name, e),
+                    error_data.clone(),
+                )
+            }),
+            _ = ct.cancelled() => {
+                let message = Message::ToolCancelled {
+                    tool: tool_name.to_string(),
+                };
+                Err(McpError::invalid_request(
+                    self.options.messages.render(&message),
+                    error_data.clone(),
+                ))
+            }
+        }
+    }
+
+    /// Run a call to `tool_name` on the blocking thread pool, giving up on it
+    /// after the guard's timeout or when the client cancels it
+    ///
+    /// Other calls are never held up by a slow one. Synchronous handlers
+    /// cannot be interrupted: one given up on keeps its thread and the
+    /// guard's permits, its concurrency slot and `serialize_by` lock, until
+    /// it returns.
+    async fn run_guarded(
+        &self,
+        tool_name: String,
+        run: impl FnOnce(&Self) -> Result<CallToolResult, McpError> + Send + 'static,
+        correlation_id: &str,
+        guard: Guard,
+    ) -> Result<CallToolResult, McpError> {
+        #[cfg(target_family = "wasm")]
+        {
+            let _ = (tool_name, correlation_id);
+            let _guard = guard;
+            run(self)
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        {
+            let Guard {
+                timeout,
+                ct,
+                permits,
+                error_data,
+            } = guard;
+            // Calls need no session state, so any clone will do
+            let handler = self.clone();
+            let span = tracing::Span::current();
+            let mut task = tokio::task::spawn_blocking(move || {
+                let _permits = permits;
+                span.in_scope(|| run(&handler))
+            });
+            let finished = async {
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, &mut task).await.ok(),
+                    None => Some((&mut task).await),
+                }
+            };
+
+            let message = tokio::select! {
+                finished = finished => match finished {
+                    Some(Ok(result)) => return result,
+                    Some(Err(e)) => {
+                        tracing::error!(%correlation_id, tool = %tool_name, "tool panicked: {}", e);
+                        return Err(McpError::internal_error(
+                            format!("tool `{}` panicked", tool_name),
+                            error_data,
+                        ));
+                    }
+                    None => Message::ToolTimedOut {
+                        tool: tool_name,
+                        timeout: timeout.unwrap_or_default(),
+                    },
+                },
+                _ = ct.cancelled() => Message::ToolCancelled { tool: tool_name },
+            };
+            Ok(self.given_up(&message, correlation_id))
+        }
+    }
+
+    /// Forward a call to the bridged server owning `tool_name`, giving up on
+    /// it after the guard's timeout or when the client cancels it
+    ///
+    /// The guard's permits are held until the remote answers or the call is
+    /// given up on.
+    async fn run_bridged(
+        &self,
+        tool_name: String,
+        arguments: JsonObject,
+        correlation_id: &str,
+        guard: Guard,
+    ) -> Result<CallToolResult, McpError> {
+        let Guard {
+            timeout,
+            ct,
+            permits: _permits,
+            error_data,
+        } = guard;
+        let call = async {
+            match self.remotes.call_tool(&tool_name, Some(arguments)).await {
+                Some(result) => result,
+                None => {
+                    let message = Message::UnknownTool {
+                        tool: tool_name.clone(),
+                    };
+                    Err(self.invalid_params(&message, &error_data))
+                }
+            }
+        };
+        let finished = async {
+            match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, call).await.ok(),
+                None => Some(call.await),
+            }
+        };
+
+        let message = tokio::select! {
+            finished = finished => match finished {
+                Some(result) => return result,
+                None => Message::ToolTimedOut {
+                    tool: tool_name,
+                    timeout: timeout.unwrap_or_default(),
+                },
+            },
+            _ = ct.cancelled() => Message::ToolCancelled { tool: tool_name },
+        };
+        Ok(self.given_up(&message, correlation_id))
+    }
+
+    /// The error result of a call given up on
+    fn given_up(&self, message: &Message, correlation_id: &str) -> CallToolResult {
+        let message = self.options.messages.render(message);
+        tracing::warn!(%correlation_id, "{}", message);
+        CallToolResult::error(vec![Content::text(message)])
+    }
+
+    /// Run a parsed call through the handler, keeping the values of secret
+    /// arguments out of its errors
+    fn run(
+        &self,
+        call: ParsedCall<T>,
+        correlation_id: String,
+        client: ClientFeatures,
+    ) -> Result<CallToolResult, McpError> {
+        let secret_args = call.secret_args.clone();
+        self.run_handler(call, correlation_id, client)
+            .map(|result| secret_args.redact_result(result))
+            .map_err(|e| secret_args.redact_error(e))
+    }
+
+    /// Run a parsed call through the handler
+    ///
+    /// Internal handler errors fail the request; other errors are results.
+    fn run_handler(
+        &self,
+        call: ParsedCall<T>,
+        correlation_id: String,
+        client: ClientFeatures,
+    ) -> Result<CallToolResult, McpError> {
+        let ParsedCall {
+            tool_name,
+            subcommand,
+            global_args,
+            args,
+            secret_args: _,
+            serial_key: _,
+            log_sink,
+            stream,
+        } = call;
+
+        if let Some(exec) = &self.options.exec {
+            return Ok(self.exec(exec, &tool_name, &args, &correlation_id));
+        }
+        // Use the handler if provided
+        let Some(handler) = &self.handler else {
+            return self.run_missing_handler(&tool_name, &args, correlation_id);
+        };
+        let output_mime = self
+            .options
+            .tool_attributes
+            .get(&tool_name)
+            .and_then(|attrs| attrs.output_mime.clone());
+        let call = CallContext {
+            correlation_id: correlation_id.clone(),
+            tool: tool_name.clone(),
+            client,
+        };
+        let result = crate::logging::scope(log_sink, || {
+            crate::streaming::scope(stream, || {
+                crate::context::scope(call, || {
+                    crate::context::scope_global_args(global_args, || handler(subcommand))
+                })
+            })
+        });
+        self.tool_result(result, &tool_name, output_mime.as_deref(), correlation_id)
+    }
+
+    /// Run a call on a server without a handler as its
+    /// [`MissingHandler`] says
+    fn run_missing_handler(
+        &self,
+        tool_name: &str,
+        args: &[String],
+        correlation_id: String,
+    ) -> Result<CallToolResult, McpError> {
+        match &self.options.missing_handler {
+            MissingHandler::Error => Ok(CallToolResult::error(vec![Content::text(
+                self.options.messages.render(&Message::NoHandler),
+            )])),
+            MissingHandler::Exec => {
+                let exec = match std::env::current_exe() {
+                    Ok(program) => Exec::new(program),
+                    Err(e) => {
+                        let message = Message::ExecFailed {
+                            program: "the current executable".to_string(),
+                            details: e.to_string(),
+                        };
+                        return Ok(CallToolResult::error(vec![Content::text(
+                            self.options.messages.render(&message),
+                        )]));
+                    }
+                };
+                Ok(self.exec(&exec, tool_name, args, &correlation_id))
+            }
+            MissingHandler::DryRun => {
+                let output = ToolOutput::Json(json!({
+                    "tool": tool_name,
+                    "argv": args,
+                    "dry_run": true,
+                }));
+                self.tool_result(Ok(output), tool_name, None, correlation_id)
+            }
+            MissingHandler::Fallback(fallback) => {
+                let result = fallback(tool_name, args);
+                self.tool_result(result, tool_name, None, correlation_id)
+            }
+        }
+    }
+
+    /// The answer to a call whose handler returned `result`
+    ///
+    /// Internal handler errors fail the request; other errors are results.
+    fn tool_result(
+        &self,
+        result: Result<ToolOutput, ToolError>,
+        tool_name: &str,
+        output_mime: Option<&str>,
+        correlation_id: String,
+    ) -> Result<CallToolResult, McpError> {
+        match result {
+            Ok(output) => Ok(CallToolResult::success(self.contents(
+                output,
+                tool_name,
+                output_mime,
+            ))),
+            Err(e) if e.is_internal() => {
+                tracing::error!(%correlation_id, tool = %tool_name, "tool failed: {}", e);
+                let mut data = json!({ CORRELATION_ID_META_KEY: correlation_id });
+                if let Some(serde_json::Value::Object(details)) = e.details() {
+                    data.as_object_mut().unwrap().extend(details);
+                }
+                let message = self.options.secrets.redact(&e.to_string());
+                Err(McpError::internal_error(message, Some(data)))
+            }
+            Err(e) => {
+                tracing::warn!(%correlation_id, tool = %tool_name, "tool failed: {}", e);
+                let details = e.details();
+                let mut contents = self.contents(e.output, tool_name, None);
+                if let Some(details) = details {
+                    contents.extend(self.contents(ToolOutput::Json(details), tool_name, None));
+                }
+                Ok(CallToolResult::error(contents))
+            }
+        }
+    }
+
+    /// Run a call to a tool registered at runtime, whose arguments were
+    /// validated against the tool's schema, keeping the values of secret
+    /// arguments out of its errors
+    fn run_runtime_tool(
+        &self,
+        runtime_tool: RuntimeTool,
+        arguments: JsonObject,
+        correlation_id: String,
+        client: ClientFeatures,
+    ) -> Result<CallToolResult, McpError> {
+        let RuntimeTool {
+            tool,
+            handler,
+            secret_args,
+        } = runtime_tool;
+        let secret_args = SecretArgs::collect(&arguments, &secret_args);
+        let call = CallContext {
+            correlation_id: correlation_id.clone(),
+            tool: tool.name.to_string(),
+            client,
+        };
+        let result = crate::context::scope(call, || handler(arguments));
+        self.tool_result(result, &tool.name, None, correlation_id)
+            .map(|result| secret_args.redact_result(result))
+            .map_err(|e| secret_args.redact_error(e))
+    }
+
+    /// Run a parsed call as an invocation of the exec program
+    fn exec(
+        &self,
+        exec: &Exec,
+        tool_name: &str,
+        args: &[String],
+        correlation_id: &str,
+    ) -> CallToolResult {
+        let mut exec = exec.clone();
+        if let Some(timeout) = self.tool_timeout(tool_name) {
+            exec.timeout = exec.timeout.min(timeout);
+        }
+        let execution = match exec.run(args) {
+            Ok(execution) => execution,
+            Err(e) => {
+                tracing::warn!(%correlation_id, tool = %tool_name, "failed to run {}: {}", exec.program(), e);
+                let message = Message::ExecFailed {
+                    program: exec.program(),
+                    details: e.to_string(),
+                };
+                return CallToolResult::error(vec![Content::text(
+                    self.options.messages.render(&message),
+                )]);
+            }
+        };
+        let success = execution.success();
+        let output_mime = self
+            .options
+            .tool_attributes
+            .get(tool_name)
+            .and_then(|attrs| attrs.output_mime.clone());
+        let (stdout, trailer) = execution.into_outputs(exec.timeout, &self.options.messages);
+        let mut contents =
+            self.contents(ToolOutput::Text(stdout), tool_name, output_mime.as_deref());
+        contents.extend(self.contents(trailer, tool_name, None));
+        if success {
+            CallToolResult::success(contents)
+        } else {
+            tracing::warn!(%correlation_id, tool = %tool_name, "{} failed", exec.program());
+            CallToolResult::error(contents)
+        }
+    }
+
+    /// Take a local call through name inference, interpolation, secret
+    /// resolution, schema validation and clap, without running it
+    ///
+    /// `vars` are the session variables interpolated into the arguments.
+    fn parse_call(
+        &self,
+        tool_name: String,
+        arguments: JsonObject,
+        vars: &SessionVars,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<ParsedCall<T>, McpError> {
+        let call = self.prepare_call(tool_name, arguments, vars, error_data)?;
+        self.parse_prepared(call, error_data)
+    }
+
+    /// Parse a prepared call into its subcommand
+    fn parse_prepared(
+        &self,
+        call: PreparedCall,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<ParsedCall<T>, McpError> {
+        let (subcommand, _) = self.parse_argv(&call, error_data)?;
+        let mut args = call.global_argv;
+        args.extend(call.argv.into_iter().skip(1));
+        Ok(ParsedCall {
+            tool_name: call.tool_name,
+            subcommand,
+            global_args: call.global_args,
+            args,
+            secret_args: call.secret_args,
+            serial_key: call.serial_key,
+            log_sink: None,
+            stream: None,
+        })
+    }
+
+    /// A call's command line with secret values replaced by their handles,
+    /// for logs
+    fn redact_argv(&self, call: &PreparedCall) -> Vec<String> {
+        call.argv
+            .iter()
+            .map(|token| call.secret_args.redact(&self.options.secrets.redact(token)))
+            .collect()
+    }
+
+    /// Resolve the tool (following renames), interpolate variables and
+    /// secrets, validate the arguments against the schema and build the
+    /// command line
+    fn prepare_call(
+        &self,
+        tool_name: String,
+        mut arguments: JsonObject,
+        vars: &SessionVars,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<PreparedCall, McpError> {
+        let catalog = self.catalog();
+        let tool_name = match self.renamed_tool(&tool_name) {
+            Some(new) => new.to_string(),
+            None => tool_name,
+        };
+        let tool_name = match self.infer_tool_name(&tool_name) {
+            Ok(name) => name,
+            Err(candidates) => {
+                let message = Message::AmbiguousTool {
+                    tool: tool_name,
+                    candidates,
+                };
+                return Err(self.invalid_params(&message, error_data));
+            }
+        };
+        if self.is_skipped(&tool_name) {
+            let message = Message::UnknownTool { tool: tool_name };
+            return Err(self.invalid_params(&message, error_data));
+        }
+        let attrs = self.options.tool_attributes.get(&tool_name);
+        if let Some(attrs) = attrs {
+            attrs.resolve_arg_names(&mut arguments);
+            vars.apply_sticky(&mut arguments, &attrs.sticky_args);
+        }
+        if let Some(subcommand) = catalog.subcommand(&tool_name) {
+            vars.apply_workspace(&mut arguments, |id| {
+                subcommand.get_arguments().any(|arg| arg.get_id() == id)
+            });
+        }
+        // A null or unset argument is an omitted one, falling back to clap's
+        // default
+        arguments.retain(|_, value| !crate::vars::is_omitted(value));
+
+        if let Some(attrs) = attrs {
+            if attrs.interpolate {
+                vars.interpolate_arguments(&mut arguments)
+                    .map_err(|e| self.invalid_params(&e, error_data))?;
+            }
+            self.options
+                .secrets
+                .resolve(&mut arguments, &attrs.secret_refs)
+                .map_err(|e| self.invalid_params(&e, error_data))?;
+        }
+        let secret_args = match attrs {
+            Some(attrs) => SecretArgs::collect(&arguments, &attrs.secret_args),
+            None => SecretArgs::default(),
+        };
+        // Omitted values serialize with each other, as `null`
+        let serial_key = attrs
+            .and_then(|attrs| attrs.serialize_by.as_deref().map(|arg| attrs.arg_id(arg)))
+            .map(|id| {
+                let value = arguments.get(id).unwrap_or(&serde_json::Value::Null);
+                format!("{}={}", id, value)
+            });
+
+        let (global_args, global_argv) = match &self.options.globals {
+            Some(globals) => {
+                let (values, argv) =
+                    globals
+                        .parse(arguments.remove(GLOBAL_KEY))
+                        .map_err(|details| {
+                            self.invalid_params(&Message::InvalidArguments { details }, error_data)
+                        })?;
+                (Some(values), argv)
+            }
+            None => (None, Vec::new()),
+        };
+
+        if let Some(schema) = self.input_schema(&tool_name) {
+            let encoders = self.options.arg_encoders.get(&tool_name);
+            let has_encoder = |arg: &str| {
+                let id = attrs.map_or(arg, |attrs| attrs.arg_id(arg));
+                encoders.is_some_and(|encoders| encoders.contains_key(id))
+            };
+            // The schema lists renamed arguments under their new names
+            let listed = match attrs {
+                Some(attrs) => std::borrow::Cow::Owned(attrs.listed_arg_names(arguments.clone())),
+                None => std::borrow::Cow::Borrowed(&arguments),
+            };
+            crate::validate::validate_arguments(&schema, &listed, &has_encoder)
+                .map_err(|e| secret_args.redact_error(self.invalid_params(&e, error_data)))?;
+        }
+
+        // Build command line arguments
+        // First arg should be the program name, then the subcommand
+        let mut args = vec!["mcp".to_string(), tool_name.clone()];
+        args.extend(argv::build_argv(
+            catalog.subcommand(&tool_name),
+            arguments,
+            self.options.arg_encoders.get(&tool_name),
+        ));
+
+        Ok(PreparedCall {
+            tool_name,
+            argv: args,
+            global_args,
+            global_argv,
+            secret_args,
+            serial_key,
+        })
+    }
+
+    /// Parse a prepared command line into a subcommand, returning clap's
+    /// matches as well
+    fn parse_argv(
+        &self,
+        call: &PreparedCall,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<(T, clap::ArgMatches), McpError> {
+        // A root holding only the called subcommand is enough and spares
+        // cloning the whole tree; unknown tools go through the full root for
+        // clap's error
+        let catalog = self.catalog();
+        let root = match catalog.subcommand(&call.tool_name) {
+            Some(subcommand) => clap::Command::new("mcp").subcommand(subcommand.clone()),
+            None => catalog.command.clone(),
+        };
+        let matches = root.try_get_matches_from(&call.argv).map_err(|e| {
+            let message = Message::InvalidArguments {
+                details: e.to_string(),
+            };
+            call.secret_args
+                .redact_error(self.invalid_params(&message, error_data))
+        })?;
+        let subcommand = T::from_arg_matches(&matches).map_err(|e| {
+            let message = Message::ParseFailed {
+                details: e.to_string(),
+            };
+            call.secret_args
+                .redact_error(self.invalid_params(&message, error_data))
+        })?;
+        Ok((subcommand, matches))
+    }
+
+    /// Answer a `_parse_only` call with the arguments clap parsed
+    fn parse_only(
+        &self,
+        call: PreparedCall,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<CallToolResult, McpError> {
+        let (_, matches) = self.parse_argv(&call, error_data)?;
+        let arguments = match (
+            self.catalog().subcommand(&call.tool_name),
+            matches.subcommand(),
+        ) {
+            (Some(subcommand), Some((_, matches))) => {
+                crate::schema::parsed_values(subcommand, matches)
+            }
+            _ => JsonObject::new(),
+        };
+        let arguments = match self.options.tool_attributes.get(&call.tool_name) {
+            Some(attrs) => attrs.listed_arg_names(arguments),
+            None => arguments,
+        };
+        let report = json!({
+            "tool": call.tool_name,
+            "arguments": arguments,
+            "argv": self.redact_argv(&call)[1..],
+        });
+        // Secret values parse like any other
+        let report = self.options.secrets.redact(&report.to_string());
+        let report = call.secret_args.redact(&report);
+        Ok(CallToolResult::success(vec![Content::text(report)]))
+    }
+
+    /// Answer a `validate_call` by parsing the call it describes
+    ///
+    /// Variables the inner call sets are interpolated but not remembered.
+    fn validate_call(
+        &self,
+        mut arguments: JsonObject,
+        error_data: &Option<serde_json::Value>,
+    ) -> Result<CallToolResult, McpError> {
+        let schema = validate::validate_tool().input_schema;
+        validate::validate_arguments(&schema, &arguments, &|_| false)
+            .map_err(|e| self.invalid_params(&e, error_data))?;
+        let tool = match arguments.remove("tool") {
+            Some(serde_json::Value::String(tool)) => tool,
+            _ => {
+                let message = Message::InvalidArgument {
+                    field: "tool".to_string(),
+                    details: "expected a string".to_string(),
+                };
+                return Err(self.invalid_params(&message, error_data));
+            }
+        };
+        let mut inner = match arguments.remove("arguments") {
+            Some(serde_json::Value::Object(inner)) => inner,
+            _ => JsonObject::new(),
+        };
+
+        let vars = self.vars.snapshot();
+        let parsed = vars
+            .take_from(&mut inner)
+            .map_err(|e| self.invalid_params(&e, error_data))
+            .and_then(|()| self.parse_call(tool, inner, &vars, error_data));
+        let report = match parsed {
+            Ok(call) => json!({ "valid": true, "tool": call.tool_name }),
+            Err(e) => json!({ "valid": false, "error": e.message }),
+        };
+        Ok(CallToolResult::success(vec![Content::text(
+            report.to_string(),
+        )]))
+    }
+
+    /// Re-run a recorded call through the local pipeline
+    ///
+    /// Policies and quotas are not applied, and the call starts from empty
+    /// session variables.
+    pub(crate) fn replay(&self, entry: &AuditEntry) -> Replay {
+        let correlation_id = entry
+            .correlation_id
+            .clone()
+            .unwrap_or_else(crate::context::generate_id);
+        let error_data = Some(json!({ CORRELATION_ID_META_KEY: correlation_id }));
+        let mut arguments = entry.arguments.clone();
+        let vars = SessionVars::default();
+        let prepared = vars
+            .take_from(&mut arguments)
+            .map_err(|e| self.invalid_params(&e, &error_data))
+            .and_then(|()| self.prepare_call(entry.name.clone(), arguments, &vars, &error_data));
+        let call = match prepared {
+            Ok(call) => call,
+            Err(e) => {
+                return Replay {
+                    tool: entry.name.clone(),
+                    argv: None,
+                    result: Err(e),
+                }
+            }
+        };
+
+        let tool = call.tool_name.clone();
+        let argv = self.redact_argv(&call);
+        let result = self
+            .parse_prepared(call, &error_data)
+            .and_then(|call| self.run(call, correlation_id, ClientFeatures::default()));
+        Replay {
+            tool,
+            argv: Some(argv),
+            result,
+        }
+    }
+
+    /// Wait while `policy` queues the call, or fail it when rejected or the
+    /// client cancels it while queued
+    ///
+    /// The error data carries `retryAfter` in seconds.
+    async fn apply_policy(
+        &self,
+        policy: &dyn ToolPolicy,
+        tool_name: &str,
+        correlation_id: &str,
+        ct: &CancellationToken,
+    ) -> Result<(), McpError> {
+        loop {
+            match policy.check(tool_name, std::time::SystemTime::now()) {
+                PolicyDecision::Allow => return Ok(()),
+                PolicyDecision::Queue { wait } => {
+                    tracing::info!(%correlation_id, tool = %tool_name, "call queued for {:?} by policy", wait);
+                    tokio::select! {
+                        _ = tokio::time::sleep(wait) => {}
+                        _ = ct.cancelled() => {
+                            let message = Message::ToolCancelled {
+                                tool: tool_name.to_string(),
+                            };
+                            return Err(McpError::invalid_request(
+                                self.options.messages.render(&message),
+                                Some(json!({ CORRELATION_ID_META_KEY: correlation_id })),
+                            ));
+                        }
+                    }
+                }
+                PolicyDecision::Reject {
+                    reason,
+                    retry_after,
+                } => {
+                    let retry_after_secs = retry_after.as_secs();
+                    let message = Message::ToolUnavailable {
+                        tool: tool_name.to_string(),
+                        reason: reason.clone(),
+                        retry_after_secs,
+                    };
+                    return Err(McpError::invalid_request(
+                        self.options.messages.render(&message),
+                        Some(json!({
+                            CORRELATION_ID_META_KEY: correlation_id,
+                            "reason": reason,
+                            "retryAfter": retry_after_secs,
+                        })),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Metering and quota weight of calls to `tool`
+    fn tool_cost(&self, tool: &str) -> u64 {
+        self.options.tool_costs.get(tool).copied().unwrap_or(1)
+    }
+
+    /// Charge `cost` units to `principal`, failing the call when over quota
+    ///
+    /// The error data carries the exhausted `period`, its `limit` and
+    /// `retryAfter` in seconds.
+    fn consume_quota(
+        &self,
+        principal: &str,
+        cost: u64,
+        correlation_id: &str,
+    ) -> Result<Option<Remaining>, McpError> {
+        let Some(quotas) = &self.options.quotas else {
+            return Ok(None);
+        };
+        match quotas.consume(principal, cost, std::time::SystemTime::now()) {
+            Ok(remaining) => {
+                tracing::debug!(%correlation_id, %principal, ?remaining, "quota consumed");
+                Ok(Some(remaining))
+            }
+            Err(exceeded) => {
+                let message = Message::QuotaExceeded {
+                    principal: principal.to_string(),
+                    period: exceeded.period.to_string(),
+                    limit: exceeded.limit,
+                    retry_after_secs: exceeded.retry_after_secs,
+                };
+                Err(McpError::invalid_request(
+                    self.options.messages.render(&message),
+                    Some(json!({
+                        CORRELATION_ID_META_KEY: correlation_id,
+                        "period": exceeded.period.to_string(),
+                        "limit": exceeded.limit,
+                        "retryAfter": exceeded.retry_after_secs,
+                        "remaining": quotas.remaining(principal),
+                    })),
+                ))
+            }
+        }
+    }
+
+    /// Fail the call unless the authorizer lets `principal` make it
+    ///
+    /// Former names of renamed tools are checked as the current name.
+    async fn authorize(
+        &self,
+        principal: &str,
+        request: &CallToolRequestParam,
+        correlation_id: &str,
+    ) -> Result<(), McpError> {
+        let Some(authorizer) = &self.options.authorizer else {
+            return Ok(());
+        };
+        let tool_name = self.renamed_tool(&request.name).unwrap_or(&request.name);
+        let tool = self.usage_name(tool_name.to_string(), true);
+        let annotations = self
+            .options
+            .tool_attributes
+            .get(&tool)
+            .and_then(ToolAttributes::annotations);
+        let no_arguments = JsonObject::new();
+        let request = AuthorizationRequest {
+            principal,
+            tool: &tool,
+            arguments: request.arguments.as_ref().unwrap_or(&no_arguments),
+            annotations: annotations.as_ref(),
+        };
+        if authorizer.authorize(&request).await {
+            return Ok(());
+        }
+        tracing::warn!(%correlation_id, %principal, %tool, "tool call denied");
+        let message = Message::AccessDenied {
+            principal: principal.to_string(),
+            tool,
+        };
+        Err(McpError::invalid_request(
+            self.options.messages.render(&message),
+            Some(json!({ CORRELATION_ID_META_KEY: correlation_id })),
+        ))
+    }
+
+    /// Tool name to count a call under in usage statistics
+    ///
+    /// Names the server does not know are pooled so arbitrary client input
+    /// cannot grow the counters.
+    fn usage_name(&self, tool_name: String, handled: bool) -> String {
+        let cmd = &self.catalog().command;
+        match self.infer_tool_name(&tool_name) {
+            Ok(name) if cmd.find_subcommand(&name).is_some() && !self.is_skipped(&name) => name,
+            _ if self.options.runtime_tools.contains(&tool_name) => tool_name,
+            // Not local, so answered by a bridged server
+            _ if handled => tool_name,
+            _ => crate::stats::UNKNOWN_TOOL.to_string(),
+        }
+    }
+
+    /// Local, built-in, runtime and bridged tools
+    pub(crate) async fn list_all_tools(&self) -> Vec<Tool> {
+        let mut tools = self.tools().to_vec();
+        let cmd = &self.catalog().command;
+        if self.is_support_tool(cmd, support::SUPPORT_TOOL) {
+            tools.push(support::support_tool());
+        }
+        if self.is_validate_tool(cmd, validate::VALIDATE_TOOL) {
+            tools.push(validate::validate_tool());
+        }
+        if self.is_artifacts_tool(cmd, artifacts::LIST_ARTIFACTS_TOOL) {
+            tools.push(artifacts::list_artifacts_tool());
+        }
+        let runtime_tools = self.options.runtime_tools.list();
+        tools.extend(
+            runtime_tools
+                .into_iter()
+                .filter(|tool| cmd.find_subcommand(tool.name.as_ref()).is_none()),
+        );
+        tools.extend(self.remotes.list_tools().await);
+        tools.retain(|tool| self.is_exposed(&tool.name));
+        tools
+    }
+
+    /// The page of `tools` starting at `cursor`, the offset of its first
+    /// tool, when a page size is set
+    fn tool_page(
+        &self,
+        mut tools: Vec<Tool>,
+        cursor: Option<String>,
+    ) -> Result<ListToolsResult, McpError> {
+        let Some(page_size) = self.options.page_size else {
+            return Ok(ListToolsResult {
+                tools,
+                next_cursor: None,
+            });
+        };
+        let start = match &cursor {
+            Some(cursor) => match cursor.parse::<usize>() {
+                Ok(start) if start <= tools.len() => start,
+                _ => {
+                    let message = Message::InvalidCursor {
+                        cursor: cursor.clone(),
+                    };
+                    return Err(self.invalid_params(&message, &None));
+                }
+            },
+            None => 0,
+        };
+        let end = start.saturating_add(page_size.max(1)).min(tools.len());
+        let next_cursor = (end < tools.len()).then(|| end.to_string());
+        tools.truncate(end);
+        Ok(ListToolsResult {
+            tools: tools.split_off(start),
+            next_cursor,
+        })
+    }
+
+    /// Bind the session to the token in `meta`, restoring the state saved
+    /// under it the first time, and return the key the state is stored
+    /// under; `None` without a token or store
+    ///
+    /// With authentication the key includes the principal, so naming another
+    /// principal's token restores nothing of theirs.
+    fn resume_session(&self, meta: &Meta, principal: &str) -> Option<String> {
+        let store = self.options.session_store.as_ref()?;
+        let token = crate::vars::session_token(meta)?;
+        let key = match self.options.authenticated {
+            true => json!([principal, token]).to_string(),
+            false => token.to_string(),
+        };
+        if self.vars.bind(&key) {
+            if let Some(state) = store.load(&key) {
+                self.vars.restore(state);
+            }
+        }
+        Some(key)
+    }
+
+    /// Who a request comes from
+    fn principal(&self, context: &RequestContext<RoleServer>) -> String {
+        // Authenticated clients cannot name someone else in `_meta`
+        match crate::auth::authenticated(&context.extensions) {
+            Some(principal) => principal.to_string(),
+            None => crate::metering::principal(
+                (!self.options.authenticated).then_some(&context.meta),
+                context.peer.peer_info().map(|info| &info.client_info),
+            ),
+        }
+    }
+
+    /// Whose last results a request records and reads: its principal's, and
+    /// within them its session's when sessions are stored
+    ///
+    /// Naming another principal's session token finds nothing.
+    fn results_owner(&self, context: &RequestContext<RoleServer>) -> String {
+        let token = crate::vars::session_token(&context.meta);
+        let token = token.filter(|_| self.options.session_store.is_some());
+        json!([self.principal(context), token]).to_string()
+    }
+
+    /// Answer a tool call, authorization, metering and quota included
+    async fn handle_call(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let tool_name = request.name.to_string();
+        let correlation_id = crate::context::correlation_id_from_meta(&context.meta);
+        tracing::info!(%correlation_id, tool = %tool_name, "tool call");
+        let started = std::time::Instant::now();
+
+        let peer_info = context.peer.peer_info();
+        let client = peer_info
+            .map(|info| ClientFeatures::from_capabilities(&info.capabilities))
+            .unwrap_or_default();
+
+        let principal = self.principal(&context);
+        let cost = self.tool_cost(&self.usage_name(tool_name.clone(), true));
+
+        let admitted = match self.authorize(&principal, &request, &correlation_id).await {
+            Ok(()) => self.consume_quota(&principal, cost, &correlation_id),
+            Err(e) => Err(e),
+        };
+        let result = match admitted {
+            Ok(remaining) => {
+                let session_key = self.resume_session(&context.meta, &principal);
+                let ran = AtomicBool::new(false);
+                let mut result = self
+                    .dispatch(request, correlation_id.clone(), client, &context, &ran)
+                    .await;
+                if let (Some(key), Some(store)) = (session_key, &self.options.session_store) {
+                    store.save(&key, &self.vars.state());
+                }
+                // Calls refused before running do not count against the quota
+                if let (Err(_), Some(quotas)) = (&result, &self.options.quotas) {
+                    if !ran.load(Ordering::Relaxed) {
+                        quotas.refund(&principal, cost, std::time::SystemTime::now());
+                    }
+                }
+                if let (Ok(result), Some(remaining)) = (&mut result, remaining) {
+                    if self.options.quota_report {
+                        let report = json!({ "quota": remaining });
+                        result.content.push(Content::text(report.to_string()));
+                    }
+                }
+                result
+            }
+            Err(e) => Err(e),
+        };
+        if self.options.usage_stats.is_none() && self.options.meter.is_none() {
+            return result;
+        }
+
+        let usage_name = self.usage_name(tool_name, result.is_ok());
+        let outcome = outcome(&result);
+        if let Some(stats) = &self.options.usage_stats {
+            stats.record(&usage_name, outcome != Outcome::Success);
+        }
+        if let Some(meter) = &self.options.meter {
+            let call = ToolCall {
+                cost: self.tool_cost(&usage_name),
+                tool: usage_name,
+                correlation_id,
+                principal,
+                duration: started.elapsed(),
+            };
+            meter.record(&call, &outcome);
+        }
+        result
+    }
+}
+
+impl<T: Subcommand + Send + Sync + 'static> ServerHandler for ClapMcpHandler<T> {
+    fn get_info(&self) -> InitializeResult {
+        let experimental = (!self.options.metadata.is_empty()).then(|| {
+            let mut experimental = ExperimentalCapabilities::new();
+            if let serde_json::Value::Object(metadata) = json!(self.options.metadata) {
+                experimental.insert("serverMetadata".to_string(), metadata);
+            }
+            experimental
+        });
+
+        let mut capabilities = ServerCapabilities {
+            experimental,
+            // Tools may be registered at runtime
+            tools: Some(ToolsCapability {
+                list_changed: Some(true),
+            }),
+            resources: (!self.options.resources.is_empty()).then(ResourcesCapability::default),
+            logging: self.options.logging.then(JsonObject::new),
+            ..Default::default()
+        };
+        for hook in &self.options.capability_hooks {
+            hook(&mut capabilities);
+        }
+
+        InitializeResult {
+            protocol_version: ProtocolVersion::V_2024_11_05,
+            capabilities,
+            server_info: Implementation {
+                name: self
+                    .options
+                    .name
+                    .as_deref()
+                    .unwrap_or("clap-mcp-server")
+                    .to_string(),
+                version: self
+                    .options
+                    .version
+                    .as_deref()
+                    .unwrap_or(env!("CARGO_PKG_VERSION"))
+                    .to_string(),
+            },
+            instructions: self.options.instructions.clone(),
+        }
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        if !self.options.logging {
+            return Err(McpError::method_not_found::<SetLevelRequestMethod>());
+        }
+        *self.log_level.lock().unwrap() = request.level;
+        Ok(())
+    }
+
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        self.options.runtime_tools.subscribe(context.peer);
+    }
+
+    async fn on_roots_list_changed(&self, _context: NotificationContext<RoleServer>) {
+        self.vars.forget_workspace();
+    }
+
+    async fn list_tools(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        let cursor = request.and_then(|request| request.cursor);
+        if !self.options.tracing {
+            return self.tool_page(self.list_all_tools().await, cursor);
+        }
+
+        let span = tracing::info_span!(
+            "list_tools",
+            tools = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+        );
+        let started = std::time::Instant::now();
+        let tools = self.list_all_tools().instrument(span.clone()).await;
+        let page = self.tool_page(tools, cursor)?;
+        span.record("tools", page.tools.len());
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        span.in_scope(|| tracing::info!("listed tools"));
+        Ok(page)
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        // Help resources exist once the tool list is built
+        self.catalog();
+        let mut resources: Vec<_> = self
+            .options
+            .resources
+            .iter()
+            .flat_map(|provider| provider.list())
+            .collect();
+        if let Some(last_results) = &self.options.last_results {
+            resources.extend(last_results.list(&self.results_owner(&context)));
+        }
+        Ok(ListResourcesResult {
+            resources,
+            next_cursor: None,
+        })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let data = Some(json!({ "uri": request.uri }));
+        self.catalog();
+        let last_result = self.options.last_results.as_ref().and_then(|last_results| {
+            last_results.read(&self.results_owner(&context), &request.uri)
+        });
+        let contents = self
+            .options
+            .resources
+            .iter()
+            .find_map(|provider| provider.read(&request.uri))
+            .or(last_result.map(Ok))
+            .ok_or_else(|| {
+                McpError::resource_not_found(
+                    format!("unknown resource {}", request.uri),
+                    data.clone(),
+                )
+            })?
+            .map_err(|e| McpError::internal_error(e, data))?;
+        Ok(ReadResourceResult {
+            contents: vec![contents],
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if !self.options.tracing {
+            return self.handle_call(request, context).await;
+        }
+
+        let span = tracing::info_span!(
+            "call_tool",
+            tool = %request.name,
+            outcome = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+        );
+        let started = std::time::Instant::now();
+        let result = self
+            .handle_call(request, context)
+            .instrument(span.clone())
+            .await;
+        span.record("outcome", tracing::field::debug(outcome(&result)));
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        span.in_scope(|| tracing::info!("tool call finished"));
+        result
+    }
+}
+
+/// How a call answered with `result` ended
+fn outcome(result: &Result<CallToolResult, McpError>) -> Outcome {
+    match result {
+        Ok(r) if !r.is_error.unwrap_or(false) => Outcome::Success,
+        Ok(_) => Outcome::Failed,
+        Err(_) => Outcome::Rejected,
+    }
+}
+
+/// Resolve a tool name to the subcommand it calls
+///
+/// Mirrors clap: an exact name or alias resolves to the subcommand's name,
+/// otherwise, when `infers` (`infer_subcommands` is set), a prefix matching
+/// exactly one `visible` subcommand resolves to it. Ambiguous prefixes
+/// return the visible candidates. With no match, the name is returned
+/// unchanged.
+fn infer_tool_name(
+    cmd: &clap::Command,
+    name: &str,
+    infers: bool,
+    visible: &dyn Fn(&str) -> bool,
+) -> Result<String, Vec<String>> {
+    if let Some(subcommand) = cmd.find_subcommand(name) {
+        return Ok(subcommand.get_name().to_string());
+    }
+    if !infers {
+        return Ok(name.to_string());
+    }
+    let candidates: Vec<String> = cmd
+        .get_subcommands()
+        .filter(|sub| {
+            std::iter::once(sub.get_name())
+                .chain(sub.get_all_aliases())
+                .any(|candidate| candidate.starts_with(name))
+        })
+        .map(|sub| sub.get_name().to_string())
+        .filter(|candidate| visible(candidate))
+        .collect();
+    match candidates.len() {
+        0 => Ok(name.to_string()),
+        1 => Ok(candidates.into_iter().next().expect("one candidate")),
+        _ => Err(candidates),
+    }
+}
+
+/// Whether `infer_subcommands` is set, which clap has no public getter for
+///
+/// Probes a copy of the command with a prefix of a subcommand only it has.
+fn infers_subcommands(cmd: &clap::Command) -> bool {
+    const PROBE: &str = "__clap_mcp_infer_probe";
+    cmd.clone()
+        .subcommand(clap::Command::new(PROBE))
+        .try_get_matches_from(["mcp", &PROBE[..PROBE.len() - 1]])
+        .is_ok_and(|matches| matches.subcommand_name() == Some(PROBE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Subcommand, Clone)]
+    #[command(infer_subcommands = true)]
+    enum Inferred {
+        Status,
+        Stash,
+        #[command(alias = "ls")]
+        List,
+    }
+
+    #[derive(Subcommand, Clone)]
+    enum Exact {
+        Status,
+    }
+
+    #[test]
+    fn test_infer_tool_name() {
+        let cmd = Inferred::augment_subcommands(clap::Command::new("mcp"));
+        assert!(infers_subcommands(&cmd));
+        let infer = |name: &str| infer_tool_name(&cmd, name, true, &|_| true);
+        assert_eq!(infer("stat").unwrap(), "status");
+        assert_eq!(infer("li").unwrap(), "list");
+        assert_eq!(infer("ls").unwrap(), "list");
+        assert_eq!(
+            infer("st").unwrap_err(),
+            vec!["status".to_string(), "stash".to_string()]
+        );
+        assert_eq!(infer("zzz").unwrap(), "zzz");
+
+        // Hidden tools are neither candidates nor inferred
+        let hide_stash = |name: &str| name != "stash";
+        assert_eq!(
+            infer_tool_name(&cmd, "st", true, &hide_stash).unwrap(),
+            "status"
+        );
+        assert_eq!(
+            infer_tool_name(&cmd, "stas", true, &hide_stash).unwrap(),
+            "stas"
+        );
+
+        let cmd = Exact::augment_subcommands(clap::Command::new("mcp"));
+        assert!(!infers_subcommands(&cmd));
+        assert_eq!(
+            infer_tool_name(&cmd, "stat", false, &|_| true).unwrap(),
+            "stat"
+        );
+        assert_eq!(
+            infer_tool_name(&cmd, "status", false, &|_| true).unwrap(),
+            "status"
+        );
+    }
+}
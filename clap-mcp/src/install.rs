@@ -0,0 +1,642 @@
+//! `mcp install-config`: register the server in an MCP client's config file
+//!
+//! Opt in with `#[mcp(install_config)]` on the `McpMode` parser and handle the
+//! command before regular argument parsing:
+//!
+//! ```ignore
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     if let Some(result) = Cli::handle_mcp_install_config() {
+//!         return result;
+//!     }
+//!     let cli = Cli::parse();
+//!     // ...
+//! }
+//! ```
+//!
+//! `mytool mcp install-config --client cursor` then adds (or updates) the
+//! server's entry in the client's config and prints a diff of the change.
+//!
+//! The file is edited in place rather than rewritten: key order, formatting,
+//! the comments and trailing commas VS Code allows, and settings of the entry
+//! this does not manage (e.g. `env`) are kept.
+
+use clap::{Args, FromArgMatches, ValueEnum};
+use serde_json::{json, Value};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// MCP clients whose config files can be updated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum McpClient {
+    /// Claude Desktop (`claude_desktop_config.json`)
+    Claude,
+    /// Cursor (`~/.cursor/mcp.json`)
+    Cursor,
+    /// Visual Studio Code (user `mcp.json`)
+    Vscode,
+}
+
+impl McpClient {
+    /// Default location of the client's user-level config file
+    pub fn config_path(self) -> Option<PathBuf> {
+        let home = env_path("HOME").or_else(|| env_path("USERPROFILE"));
+        let app_support = if cfg!(windows) {
+            env_path("APPDATA")
+        } else if cfg!(target_os = "macos") {
+            home.as_ref()
+                .map(|home| home.join("Library").join("Application Support"))
+        } else {
+            env_path("XDG_CONFIG_HOME").or_else(|| home.as_ref().map(|home| home.join(".config")))
+        };
+
+        match self {
+            McpClient::Claude => {
+                app_support.map(|dir| dir.join("Claude").join("claude_desktop_config.json"))
+            }
+            McpClient::Cursor => home.map(|home| home.join(".cursor").join("mcp.json")),
+            McpClient::Vscode => {
+                app_support.map(|dir| dir.join("Code").join("User").join("mcp.json"))
+            }
+        }
+    }
+
+    /// Key of the object holding server entries
+    fn servers_key(self) -> &'static str {
+        match self {
+            McpClient::Vscode => "servers",
+            McpClient::Claude | McpClient::Cursor => "mcpServers",
+        }
+    }
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Arguments of the `mcp install-config` command
+#[derive(Debug, Clone, Args)]
+pub struct InstallConfigArgs {
+    /// Client to configure
+    #[arg(long, value_enum)]
+    pub client: McpClient,
+
+    /// Config file to edit instead of the client's default location
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Name of the server entry (defaults to the command name)
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Print the diff without writing the file
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// How the client should launch this server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerEntry {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+impl ServerEntry {
+    /// Entry launching the running executable with `args`
+    pub fn current_exe(name: impl Into<String>, args: Vec<String>) -> io::Result<Self> {
+        Ok(Self {
+            name: name.into(),
+            command: std::env::current_exe()?.to_string_lossy().into_owned(),
+            args,
+        })
+    }
+
+    /// Launch this entry on `destination` (`host` or `user@host`) over SSH
+    ///
+    /// The server keeps serving stdio on the remote host and SSH forwards it,
+    /// so nothing listens on the network. `command` must be the path on the
+    /// remote host. Runs non-interactively, so key-based login is needed.
+    pub fn over_ssh(self, destination: &str) -> Self {
+        let remote = std::iter::once(&self.command)
+            .chain(&self.args)
+            .map(|arg| crate::argv::quote_posix_arg(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        Self {
+            name: self.name,
+            command: "ssh".to_string(),
+            args: vec![
+                "-T".to_string(),
+                "-o".to_string(),
+                "BatchMode=yes".to_string(),
+                destination.to_string(),
+                "--".to_string(),
+                remote,
+            ],
+        }
+    }
+
+    fn to_json(&self, client: McpClient) -> Value {
+        match client {
+            McpClient::Vscode => json!({
+                "type": "stdio",
+                "command": self.command,
+                "args": self.args,
+            }),
+            McpClient::Claude | McpClient::Cursor => json!({
+                "command": self.command,
+                "args": self.args,
+            }),
+        }
+    }
+}
+
+/// Insert `entry` in a client config document, or update the existing entry
+/// of that name
+///
+/// Returns the updated document. Only the entry's launch settings change;
+/// everything else, including its other settings and comments, is kept.
+pub fn upsert_entry(config: &str, client: McpClient, entry: &ServerEntry) -> io::Result<String> {
+    let mut text = match config.trim().is_empty() {
+        true => "{}\n".to_string(),
+        false => config.to_string(),
+    };
+    let root = skip_trivia(&text, 0);
+    if text.as_bytes().get(root) != Some(&b'{') {
+        return Err(invalid("config file is not a JSON object"));
+    }
+
+    let key = client.servers_key();
+    let servers = match member(&text, root, key)? {
+        Some(servers) => servers.value.start,
+        None => set_member(&mut text, root, key, &json!({}))?,
+    };
+    if text.as_bytes()[servers] != b'{' {
+        return Err(invalid(format!("`{}` is not a JSON object", key)));
+    }
+
+    let settings = entry.to_json(client);
+    match member(&text, servers, &entry.name)? {
+        Some(existing) if text.as_bytes()[existing.value.start] == b'{' => {
+            let Value::Object(settings) = settings else {
+                unreachable!("entries are objects")
+            };
+            for (field, value) in &settings {
+                set_member(&mut text, existing.value.start, field, value)?;
+            }
+        }
+        _ => {
+            set_member(&mut text, servers, &entry.name, &settings)?;
+        }
+    }
+    Ok(text)
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// A member of an object in a config file's text
+struct Member {
+    key: String,
+    /// Offset of the key's opening quote
+    start: usize,
+    value: Range<usize>,
+}
+
+/// Offset of the first character at or after `i` that is not whitespace or
+/// part of a `//` or `/* */` comment
+fn skip_trivia(text: &str, mut i: usize) -> usize {
+    loop {
+        i += text[i..].len() - text[i..].trim_start().len();
+        let rest = &text[i..];
+        if rest.starts_with("//") {
+            i = rest.find('\n').map_or(text.len(), |end| i + end);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            i = comment.find("*/").map_or(text.len(), |end| i + 2 + end + 2);
+        } else {
+            return i;
+        }
+    }
+}
+
+/// Offset just past the string starting at `i`
+fn string_end(text: &str, i: usize) -> io::Result<usize> {
+    let bytes = text.as_bytes();
+    let mut j = i + 1;
+    while j < bytes.len() {
+        match bytes[j] {
+            b'\\' => j += 2,
+            b'"' => return Ok(j + 1),
+            _ => j += 1,
+        }
+    }
+    Err(invalid("unterminated string"))
+}
+
+/// Offset just past the value starting at `i`
+fn value_end(text: &str, i: usize) -> io::Result<usize> {
+    let bytes = text.as_bytes();
+    match bytes.get(i) {
+        Some(b'"') => string_end(text, i),
+        Some(b'{' | b'[') => {
+            let mut depth = 0;
+            let mut j = i;
+            while j < bytes.len() {
+                match bytes[j] {
+                    b'"' => {
+                        j = string_end(text, j)?;
+                        continue;
+                    }
+                    b'/' if matches!(bytes.get(j + 1), Some(b'/' | b'*')) => {
+                        j = skip_trivia(text, j);
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Ok(j + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                j += 1;
+            }
+            Err(invalid("unterminated object or array"))
+        }
+        Some(_) => match text[i..].find(|c: char| ",}]/".contains(c) || c.is_whitespace()) {
+            Some(0) => Err(invalid(format!("expected a value at offset {}", i))),
+            Some(len) => Ok(i + len),
+            None => Ok(text.len()),
+        },
+        None => Err(invalid("unexpected end of file")),
+    }
+}
+
+/// Members of the object opening at `open`, and the offset of its closing
+/// brace
+fn members(text: &str, open: usize) -> io::Result<(Vec<Member>, usize)> {
+    let bytes = text.as_bytes();
+    let mut members = Vec::new();
+    let mut i = skip_trivia(text, open + 1);
+    loop {
+        match bytes.get(i) {
+            Some(b'}') => return Ok((members, i)),
+            Some(b'"') => {
+                let key_end = string_end(text, i)?;
+                let key = serde_json::from_str(&text[i..key_end])?;
+                let colon = skip_trivia(text, key_end);
+                if bytes.get(colon) != Some(&b':') {
+                    return Err(invalid(format!("expected `:` at offset {}", colon)));
+                }
+                let value_start = skip_trivia(text, colon + 1);
+                let value_end = value_end(text, value_start)?;
+                members.push(Member {
+                    key,
+                    start: i,
+                    value: value_start..value_end,
+                });
+                i = skip_trivia(text, value_end);
+                match bytes.get(i) {
+                    // A trailing comma before the brace is fine too
+                    Some(b',') => i = skip_trivia(text, i + 1),
+                    Some(b'}') => {}
+                    _ => return Err(invalid(format!("expected `,` or `}}` at offset {}", i))),
+                }
+            }
+            _ => return Err(invalid(format!("expected a key at offset {}", i))),
+        }
+    }
+}
+
+/// The member `key` of the object opening at `open`
+fn member(text: &str, open: usize, key: &str) -> io::Result<Option<Member>> {
+    let (members, _) = members(text, open)?;
+    Ok(members.into_iter().find(|member| member.key == key))
+}
+
+/// Whitespace the line holding offset `i` starts with
+fn line_indent(text: &str, i: usize) -> &str {
+    let line = &text[text[..i].rfind('\n').map_or(0, |end| end + 1)..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// `value` pretty-printed for a line indented by `indent`
+fn pretty(value: &Value, indent: &str) -> io::Result<String> {
+    let pretty = serde_json::to_string_pretty(value)?;
+    Ok(pretty.replace('\n', &format!("\n{}", indent)))
+}
+
+/// Set the member `key` of the object opening at `open` to `value`, adding
+/// it at the end if missing, and return the offset of the value
+fn set_member(text: &mut String, open: usize, key: &str, value: &Value) -> io::Result<usize> {
+    let (members, close) = members(text, open)?;
+    if let Some(member) = members.iter().find(|member| member.key == key) {
+        let indent = line_indent(text, member.start).to_string();
+        text.replace_range(member.value.clone(), &pretty(value, &indent)?);
+        return Ok(member.value.start);
+    }
+
+    // New members line up with the others, unless they share the brace's line
+    let outer = line_indent(text, open).to_string();
+    let indent = match members.first() {
+        Some(first) if text[open..first.start].contains('\n') => {
+            line_indent(text, first.start).to_string()
+        }
+        _ => format!("{}  ", outer),
+    };
+    let prefix = format!("\n{}{}: ", indent, serde_json::to_string(key)?);
+    let added = format!("{}{}", prefix, pretty(value, &indent)?);
+    let at = match members.last() {
+        Some(last) => {
+            let after = skip_trivia(text, last.value.end);
+            if text.as_bytes()[after] == b',' {
+                text.insert_str(after + 1, &added);
+                after + 1
+            } else {
+                text.insert_str(last.value.end, &format!(",{}", added));
+                last.value.end + 1
+            }
+        }
+        None if text[open + 1..close].trim().is_empty() => {
+            text.replace_range(open + 1..close, &format!("{}\n{}", added, outer));
+            open + 1
+        }
+        None => {
+            text.insert_str(open + 1, &added);
+            open + 1
+        }
+    };
+    Ok(at + prefix.len())
+}
+
+/// Update the client's config file and return a diff of the change
+pub fn install(args: &InstallConfigArgs, entry: &ServerEntry) -> io::Result<String> {
+    let path = match &args.config {
+        Some(path) => path.clone(),
+        None => args.client.config_path().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "could not determine the client's config directory",
+            )
+        })?,
+    };
+
+    let mut entry = entry.clone();
+    if let Some(name) = &args.name {
+        entry.name = name.clone();
+    }
+
+    let existing = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let updated = upsert_entry(&existing, args.client, &entry)?;
+
+    if !args.dry_run && updated != existing {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, &updated)?;
+    }
+
+    Ok(format!(
+        "--- {path}\n+++ {path}\n{}",
+        line_diff(&existing, &updated),
+        path = path.display()
+    ))
+}
+
+/// Run `mcp install-config` if it is the command being invoked
+///
+/// `args` are the full process arguments including the binary name. Returns
+/// `None` when the invocation is something else, so regular parsing can go on.
+pub fn handle_from_args(
+    args: impl IntoIterator<Item = impl Into<OsString>>,
+    entry: impl FnOnce() -> io::Result<ServerEntry>,
+) -> Option<Result<(), Box<dyn std::error::Error>>> {
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    if args.get(1).is_none_or(|arg| arg != "mcp")
+        || args.get(2).is_none_or(|arg| arg != "install-config")
+    {
+        return None;
+    }
+
+    let bin = args[0].clone();
+    let cmd = InstallConfigArgs::augment_args(clap::Command::new("install-config"));
+    let run = || -> Result<(), Box<dyn std::error::Error>> {
+        let matches =
+            cmd.try_get_matches_from(std::iter::once(bin).chain(args[3..].iter().cloned()))?;
+        let install_args = InstallConfigArgs::from_arg_matches(&matches)?;
+        print!("{}", install(&install_args, &entry()?)?);
+        Ok(())
+    };
+    Some(run())
+}
+
+/// Line-based diff with `-`/`+`/` ` prefixes
+fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            out.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        } else {
+            out.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Whether `path` exists and already contains an entry named `name`
+pub fn is_installed(path: &Path, client: McpClient, name: &str) -> bool {
+    let Ok(text) = fs::read_to_string(path) else {
+        return false;
+    };
+    let root = skip_trivia(&text, 0);
+    if text.as_bytes().get(root) != Some(&b'{') {
+        return false;
+    }
+    match member(&text, root, client.servers_key()) {
+        Ok(Some(servers)) if text.as_bytes()[servers.value.start] == b'{' => {
+            matches!(member(&text, servers.value.start, name), Ok(Some(_)))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> ServerEntry {
+        ServerEntry {
+            name: "calculator".to_string(),
+            command: "/usr/local/bin/calculator".to_string(),
+            args: vec!["--mcp".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_over_ssh() {
+        let entry = ServerEntry {
+            name: "deploy".to_string(),
+            command: "/opt/deploy tool".to_string(),
+            args: vec!["--mcp".to_string()],
+        }
+        .over_ssh("ops@build-1");
+        assert_eq!(entry.command, "ssh");
+        assert_eq!(
+            entry.args,
+            [
+                "-T",
+                "-o",
+                "BatchMode=yes",
+                "ops@build-1",
+                "--",
+                "'/opt/deploy tool' --mcp"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_upsert_preserves_other_servers() {
+        let config = r#"{"mcpServers": {"other": {"command": "other"}}, "theme": "dark"}"#;
+        let updated: Value =
+            serde_json::from_str(&upsert_entry(config, McpClient::Claude, &entry()).unwrap())
+                .unwrap();
+        assert_eq!(updated["theme"], "dark");
+        assert_eq!(updated["mcpServers"]["other"]["command"], "other");
+        assert_eq!(
+            updated["mcpServers"]["calculator"],
+            json!({ "command": "/usr/local/bin/calculator", "args": ["--mcp"] })
+        );
+
+        let vscode: Value =
+            serde_json::from_str(&upsert_entry("", McpClient::Vscode, &entry()).unwrap()).unwrap();
+        assert_eq!(vscode["servers"]["calculator"]["type"], "stdio");
+    }
+
+    #[test]
+    fn test_upsert_edits_in_place() {
+        let config = r#"{
+  "theme": "dark",
+  "mcpServers": {
+    "calculator": {
+      "command": "/old/calculator",
+      "env": { "RUST_LOG": "debug" }
+    }
+  },
+  "fontSize": 12
+}
+"#;
+        let updated = upsert_entry(config, McpClient::Claude, &entry()).unwrap();
+        assert_eq!(
+            updated,
+            r#"{
+  "theme": "dark",
+  "mcpServers": {
+    "calculator": {
+      "command": "/usr/local/bin/calculator",
+      "env": { "RUST_LOG": "debug" },
+      "args": [
+        "--mcp"
+      ]
+    }
+  },
+  "fontSize": 12
+}
+"#
+        );
+        assert_eq!(
+            upsert_entry(&updated, McpClient::Claude, &entry()).unwrap(),
+            updated
+        );
+    }
+
+    #[test]
+    fn test_upsert_keeps_vscode_comments() {
+        let config = r#"{
+	// Servers started by the editor
+	"servers": {
+		"other": { "type": "stdio", "command": "other" }, /* keep */
+	},
+}
+"#;
+        let updated = upsert_entry(config, McpClient::Vscode, &entry()).unwrap();
+        assert!(updated.contains("// Servers started by the editor"));
+        assert!(updated.contains("/* keep */"));
+        assert!(updated.contains("\t\t\"calculator\": {\n\t\t  \"args\""));
+
+        let dir = std::env::temp_dir().join(format!("clap-mcp-vscode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mcp.json");
+        fs::write(&path, &updated).unwrap();
+        assert!(is_installed(&path, McpClient::Vscode, "calculator"));
+        assert!(is_installed(&path, McpClient::Vscode, "other"));
+        assert!(!is_installed(&path, McpClient::Vscode, "missing"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(upsert_entry("[]", McpClient::Vscode, &entry()).is_err());
+        assert!(upsert_entry(r#"{"servers": 1}"#, McpClient::Vscode, &entry()).is_err());
+    }
+
+    #[test]
+    fn test_install_writes_config_and_diff() {
+        let dir = std::env::temp_dir().join(format!("clap-mcp-install-{}", std::process::id()));
+        let path = dir.join("mcp.json");
+        let args = InstallConfigArgs {
+            client: McpClient::Cursor,
+            config: Some(path.clone()),
+            name: None,
+            dry_run: false,
+        };
+
+        let diff = install(&args, &entry()).unwrap();
+        assert!(diff.contains("+    \"calculator\": {"));
+        assert!(is_installed(&path, McpClient::Cursor, "calculator"));
+
+        // Re-installing the same entry is a no-op
+        let diff = install(&args, &entry()).unwrap();
+        assert!(!diff
+            .lines()
+            .skip(2)
+            .any(|line| line.starts_with(['+', '-'])));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_handle_from_args_ignores_other_commands() {
+        assert!(handle_from_args(["tool", "add", "1", "2"], || Ok(entry())).is_none());
+        assert!(handle_from_args(["tool", "mcp"], || Ok(entry())).is_none());
+    }
+}
@@ -1,33 +1,150 @@
-pub use clap_mcp_derive::McpMode;
+pub use clap_mcp_derive::{McpClient, McpMode, McpSchema, McpTools};
+pub use metadata::{Icon, McpArgs, McpTools, ServerMetadata, ToolAttributes};
+pub use schema::{McpArgsSchema, McpSchema};
+#[cfg(feature = "schemars")]
+pub use schemars;
 
+// Lets the derive macros' `clap_mcp::` paths resolve inside this crate's tests
+extern crate self as clap_mcp;
+
+pub mod argv;
+pub mod artifacts;
+pub mod auth;
+pub mod authz;
+mod bridge;
+pub mod bundle;
+#[cfg(feature = "client")]
+pub mod call;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod confirm;
+pub mod conformance;
+pub mod context;
+pub mod descriptions;
+pub mod encoding;
+pub mod error;
+pub mod exec;
+pub mod export;
+pub mod globals;
+pub mod handler;
+pub mod install;
+pub mod logging;
+pub mod messages;
+pub mod metadata;
+pub mod metering;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "opa")]
+pub mod opa;
+pub mod output;
+pub mod policy;
+pub mod quota;
+#[cfg(feature = "redis")]
+pub mod redis_locks;
+pub mod registry;
+pub mod replay;
+pub mod resources;
+pub mod sanitize;
+pub mod scaffold;
+pub mod schema;
+pub mod secrets;
+pub mod stats;
+pub mod stdio;
+pub mod streaming;
+pub mod support;
+#[cfg(feature = "client")]
 pub mod test_client;
+pub mod tools;
+pub mod transaction;
+pub mod transport;
+pub mod validate;
+pub mod vars;
+pub mod workspace;
 
-use clap::Subcommand;
-use rmcp::{
-    handler::server::ServerHandler,
-    model::*,
-    service::{RequestContext, RoleServer},
-    Error as McpError,
+pub use handler::ClapMcpHandler;
+pub use transport::{
+    IoTransport, McpTransport, McpTransportProvider, ServerHandle, SseTransport, StdioTransport,
+    TransportSetting,
 };
-use serde_json::json;
-use std::collections::HashMap;
+
+use bridge::{BridgeConfig, Remotes};
+use clap::Subcommand;
+use handler::ServerOptions;
+use sanitize::OutputSanitizer;
 use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-/// Configuration for MCP server transport
-pub enum McpTransport {
-    /// Standard I/O (stdin/stdout)
-    Stdio,
-    /// HTTP Server-Sent Events (SSE) on specified address
-    Http(SocketAddr),
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::metadata::{
+        ArgsProbe, AttributesProbe, ViaDefault, ViaMcpArgs, ViaMcpTools, ViaPlainArgs,
+    };
+    pub use crate::schema::{SchemaProbe, ViaMcpSchema, ViaRuntimeSchema};
+    pub use clap;
+    pub use serde_json;
+
+    /// Runtime for the derive-generated `run_mcp_server*` methods
+    ///
+    /// Multi-threaded when the HTTP transport is available, single-threaded
+    /// otherwise (which is all `wasm32-wasi` supports).
+    pub fn runtime() -> std::io::Result<tokio::runtime::Runtime> {
+        #[cfg(feature = "http")]
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        #[cfg(not(feature = "http"))]
+        let mut builder = tokio::runtime::Builder::new_current_thread();
+        builder.enable_all().build()
+    }
 }
 
 /// Handler function that processes a subcommand and returns output
 pub type CommandHandler<T> = Box<dyn Fn(T) -> Result<String, String> + Send + Sync>;
 
+/// Handler function returning typed content instead of a string
+pub type StructuredHandler<T> =
+    Box<dyn Fn(T) -> Result<output::ToolOutput, output::ToolError> + Send + Sync>;
+
+/// Handler function pushing output through a [`Sink`](streaming::Sink) as
+/// it runs; what it returns ends the output
+pub type StreamingHandler<T> =
+    Box<dyn Fn(T, &streaming::Sink) -> Result<String, String> + Send + Sync>;
+
+/// Adapt a [`CommandHandler`] into a [`StructuredHandler`] returning text
+pub(crate) fn structured<T: 'static>(handler: CommandHandler<T>) -> StructuredHandler<T> {
+    Box::new(move |cmd| {
+        handler(cmd)
+            .map(output::ToolOutput::Text)
+            .map_err(output::ToolError::from)
+    })
+}
+
+/// Adapt a [`StreamingHandler`] into a [`StructuredHandler`] returning the
+/// streamed text
+pub(crate) fn streamed<T: 'static>(handler: StreamingHandler<T>) -> StructuredHandler<T> {
+    Box::new(move |cmd| {
+        let sink = streaming::current();
+        let result = handler(cmd, &sink);
+        let output = sink.take_output();
+        match result {
+            Ok(end) => Ok(output::ToolOutput::Text(output + &end)),
+            Err(e) => Err(output::ToolError::from(output + &e)),
+        }
+    })
+}
+
+/// Hook that adjusts the capabilities advertised during initialization
+pub type CapabilitiesHook = dyn Fn(&mut rmcp::model::ServerCapabilities) + Send + Sync;
+
 pub struct McpServer<T: Subcommand> {
-    handler: Option<CommandHandler<T>>,
+    handler: Option<StructuredHandler<T>>,
+    options: ServerOptions,
+    bridges: Vec<BridgeConfig>,
+    /// Accepted credentials; any one of them grants access
+    auth: Vec<Arc<dyn auth::AuthProvider>>,
     _phantom: PhantomData<T>,
 }
 
@@ -35,6 +152,9 @@ impl<T: Subcommand + Send + Sync + Clone + 'static> Default for McpServer<T> {
     fn default() -> Self {
         Self {
             handler: None,
+            options: ServerOptions::default(),
+            bridges: Vec::new(),
+            auth: Vec::new(),
             _phantom: PhantomData,
         }
     }
@@ -46,324 +166,714 @@ impl<T: Subcommand + Send + Sync + Clone + 'static> McpServer<T> {
     }
 
     pub fn with_handler(mut self, handler: CommandHandler<T>) -> Self {
+        self.handler = Some(structured(handler));
+        self.options.streaming = false;
+        self
+    }
+
+    /// Use a handler returning [`ToolOutput`](output::ToolOutput), e.g. JSON
+    /// or images, instead of a string
+    pub fn with_structured_handler(mut self, handler: StructuredHandler<T>) -> Self {
         self.handler = Some(handler);
+        self.options.streaming = false;
         self
     }
 
-    pub async fn serve_stdio(self) -> Result<(), Box<dyn std::error::Error>> {
-        let handler = ClapMcpHandler::<T>::new(self.handler);
-        rmcp::serve_server(handler, rmcp::transport::stdio()).await?;
-        Ok(())
+    /// Use a handler streaming its output as it runs, e.g. for `tail -f`
+    /// (see [`streaming`])
+    pub fn with_streaming_handler(mut self, handler: StreamingHandler<T>) -> Self {
+        self.handler = Some(streamed(handler));
+        self.options.streaming = true;
+        self
     }
 
-    pub async fn serve_http(self, addr: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
-        use rmcp::transport::sse_server::{SseServer, SseServerConfig};
+    /// Set how text results are cleaned up before being returned
+    ///
+    /// By default ANSI escapes are stripped and newlines are normalized. Use
+    /// [`OutputSanitizer::disabled`] to return handler output verbatim.
+    pub fn with_output_sanitizer(mut self, sanitizer: OutputSanitizer) -> Self {
+        self.options.sanitizer = sanitizer;
+        self
+    }
 
-        let handler = ClapMcpHandler::<T>::new(self.handler);
+    /// Apply per-tool attributes, typically from `#[derive(McpTools)]`
+    ///
+    /// ```ignore
+    /// McpServer::<Commands>::new().with_tool_attributes(Commands::tool_attributes())
+    /// ```
+    pub fn with_tool_attributes(
+        mut self,
+        attributes: impl IntoIterator<Item = ToolAttributes>,
+    ) -> Self {
+        for attrs in attributes {
+            self.options
+                .tool_attributes
+                .insert(attrs.name.clone(), attrs);
+        }
+        self
+    }
 
-        let config = SseServerConfig {
-            bind: addr,
-            sse_path: "/sse".to_string(),
-            post_path: "/message".to_string(),
-            ct: tokio_util::sync::CancellationToken::new(),
-            sse_keep_alive: None,
-        };
+    /// Set the title, website, icons and contact advertised to clients
+    ///
+    /// The `Implementation` type of the MCP SDK in use only carries a name and
+    /// version, so these fields are published under the `serverMetadata` key of
+    /// the server's experimental capabilities.
+    pub fn with_metadata(mut self, metadata: ServerMetadata) -> Self {
+        self.options.metadata = metadata;
+        self
+    }
 
-        let (sse_server, router) = SseServer::new(config);
+    /// Set the name advertised in `serverInfo`, e.g. the CLI's binary name
+    ///
+    /// Defaults to `clap-mcp-server`.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.options.name = Some(name.into());
+        self
+    }
 
-        let listener = tokio::net::TcpListener::bind(sse_server.config.bind).await?;
-        println!("MCP server listening on http://{}", addr);
-        println!("SSE endpoint: http://{}/sse", addr);
-        println!("Message endpoint: http://{}/message", addr);
+    /// Set the version advertised in `serverInfo`
+    ///
+    /// Defaults to the version of clap-mcp.
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.options.version = Some(version.into());
+        self
+    }
 
-        let ct = sse_server.config.ct.child_token();
+    /// Tell clients how to use the tools, e.g. which to call first
+    ///
+    /// Sent as the `instructions` of the initialize result, which clients
+    /// may add to the model's system prompt.
+    pub fn with_instructions(mut self, instructions: impl Into<String>) -> Self {
+        self.options.instructions = Some(instructions.into());
+        self
+    }
 
-        let server =
-            axum::serve(listener, router.into_make_service()).with_graceful_shutdown(async move {
-                ct.cancelled().await;
-            });
+    /// Describe argument `arg` of tool `tool` with the JSON schema of `V`
+    ///
+    /// The builder form of `#[mcp(json_schema)]`, for value types that
+    /// implement `schemars::JsonSchema`.
+    #[cfg(feature = "schemars")]
+    pub fn with_arg_schema<V: schemars::JsonSchema>(
+        mut self,
+        tool: impl Into<String>,
+        arg: impl Into<String>,
+    ) -> Self {
+        let tool = tool.into();
+        self.options
+            .tool_attributes
+            .entry(tool.clone())
+            .or_insert_with(|| ToolAttributes {
+                name: tool,
+                ..Default::default()
+            })
+            .arg_schemas
+            .insert(arg.into(), schema::schema_for::<V>());
+        self
+    }
 
-        tokio::spawn(async move {
-            if let Err(e) = server.await {
-                eprintln!("MCP SSE server error: {}", e);
-            }
-        });
+    /// List tools from schemas generated by `#[derive(McpSchema)]`
+    ///
+    /// Skips clap schema introspection at startup. Calls are still parsed
+    /// by clap, so the schemas must come from the same enum.
+    pub fn with_static_schemas(mut self, tools: &'static [schema::StaticTool]) -> Self {
+        self.options.static_tools = Some(tools.iter().map(|tool| tool.to_tool()).collect());
+        self
+    }
 
-        let ct = sse_server.with_service(move || handler.clone());
+    /// Convert argument `arg` of tool `tool` to CLI tokens with `encoder`
+    ///
+    /// For flag grammars the default `--long=value` mapping cannot express,
+    /// e.g. an object that must become a repeated `--filter key=value`:
+    ///
+    /// ```ignore
+    /// McpServer::<Commands>::new().with_arg_encoder("query", "filter", argv::KeyValuePairs)
+    /// ```
+    pub fn with_arg_encoder(
+        mut self,
+        tool: impl Into<String>,
+        arg: impl Into<String>,
+        encoder: impl argv::ArgEncoder + 'static,
+    ) -> Self {
+        self.options
+            .arg_encoders
+            .entry(tool.into())
+            .or_default()
+            .insert(arg.into(), Arc::new(encoder));
+        self
+    }
 
-        tokio::signal::ctrl_c().await?;
-        println!("\nShutting down MCP server...");
-        ct.cancel();
-        Ok(())
+    /// Count calls and failures per tool and report them to `stats`
+    ///
+    /// Opt-in; only tool names and counts are collected, never arguments.
+    pub fn with_usage_stats(mut self, stats: stats::UsageStats) -> Self {
+        self.options.usage_stats = Some(stats);
+        self
     }
 
-    pub async fn serve(self, transport: McpTransport) -> Result<(), Box<dyn std::error::Error>> {
-        match transport {
-            McpTransport::Stdio => self.serve_stdio().await,
-            McpTransport::Http(addr) => self.serve_http(addr).await,
-        }
+    /// Report every completed tool call to `meter`, e.g. a
+    /// [`Ledger`](metering::Ledger) totalling usage per principal
+    pub fn with_meter(mut self, meter: impl metering::Meter + 'static) -> Self {
+        self.options.meter = Some(Arc::new(meter));
+        self
     }
-}
 
-struct ClapMcpHandler<T> {
-    handler: Option<Arc<CommandHandler<T>>>,
-    _phantom: PhantomData<T>,
-}
+    /// Serve the resources of `provider`, e.g. [`FileResources`](resources::FileResources)
+    /// that handlers register output files with
+    ///
+    /// Advertises the `resources` capability; may be called several times.
+    pub fn with_resources(mut self, provider: impl resources::ResourceProvider + 'static) -> Self {
+        self.options.resources.push(Arc::new(provider));
+        self
+    }
 
-impl<T> Clone for ClapMcpHandler<T> {
-    fn clone(&self) -> Self {
-        Self {
-            handler: self.handler.clone(),
-            _phantom: PhantomData,
+    /// Keep agent-generated files in `artifacts`, serve them as resources
+    /// and register a `list_artifacts` tool
+    ///
+    /// The directory is cleaned up right away. See [`artifacts`].
+    pub fn with_artifacts(mut self, artifacts: artifacts::Artifacts) -> Self {
+        if let Err(e) = artifacts.cleanup() {
+            tracing::warn!(
+                "failed to clean up artifacts in {}: {}",
+                artifacts.dir().display(),
+                e
+            );
         }
+        self.options.artifacts = Some(artifacts.clone());
+        self.with_resources(artifacts)
     }
-}
 
-impl<T: Subcommand> ClapMcpHandler<T> {
-    fn new(handler: Option<CommandHandler<T>>) -> Self {
-        Self {
-            handler: handler.map(Arc::new),
-            _phantom: PhantomData,
-        }
+    /// Give up on tool calls still running after `timeout`
+    ///
+    /// The client gets an error result instead. Handlers cannot be
+    /// interrupted, so they run on to completion in the background; exec
+    /// programs are killed. `#[mcp(timeout_secs = N)]` overrides the timeout
+    /// for one tool.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
     }
-}
 
-impl<T: Subcommand> ClapMcpHandler<T> {
-    fn extract_subcommands() -> Vec<Tool> {
-        let cmd = T::augment_subcommands(clap::Command::new("mcp"));
-        let mut tools = Vec::new();
-
-        for subcommand in cmd.get_subcommands() {
-            let name = subcommand.get_name().to_string();
-            let description = subcommand
-                .get_about()
-                .map(|s| s.to_string())
-                .unwrap_or_default();
-
-            let mut properties = HashMap::new();
-            let mut required = Vec::new();
-
-            // Extract arguments
-            let mut positional_count = 0;
-            for arg in subcommand.get_arguments() {
-                if arg.is_hide_set() || arg.get_id() == "help" || arg.get_id() == "version" {
-                    continue;
-                }
+    /// Run at most `limit` tool calls at once
+    ///
+    /// Further calls wait for a running one to finish, or fail with a busy
+    /// error after [`with_busy_action`](Self::with_busy_action)`(BusyAction::Reject)`.
+    /// Calls given up on after a timeout hold their slot until the handler
+    /// returns.
+    pub fn with_max_concurrency(mut self, limit: usize) -> Self {
+        self.options.concurrency = Some(policy::ConcurrencyLimit::new(limit));
+        self
+    }
 
-                let arg_name = arg.get_id().to_string();
-                let is_positional = arg.get_long().is_none() && arg.get_short().is_none();
+    /// How calls beyond [`with_max_concurrency`](Self::with_max_concurrency)
+    /// are handled
+    pub fn with_busy_action(mut self, action: policy::BusyAction) -> Self {
+        self.options.busy_action = action;
+        self
+    }
 
-                let arg_type = if arg.get_num_args().map(|r| r.min_values()).unwrap_or(0) == 0 {
-                    "boolean"
-                } else {
-                    // For now, default to string. A more sophisticated type detection
-                    // would require runtime information about the value parser
-                    "string"
-                };
+    /// Take the locks of `#[mcp(serialize_by = "...")]` tools from
+    /// `provider` instead of the process, so they hold across every server
+    /// sharing it, e.g. `RedisLocks` with the `redis` feature
+    pub fn with_lock_provider(mut self, provider: impl policy::LockProvider + 'static) -> Self {
+        self.options.lock_provider = Some(Arc::new(provider));
+        self
+    }
 
-                let mut schema = json!({
-                    "type": arg_type
-                });
+    /// Keep the variables and sticky arguments of sessions in `store`, so
+    /// clients passing the same `_meta.sessionToken` after reconnecting
+    /// resume them; see [`vars`]
+    pub fn with_session_store(mut self, store: impl vars::SessionStore + 'static) -> Self {
+        self.options.session_store = Some(Arc::new(store));
+        self
+    }
 
-                if let Some(help) = arg.get_help() {
-                    schema["description"] = json!(help.to_string());
-                }
+    /// Fill in arguments calls omit from the client's workspace, e.g. a
+    /// `--path` from its first root; see [`workspace`]
+    pub fn with_workspace_defaults(mut self, defaults: workspace::WorkspaceDefaults) -> Self {
+        self.options.workspace_defaults = Some(defaults);
+        self
+    }
 
-                // Add metadata to indicate positional arguments
-                if is_positional {
-                    schema["x-positional"] = json!(true);
-                    // Use the index if available, otherwise use a counter
-                    let position = arg.get_index().unwrap_or_else(|| {
-                        let pos = positional_count;
-                        positional_count += 1;
-                        pos
-                    });
-                    schema["x-position"] = json!(position);
-                }
+    /// Offer the MCP `logging` capability, sending the `tracing` events of
+    /// handlers to the calling client as log messages
+    ///
+    /// Needs [`logging::McpLogLayer`] in the subscriber; see [`logging`].
+    #[cfg(feature = "logging")]
+    pub fn with_logging(mut self) -> Self {
+        self.options.logging = true;
+        self
+    }
 
-                properties.insert(arg_name.clone(), schema);
+    /// Wrap `tools/list` and `tools/call` in `tracing` spans
+    ///
+    /// `call_tool` spans carry the tool name and record the call's outcome
+    /// and `duration_ms`; `list_tools` spans record the number of tools.
+    /// Events logged while handling a call, including the redacted command
+    /// line at trace level, are nested in its span.
+    pub fn with_tracing(mut self, enabled: bool) -> Self {
+        self.options.tracing = enabled;
+        self
+    }
 
-                if arg.is_required_set() {
-                    required.push(arg_name);
-                }
+    /// Serve each tool's most recent successful result as the resource
+    /// `last_result://<tool>`, to the caller that got it (see
+    /// [`resources::LastResults`])
+    pub fn with_last_results(mut self) -> Self {
+        self.options.last_results = Some(resources::LastResults::new());
+        self
+    }
+
+    /// Shorten tool and argument descriptions over `limits`, serving the
+    /// full text as the resource `help://<tool>` (see [`descriptions`])
+    pub fn with_description_limits(mut self, limits: descriptions::DescriptionLimits) -> Self {
+        let limited = self.options.description_limits.replace(limits).is_some();
+        match limited {
+            true => self,
+            false => {
+                let help = self.options.help.clone();
+                self.with_resources(help)
             }
+        }
+    }
 
-            let input_schema = json!({
-                "type": "object",
-                "properties": properties,
-                "required": required
-            });
+    /// Expose only the tools named in `tools`, hiding the rest from listings
+    /// and rejecting calls to them as calls to unknown tools
+    ///
+    /// Applies to subcommands, built-in, runtime and bridged tools alike, and
+    /// can be called again to allow more. Meant for operators trimming a
+    /// server through configuration:
+    ///
+    /// ```ignore
+    /// if let Ok(tools) = std::env::var("MYTOOL_MCP_TOOLS") {
+    ///     server = server.with_allowed_tools(tools.split(','));
+    /// }
+    /// ```
+    pub fn with_allowed_tools(
+        mut self,
+        tools: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let allowed = self
+            .options
+            .allowed_tools
+            .get_or_insert_with(Default::default);
+        allowed.extend(tools.into_iter().map(Into::into));
+        self
+    }
 
-            tools.push(Tool {
-                name: name.into(),
-                description: Some(description.into()),
-                input_schema: Arc::new(object(input_schema)),
-                annotations: None,
-            });
-        }
+    /// Hide the tools named in `tools`, even if allowed, rejecting calls to
+    /// them as calls to unknown tools
+    pub fn with_denied_tools(mut self, tools: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let denied = tools.into_iter().map(Into::into);
+        self.options.denied_tools.extend(denied);
+        self
+    }
 
-        tools
+    /// Let only the principals `authorizer` accepts call each tool (see
+    /// [`authz`])
+    pub fn with_authorizer(mut self, authorizer: impl authz::ToolAuthorizer + 'static) -> Self {
+        self.options.authorizer = Some(Arc::new(authorizer));
+        self
     }
-}
 
-impl<T: Subcommand + Send + Sync + 'static> ServerHandler for ClapMcpHandler<T> {
-    fn get_info(&self) -> InitializeResult {
-        InitializeResult {
-            protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities {
-                tools: Some(ToolsCapability::default()),
-                ..Default::default()
-            },
-            server_info: Implementation {
-                name: "clap-mcp-server".to_string(),
-                version: env!("CARGO_PKG_VERSION").to_string(),
-            },
-            instructions: None,
+    /// Enforce daily and monthly quotas per principal (see [`quota`])
+    pub fn with_quotas(mut self, quotas: quota::Quotas) -> Self {
+        self.options.quotas = Some(quotas);
+        self
+    }
+
+    /// End the results of calls counted against a quota with a JSON block
+    /// giving the units left, e.g. `{"quota":{"daily":997}}`
+    pub fn with_quota_report(mut self) -> Self {
+        self.options.quota_report = true;
+        self
+    }
+
+    /// Weight calls of `tool` by `cost` when metering and enforcing quotas;
+    /// calls cost 1 by default
+    pub fn with_tool_cost(mut self, tool: impl Into<String>, cost: u64) -> Self {
+        self.options.tool_costs.insert(tool.into(), cost);
+        self
+    }
+
+    /// Generate server messages (validation errors, notices) in `language`
+    pub fn with_language(self, language: messages::Language) -> Self {
+        self.with_translator(language)
+    }
+
+    /// Generate server messages with a custom translator
+    ///
+    /// Messages it returns `None` for fall back to English.
+    pub fn with_translator(mut self, translator: impl messages::Translator + 'static) -> Self {
+        self.options.messages = messages::Messages::new(translator);
+        self
+    }
+
+    /// Accept the top-level options `G` in a `global` object on every tool
+    ///
+    /// Handlers read them with [`context::global_args`]; see [`globals`].
+    pub fn with_global_args<G: clap::Args + Send + Sync + 'static>(mut self) -> Self {
+        self.options.globals = Some(globals::GlobalArgs::new::<G>());
+        self
+    }
+
+    /// Check every tool call against `policy` before it runs, e.g.
+    /// [`MaintenanceWindows`](policy::MaintenanceWindows) loaded from a config file
+    pub fn with_policy(mut self, policy: impl policy::ToolPolicy + 'static) -> Self {
+        self.options.policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Register a secret that `#[mcp(secret_ref)]` arguments can name
+    ///
+    /// Agents pass `name`; the server substitutes `value` before running the
+    /// command and redacts it from the output.
+    pub fn with_secret(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.secrets.insert(name.into(), value.into());
+        self
+    }
+
+    /// Register a `capabilities` tool listing the optional features enabled
+    /// on this server, for agents that adapt to what is available
+    ///
+    /// A subcommand of the same name takes precedence.
+    pub fn with_support_matrix(mut self) -> Self {
+        self.options.support_matrix = true;
+        self
+    }
+
+    /// Return at most `page_size` tools per `tools/list` request
+    ///
+    /// Clients follow the `nextCursor` of each page to the next one, so CLIs
+    /// with hundreds of subcommands need not answer with one huge list.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.options.page_size = Some(page_size);
+        self
+    }
+
+    /// Register a `validate_call` tool that checks a call to another tool
+    /// without running it
+    ///
+    /// Agents can pre-check risky invocations and fix their arguments before
+    /// any side effect happens; see [`validate`]. A subcommand of the same
+    /// name takes precedence.
+    pub fn with_validate_tool(mut self) -> Self {
+        self.options.validate_tool = true;
+        self
+    }
+
+    /// Let calls pass `"_parse_only": true` to get the arguments clap parsed,
+    /// as JSON, instead of running the command
+    ///
+    /// Shows agents (and whoever debugs the argument mapping) what a call
+    /// turns into. See [`validate`].
+    pub fn with_parse_only(mut self) -> Self {
+        self.options.parse_only = true;
+        self
+    }
+
+    /// Run calls to `#[mcp(destructive)]` tools only once the user confirmed
+    /// them with the token the first call returns
+    ///
+    /// The token passes through the agent, so this is advisory only; use
+    /// [`with_confirmation_handler`](Self::with_confirmation_handler) to ask
+    /// the user directly. See [`confirm`].
+    pub fn with_destructive_confirmation(mut self) -> Self {
+        self.options.confirm_destructive = true;
+        self
+    }
+
+    /// Run calls to `#[mcp(destructive)]` tools only if `approver`, given the
+    /// tool name and the command line it would run, returns `true`
+    ///
+    /// The approver asks the user outside the conversation, e.g. in a dialog,
+    /// and may block until they answer. See [`confirm`].
+    pub fn with_confirmation_handler(
+        mut self,
+        approver: impl Fn(&str, &[String]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.options.confirm_destructive = true;
+        self.options.approver = Some(Arc::new(approver));
+        self
+    }
+
+    /// Run tool calls as invocations of `program` instead of a handler
+    ///
+    /// Each call's subcommand and arguments, as clap parsed them, become the
+    /// program's command line; its stdout, stderr and exit status are
+    /// returned. See [`exec`].
+    pub fn with_exec(mut self, program: impl Into<std::path::PathBuf>) -> Self {
+        self.options.exec = Some(exec::Exec::new(program));
+        self
+    }
+
+    /// Choose what calls do when no handler is set, instead of failing
+    ///
+    /// Has no effect with a handler or [`with_exec`](Self::with_exec). See
+    /// [`exec::MissingHandler`].
+    pub fn with_missing_handler(mut self, behavior: exec::MissingHandler) -> Self {
+        self.options.missing_handler = behavior;
+        self
+    }
+
+    /// Kill exec programs still running after `timeout`
+    ///
+    /// Defaults to [`exec::DEFAULT_TIMEOUT`]. Has no effect without
+    /// [`with_exec`](Self::with_exec).
+    pub fn with_exec_timeout(mut self, timeout: std::time::Duration) -> Self {
+        if let Some(exec) = &mut self.options.exec {
+            exec.timeout = timeout;
         }
+        self
     }
 
-    async fn list_tools(
-        &self,
-        _request: Option<PaginatedRequestParam>,
-        _context: RequestContext<RoleServer>,
-    ) -> Result<ListToolsResult, McpError> {
-        let tools = Self::extract_subcommands();
-        Ok(ListToolsResult {
-            tools,
-            next_cursor: None,
+    /// Decode the output of exec programs as `encoding`
+    ///
+    /// Defaults to [`encoding::OutputEncoding::Auto`]: UTF-8 unless a byte
+    /// order mark says otherwise. Has no effect without
+    /// [`with_exec`](Self::with_exec).
+    pub fn with_exec_encoding(mut self, encoding: encoding::OutputEncoding) -> Self {
+        if let Some(exec) = &mut self.options.exec {
+            exec.encoding = encoding;
+        }
+        self
+    }
+
+    /// Require HTTP clients to send `Authorization: Bearer <token>`
+    ///
+    /// Can be called several times to accept several tokens. See [`auth`].
+    pub fn with_auth_token(self, token: impl Into<String>) -> Self {
+        self.with_auth(auth::BearerToken::new(token))
+    }
+
+    /// Require HTTP clients to send a bearer token `provider` accepts
+    ///
+    /// Calls are attributed to the principal the token authenticates.
+    pub fn with_auth(mut self, provider: impl auth::AuthProvider + 'static) -> Self {
+        self.auth.push(Arc::new(provider));
+        self.options.authenticated = true;
+        self
+    }
+
+    /// Adjust the capabilities advertised to clients
+    ///
+    /// Runs after the defaults (tools, plus `serverMetadata` when set) are
+    /// filled in, so it can toggle draft features the SDK has no builder for:
+    ///
+    /// ```ignore
+    /// McpServer::<Commands>::new().with_capabilities(|caps| {
+    ///     caps.tools.get_or_insert_with(Default::default).list_changed = Some(true);
+    /// })
+    /// ```
+    pub fn with_capabilities(
+        mut self,
+        hook: impl Fn(&mut rmcp::model::ServerCapabilities) + Send + Sync + 'static,
+    ) -> Self {
+        self.options.capability_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Advertise `value` under `name` in the experimental capabilities
+    pub fn with_experimental_capability(
+        self,
+        name: impl Into<String>,
+        value: rmcp::model::JsonObject,
+    ) -> Self {
+        let name = name.into();
+        self.with_capabilities(move |caps| {
+            caps.experimental
+                .get_or_insert_with(Default::default)
+                .insert(name.clone(), value.clone());
         })
     }
 
-    async fn call_tool(
+    /// The registry of tools added while the server runs (see [`tools`])
+    ///
+    /// Clones share it, so one kept before serving still adds tools after.
+    pub fn tool_registry(&self) -> tools::ToolRegistry {
+        self.options.runtime_tools.clone()
+    }
+
+    /// Add a tool not backed by a subcommand, calling `handler` with its
+    /// arguments; connected clients are told the tool list changed
+    pub fn register_tool(
         &self,
-        request: CallToolRequestParam,
-        _context: RequestContext<RoleServer>,
-    ) -> Result<CallToolResult, McpError> {
-            let tool_name = request.name.to_string();
-            let arguments = request.arguments.unwrap_or_default();
-
-            // Get the tool definition to check which arguments are positional
-            let tools = Self::extract_subcommands();
-            let tool = tools.iter().find(|t| t.name == tool_name);
-
-            // Build command line arguments
-            // First arg should be the program name, then the subcommand
-            let mut args = vec!["mcp".to_string(), tool_name.clone()];
-
-            // Separate positional and named arguments
-            let mut positional_args: Vec<(String, serde_json::Value, usize)> = Vec::new();
-            let mut named_args = HashMap::new();
-
-            for (key, value) in arguments {
-                // Check if this argument is positional by looking at the tool schema
-                let is_positional = tool
-                    .and_then(|t| {
-                        t.input_schema
-                            .get("properties")
-                            .and_then(|props| props.get(&key))
-                            .and_then(|schema| schema.get("x-positional"))
-                            .and_then(|v| v.as_bool())
-                    })
-                    .unwrap_or(false);
-
-                if is_positional {
-                    let position =
-                        tool.and_then(|t| {
-                            t.input_schema
-                                .get("properties")
-                                .and_then(|props| props.get(&key))
-                                .and_then(|schema| schema.get("x-position"))
-                                .and_then(|v| v.as_u64())
-                        })
-                        .unwrap_or(positional_args.len() as u64) as usize;
-
-                    positional_args.push((key, value, position));
-                } else {
-                    named_args.insert(key, value);
-                }
-            }
+        tool: rmcp::model::Tool,
+        handler: impl Fn(rmcp::model::JsonObject) -> Result<output::ToolOutput, output::ToolError>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        self.options.runtime_tools.register(tool, handler);
+    }
 
-            // Sort positional arguments by their position
-            positional_args.sort_by_key(|&(_, _, pos)| pos);
+    /// Also serve the subcommands of another enum `U`, with tool names
+    /// starting with `prefix` (which may be empty), running `handler` for them
+    ///
+    /// For CLIs composing several `Subcommand` enums. The tools are listed
+    /// after those of `T`, which wins when names collide (see
+    /// [`tools::ToolRegistry::register_subcommands`]).
+    pub fn with_subcommands<U: Subcommand + McpTools + 'static>(
+        self,
+        prefix: &str,
+        handler: CommandHandler<U>,
+    ) -> Self {
+        self.options
+            .runtime_tools
+            .register_subcommands(prefix, structured(handler));
+        self
+    }
 
-            // Add positional arguments first (without -- prefix)
-            for (_, value, _) in positional_args {
-                match value {
-                    serde_json::Value::String(s) => args.push(s),
-                    serde_json::Value::Number(n) => args.push(n.to_string()),
-                    serde_json::Value::Bool(b) => args.push(b.to_string()),
-                    _ => args.push(value.to_string()),
-                }
-            }
+    /// Remove a tool added with [`McpServer::register_tool`], returning
+    /// whether it was registered
+    pub fn unregister_tool(&self, name: &str) -> bool {
+        self.options.runtime_tools.unregister(name)
+    }
 
-            // Then add named arguments with -- prefix
-            for (key, value) in named_args {
-                match value {
-                    serde_json::Value::Bool(b) => {
-                        if b {
-                            args.push(format!("--{}", key));
-                        }
-                        // Skip false boolean flags
-                    }
-                    serde_json::Value::String(s) => {
-                        args.push(format!("--{}", key));
-                        args.push(s);
-                    }
-                    serde_json::Value::Number(n) => {
-                        args.push(format!("--{}", key));
-                        args.push(n.to_string());
-                    }
-                    _ => {
-                        args.push(format!("--{}", key));
-                        args.push(value.to_string());
-                    }
-                }
-            }
+    /// Re-export the tools of the MCP server at `remote_url` (an SSE endpoint)
+    ///
+    /// Remote tools are listed next to the clap-derived ones, prefixed with the
+    /// remote server's name (e.g. `github_create_issue`), and calls to them are
+    /// forwarded. The connection is made when the server starts serving.
+    #[cfg(feature = "client")]
+    pub fn bridge(mut self, remote_url: impl Into<String>) -> Self {
+        self.bridges.push(BridgeConfig {
+            url: remote_url.into(),
+            prefix: None,
+        });
+        self
+    }
 
-            // Parse the arguments into a subcommand
-            let cmd = T::augment_subcommands(clap::Command::new("mcp"));
-            match cmd.try_get_matches_from(&args) {
-                Ok(matches) => {
-                    match T::from_arg_matches(&matches) {
-                        Ok(subcommand) => {
-                            // Use the handler if provided
-                            if let Some(handler) = &self.handler {
-                                match handler(subcommand) {
-                                    Ok(output) => {
-                                        Ok(CallToolResult::success(vec![Content::text(output)]))
-                                    }
-                                    Err(e) => Ok(CallToolResult::error(vec![Content::text(e)])),
-                                }
-                            } else {
-                                Ok(CallToolResult::error(vec![Content::text(
-                                    "No command handler provided. The CLI must provide a handler function to execute commands in MCP mode."
-                                )]))
-                            }
-                        }
-                        Err(e) => Err(McpError::invalid_params(
-                            format!("Failed to parse subcommand: {}", e),
-                            None,
-                        )),
-                    }
-                }
-                Err(e) => Err(McpError::invalid_params(
-                    format!("Invalid arguments: {}", e),
-                    None,
-                )),
-            }
+    /// Like [`McpServer::bridge`], with an explicit tool name prefix
+    #[cfg(feature = "client")]
+    pub fn bridge_with_prefix(
+        mut self,
+        remote_url: impl Into<String>,
+        prefix: impl Into<String>,
+    ) -> Self {
+        self.bridges.push(BridgeConfig {
+            url: remote_url.into(),
+            prefix: Some(prefix.into()),
+        });
+        self
+    }
+
+    /// Build the request handler without serving it
+    ///
+    /// Connects bridged servers and returns a [`ClapMcpHandler`] carrying this
+    /// builder's settings, for mounting in a custom rmcp service.
+    pub async fn into_handler(self) -> Result<ClapMcpHandler<T>, Box<dyn std::error::Error>> {
+        let remotes = Remotes::connect(&self.bridges).await?;
+        Ok(ClapMcpHandler::with_options(self.handler, self.options).with_remotes(remotes))
+    }
+
+    /// Re-execute a recorded tool call locally, for debugging
+    ///
+    /// The call goes through the same argument pipeline as a live one; the
+    /// returned [`Replay`](replay::Replay) holds the command line clap parsed,
+    /// secrets redacted, and the result the client would have received.
+    pub async fn replay(
+        self,
+        entry: &replay::AuditEntry,
+    ) -> Result<replay::Replay, Box<dyn std::error::Error>> {
+        Ok(self.into_handler().await?.replay(entry))
+    }
+
+    /// Every tool the server lists, as a JSON Schema or OpenAPI document
+    /// (see [`export`])
+    pub async fn export_schema(
+        self,
+        format: export::SchemaFormat,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        use rmcp::ServerHandler;
+
+        let handler = self.into_handler().await?;
+        let tools = handler.list_all_tools().await;
+        let server = handler.get_info().server_info;
+        Ok(export::document(format, &server, &tools))
+    }
+
+    pub async fn serve_stdio(self) -> Result<(), Box<dyn std::error::Error>> {
+        self.serve(StdioTransport).await
+    }
+
+    /// Serve one session with stdio framing over `reader` and `writer`
+    ///
+    /// For byte streams other than the process's stdin/stdout, such as an SSH
+    /// exec channel, so remote hosts can be reached without exposing HTTP.
+    pub async fn serve_stdio_on<R, W>(
+        self,
+        reader: R,
+        writer: W,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        R: tokio::io::AsyncRead + Send + Unpin + 'static,
+        W: tokio::io::AsyncWrite + Send + Unpin + 'static,
+    {
+        self.serve(IoTransport::new(reader, writer)).await
+    }
+
+    pub async fn serve_http(self, addr: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+        self.serve(SseTransport::new(addr)).await
+    }
+
+    /// Serve over HTTP in the background, returning once listening
+    ///
+    /// Unlike [`serve_http`](Self::serve_http), which runs until Ctrl-C, the
+    /// server stops when the returned handle cancels it. Bind port 0 to have
+    /// one picked, and read it from [`ServerHandle::local_addr`].
+    #[cfg(feature = "http")]
+    pub async fn spawn_http(
+        mut self,
+        addr: SocketAddr,
+    ) -> Result<ServerHandle, Box<dyn std::error::Error>> {
+        let mut transport = SseTransport::new(addr);
+        let auth = std::mem::take(&mut self.auth);
+        if !auth.is_empty() {
+            transport = transport.with_auth(auth);
+        }
+        transport.spawn(self.into_handler().await?).await
+    }
+
+    /// Serve over stdio in the background, until the client disconnects or
+    /// the returned handle cancels it
+    #[cfg(not(target_family = "wasm"))]
+    pub async fn spawn_stdio(self) -> Result<ServerHandle, Box<dyn std::error::Error>> {
+        StdioTransport.spawn(self.into_handler().await?).await
+    }
+
+    /// Serve over `transport`: an [`McpTransport`], a built-in transport, or
+    /// one from another crate
+    ///
+    /// With authentication configured, transports that cannot check
+    /// credentials refuse to serve.
+    pub async fn serve(
+        mut self,
+        transport: impl McpTransportProvider,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let auth = std::mem::take(&mut self.auth);
+        let handler = self.into_handler().await?;
+        if auth.is_empty() {
+            transport.serve(handler).await
+        } else {
+            transport.serve_with_auth(handler, Arc::new(auth)).await
+        }
     }
 }
 
-#[cfg(test)]
+// These tests drive the server over SSE with the bundled client
+#[cfg(all(test, feature = "http", feature = "client"))]
 mod tests {
     use super::*;
     use clap::Subcommand;
     use rmcp::transport::sse_server::{SseServer, SseServerConfig};
+    use rmcp::{handler::server::ServerHandler, model::*};
     use serde_json::json;
     use std::time::Duration;
     use tokio_util::sync::CancellationToken;
 
-    #[derive(Subcommand, Clone)]
+    #[derive(
+        Subcommand, Clone, serde::Serialize, crate::McpClient, crate::McpSchema, crate::McpTools,
+    )]
     enum TestCommands {
         /// Add two numbers
         Add {
@@ -375,6 +885,7 @@ mod tests {
             b: i32,
         },
         /// Subtract two numbers
+        #[command(alias = "sub")]
         Subtract {
             /// Number to subtract from
             #[arg(long)]
@@ -506,10 +1017,35 @@ mod tests {
     /// Start an in-process MCP server
     async fn start_in_process_server<T: Subcommand + Send + Sync + Clone + 'static>(
         handler: CommandHandler<T>,
+    ) -> Result<(CancellationToken, u16), Box<dyn std::error::Error>> {
+        start_in_process_server_with_options(handler, ServerOptions::default()).await
+    }
+
+    /// Start an in-process MCP server with custom server options
+    async fn start_in_process_server_with_options<T: Subcommand + Send + Sync + Clone + 'static>(
+        handler: CommandHandler<T>,
+        options: ServerOptions,
+    ) -> Result<(CancellationToken, u16), Box<dyn std::error::Error>> {
+        start_in_process_handler(ClapMcpHandler::<T>::with_options(
+            Some(crate::structured(handler)),
+            options,
+        ))
+        .await
+    }
+
+    async fn start_in_process_handler<T: Subcommand + Send + Sync + Clone + 'static>(
+        handler: ClapMcpHandler<T>,
+    ) -> Result<(CancellationToken, u16), Box<dyn std::error::Error>> {
+        start_in_process_handler_with_auth(handler, None).await
+    }
+
+    /// Start an in-process MCP server whose endpoints require `auth`
+    async fn start_in_process_handler_with_auth<T: Subcommand + Send + Sync + Clone + 'static>(
+        handler: ClapMcpHandler<T>,
+        auth: Option<Arc<dyn auth::AuthProvider>>,
     ) -> Result<(CancellationToken, u16), Box<dyn std::error::Error>> {
         let port = get_available_port().await;
         let addr = format!("127.0.0.1:{}", port).parse()?;
-        let handler = ClapMcpHandler::<T>::new(Some(handler));
 
         let config = SseServerConfig {
             bind: addr,
@@ -520,6 +1056,10 @@ mod tests {
         };
 
         let (sse_server, router) = SseServer::new(config);
+        let router = match auth {
+            Some(auth) => auth::require_auth(router, auth),
+            None => router,
+        };
         let ct = sse_server.config.ct.clone();
 
         let listener = tokio::net::TcpListener::bind(sse_server.config.bind).await?;
@@ -607,35 +1147,102 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_missing_arguments() {
-        use crate::test_client::McpTestClient;
+    async fn test_typed_client_call() {
+        use crate::client::McpClient;
 
-        // Start server
         let (ct, port) = start_in_process_server::<TestCommands>(Box::new(execute_test_command))
             .await
             .expect("Failed to start server");
 
-        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+        let client = McpClient::connect(&format!("127.0.0.1:{}", port))
             .await
             .expect("Failed to connect to server");
 
-        // Try calling add without any arguments - the call itself succeeds but returns an error result
-        let result = client.call_tool("add", Some(json!({}))).await;
+        let result = client
+            .call(&TestCommands::Add { a: 10, b: 32 })
+            .await
+            .expect("Failed to call add");
+        assert_eq!(
+            McpClient::extract_text(&result).as_deref(),
+            Some("10 + 32 = 42")
+        );
 
-        // The call should succeed but return an error in the result
-        match result {
-            Ok(call_result) => {
-                // We expect this to be an error response (missing required arguments)
-                assert!(
-                    call_result.is_error.unwrap_or(false),
-                    "Expected error for missing arguments"
-                );
-            }
-            Err(e) => {
-                // This is actually expected - the MCP error for invalid arguments
-                assert!(
-                    e.to_string().contains("Invalid arguments")
-                        || e.to_string().contains("required arguments"),
+        let result = client
+            .call(&TestCommands::Hello {
+                name: "Typed".to_string(),
+                excited: false,
+            })
+            .await
+            .expect("Failed to call hello");
+        assert_eq!(
+            McpClient::extract_text(&result).as_deref(),
+            Some("Hello, Typed.")
+        );
+
+        client.shutdown().await.expect("Failed to shutdown client");
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_derived_client_stub() {
+        let (ct, port) = start_in_process_server::<TestCommands>(Box::new(execute_test_command))
+            .await
+            .expect("Failed to start server");
+
+        let client = TestCommandsClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let result = client
+            .multiply(7, 6)
+            .await
+            .expect("Failed to call multiply");
+        assert_eq!(
+            crate::client::McpClient::extract_text(&result).as_deref(),
+            Some("7 * 6 = 42")
+        );
+
+        let result = client
+            .hello("Stub".to_string(), true)
+            .await
+            .expect("Failed to call hello");
+        assert_eq!(
+            crate::client::McpClient::extract_text(&result).as_deref(),
+            Some("Hello, Stub!!!")
+        );
+
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_missing_arguments() {
+        use crate::test_client::McpTestClient;
+
+        // Start server
+        let (ct, port) = start_in_process_server::<TestCommands>(Box::new(execute_test_command))
+            .await
+            .expect("Failed to start server");
+
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        // Try calling add without any arguments - the call itself succeeds but returns an error result
+        let result = client.call_tool("add", Some(json!({}))).await;
+
+        // The call should succeed but return an error in the result
+        match result {
+            Ok(call_result) => {
+                // We expect this to be an error response (missing required arguments)
+                assert!(
+                    call_result.is_error.unwrap_or(false),
+                    "Expected error for missing arguments"
+                );
+            }
+            Err(e) => {
+                // This is actually expected - the MCP error for invalid arguments
+                assert!(
+                    e.to_string().contains("Missing required argument `a`"),
                     "Unexpected error: {}",
                     e
                 );
@@ -653,8 +1260,7 @@ mod tests {
             }
             Err(e) => {
                 assert!(
-                    e.to_string().contains("Invalid arguments")
-                        || e.to_string().contains("required arguments"),
+                    e.to_string().contains("Missing required argument `b`"),
                     "Unexpected error: {}",
                     e
                 );
@@ -666,6 +1272,40 @@ mod tests {
         ct.cancel();
     }
 
+    #[tokio::test]
+    async fn test_arguments_validated_against_schema() {
+        use crate::test_client::McpTestClient;
+
+        let (ct, port) = start_in_process_server::<TestCommands>(Box::new(execute_test_command))
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let err = client
+            .call_tool("add", Some(json!({ "a": "five", "b": 3 })))
+            .await
+            .expect_err("a string for an integer should fail");
+        assert!(
+            err.to_string()
+                .contains("Invalid argument `a`: \"five\" is not of type \"integer\""),
+            "unexpected error: {err}"
+        );
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 5, "b": 3 })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("5 + 3 = 8")
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
     #[tokio::test]
     async fn test_positional_args() {
         use crate::test_client::McpTestClient;
@@ -777,4 +1417,4646 @@ mod tests {
         client.shutdown().await.expect("Failed to shutdown client");
         ct.cancel();
     }
+
+    #[tokio::test]
+    async fn test_output_sanitization() {
+        use crate::test_client::McpTestClient;
+
+        let options = ServerOptions {
+            sanitizer: OutputSanitizer::default().with_tables_to_markdown(true),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(|_| Ok("\x1b[1;32mdone\x1b[0m\r\n+---+\r\n| a |\r\n+---+".to_string())),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .expect("Failed to call add");
+        let text = McpTestClient::extract_text(&result).expect("No text in result");
+        assert_eq!(text, "done\n| a |\n| --- |");
+
+        client.shutdown().await.expect("Failed to shutdown client");
+        ct.cancel();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools, crate::McpSchema)]
+    enum TypedOutputCommands {
+        /// Show the current configuration
+        #[mcp(output_mime = "application/json", read_only)]
+        ShowConfig,
+        /// Say hello
+        Greet,
+        /// Create a tag
+        #[command(alias = "mktag")]
+        #[mcp(interpolate, destructive, idempotent)]
+        Tag {
+            /// Tag name
+            #[arg(long, id = "tag_name")]
+            #[mcp(schema = r#"{"type": "string", "pattern": "^[a-z-]+$"}"#)]
+            name: String,
+        },
+        /// Log in to the registry
+        Login {
+            /// Registry token
+            #[arg(long)]
+            #[mcp(secret_ref)]
+            token: String,
+        },
+        /// Delete every record
+        #[command(alias = "nuke")]
+        #[mcp(skip)]
+        WipeDb,
+    }
+
+    #[tokio::test]
+    async fn test_bridge_reexports_remote_tools() {
+        use crate::test_client::McpTestClient;
+
+        let (remote_ct, remote_port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(|cmd| match cmd {
+                TestCommands::Add { a, b } => Ok(format!("{}", a + b)),
+                _ => Err("unsupported".to_string()),
+            }),
+            ServerOptions::default(),
+        )
+        .await
+        .expect("Failed to start remote server");
+        let remote_url = format!("http://127.0.0.1:{}/sse", remote_port);
+
+        let handler = McpServer::<TypedOutputCommands>::new()
+            .with_handler(Box::new(|_| Ok("local".to_string())))
+            .bridge_with_prefix(remote_url.as_str(), "calc_")
+            .bridge(remote_url.as_str())
+            .into_handler()
+            .await
+            .expect("Failed to connect bridges");
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start bridging server");
+
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.expect("Failed to list tools");
+        let names: Vec<&str> = tools.iter().map(|t| t.name.as_ref()).collect();
+        assert!(names.contains(&"greet"));
+        assert!(names.contains(&"calc_add"));
+        // Default prefix comes from the remote's server name
+        assert!(names.contains(&"clap_mcp_server_add"));
+
+        let result = client
+            .call_tool("calc_add", Some(json!({"a": 2, "b": 3})))
+            .await
+            .expect("Failed to call bridged tool");
+        assert_eq!(McpTestClient::extract_text(&result).as_deref(), Some("5"));
+
+        let result = client
+            .call_tool("greet", None)
+            .await
+            .expect("Failed to call local tool");
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("local")
+        );
+
+        client.shutdown().await.expect("Failed to shutdown client");
+        ct.cancel();
+        remote_ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_bridged_calls_time_out() {
+        use crate::test_client::McpTestClient;
+
+        // The remote stalls on every call
+        let (remote_ct, remote_port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(|_| {
+                std::thread::sleep(Duration::from_secs(2));
+                Ok("late".to_string())
+            }),
+            ServerOptions::default(),
+        )
+        .await
+        .expect("Failed to start remote server");
+        let remote_url = format!("http://127.0.0.1:{}/sse", remote_port);
+
+        let handler = McpServer::<TypedOutputCommands>::new()
+            .with_handler(Box::new(|_| Ok("local".to_string())))
+            .with_timeout(Duration::from_millis(300))
+            .bridge_with_prefix(remote_url.as_str(), "calc_")
+            .into_handler()
+            .await
+            .expect("Failed to connect bridges");
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start bridging server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let started = std::time::Instant::now();
+        let result = client
+            .call_tool("calc_add", Some(json!({"a": 2, "b": 3})))
+            .await
+            .expect("Failed to call bridged tool");
+        assert!(started.elapsed() < Duration::from_millis(1500));
+        assert_eq!(result.is_error, Some(true));
+        let text = McpTestClient::extract_text(&result).unwrap();
+        assert!(text.contains("timed out"), "{}", text);
+
+        client.shutdown().await.expect("Failed to shutdown client");
+        ct.cancel();
+        remote_ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_output_mime_attribute() {
+        use crate::test_client::McpTestClient;
+
+        let options = ServerOptions {
+            tool_attributes: TypedOutputCommands::tool_attributes()
+                .into_iter()
+                .map(|attrs| (attrs.name.clone(), attrs))
+                .collect(),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TypedOutputCommands>(
+            Box::new(|cmd| match cmd {
+                TypedOutputCommands::ShowConfig => Ok(r#"{"debug":true}"#.to_string()),
+                TypedOutputCommands::Greet => Ok("hello".to_string()),
+                TypedOutputCommands::Tag { name } => Ok(name),
+                TypedOutputCommands::Login { token } => Ok(token),
+                TypedOutputCommands::WipeDb => Ok("wiped".to_string()),
+            }),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let result = client
+            .call_tool("show-config", None)
+            .await
+            .expect("Failed to call show-config");
+        match &result.content[0].raw {
+            RawContent::Resource(embedded) => match &embedded.resource {
+                ResourceContents::TextResourceContents {
+                    mime_type, text, ..
+                } => {
+                    assert_eq!(mime_type.as_deref(), Some("application/json"));
+                    assert_eq!(text, r#"{"debug":true}"#);
+                }
+                other => panic!("Unexpected resource: {:?}", other),
+            },
+            other => panic!("Expected embedded resource, got {:?}", other),
+        }
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some(r#"{"debug":true}"#)
+        );
+
+        let result = client
+            .call_tool("greet", None)
+            .await
+            .expect("Failed to call greet");
+        assert!(matches!(result.content[0].raw, RawContent::Text(_)));
+
+        // Per-argument schema overrides merge over the generated schema
+        let tools = client.list_tools().await.expect("Failed to list tools");
+        let tag = tools.iter().find(|t| t.name == "tag").unwrap();
+        let schema = &tag.input_schema["properties"]["tag_name"];
+        assert_eq!(schema["pattern"], "^[a-z-]+$");
+        assert_eq!(schema["description"], "Tag name");
+
+        // Tools that interpolate list `_vars`, in both schema sources
+        let vars = &tag.input_schema["properties"][vars::VARS_KEY];
+        assert_eq!(vars["type"], "object");
+        let generated = TypedOutputCommands::TOOLS
+            .iter()
+            .find(|t| t.name == "tag")
+            .unwrap()
+            .to_tool();
+        assert_eq!(&generated.input_schema["properties"][vars::VARS_KEY], vars);
+
+        client.shutdown().await.expect("Failed to shutdown client");
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_skip_attribute_hides_tool() {
+        use crate::test_client::McpTestClient;
+
+        let options = ServerOptions {
+            tool_attributes: TypedOutputCommands::tool_attributes()
+                .into_iter()
+                .map(|attrs| (attrs.name.clone(), attrs))
+                .collect(),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TypedOutputCommands>(
+            Box::new(|cmd| match cmd {
+                TypedOutputCommands::WipeDb => panic!("skipped tools must never run"),
+                _ => Ok("ok".to_string()),
+            }),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.expect("Failed to list tools");
+        assert!(tools.iter().any(|t| t.name == "greet"));
+        assert!(!tools.iter().any(|t| t.name == "wipe-db"));
+        assert!(!TypedOutputCommands::TOOLS
+            .iter()
+            .any(|t| t.name == "wipe-db"));
+
+        let err = client
+            .call_tool("wipe-db", None)
+            .await
+            .expect_err("skipped tool should be rejected");
+        assert!(
+            err.to_string().contains("Unknown tool `wipe-db`"),
+            "unexpected error: {err}"
+        );
+        // Aliases are the same tool, without revealing its name
+        let err = client
+            .call_tool("nuke", None)
+            .await
+            .expect_err("alias of a skipped tool should be rejected");
+        assert!(
+            err.to_string().contains("Unknown tool `nuke`"),
+            "unexpected error: {err}"
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_validate_call_tool() {
+        use crate::test_client::McpTestClient;
+
+        let options = ServerOptions {
+            tool_attributes: TypedOutputCommands::tool_attributes()
+                .into_iter()
+                .map(|attrs| (attrs.name.clone(), attrs))
+                .collect(),
+            validate_tool: true,
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TypedOutputCommands>(
+            Box::new(|_| panic!("validated calls must not run")),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let tools = client.list_tools().await.unwrap();
+        assert!(tools.iter().any(|t| t.name == "validate_call"));
+
+        let validate = |arguments: serde_json::Value| {
+            let client = &client;
+            async move {
+                let result = client
+                    .call_tool("validate_call", Some(arguments))
+                    .await
+                    .unwrap();
+                let text = McpTestClient::extract_text(&result).unwrap();
+                serde_json::from_str::<serde_json::Value>(&text).unwrap()
+            }
+        };
+        let report = validate(json!({
+            "tool": "tag",
+            "arguments": { "_vars": { "env": "prod" }, "tag_name": "${env}" },
+        }))
+        .await;
+        assert_eq!(report, json!({ "valid": true, "tool": "tag" }));
+
+        let report =
+            validate(json!({ "tool": "tag", "arguments": { "tag_name": "Bad Tag" } })).await;
+        assert_eq!(report["valid"], false);
+        assert!(
+            report["error"]
+                .as_str()
+                .unwrap()
+                .contains("Invalid argument `tag_name`"),
+            "{report}"
+        );
+
+        // Variables set by a validated call are not remembered
+        let report =
+            validate(json!({ "tool": "tag", "arguments": { "tag_name": "${env}" } })).await;
+        assert_eq!(report["valid"], false);
+
+        let err = client
+            .call_tool("validate_call", Some(json!({ "arguments": {} })))
+            .await
+            .expect_err("the tool to validate is required");
+        assert!(err.to_string().contains("Missing required argument `tool`"));
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[test]
+    fn test_tool_annotations() {
+        let options = ServerOptions {
+            tool_attributes: TypedOutputCommands::tool_attributes()
+                .into_iter()
+                .map(|attrs| (attrs.name.clone(), attrs))
+                .collect(),
+            ..Default::default()
+        };
+        let handler = ClapMcpHandler::<TypedOutputCommands>::with_options(None, options);
+        let tools = handler.tools();
+        let annotations = |name: &str| {
+            tools
+                .iter()
+                .find(|tool| tool.name == name)
+                .unwrap()
+                .annotations
+                .clone()
+        };
+
+        let show_config = annotations("show-config").unwrap();
+        assert_eq!(show_config.read_only_hint, Some(true));
+        assert_eq!(show_config.destructive_hint, None);
+        let tag = annotations("tag").unwrap();
+        assert_eq!(tag.destructive_hint, Some(true));
+        assert_eq!(tag.idempotent_hint, Some(true));
+        assert_eq!(annotations("greet"), None);
+
+        // Schemas are computed once and shared by the sessions' clones
+        assert!(Arc::ptr_eq(
+            &handler.clone().tools()[0].input_schema,
+            &tools[0].input_schema
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_policy_rejects_with_retry_after() {
+        use crate::policy::PolicyDecision;
+        use crate::test_client::McpTestClient;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::Duration;
+
+        // `subtract` is queued once, then allowed when the policy is asked again
+        let queued = Arc::new(AtomicBool::new(false));
+        let policy = {
+            let queued = queued.clone();
+            move |tool: &str, _| match tool {
+                "add" => PolicyDecision::Reject {
+                    reason: "change freeze".to_string(),
+                    retry_after: Duration::from_secs(90),
+                },
+                "subtract" if !queued.swap(true, Ordering::SeqCst) => PolicyDecision::Queue {
+                    wait: Duration::from_millis(10),
+                },
+                "multiply" => PolicyDecision::Queue {
+                    wait: Duration::from_secs(60),
+                },
+                _ => PolicyDecision::Allow,
+            }
+        };
+        let (calls, mut ended) = tokio::sync::mpsc::unbounded_channel();
+        let options = ServerOptions {
+            policy: Some(Arc::new(policy)),
+            meter: Some(Arc::new(
+                move |call: &metering::ToolCall, _: &metering::Outcome| {
+                    let _ = calls.send((call.tool.clone(), call.duration));
+                },
+            )),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(execute_test_command),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let err = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .expect_err("policy should reject add");
+        match err.downcast_ref::<rmcp::service::ServiceError>() {
+            Some(rmcp::service::ServiceError::McpError(e)) => {
+                assert!(e.message.contains("change freeze"), "{}", e.message);
+                assert_eq!(e.data.as_ref().unwrap()["retryAfter"], 90);
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        let result = client
+            .call_tool("subtract", Some(json!({ "minuend": 5, "subtrahend": 2 })))
+            .await
+            .expect("queued call should run");
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "5 - 2 = 3");
+        assert!(queued.load(Ordering::SeqCst));
+
+        // A call cancelled while queued ends without waiting out the queue
+        while ended.try_recv().is_ok() {}
+        let request = rmcp::model::ClientRequest::CallToolRequest(rmcp::model::Request::new(
+            CallToolRequestParam {
+                name: "multiply".into(),
+                arguments: json!({ "value1": 2, "value2": 3 }).as_object().cloned(),
+            },
+        ));
+        let handle = client
+            .peer()
+            .send_cancellable_request(request, rmcp::service::PeerRequestOptions::no_options())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.cancel(None).await.unwrap();
+        let (tool, duration) = tokio::time::timeout(Duration::from_secs(2), ended.recv())
+            .await
+            .expect("cancelled call should end promptly")
+            .unwrap();
+        assert_eq!(tool, "multiply");
+        assert!(duration < Duration::from_secs(1));
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_meter_totals_weighted_costs() {
+        use crate::metering::{Ledger, PrincipalUsage};
+        use crate::test_client::McpTestClient;
+
+        let ledger = Ledger::new();
+        let options = ServerOptions {
+            meter: Some(Arc::new(ledger.clone())),
+            tool_costs: [("multiply".to_string(), 10)].into_iter().collect(),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(execute_test_command),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        client
+            .call_tool("multiply", Some(json!({ "value1": 2, "value2": 3 })))
+            .await
+            .unwrap();
+        client
+            .call_tool("multiply", Some(json!({})))
+            .await
+            .expect_err("missing arguments should fail");
+
+        // Billed to the client's declared name, as the calls name no principal
+        assert_eq!(
+            ledger.usage("clap-mcp-client"),
+            PrincipalUsage { calls: 3, cost: 11 }
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_quota_enforced_per_principal() {
+        use crate::quota::{Quota, Quotas, Remaining};
+        use crate::test_client::McpTestClient;
+
+        let quotas = Quotas::new(Quota::default().daily(3));
+        let options = ServerOptions {
+            quotas: Some(quotas.clone()),
+            quota_report: true,
+            tool_costs: [("multiply".to_string(), 2)].into_iter().collect(),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(execute_test_command),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        assert!(matches!(
+            &result.content.last().unwrap().raw,
+            RawContent::Text(text) if text.text == r#"{"quota":{"daily":2}}"#
+        ));
+        // Refused before running, so refunded
+        client
+            .call_tool("add", Some(json!({})))
+            .await
+            .expect_err("missing arguments should fail");
+        client
+            .call_tool("multiply", Some(json!({ "value1": 2, "value2": 3 })))
+            .await
+            .unwrap();
+        assert_eq!(
+            quotas.remaining("clap-mcp-client"),
+            Remaining {
+                daily: Some(0),
+                monthly: None
+            }
+        );
+
+        let err = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .expect_err("quota should be exhausted");
+        match err.downcast_ref::<rmcp::service::ServiceError>() {
+            Some(rmcp::service::ServiceError::McpError(e)) => {
+                let data = e.data.as_ref().unwrap();
+                assert_eq!(data["period"], "daily");
+                assert_eq!(data["limit"], 3);
+                assert!(data["retryAfter"].as_u64().unwrap() <= 86_400);
+                assert_eq!(data["remaining"], json!({ "daily": 0 }));
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_quota_kept_for_calls_that_ran() {
+        use crate::quota::{Quota, Quotas};
+        use rmcp::ServiceExt;
+
+        let quotas = Quotas::new(Quota::default().daily(5));
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_quotas(quotas.clone());
+        let schema = Arc::new(object(json!({ "type": "object" })));
+        server.register_tool(Tool::new("crash", "Panic", schema), |_| {
+            panic!("runtime tool panicked")
+        });
+        let handler = server.into_handler().await.unwrap();
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            if let Ok(server) = handler.serve(server_io).await {
+                let _ = server.waiting().await;
+            }
+        });
+        let client = ().serve(client_io).await.unwrap();
+        let call = |name: &'static str, arguments: serde_json::Value| CallToolRequestParam {
+            name: name.into(),
+            arguments: arguments.as_object().cloned(),
+        };
+
+        // The default client's name
+        let principal = rmcp::model::Implementation::from_build_env().name;
+
+        assert!(client
+            .call_tool(call("add", json!({ "a": 1, "b": 2 })))
+            .await
+            .is_ok());
+        assert_eq!(quotas.remaining(&principal).daily, Some(4));
+        // Refused before running
+        assert!(client.call_tool(call("add", json!({}))).await.is_err());
+        assert_eq!(quotas.remaining(&principal).daily, Some(4));
+        // Ran, then failed
+        assert!(client.call_tool(call("crash", json!({}))).await.is_err());
+        assert_eq!(quotas.remaining(&principal).daily, Some(3));
+
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cached_authorizer() {
+        use crate::authz::{CacheStats, CachedAuthorizer};
+        use crate::test_client::McpTestClient;
+
+        let authorizer = CachedAuthorizer::new(
+            |principal: &str, tool: &str| principal == "clap-mcp-client" && tool != "divide",
+            Duration::from_secs(60),
+        );
+        let options = ServerOptions {
+            authorizer: Some(Arc::new(authorizer.clone())),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(execute_test_command),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        for _ in 0..2 {
+            let result = client
+                .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+                .await
+                .unwrap();
+            assert_eq!(result.is_error, Some(false));
+        }
+        let err = client
+            .call_tool("divide", Some(json!({ "dividend": 4, "divisor": 2 })))
+            .await
+            .expect_err("divide should be denied");
+        assert!(err
+            .to_string()
+            .contains("`clap-mcp-client` is not allowed to call tool `divide`"));
+        assert_eq!(
+            authorizer.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 2,
+                entries: 2
+            }
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_localized_server_messages() {
+        use crate::messages::{Language, Messages};
+        use crate::test_client::McpTestClient;
+
+        let options = ServerOptions {
+            messages: Messages::new(Language::German),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(execute_test_command),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let err = client
+            .call_tool("add", Some(json!({ "a": 1 })))
+            .await
+            .expect_err("missing arguments should fail");
+        assert!(
+            err.to_string()
+                .contains("Erforderliches Argument `b` fehlt"),
+            "unexpected error: {err}"
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_secret_refs_resolved_and_redacted() {
+        use crate::test_client::McpTestClient;
+
+        let mut options = ServerOptions {
+            tool_attributes: TypedOutputCommands::tool_attributes()
+                .into_iter()
+                .map(|attrs| (attrs.name.clone(), attrs))
+                .collect(),
+            ..Default::default()
+        };
+        options
+            .secrets
+            .insert("REGISTRY_TOKEN".to_string(), "tok-9f8e7d".to_string());
+        let (ct, port) = start_in_process_server_with_options::<TypedOutputCommands>(
+            Box::new(|cmd| match cmd {
+                TypedOutputCommands::Login { token } => {
+                    Ok(format!("{} ({} chars)", token, token.len()))
+                }
+                _ => Ok("ok".to_string()),
+            }),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        let login = tools.iter().find(|t| t.name == "login").unwrap();
+        assert_eq!(
+            login.input_schema["properties"]["token"]["x-secret-ref"],
+            true
+        );
+        let generated = TypedOutputCommands::TOOLS
+            .iter()
+            .find(|t| t.name == "login")
+            .unwrap()
+            .to_tool();
+        assert_eq!(
+            generated.input_schema["properties"]["token"],
+            login.input_schema["properties"]["token"]
+        );
+
+        // The handler gets the value; the client only sees the handle
+        let result = client
+            .call_tool("login", Some(json!({ "token": "REGISTRY_TOKEN" })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("[secret:REGISTRY_TOKEN] (10 chars)")
+        );
+
+        let err = client
+            .call_tool("login", Some(json!({ "token": "tok-raw" })))
+            .await
+            .expect_err("unknown handles should fail");
+        assert!(err.to_string().contains("unknown secret `tok-raw`"));
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_argv_traced_with_secrets_redacted() {
+        use crate::test_client::McpTestClient;
+        use std::sync::Mutex;
+
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        // The server runs on this thread, under the test's subscriber
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut options = ServerOptions {
+            tool_attributes: TypedOutputCommands::tool_attributes()
+                .into_iter()
+                .map(|attrs| (attrs.name.clone(), attrs))
+                .collect(),
+            ..Default::default()
+        };
+        options
+            .secrets
+            .insert("REGISTRY_TOKEN".to_string(), "tok-9f8e7d".to_string());
+        let (ct, port) = start_in_process_server_with_options::<TypedOutputCommands>(
+            Box::new(|_| Ok("ok".to_string())),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        client
+            .call_tool("login", Some(json!({ "token": "REGISTRY_TOKEN" })))
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+        ct.cancel();
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            logs.contains(r#"argv=["mcp", "login", "--token=[secret:REGISTRY_TOKEN]"]"#),
+            "{}",
+            logs
+        );
+        assert!(!logs.contains("tok-9f8e7d"), "{}", logs);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exec_mode() {
+        use crate::test_client::McpTestClient;
+
+        let start = |program: &str| {
+            let options = ServerOptions {
+                exec: Some(crate::exec::Exec::new(program)),
+                ..Default::default()
+            };
+            start_in_process_handler(ClapMcpHandler::<TestCommands>::with_options(None, options))
+        };
+
+        // `echo` shows the command line the call became
+        let (ct, port) = start("echo").await.expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let result = client
+            .call_tool("add", Some(json!({ "a": 5, "b": 3 })))
+            .await
+            .unwrap();
+        assert!(!result.is_error.unwrap_or(false));
+        let texts: Vec<_> = result
+            .content
+            .iter()
+            .filter_map(|content| content.as_text().map(|text| text.text.as_str()))
+            .collect();
+        assert_eq!(texts, ["add --a=5 --b=3\n", "exit status: 0"]);
+        // Arguments are still checked before anything runs
+        assert!(client
+            .call_tool("add", Some(json!({ "a": "five", "b": 3 })))
+            .await
+            .is_err());
+        client.shutdown().await.unwrap();
+        ct.cancel();
+
+        let (ct, port) = start("false").await.expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let result = client
+            .call_tool("add", Some(json!({ "a": 5, "b": 3 })))
+            .await
+            .unwrap();
+        assert!(result.is_error.unwrap_or(false));
+        let status = result.content.last().and_then(|content| content.as_text());
+        assert_eq!(status.unwrap().text, "exit status: 1");
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_missing_handler() {
+        use crate::exec::MissingHandler;
+        use crate::output::{ToolError, ToolOutput};
+        use crate::test_client::McpTestClient;
+
+        let call = |missing_handler: MissingHandler| async move {
+            let options = ServerOptions {
+                missing_handler,
+                ..Default::default()
+            };
+            let handler = ClapMcpHandler::<TestCommands>::with_options(None, options);
+            let (ct, port) = start_in_process_handler(handler)
+                .await
+                .expect("Failed to start server");
+            let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+                .await
+                .expect("Failed to connect to server");
+            let result = client
+                .call_tool("add", Some(json!({ "a": 5, "b": 3 })))
+                .await
+                .unwrap();
+            client.shutdown().await.unwrap();
+            ct.cancel();
+            result
+        };
+
+        let result = call(MissingHandler::Error).await;
+        assert!(result.is_error.unwrap_or(false));
+        let text = &result.content[0].as_text().unwrap().text;
+        assert!(text.starts_with("No command handler provided"));
+
+        let result = call(MissingHandler::DryRun).await;
+        assert!(!result.is_error.unwrap_or(false));
+        let echoed: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(
+            echoed,
+            json!({ "tool": "add", "argv": ["add", "--a=5", "--b=3"], "dry_run": true })
+        );
+
+        let fallback = MissingHandler::fallback(|tool, argv| match tool {
+            "add" => Ok(ToolOutput::Text(argv.join(" "))),
+            _ => Err(ToolError::new("unsupported")),
+        });
+        let result = call(fallback).await;
+        assert_eq!(result.content[0].as_text().unwrap().text, "add --a=5 --b=3");
+    }
+
+    #[tokio::test]
+    async fn test_parse_only() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<TestCommands>::new()
+            .with_parse_only()
+            .with_handler(Box::new(|_| panic!("parse-only calls must not run")))
+            .into_handler()
+            .await
+            .unwrap();
+        let add = handler.tools().iter().find(|tool| tool.name == "add");
+        assert_eq!(
+            add.unwrap().input_schema["properties"]["_parse_only"]["type"],
+            "boolean"
+        );
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 5, "b": 3, "_parse_only": true })))
+            .await
+            .unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&McpTestClient::extract_text(&result).unwrap()).unwrap();
+        assert_eq!(
+            report,
+            json!({
+                "tool": "add",
+                "arguments": { "a": 5, "b": 3 },
+                "argv": ["add", "--a=5", "--b=3"],
+            })
+        );
+
+        // Invalid calls fail as they would when run
+        assert!(client
+            .call_tool("add", Some(json!({ "a": 5, "_parse_only": true })))
+            .await
+            .is_err());
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_allowed_and_denied_tools() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_validate_tool()
+            .with_allowed_tools(["add", "subtract"])
+            .with_allowed_tools(["validate_call"])
+            .with_denied_tools(["subtract"])
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        let names: Vec<_> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+        assert_eq!(names, ["add", "validate_call"]);
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "1 + 2 = 3");
+        // Denying a tool also denies its aliases
+        for tool in ["subtract", "sub", "multiply"] {
+            let err = client
+                .call_tool(tool, Some(json!({ "minuend": 3, "subtrahend": 1 })))
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("Unknown tool"), "{err}");
+        }
+        let result = client
+            .call_tool(
+                "validate_call",
+                Some(json!({ "tool": "subtract", "arguments": { "minuend": 3, "subtrahend": 1 } })),
+            )
+            .await
+            .unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&McpTestClient::extract_text(&result).unwrap()).unwrap();
+        assert_eq!(report["valid"], false);
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools)]
+    enum LogCommands {
+        /// Show recent commits
+        Log {
+            /// Number of commits
+            #[arg(short = 'n', required = true)]
+            #[mcp(rename = "max_count", alias = "limit")]
+            n: u32,
+            /// Skip the commit hooks
+            #[arg(long)]
+            #[mcp(alias = "skip_hooks")]
+            no_verify: bool,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_argument_renames_and_aliases() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<LogCommands>::new()
+            .with_tool_attributes(LogCommands::tool_attributes())
+            .with_handler(Box::new(|cmd| match cmd {
+                LogCommands::Log { n, no_verify } => Ok(format!("{} {}", n, no_verify)),
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let schema = handler.tools()[0].input_schema.clone();
+        assert!(schema["properties"].get("n").is_none());
+        assert_eq!(
+            schema["properties"]["max_count"]["x-aliases"],
+            json!(["limit"])
+        );
+        assert_eq!(
+            schema["properties"]["no_verify"]["x-aliases"],
+            json!(["skip_hooks"])
+        );
+        assert_eq!(schema["required"], json!(["max_count"]));
+
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        for arguments in [
+            json!({ "max_count": 3, "no_verify": true }),
+            json!({ "limit": 3, "skip_hooks": true }),
+            json!({ "n": 3, "no_verify": true }),
+        ] {
+            let result = client.call_tool("log", Some(arguments)).await.unwrap();
+            assert_eq!(McpTestClient::extract_text(&result).unwrap(), "3 true");
+        }
+        // Errors name the argument as listed
+        let err = client
+            .call_tool("log", Some(json!({ "limit": "three" })))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("`max_count`"), "{err}");
+        let err = client.call_tool("log", Some(json!({}))).await.unwrap_err();
+        assert!(err.to_string().contains("`max_count`"), "{err}");
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools)]
+    enum SerialCommands {
+        /// Deploy a project
+        #[mcp(serialize_by = "project")]
+        Deploy {
+            #[arg(long)]
+            project: String,
+        },
+        /// Migrate a project's database
+        #[mcp(serialize_by = "project")]
+        Migrate {
+            #[arg(long)]
+            project: String,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_serialize_by_argument() {
+        use crate::test_client::McpTestClient;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+
+        // Calls running per project, and the most seen at once per project
+        // and overall
+        let running_calls = Mutex::new(HashMap::<String, usize>::new());
+        let peaks = Arc::new(Mutex::new(HashMap::<String, usize>::new()));
+        let call_peaks = peaks.clone();
+        let handler = McpServer::<SerialCommands>::new()
+            .with_tool_attributes(SerialCommands::tool_attributes())
+            .with_handler(Box::new(move |cmd| {
+                let (SerialCommands::Deploy { project } | SerialCommands::Migrate { project }) =
+                    cmd;
+                {
+                    let mut running = running_calls.lock().unwrap();
+                    *running.entry(project.clone()).or_default() += 1;
+                    let total = running.values().sum();
+                    let mut peaks = call_peaks.lock().unwrap();
+                    for (key, now) in [(project.clone(), running[&project]), ("*".into(), total)] {
+                        let peak = peaks.entry(key).or_default();
+                        *peak = (*peak).max(now);
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                *running_calls.lock().unwrap().get_mut(&project).unwrap() -= 1;
+                Ok(project)
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let started = std::time::Instant::now();
+        let (deploy, migrate, other) = tokio::join!(
+            client.call_tool("deploy", Some(json!({ "project": "acme" }))),
+            client.call_tool("migrate", Some(json!({ "project": "acme" }))),
+            client.call_tool("deploy", Some(json!({ "project": "zeta" }))),
+        );
+        for (result, project) in [(deploy, "acme"), (migrate, "acme"), (other, "zeta")] {
+            assert_eq!(
+                McpTestClient::extract_text(&result.unwrap()).unwrap(),
+                project
+            );
+        }
+        // Both `acme` calls ran one after the other, `zeta` alongside them
+        assert!(started.elapsed() >= std::time::Duration::from_millis(400));
+        let peaks = peaks.lock().unwrap().clone();
+        assert_eq!(peaks["acme"], 1);
+        assert_eq!(peaks["zeta"], 1);
+        assert_eq!(peaks["*"], 2);
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_lock_provider() {
+        use crate::policy::{LockFuture, LockProvider};
+        use crate::test_client::McpTestClient;
+        use std::sync::Mutex;
+
+        /// Records the keys locked, refusing those of `down`
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+
+        impl LockProvider for Recorder {
+            fn lock<'a>(&'a self, key: &'a str) -> LockFuture<'a> {
+                self.0.lock().unwrap().push(key.to_string());
+                Box::pin(async move {
+                    match key.contains("down") {
+                        true => Err("lock server unreachable".into()),
+                        false => Ok(crate::policy::LockGuard::new(())),
+                    }
+                })
+            }
+        }
+
+        let locks = Recorder::default();
+        let handler = McpServer::<SerialCommands>::new()
+            .with_tool_attributes(SerialCommands::tool_attributes())
+            .with_handler(Box::new(|cmd| {
+                let (SerialCommands::Deploy { project } | SerialCommands::Migrate { project }) =
+                    cmd;
+                Ok(project)
+            }))
+            .with_lock_provider(locks.clone())
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let result = client
+            .call_tool("migrate", Some(json!({ "project": "acme" })))
+            .await
+            .unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "acme");
+        assert_eq!(*locks.0.lock().unwrap(), [r#"project="acme""#]);
+
+        let err = client
+            .call_tool("deploy", Some(json!({ "project": "down" })))
+            .await
+            .expect_err("unavailable locks should fail the call");
+        assert!(err.to_string().contains("lock server unreachable"));
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_spawn_http_shutdown() {
+        use crate::test_client::McpTestClient;
+
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .spawn_http("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        assert_ne!(addr.port(), 0);
+
+        let client = McpTestClient::connect(&addr.to_string())
+            .await
+            .expect("Failed to connect to server");
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "1 + 2 = 3");
+
+        // Stops with the client still connected
+        server.cancel();
+        tokio::time::timeout(Duration::from_secs(5), server.join())
+            .await
+            .expect("server should stop")
+            .unwrap();
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+        let call = client.call_tool("add", Some(json!({ "a": 1, "b": 2 })));
+        let call = tokio::time::timeout(Duration::from_secs(5), call).await;
+        assert!(matches!(call, Ok(Err(_))), "{:?}", call);
+    }
+
+    #[cfg(feature = "logging")]
+    #[tokio::test]
+    async fn test_logging_notifications() {
+        use rmcp::service::{NotificationContext, RoleClient};
+        use rmcp::ServiceExt;
+        use std::sync::Mutex;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        #[derive(Clone, Default)]
+        struct LogClient(Arc<Mutex<Vec<LoggingMessageNotificationParam>>>);
+
+        impl rmcp::ClientHandler for LogClient {
+            async fn on_logging_message(
+                &self,
+                params: LoggingMessageNotificationParam,
+                _context: NotificationContext<RoleClient>,
+            ) {
+                self.0.lock().unwrap().push(params);
+            }
+        }
+
+        // Handlers run on the blocking pool, so the layer must be global
+        let subscriber = tracing_subscriber::registry().with(crate::logging::layer());
+        let _ = tracing::subscriber::set_global_default(subscriber);
+
+        let handler = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(|cmd| {
+                if let TestCommands::Hello { name, .. } = &cmd {
+                    tracing::debug!("looking up a greeting");
+                    tracing::warn!(%name, attempts = 2, "no greeting configured");
+                }
+                execute_test_command(cmd)
+            }))
+            .with_logging()
+            .into_handler()
+            .await
+            .unwrap();
+        assert!(handler.get_info().capabilities.logging.is_some());
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            if let Ok(server) = handler.serve(server_io).await {
+                let _ = server.waiting().await;
+            }
+        });
+        let logs = LogClient::default();
+        let client = logs.clone().serve(client_io).await.unwrap();
+        let hello = || {
+            client.call_tool(CallToolRequestParam {
+                name: "hello".into(),
+                arguments: json!({ "name": "Ada" }).as_object().cloned(),
+            })
+        };
+        let received = |count: usize| {
+            let logs = logs.clone();
+            async move {
+                for _ in 0..100 {
+                    if logs.0.lock().unwrap().len() >= count {
+                        break;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                logs.0.lock().unwrap().clone()
+            }
+        };
+
+        hello().await.unwrap();
+        // Debug events are left out by default
+        let messages = received(1).await;
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0].level, LoggingLevel::Warning));
+        assert_eq!(messages[0].logger.as_deref(), Some("clap_mcp::tests"));
+        assert_eq!(
+            messages[0].data,
+            json!({ "message": "no greeting configured", "name": "Ada", "attempts": 2 })
+        );
+
+        client
+            .set_level(SetLevelRequestParam {
+                level: LoggingLevel::Debug,
+            })
+            .await
+            .unwrap();
+        hello().await.unwrap();
+        let messages = received(3).await;
+        assert_eq!(messages.len(), 3);
+        assert!(matches!(messages[1].level, LoggingLevel::Debug));
+        assert_eq!(messages[1].data["message"], "looking up a greeting");
+
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_streaming_handler() {
+        use crate::test_client::McpTestClient;
+        use rmcp::model::ProgressNotificationParam;
+        use rmcp::service::{NotificationContext, RoleClient};
+        use rmcp::ServiceExt;
+        use std::sync::Mutex;
+
+        #[derive(Clone, Default)]
+        struct ProgressClient(Arc<Mutex<Vec<ProgressNotificationParam>>>);
+
+        impl rmcp::ClientHandler for ProgressClient {
+            async fn on_progress(
+                &self,
+                params: ProgressNotificationParam,
+                _context: NotificationContext<RoleClient>,
+            ) {
+                self.0.lock().unwrap().push(params);
+            }
+        }
+
+        let (stopped_tx, stopped) = std::sync::mpsc::channel();
+        let handler = McpServer::<TestCommands>::new()
+            .with_timeout(Duration::from_millis(200))
+            .with_streaming_handler(Box::new(move |cmd, sink| match cmd {
+                TestCommands::Add { a, b } => {
+                    for i in a..=b {
+                        sink.send(format!("{}\n", i));
+                    }
+                    Ok("done".to_string())
+                }
+                // Follows until the call is given up on
+                _ => {
+                    while sink.send("tick\n") {
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                    let _ = stopped_tx.send(());
+                    Ok(String::new())
+                }
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            if let Ok(server) = handler.serve(server_io).await {
+                let _ = server.waiting().await;
+            }
+        });
+        let progress = ProgressClient::default();
+        let client = progress.clone().serve(client_io).await.unwrap();
+        let call = |name: &str, arguments: serde_json::Value| {
+            client.call_tool(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments: arguments.as_object().cloned(),
+            })
+        };
+
+        // Chunks arrive as progress before the result, which holds them all.
+        // rmcp clients give every request a progress token.
+        let result = call("add", json!({ "a": 1, "b": 3 })).await.unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).unwrap(),
+            "1\n2\n3\ndone"
+        );
+        let received = progress.0.lock().unwrap().clone();
+        let messages: Vec<_> = received
+            .iter()
+            .map(|params| params.message.as_deref().unwrap())
+            .collect();
+        assert_eq!(messages, ["1\n", "2\n", "3\n"]);
+        assert_eq!(received[2].progress, 3);
+        assert_eq!(received[0].progress_token, received[2].progress_token);
+
+        // A handler given up on is told to stop
+        let result = call("multiply", json!({ "value1": 1, "value2": 2 }))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        stopped
+            .recv_timeout(Duration::from_secs(5))
+            .expect("handler should stop streaming");
+
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_state_survives_reconnects() {
+        use crate::test_client::McpTestClient;
+        use crate::vars::{MemorySessionStore, SESSION_TOKEN_META_KEY};
+        use rmcp::model::{CallToolRequest, ClientRequest, GetMeta};
+
+        let handler = McpServer::<ProjectCommands>::new()
+            .with_tool_attributes(ProjectCommands::tool_attributes())
+            .with_session_store(MemorySessionStore::new())
+            .with_handler(Box::new(|cmd| match cmd {
+                ProjectCommands::Deploy { project, version } => {
+                    Ok(format!("{} {}", project, version))
+                }
+                ProjectCommands::Status { project } => Ok(project),
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let call = |client: &McpTestClient,
+                    name: &'static str,
+                    arguments: serde_json::Value,
+                    token: &str| {
+            let mut request =
+                ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+                    name: name.into(),
+                    arguments: arguments.as_object().cloned(),
+                }));
+            request
+                .get_meta_mut()
+                .insert(SESSION_TOKEN_META_KEY.to_string(), json!(token));
+            let peer = client.peer().clone();
+            async move {
+                match peer.send_request(request).await {
+                    Ok(rmcp::model::ServerResult::CallToolResult(result)) => {
+                        McpTestClient::extract_text(&result)
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        let first = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let deployed = call(
+            &first,
+            "deploy",
+            json!({ "project": "acme", "version": "1.2" }),
+            "tok-1",
+        );
+        assert_eq!(deployed.await.unwrap(), "acme 1.2");
+        first.shutdown().await.unwrap();
+
+        // A new connection with the token resumes the sticky project
+        let second = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let status = call(&second, "status", json!({}), "tok-1").await;
+        assert_eq!(status.unwrap(), "acme");
+        // Other tokens and calls without one start afresh
+        let third = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        assert!(call(&third, "status", json!({}), "tok-2").await.is_none());
+        assert!(third.call_tool("status", Some(json!({}))).await.is_err());
+
+        second.shutdown().await.unwrap();
+        third.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_session_state_is_bound_to_the_principal() {
+        use crate::client::McpClient;
+        use crate::vars::{MemorySessionStore, SESSION_TOKEN_META_KEY};
+        use rmcp::model::{CallToolRequest, ClientRequest, GetMeta, ServerResult};
+
+        let server = McpServer::<ProjectCommands>::new()
+            .with_tool_attributes(ProjectCommands::tool_attributes())
+            .with_session_store(MemorySessionStore::new())
+            .with_auth(auth::Principals(|token: &str| {
+                token.strip_prefix("user-").map(str::to_string)
+            }))
+            .with_handler(Box::new(|cmd| match cmd {
+                ProjectCommands::Deploy { project, version } => {
+                    Ok(format!("{} {}", project, version))
+                }
+                ProjectCommands::Status { project } => Ok(project),
+            }))
+            .spawn_http("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let url = format!("http://{}/sse", server.local_addr().unwrap());
+        // Everyone names the same session token
+        let call = |client: &McpClient, name: &'static str, arguments: serde_json::Value| {
+            let mut request =
+                ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+                    name: name.into(),
+                    arguments: arguments.as_object().cloned(),
+                }));
+            request
+                .get_meta_mut()
+                .insert(SESSION_TOKEN_META_KEY.to_string(), json!("shared"));
+            let peer = client.peer().clone();
+            async move {
+                match peer.send_request(request).await {
+                    Ok(ServerResult::CallToolResult(result)) => McpClient::extract_text(&result),
+                    _ => None,
+                }
+            }
+        };
+        let connect = |token: &'static str| McpClient::connect_url_with_token(&url, token);
+
+        let alice = connect("user-alice").await.unwrap();
+        let deploy = json!({ "project": "acme", "version": "1.2" });
+        assert_eq!(call(&alice, "deploy", deploy).await.unwrap(), "acme 1.2");
+        alice.shutdown().await.unwrap();
+
+        let bob = connect("user-bob").await.unwrap();
+        assert!(call(&bob, "status", json!({})).await.is_none());
+        let deploy = json!({ "project": "globex", "version": "2.0" });
+        assert_eq!(call(&bob, "deploy", deploy).await.unwrap(), "globex 2.0");
+        bob.shutdown().await.unwrap();
+
+        // Each principal resumes its own state under the shared token
+        let alice = connect("user-alice").await.unwrap();
+        assert_eq!(call(&alice, "status", json!({})).await.unwrap(), "acme");
+        let bob = connect("user-bob").await.unwrap();
+        assert_eq!(call(&bob, "status", json!({})).await.unwrap(), "globex");
+
+        alice.shutdown().await.unwrap();
+        bob.shutdown().await.unwrap();
+        server.cancel();
+    }
+
+    #[derive(Subcommand, Clone)]
+    enum WorkspaceCommands {
+        /// Build a project
+        Build {
+            #[arg(long)]
+            path: Option<String>,
+            #[arg(long)]
+            manifest: Option<String>,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_workspace_defaults_from_roots() {
+        use rmcp::model::{
+            ClientCapabilities, ClientInfo, ListRootsResult, Root, RootsCapabilities,
+        };
+        use rmcp::service::{RequestContext, RoleClient};
+        use rmcp::ServiceExt;
+        use std::sync::Mutex;
+
+        #[derive(Clone, Default)]
+        struct RootsClient(Arc<Mutex<String>>);
+
+        impl rmcp::ClientHandler for RootsClient {
+            fn get_info(&self) -> ClientInfo {
+                ClientInfo {
+                    capabilities: ClientCapabilities {
+                        roots: Some(RootsCapabilities {
+                            list_changed: Some(true),
+                        }),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }
+            }
+
+            async fn list_roots(
+                &self,
+                _context: RequestContext<RoleClient>,
+            ) -> Result<ListRootsResult, rmcp::Error> {
+                let uri = self.0.lock().unwrap().clone();
+                Ok(ListRootsResult {
+                    roots: vec![Root { uri, name: None }],
+                })
+            }
+        }
+
+        let base = std::env::temp_dir().join(format!("clap-mcp-roots-{}", std::process::id()));
+        let (first, second) = (base.join("first"), base.join("second"));
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(first.join("Cargo.toml"), "").unwrap();
+
+        let handler = McpServer::<WorkspaceCommands>::new()
+            .with_workspace_defaults(
+                crate::workspace::WorkspaceDefaults::new()
+                    .root("path")
+                    .project_file("manifest", ["Cargo.toml"]),
+            )
+            .with_handler(Box::new(|cmd| match cmd {
+                WorkspaceCommands::Build { path, manifest } => Ok(format!(
+                    "{} {}",
+                    path.unwrap_or_default(),
+                    manifest.unwrap_or_else(|| "-".to_string())
+                )),
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            if let Ok(server) = handler.serve(server_io).await {
+                let _ = server.waiting().await;
+            }
+        });
+        let roots = RootsClient::default();
+        *roots.0.lock().unwrap() = format!("file://{}", first.display());
+        let client = roots.clone().serve(client_io).await.unwrap();
+        let build = |arguments: serde_json::Value| {
+            let call = client.call_tool(CallToolRequestParam {
+                name: "build".into(),
+                arguments: arguments.as_object().cloned(),
+            });
+            async move {
+                let result = call.await.unwrap();
+                crate::test_client::McpTestClient::extract_text(&result).unwrap()
+            }
+        };
+
+        let expected = format!("{} {}", first.display(), first.join("Cargo.toml").display());
+        assert_eq!(build(json!({})).await, expected);
+        // Arguments the call passes win
+        assert_eq!(
+            build(json!({ "path": "/elsewhere" })).await,
+            format!("/elsewhere {}", first.join("Cargo.toml").display())
+        );
+
+        // Changed roots are listed again
+        *roots.0.lock().unwrap() = format!("file://{}", second.display());
+        client.notify_roots_list_changed().await.unwrap();
+        let mut text = String::new();
+        for _ in 0..100 {
+            text = build(json!({})).await;
+            if text.starts_with(&second.display().to_string()) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(text, format!("{} -", second.display()));
+
+        client.cancel().await.unwrap();
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_description_limits() {
+        use crate::descriptions::DescriptionLimits;
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_description_limits(DescriptionLimits::new().tool(40))
+            .with_description_limits(DescriptionLimits::new().tool(12).argument(10))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        let hello = tools.iter().find(|tool| tool.name == "hello").unwrap();
+        let description = hello.description.as_deref().unwrap();
+        assert_eq!(description, "Say hello to");
+        for property in hello.input_schema["properties"]
+            .as_object()
+            .unwrap()
+            .values()
+        {
+            if let Some(description) = property["description"].as_str() {
+                assert!(description.chars().count() <= 10, "{}", description);
+            }
+        }
+
+        let resources = client.list_resources().await.unwrap();
+        assert!(resources
+            .iter()
+            .any(|resource| resource.uri == "help://hello"));
+        let help = client.read_resource("help://hello").await.unwrap();
+        let rmcp::model::ResourceContents::TextResourceContents { text, .. } = &help[0] else {
+            panic!("help is not text");
+        };
+        let original = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .into_handler()
+            .await
+            .unwrap();
+        let full = original
+            .tools()
+            .iter()
+            .find(|tool| tool.name == "hello")
+            .unwrap();
+        assert!(text.contains(full.description.as_deref().unwrap()));
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_destructive_confirmation() {
+        use crate::confirm::CONFIRM_KEY;
+        use crate::test_client::McpTestClient;
+
+        let ran = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let calls = ran.clone();
+        let server = McpServer::<TypedOutputCommands>::new()
+            .with_tool_attributes(TypedOutputCommands::tool_attributes())
+            .with_destructive_confirmation()
+            .with_handler(Box::new(move |cmd| {
+                let output = match cmd {
+                    TypedOutputCommands::Tag { name } => name,
+                    _ => "other".to_string(),
+                };
+                calls.lock().unwrap().push(output.clone());
+                Ok(output)
+            }))
+            .spawn_http("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let client = McpTestClient::connect(&server.local_addr().unwrap().to_string())
+            .await
+            .unwrap();
+
+        let tools = client.list_tools().await.unwrap();
+        let schema = |name: &str| {
+            let tool = tools.iter().find(|tool| tool.name == name).unwrap();
+            serde_json::Value::Object((*tool.input_schema).clone())
+        };
+        assert_eq!(schema("tag")["properties"][CONFIRM_KEY]["type"], "string");
+        assert!(schema("greet")["properties"].get(CONFIRM_KEY).is_none());
+
+        // The first call only returns a token
+        let result = client
+            .call_tool("tag", Some(json!({ "tag_name": "release" })))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        let text = McpTestClient::extract_text(&result).unwrap();
+        assert!(text.contains("`tag --name=release`"), "{}", text);
+        let token = text.split('"').nth(3).unwrap().to_string();
+        assert!(ran.lock().unwrap().is_empty());
+
+        // It confirms only the command it was issued for, once
+        let tag = |name: &str, token: &str| {
+            client.call_tool("tag", Some(json!({ "tag_name": name, CONFIRM_KEY: token })))
+        };
+        let result = tag("other-tag", &token).await.unwrap();
+        assert_eq!(result.is_error, Some(true));
+        let result = tag("release", &token).await.unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "release");
+        let result = tag("release", &token).await.unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(*ran.lock().unwrap(), ["release"]);
+
+        // Aliases wait for confirmation as well
+        let result = client
+            .call_tool("mktag", Some(json!({ "tag_name": "release" })))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(ran.lock().unwrap().len(), 1);
+
+        // Other tools run right away
+        let result = client.call_tool("greet", Some(json!({}))).await.unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "other");
+
+        client.shutdown().await.unwrap();
+        server.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_confirmation_handler() {
+        use crate::confirm::CONFIRM_KEY;
+        use crate::test_client::McpTestClient;
+
+        let asked = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let questions = asked.clone();
+        let server = McpServer::<TypedOutputCommands>::new()
+            .with_tool_attributes(TypedOutputCommands::tool_attributes())
+            .with_confirmation_handler(move |tool, command| {
+                questions
+                    .lock()
+                    .unwrap()
+                    .push(format!("{}: {}", tool, command.join(" ")));
+                command.iter().any(|arg| arg == "--name=release")
+            })
+            .with_handler(Box::new(|cmd| match cmd {
+                TypedOutputCommands::Tag { name } => Ok(name),
+                _ => Ok("other".to_string()),
+            }))
+            .spawn_http("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let client = McpTestClient::connect(&server.local_addr().unwrap().to_string())
+            .await
+            .unwrap();
+
+        // No token is issued, so none is accepted either
+        let tools = client.list_tools().await.unwrap();
+        let tag = tools.iter().find(|tool| tool.name == "tag").unwrap();
+        assert!(tag.input_schema["properties"].get(CONFIRM_KEY).is_none());
+
+        // Approved calls run on the first try
+        let result = client
+            .call_tool("tag", Some(json!({ "tag_name": "release" })))
+            .await
+            .unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "release");
+
+        let result = client
+            .call_tool("tag", Some(json!({ "tag_name": "nightly" })))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            McpTestClient::extract_text(&result).unwrap(),
+            "Tool `tag` is destructive and was not run: the user did not approve `tag --name=nightly`"
+        );
+
+        // Other tools run without asking
+        let result = client.call_tool("greet", Some(json!({}))).await.unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "other");
+        assert_eq!(
+            *asked.lock().unwrap(),
+            ["tag: tag --name=release", "tag: tag --name=nightly"]
+        );
+
+        client.shutdown().await.unwrap();
+        server.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_custom_transport_provider() {
+        use rmcp::handler::server::ServerHandler;
+        use rmcp::ServiceExt;
+
+        // An in-memory pipe standing in for a downstream transport
+        struct Duplex(tokio::io::DuplexStream);
+        impl McpTransportProvider for Duplex {
+            async fn serve<H: ServerHandler + Clone>(
+                self,
+                handler: H,
+            ) -> Result<(), Box<dyn std::error::Error>> {
+                rmcp::serve_server(handler, self.0).await?.waiting().await?;
+                Ok(())
+            }
+        }
+
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .serve(Duplex(server_io));
+        let client = async {
+            let client = ().serve(client_io).await.unwrap();
+            let result = client
+                .call_tool(CallToolRequestParam {
+                    name: "add".into(),
+                    arguments: json!({ "a": 5, "b": 3 }).as_object().cloned(),
+                })
+                .await
+                .unwrap();
+            client.cancel().await.unwrap();
+            result
+        };
+
+        let (served, result) = tokio::join!(server, client);
+        served.unwrap();
+        assert_eq!(
+            result.content[0].as_text().map(|text| text.text.as_str()),
+            Some("5 + 3 = 8")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_serve_stdio_on_byte_stream() {
+        use rmcp::ServiceExt;
+
+        // Stands in for an SSH exec channel; authentication is the channel's job
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let (reader, writer) = tokio::io::split(server_io);
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_auth_token("s3cret")
+            .serve_stdio_on(reader, writer);
+        let client = async {
+            let client = ().serve(client_io).await.unwrap();
+            let tools = client.list_all_tools().await.unwrap();
+            client.cancel().await.unwrap();
+            tools.len()
+        };
+
+        let (served, tools) = tokio::join!(server, client);
+        served.unwrap();
+        assert_eq!(tools, 5);
+    }
+
+    #[tokio::test]
+    async fn test_list_tools_pagination() {
+        use rmcp::model::PaginatedRequestParam;
+        use rmcp::ServiceExt;
+
+        let (server_io, client_io) = tokio::io::duplex(4096);
+        let handler = McpServer::<TestCommands>::new()
+            .with_page_size(2)
+            .into_handler()
+            .await
+            .unwrap();
+        tokio::spawn(async move {
+            if let Ok(server) = handler.serve(server_io).await {
+                let _ = server.waiting().await;
+            }
+        });
+        let client = ().serve(client_io).await.unwrap();
+
+        let mut pages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = client
+                .list_tools(Some(PaginatedRequestParam { cursor }))
+                .await
+                .unwrap();
+            pages.push(page.tools.len());
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, [2, 2, 1]);
+        assert_eq!(client.list_all_tools().await.unwrap().len(), 5);
+
+        let cursor = Some("nope".to_string());
+        let err = client
+            .list_tools(Some(PaginatedRequestParam { cursor }))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid pagination cursor"),
+            "{err}"
+        );
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_auth() {
+        use crate::client::McpClient;
+
+        let handler = ClapMcpHandler::<TestCommands>::new(Box::new(execute_test_command));
+        let auth: Vec<Arc<dyn auth::AuthProvider>> =
+            vec![Arc::new(auth::BearerToken::new("s3cret"))];
+        let (ct, port) = start_in_process_handler_with_auth(handler, Some(Arc::new(auth)))
+            .await
+            .expect("Failed to start server");
+        let url = format!("http://127.0.0.1:{}", port);
+
+        // Both endpoints answer 401 without an accepted token
+        let http = reqwest::Client::new();
+        for (method, path) in [
+            (reqwest::Method::GET, "/sse"),
+            (reqwest::Method::POST, "/message"),
+        ] {
+            let response = http
+                .request(method.clone(), format!("{}{}", url, path))
+                .bearer_auth("wrong")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 401, "{} {}", method, path);
+            assert_eq!(response.headers()["www-authenticate"], "Bearer");
+        }
+        assert!(McpClient::connect_url(&format!("{}/sse", url))
+            .await
+            .is_err());
+
+        let client = McpClient::connect_url_with_token(&format!("{}/sse", url), "s3cret")
+            .await
+            .expect("Failed to connect with the token");
+        let result = client
+            .call_tool("add", Some(json!({ "a": 5, "b": 3 })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpClient::extract_text(&result).as_deref(),
+            Some("5 + 3 = 8")
+        );
+        client.shutdown().await.unwrap();
+
+        // Headers set on the builder reach the server
+        let client = McpClient::builder()
+            .header("Authorization", "Bearer s3cret")
+            .header("X-Tenant", "acme")
+            .connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect with the header");
+        assert_eq!(client.list_tools().await.unwrap().len(), 5);
+        client.shutdown().await.unwrap();
+        assert!(McpClient::builder()
+            .bearer_token("wrong")
+            .connect(&format!("127.0.0.1:{}", port))
+            .await
+            .is_err());
+
+        // Invalid headers are reported before connecting
+        let err = McpClient::builder()
+            .header("X-Tenant", "line\nbreak")
+            .bearer_token("s3cret")
+            .connect(&format!("127.0.0.1:{}", port))
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().starts_with("invalid value for header"),
+            "{}",
+            err
+        );
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_principal() {
+        use crate::client::McpClient;
+        use crate::metering::{Ledger, PRINCIPAL_META_KEY};
+        use rmcp::model::{CallToolRequest, ClientRequest, GetMeta};
+
+        let ledger = Ledger::new();
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_auth(auth::Principals(|token: &str| {
+                token.strip_prefix("user-").map(str::to_string)
+            }))
+            .with_auth_token("shared")
+            .with_authorizer(|principal: &str, tool: &str| principal != "bob" || tool == "add")
+            .with_meter(ledger.clone())
+            .spawn_http("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let url = format!("http://{}/sse", server.local_addr().unwrap());
+
+        // Naming someone else in `_meta` changes nothing
+        let call = |client: &McpClient, name: &'static str, arguments: serde_json::Value| {
+            let mut request =
+                ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+                    name: name.into(),
+                    arguments: arguments.as_object().cloned(),
+                }));
+            request
+                .get_meta_mut()
+                .insert(PRINCIPAL_META_KEY.to_string(), json!("alice"));
+            let peer = client.peer().clone();
+            async move { peer.send_request(request).await }
+        };
+        let bob = McpClient::connect_url_with_token(&url, "user-bob")
+            .await
+            .unwrap();
+        let subtract = json!({ "minuend": 3, "subtrahend": 1 });
+        call(&bob, "add", json!({ "a": 1, "b": 2 })).await.unwrap();
+        let err = call(&bob, "subtract", subtract.clone()).await.unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("`bob` is not allowed to call tool `subtract`"));
+        bob.shutdown().await.unwrap();
+
+        let shared = McpClient::connect_url_with_token(&url, "shared")
+            .await
+            .unwrap();
+        call(&shared, "subtract", subtract).await.unwrap();
+        shared.shutdown().await.unwrap();
+
+        assert_eq!(ledger.usage("bob").calls, 2);
+        assert_eq!(ledger.usage(auth::AUTHENTICATED).calls, 1);
+        assert_eq!(ledger.usage("alice").calls, 0);
+        server.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_quota_keyed_by_authenticated_principal() {
+        use crate::client::McpClient;
+        use crate::metering::PRINCIPAL_META_KEY;
+        use crate::quota::{Quota, Quotas};
+        use rmcp::model::{CallToolRequest, ClientRequest, GetMeta};
+
+        let quotas = Quotas::new(Quota::default().daily(1));
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_auth(auth::Principals(|token: &str| {
+                token.strip_prefix("user-").map(str::to_string)
+            }))
+            .with_quotas(quotas.clone())
+            .spawn_http("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let url = format!("http://{}/sse", server.local_addr().unwrap());
+        let client = McpClient::connect_url_with_token(&url, "user-bob")
+            .await
+            .unwrap();
+
+        // Naming a fresh principal per call does not reset the quota
+        for (principal, admitted) in [("bob-1", true), ("bob-2", false)] {
+            let mut request =
+                ClientRequest::CallToolRequest(CallToolRequest::new(CallToolRequestParam {
+                    name: "add".into(),
+                    arguments: json!({ "a": 1, "b": 2 }).as_object().cloned(),
+                }));
+            request
+                .get_meta_mut()
+                .insert(PRINCIPAL_META_KEY.to_string(), json!(principal));
+            let result = client.peer().send_request(request).await;
+            assert_eq!(result.is_ok(), admitted, "{:?}", result);
+        }
+        assert_eq!(quotas.remaining("bob").daily, Some(0));
+        assert_eq!(quotas.remaining("bob-1").daily, Some(1));
+
+        client.shutdown().await.unwrap();
+        server.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_auth_refused_by_unsupported_transport() {
+        use rmcp::handler::server::ServerHandler;
+
+        struct NoAuth;
+        impl McpTransportProvider for NoAuth {
+            async fn serve<H: ServerHandler + Clone>(
+                self,
+                _handler: H,
+            ) -> Result<(), Box<dyn std::error::Error>> {
+                panic!("served without authentication");
+            }
+        }
+
+        let err = McpServer::<TestCommands>::new()
+            .with_auth_token("s3cret")
+            .serve(NoAuth)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "this transport does not support authentication"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_variable_interpolation() {
+        use crate::test_client::McpTestClient;
+
+        let options = ServerOptions {
+            tool_attributes: TypedOutputCommands::tool_attributes()
+                .into_iter()
+                .map(|attrs| (attrs.name.clone(), attrs))
+                .collect(),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TypedOutputCommands>(
+            Box::new(|cmd| match cmd {
+                TypedOutputCommands::Tag { name } => Ok(name),
+                _ => Ok("ok".to_string()),
+            }),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        // `_vars` set on any call are remembered for the session
+        let result = client
+            .call_tool("greet", Some(json!({ "_vars": { "env": "prod" } })))
+            .await
+            .unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).as_deref(), Some("ok"));
+
+        let result = client
+            .call_tool("tag", Some(json!({ "tag_name": "release-${env}" })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("release-prod")
+        );
+
+        let err = client
+            .call_tool("tag", Some(json!({ "tag_name": "${missing}" })))
+            .await
+            .expect_err("undefined variables should fail the call");
+        assert!(err.to_string().contains("undefined variable `missing`"));
+
+        client.shutdown().await.unwrap();
+
+        // A new session starts without variables
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        assert!(client
+            .call_tool("tag", Some(json!({ "tag_name": "${env}" })))
+            .await
+            .is_err());
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_concurrent_sessions_are_isolated() {
+        use crate::test_client::McpTestClient;
+        use std::time::{Duration, Instant};
+
+        let options = ServerOptions {
+            tool_attributes: TypedOutputCommands::tool_attributes()
+                .into_iter()
+                .map(|attrs| (attrs.name.clone(), attrs))
+                .collect(),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TypedOutputCommands>(
+            Box::new(|cmd| match cmd {
+                TypedOutputCommands::Tag { name } if name == "slow" => {
+                    std::thread::sleep(Duration::from_millis(800));
+                    Ok(name)
+                }
+                TypedOutputCommands::Tag { name } => Ok(name),
+                _ => Ok("ok".to_string()),
+            }),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let addr = format!("127.0.0.1:{}", port);
+        let (alice, bob, carol) = tokio::join!(
+            McpTestClient::connect(&addr),
+            McpTestClient::connect(&addr),
+            McpTestClient::connect(&addr),
+        );
+        let (alice, bob, carol) = (alice.unwrap(), bob.unwrap(), carol.unwrap());
+
+        // Variables stay with the session that set them, even when calls interleave
+        let (a, b) = tokio::join!(
+            alice.call_tool("greet", Some(json!({ "_vars": { "env": "alpha" } }))),
+            bob.call_tool("greet", Some(json!({ "_vars": { "env": "beta" } }))),
+        );
+        a.unwrap();
+        b.unwrap();
+        let tag = json!({ "tag_name": "${env}" });
+        let (a, b, c) = tokio::join!(
+            alice.call_tool("tag", Some(tag.clone())),
+            bob.call_tool("tag", Some(tag.clone())),
+            carol.call_tool("tag", Some(tag.clone())),
+        );
+        assert_eq!(
+            McpTestClient::extract_text(&a.unwrap()).as_deref(),
+            Some("alpha")
+        );
+        assert_eq!(
+            McpTestClient::extract_text(&b.unwrap()).as_deref(),
+            Some("beta")
+        );
+        assert!(c.is_err(), "a session without variables sees none");
+
+        // A slow call does not hold up other sessions on the same worker
+        let started = Instant::now();
+        let slow = alice.call_tool("tag", Some(json!({ "tag_name": "slow" })));
+        let fast = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let result = bob.call_tool("greet", None).await;
+            (result, started.elapsed())
+        };
+        let (slow, (fast, elapsed)) = tokio::join!(slow, fast);
+        assert_eq!(
+            McpTestClient::extract_text(&slow.unwrap()).as_deref(),
+            Some("slow")
+        );
+        assert_eq!(
+            McpTestClient::extract_text(&fast.unwrap()).as_deref(),
+            Some("ok")
+        );
+        assert!(elapsed < Duration::from_millis(500), "took {:?}", elapsed);
+
+        // Ending one session leaves the others running
+        alice.shutdown().await.unwrap();
+        let result = bob.call_tool("tag", Some(tag.clone())).await.unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("beta")
+        );
+        carol.call_tool("greet", None).await.unwrap();
+
+        bob.shutdown().await.unwrap();
+        carol.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools)]
+    enum RenamedCommands {
+        /// Show the status
+        #[mcp(renamed_from = "stat", renamed_from = "info")]
+        Status {
+            #[arg(long)]
+            verbose: bool,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_renamed_tool_aliases() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<RenamedCommands>::new()
+            .with_tool_attributes(RenamedCommands::tool_attributes())
+            .with_handler(Box::new(|cmd| match cmd {
+                RenamedCommands::Status { verbose } => Ok(format!("ok verbose={}", verbose)),
+            }))
+            .with_validate_tool()
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        // Only the current name is listed
+        let tools = client.list_tools().await.unwrap();
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+        assert_eq!(names, ["status", "validate_call"]);
+
+        let result = client.call_tool("status", None).await.unwrap();
+        assert_eq!(result.content.len(), 1);
+
+        for old in ["stat", "info"] {
+            let result = client
+                .call_tool(old, Some(json!({ "verbose": true })))
+                .await
+                .unwrap();
+            assert_eq!(result.is_error, Some(false));
+            assert_eq!(
+                McpTestClient::extract_text(&result).unwrap(),
+                "ok verbose=true"
+            );
+            assert_eq!(
+                result.content[1].as_text().unwrap().text,
+                format!(
+                    "Warning: tool `{}` has been renamed to `status`; the old name is deprecated and will be removed in a future release",
+                    old
+                )
+            );
+        }
+        let report = client
+            .call_tool("validate_call", Some(json!({ "tool": "stat" })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&report).unwrap(),
+            r#"{"tool":"status","valid":true}"#
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    /// Arguments of a build
+    #[derive(clap::Args, Clone, serde::Serialize, crate::McpTools, crate::McpSchema)]
+    struct BuildArgs {
+        /// Target triple
+        #[arg(long)]
+        #[mcp(sticky)]
+        target: String,
+        /// Build with optimizations
+        #[arg(long)]
+        release: bool,
+        /// Packages to build
+        packages: Vec<String>,
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools, crate::McpClient, crate::McpSchema)]
+    enum BuildCommands {
+        /// Show the build status
+        Status,
+        /// Build the workspace
+        #[mcp(interpolate)]
+        Build(BuildArgs),
+    }
+
+    #[tokio::test]
+    async fn test_unit_and_tuple_variants() {
+        use crate::test_client::McpTestClient;
+
+        let attributes = BuildCommands::tool_attributes();
+        let build = attributes
+            .iter()
+            .find(|attrs| attrs.name == "build")
+            .unwrap();
+        assert!(build.interpolate);
+        assert_eq!(build.sticky_args, ["target".to_string()].into());
+
+        let runtime = ClapMcpHandler::<BuildCommands>::with_options(
+            None,
+            ServerOptions {
+                tool_attributes: attributes
+                    .iter()
+                    .map(|a| (a.name.clone(), a.clone()))
+                    .collect(),
+                ..Default::default()
+            },
+        )
+        .tools()
+        .to_vec();
+
+        let handler = McpServer::<BuildCommands>::new()
+            .with_tool_attributes(attributes)
+            .with_static_schemas(BuildCommands::TOOLS)
+            .with_handler(Box::new(|cmd| match cmd {
+                BuildCommands::Status => Ok("idle".to_string()),
+                BuildCommands::Build(args) => Ok(format!(
+                    "{} release={} {:?}",
+                    args.target, args.release, args.packages
+                )),
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        // Compile-time schemas of the unit and tuple variants match the
+        // runtime ones
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools.len(), runtime.len());
+        for (tool, runtime) in tools.iter().zip(&runtime) {
+            assert_eq!(tool.name, runtime.name);
+            assert_eq!(tool.description, runtime.description);
+            assert_eq!(tool.input_schema, runtime.input_schema, "{}", tool.name);
+        }
+        assert_eq!(tools[0].input_schema["properties"], json!({}));
+        let build = &tools[1].input_schema;
+        assert_eq!(build["required"], json!(["target"]));
+        assert_eq!(build["properties"]["target"]["x-sticky"], true);
+        assert!(build["properties"]["_vars"].is_object());
+
+        let result = client.call_tool("status", None).await.unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "idle");
+        let result = client
+            .call_tool(
+                "build",
+                Some(json!({ "target": "x86_64", "release": true, "packages": ["core", "cli"] })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).unwrap(),
+            r#"x86_64 release=true ["core", "cli"]"#
+        );
+        // The sticky setting from the Args struct applies to the variant
+        let result = client.call_tool("build", None).await.unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).unwrap(),
+            "x86_64 release=false []"
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpSchema)]
+    enum EnvCommands {
+        /// Publish a package
+        Publish {
+            /// Registry token
+            #[arg(long, env = "CLAP_MCP_TEST_PUBLISH_TOKEN")]
+            token: String,
+            /// Registry URL
+            #[arg(long, env = "CLAP_MCP_TEST_PUBLISH_REGISTRY")]
+            registry: String,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_env_fallback_arguments() {
+        use crate::test_client::McpTestClient;
+
+        // Only the token is provided by the server's environment
+        std::env::set_var("CLAP_MCP_TEST_PUBLISH_TOKEN", "t0k");
+        let handler = McpServer::<EnvCommands>::new()
+            .with_static_schemas(EnvCommands::TOOLS)
+            .with_handler(Box::new(|cmd| match cmd {
+                EnvCommands::Publish { token, registry } => {
+                    Ok(format!("{} to {}", token, registry))
+                }
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let runtime = ClapMcpHandler::<EnvCommands>::with_options(None, ServerOptions::default())
+            .tools()
+            .to_vec();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools[0].input_schema, runtime[0].input_schema);
+        let schema = &tools[0].input_schema;
+        assert_eq!(schema["required"], json!(["registry"]));
+        assert_eq!(
+            schema["properties"]["token"]["description"],
+            "Registry token [env: CLAP_MCP_TEST_PUBLISH_TOKEN]"
+        );
+        assert_eq!(
+            schema["properties"]["registry"]["x-env"],
+            "CLAP_MCP_TEST_PUBLISH_REGISTRY"
+        );
+
+        let result = client
+            .call_tool("publish", Some(json!({ "registry": "crates.io" })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).unwrap(),
+            "t0k to crates.io"
+        );
+        client
+            .call_tool("publish", Some(json!({ "token": "t1" })))
+            .await
+            .expect_err("registry is required");
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    fn parse_api_token(token: &str) -> Result<String, String> {
+        match token.starts_with("sk-") {
+            true => Ok(token.to_string()),
+            false => Err("expected an `sk-` token".to_string()),
+        }
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools)]
+    enum LoginCommands {
+        /// Log in to the registry
+        Login {
+            #[arg(long, value_parser = parse_api_token)]
+            #[mcp(secret)]
+            token: String,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_secret_args_redacted_from_errors() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<LoginCommands>::new()
+            .with_tool_attributes(LoginCommands::tool_attributes())
+            .with_handler(Box::new(|cmd| match cmd {
+                LoginCommands::Login { token } => Err(format!("token {} was revoked", token)),
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        // clap quotes the rejected value
+        let err = client
+            .call_tool("login", Some(json!({ "token": "hunter2-live" })))
+            .await
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(!message.contains("hunter2-live"), "{}", message);
+        assert!(
+            message.contains("invalid value '[redacted:token]' for '--token <TOKEN>'"),
+            "{}",
+            message
+        );
+
+        let result = client
+            .call_tool("login", Some(json!({ "token": "sk-live-42" })))
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            McpTestClient::extract_text(&result).unwrap(),
+            "token [redacted:token] was revoked"
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools, crate::McpSchema)]
+    enum ProjectCommands {
+        /// Deploy a project
+        Deploy {
+            #[arg(long)]
+            #[mcp(sticky)]
+            project: String,
+            #[arg(long)]
+            version: String,
+        },
+        /// Show a project's status
+        Status {
+            #[arg(long)]
+            #[mcp(sticky)]
+            project: String,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_sticky_arguments_carry_forward() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<ProjectCommands>::new()
+            .with_tool_attributes(ProjectCommands::tool_attributes())
+            .with_handler(Box::new(|cmd| match cmd {
+                ProjectCommands::Deploy { project, version } => {
+                    Ok(format!("{} {}", project, version))
+                }
+                ProjectCommands::Status { project } => Ok(project),
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let text = |result: CallToolResult| McpTestClient::extract_text(&result).unwrap();
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(
+            tools[0].input_schema["properties"]["project"]["x-sticky"],
+            true
+        );
+        let generated = ProjectCommands::TOOLS[0].to_tool();
+        assert_eq!(
+            generated.input_schema["properties"]["project"]["x-sticky"],
+            true
+        );
+
+        assert!(client.call_tool("status", Some(json!({}))).await.is_err());
+        let deployed = client
+            .call_tool(
+                "deploy",
+                Some(json!({ "project": "acme", "version": "1.2" })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(text(deployed), "acme 1.2");
+        // Carried to later calls, including of other tools
+        let status = client.call_tool("status", Some(json!({}))).await.unwrap();
+        assert_eq!(text(status), "acme");
+        let deployed = client
+            .call_tool("deploy", Some(json!({ "version": "1.3" })))
+            .await
+            .unwrap();
+        assert_eq!(text(deployed), "acme 1.3");
+
+        // Null uses the built-in default once, $unset forgets the value
+        assert!(client
+            .call_tool("status", Some(json!({ "project": null })))
+            .await
+            .is_err());
+        let status = client.call_tool("status", None).await.unwrap();
+        assert_eq!(text(status), "acme");
+        assert!(client
+            .call_tool("status", Some(json!({ "project": { "$unset": true } })))
+            .await
+            .is_err());
+        assert!(client.call_tool("status", Some(json!({}))).await.is_err());
+
+        // Other sessions start without
+        let other = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        assert!(other.call_tool("status", Some(json!({}))).await.is_err());
+
+        other.shutdown().await.unwrap();
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[derive(clap::Parser, crate::McpMode)]
+    #[mcp(
+        title = "Test Calculator",
+        website = "https://example.com/calc",
+        icon = "https://example.com/calc.png",
+        contact = "calc@example.com",
+        install_config,
+        replay,
+        call,
+        export_schema,
+        transport_flag = "mcp_port",
+        missing_handler = "dry_run",
+        name = "calc",
+        instructions = "Call `add` to add two numbers."
+    )]
+    struct MetadataCli {
+        #[command(subcommand)]
+        command: Option<TestCommands>,
+
+        #[arg(long)]
+        #[mcp(mode_flag)]
+        mcp: bool,
+
+        #[arg(long)]
+        mcp_port: Option<u16>,
+
+        #[command(flatten)]
+        #[mcp(global)]
+        globals: TestGlobals,
+    }
+
+    #[derive(clap::Args, Clone, Debug, PartialEq)]
+    struct TestGlobals {
+        /// Print more output
+        #[arg(short, long, global = true)]
+        verbose: bool,
+        /// Output format
+        #[arg(long, default_value = "text")]
+        format: String,
+    }
+
+    #[tokio::test]
+    async fn test_global_args_reach_handler() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<TestCommands>::new()
+            .with_global_args::<TestGlobals>()
+            .with_handler(Box::new(|_| {
+                let globals = context::global_args::<TestGlobals>()
+                    .ok_or_else(|| "no global args".to_string())?;
+                Ok(format!("{} {}", globals.verbose, globals.format))
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.expect("Failed to list tools");
+        let add = tools.iter().find(|t| t.name == "add").unwrap();
+        let global = &add.input_schema["properties"][globals::GLOBAL_KEY];
+        assert_eq!(global["properties"]["verbose"]["type"], "boolean");
+        assert_eq!(
+            global["properties"]["format"]["description"],
+            "Output format"
+        );
+
+        let result = client
+            .call_tool(
+                "add",
+                Some(json!({ "a": 1, "b": 2, "global": { "verbose": true, "format": "json" } })),
+            )
+            .await
+            .expect("Failed to call add");
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "true json");
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .expect("Failed to call add");
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "false text");
+
+        client
+            .call_tool(
+                "add",
+                Some(json!({ "a": 1, "b": 2, "global": { "color": true } })),
+            )
+            .await
+            .expect_err("unknown global options should fail");
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[test]
+    fn test_server_metadata_attributes() {
+        let metadata = MetadataCli::mcp_server_metadata();
+        assert_eq!(metadata.title.as_deref(), Some("Test Calculator"));
+        assert_eq!(metadata.icons[0].src, "https://example.com/calc.png");
+
+        let handler = ClapMcpHandler::<TestCommands>::with_options(
+            None,
+            ServerOptions {
+                metadata,
+                ..Default::default()
+            },
+        );
+        let info = handler.get_info();
+        let advertised = &info.capabilities.experimental.unwrap()["serverMetadata"];
+        assert_eq!(advertised["websiteUrl"], "https://example.com/calc");
+        assert_eq!(advertised["contact"], "calc@example.com");
+        assert_eq!(
+            advertised["icons"][0]["src"],
+            "https://example.com/calc.png"
+        );
+
+        let info =
+            ClapMcpHandler::<TestCommands>::with_options(None, ServerOptions::default()).get_info();
+        assert!(info.capabilities.experimental.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_server_identity() {
+        let info = McpServer::<TestCommands>::new()
+            .with_name("calc")
+            .with_version("1.2.3")
+            .with_instructions("Call `add` to add two numbers.")
+            .into_handler()
+            .await
+            .unwrap()
+            .get_info();
+        assert_eq!(info.server_info.name, "calc");
+        assert_eq!(info.server_info.version, "1.2.3");
+        assert_eq!(
+            info.instructions.as_deref(),
+            Some("Call `add` to add two numbers.")
+        );
+
+        let info =
+            ClapMcpHandler::<TestCommands>::with_options(None, ServerOptions::default()).get_info();
+        assert_eq!(info.server_info.name, "clap-mcp-server");
+        assert!(info.instructions.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrency() {
+        use crate::policy::BusyAction;
+        use crate::test_client::McpTestClient;
+
+        for action in [BusyAction::Queue, BusyAction::Reject] {
+            let handler = McpServer::<TestCommands>::new()
+                .with_handler(Box::new(|cmd| {
+                    if let TestCommands::Hello { .. } = cmd {
+                        std::thread::sleep(std::time::Duration::from_millis(300));
+                    }
+                    execute_test_command(cmd)
+                }))
+                .with_max_concurrency(1)
+                .with_busy_action(action)
+                .into_handler()
+                .await
+                .unwrap();
+            let (ct, port) = start_in_process_handler(handler)
+                .await
+                .expect("Failed to start server");
+            let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+                .await
+                .expect("Failed to connect to server");
+
+            let started = std::time::Instant::now();
+            let slow = client.call_tool("hello", Some(json!({ "name": "a" })));
+            let fast = async {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                client
+                    .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+                    .await
+            };
+            let (slow, fast) = tokio::join!(slow, fast);
+            assert!(slow.is_ok());
+            match action {
+                // The second call waits for the first
+                BusyAction::Queue => {
+                    assert_eq!(
+                        McpTestClient::extract_text(&fast.unwrap()).unwrap(),
+                        "1 + 2 = 3"
+                    );
+                    assert!(started.elapsed() >= std::time::Duration::from_millis(300));
+                }
+                BusyAction::Reject => {
+                    let err = fast.expect_err("busy server should reject");
+                    match err.downcast_ref::<rmcp::service::ServiceError>() {
+                        Some(rmcp::service::ServiceError::McpError(e)) => {
+                            assert_eq!(
+                                e.message,
+                                "Server busy: 1 tool calls already running, try again later"
+                            );
+                            assert_eq!(e.data.as_ref().unwrap()["limit"], 1);
+                        }
+                        other => panic!("unexpected error: {:?}", other),
+                    }
+                    // The slot is free again once the call finished
+                    assert!(client
+                        .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+                        .await
+                        .is_ok());
+                }
+            }
+
+            client.shutdown().await.unwrap();
+            ct.cancel();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tracing_spans() {
+        use crate::test_client::McpTestClient;
+        use std::sync::Mutex;
+
+        #[derive(Clone, Default)]
+        struct Logs(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_max_level(tracing::Level::INFO)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        for enabled in [false, true] {
+            let handler = McpServer::<TestCommands>::new()
+                .with_handler(Box::new(execute_test_command))
+                .with_tracing(enabled)
+                .into_handler()
+                .await
+                .unwrap();
+            let (ct, port) = start_in_process_handler(handler)
+                .await
+                .expect("Failed to start server");
+            let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+                .await
+                .expect("Failed to connect to server");
+            client.list_tools().await.unwrap();
+            client
+                .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+                .await
+                .unwrap();
+            client
+                .call_tool("divide", Some(json!({ "dividend": 1, "divisor": 0 })))
+                .await
+                .unwrap();
+            client.shutdown().await.unwrap();
+            ct.cancel();
+
+            let output = String::from_utf8(std::mem::take(&mut *logs.0.lock().unwrap())).unwrap();
+            if !enabled {
+                assert!(!output.contains("call_tool{"), "{}", output);
+                continue;
+            }
+            assert!(
+                output.contains("list_tools{tools=5 duration_ms="),
+                "{}",
+                output
+            );
+            let finished: Vec<&str> = output
+                .lines()
+                .filter(|line| line.contains("tool call finished"))
+                .collect();
+            assert_eq!(finished.len(), 2, "{}", output);
+            assert!(finished[0].contains("call_tool{tool=add outcome=Success duration_ms="));
+            assert!(finished[1].contains("call_tool{tool=divide outcome=Failed duration_ms="));
+            // Events of the call are nested in its span
+            assert!(output
+                .lines()
+                .any(|line| line.contains("call_tool{tool=divide")
+                    && line.contains("tool call correlation_id=")));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_structured_handler_output() {
+        use crate::output::{ToolError, ToolOutput};
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<TestCommands>::new()
+            .with_structured_handler(Box::new(|cmd| match cmd {
+                TestCommands::Add { a, b } => Ok(ToolOutput::Mixed(vec![
+                    ToolOutput::Json(json!({ "sum": a + b })),
+                    ToolOutput::image(b"hi", "image/png"),
+                    ToolOutput::blob("file:///tmp/sum.pdf", Some("application/pdf"), b"%PDF"),
+                    vec![
+                        Content::image("aGk=", "image/gif"),
+                        Content::text("\x1b[1mdone\x1b[0m"),
+                    ]
+                    .into(),
+                ])),
+                TestCommands::Divide { divisor: 0, .. } => Err(ToolError::new("division by zero")
+                    .with_code("invalid_divisor")
+                    .with_data(json!({ "divisor": 0 }))),
+                TestCommands::Hello { .. } => {
+                    Err(ToolError::internal("greeting service unavailable")
+                        .with_code("unavailable"))
+                }
+                _ => Err(ToolError::new(json!({ "code": "unsupported" }))),
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .expect("Failed to call add");
+        assert_eq!(result.content.len(), 5);
+        let json: serde_json::Value =
+            serde_json::from_str(&result.content[0].as_text().unwrap().text).unwrap();
+        assert_eq!(json, json!({ "sum": 3 }));
+        let image = result.content[1].as_image().expect("image content");
+        assert_eq!(
+            (image.data.as_str(), image.mime_type.as_str()),
+            ("aGk=", "image/png")
+        );
+        let pdf = result.content[2].as_resource().expect("resource content");
+        assert!(matches!(
+            &pdf.resource,
+            ResourceContents::BlobResourceContents { blob, mime_type: Some(mime_type), .. }
+                if blob == "JVBERg==" && mime_type == "application/pdf"
+        ));
+        // rmcp content passes through, with its text sanitized
+        assert_eq!(result.content[3].as_image().unwrap().mime_type, "image/gif");
+        assert_eq!(result.content[4].as_text().unwrap().text, "done");
+
+        let result = client
+            .call_tool("subtract", Some(json!({ "minuend": 1, "subtrahend": 2 })))
+            .await
+            .expect("Failed to call subtract");
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            McpTestClient::extract_text(&result).unwrap(),
+            r#"{"code":"unsupported"}"#
+        );
+
+        // Codes and data of user errors follow the message
+        let result = client
+            .call_tool("divide", Some(json!({ "dividend": 1, "divisor": 0 })))
+            .await
+            .expect("Failed to call divide");
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            result.content[0].as_text().unwrap().text,
+            "division by zero"
+        );
+        let details: serde_json::Value =
+            serde_json::from_str(&result.content[1].as_text().unwrap().text).unwrap();
+        assert_eq!(
+            details,
+            json!({ "code": "invalid_divisor", "data": { "divisor": 0 } })
+        );
+
+        // Internal errors fail the request instead
+        let err = client
+            .call_tool("hello", Some(json!({ "name": "x" })))
+            .await
+            .expect_err("internal errors are protocol errors");
+        match err.downcast_ref::<rmcp::service::ServiceError>() {
+            Some(rmcp::service::ServiceError::McpError(e)) => {
+                assert_eq!(e.code, rmcp::model::ErrorCode::INTERNAL_ERROR);
+                assert_eq!(e.message, "greeting service unavailable");
+                let data = e.data.as_ref().unwrap();
+                assert_eq!(data["code"], "unavailable");
+                assert!(data[crate::context::CORRELATION_ID_META_KEY].is_string());
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_file_resources() {
+        use crate::resources::FileResources;
+        use crate::test_client::McpTestClient;
+
+        let dir = std::env::temp_dir().join(format!("clap-mcp-report-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = FileResources::new();
+        let registry = files.clone();
+        let report = dir.join("report.txt");
+        let handler = McpServer::<TestCommands>::new()
+            .with_resources(files)
+            .with_handler(Box::new(move |cmd| {
+                let path = report.clone();
+                std::fs::write(&path, execute_test_command(cmd)?).map_err(|e| e.to_string())?;
+                Ok(registry.register(&path, None))
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let capabilities = &client.server_info().unwrap().capabilities;
+        assert!(capabilities.resources.is_some());
+        assert!(client.list_resources().await.unwrap().is_empty());
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        let uri = McpTestClient::extract_text(&result).unwrap();
+        let resources = client.list_resources().await.unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].uri, uri);
+        assert_eq!(resources[0].mime_type.as_deref(), Some("text/plain"));
+
+        let contents = client.read_resource(&uri).await.unwrap();
+        assert!(matches!(
+            &contents[..],
+            [ResourceContents::TextResourceContents { text, .. }] if text == "1 + 2 = 3"
+        ));
+        let err = client.read_resource("file:///missing").await.unwrap_err();
+        assert!(err.to_string().contains("unknown resource"), "{err}");
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_artifacts_directory() {
+        use crate::artifacts::{Artifacts, LIST_ARTIFACTS_TOOL};
+        use crate::test_client::McpTestClient;
+
+        let dir = std::env::temp_dir().join(format!("clap-mcp-artifacts-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let artifacts = Artifacts::new(&dir).max_size(1024);
+        let store = artifacts.clone();
+        let handler = McpServer::<TestCommands>::new()
+            .with_artifacts(artifacts)
+            .with_handler(Box::new(move |cmd| {
+                let output = execute_test_command(cmd)?;
+                let path = store
+                    .write("result.txt", &output)
+                    .map_err(|e| e.to_string())?;
+                Ok(path.display().to_string())
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let tools = client.list_tools().await.unwrap();
+        assert!(tools.iter().any(|tool| tool.name == LIST_ARTIFACTS_TOOL));
+
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        let path = McpTestClient::extract_text(&result).unwrap();
+        assert_eq!(path, dir.join("result.txt").display().to_string());
+
+        let result = client
+            .call_tool(LIST_ARTIFACTS_TOOL, Some(json!({})))
+            .await
+            .unwrap();
+        let listed: serde_json::Value =
+            serde_json::from_str(&McpTestClient::extract_text(&result).unwrap()).unwrap();
+        assert_eq!(listed["dir"], dir.display().to_string());
+        assert_eq!(listed["maxSize"], 1024);
+        assert_eq!(listed["artifacts"][0]["path"], path);
+        assert_eq!(listed["totalSize"], "1 + 2 = 3".len());
+
+        let resources = client.list_resources().await.unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].uri, listed["artifacts"][0]["uri"]);
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools)]
+    enum ReleaseCommands {
+        /// Publish a release
+        #[command(group(clap::ArgGroup::new("source").args(["tag", "branch"]).required(true)))]
+        Publish {
+            #[arg(long)]
+            tag: Option<String>,
+            #[arg(long)]
+            branch: Option<String>,
+            #[arg(long, requires = "key")]
+            sign: bool,
+            #[arg(long)]
+            #[mcp(rename = "signing_key")]
+            key: Option<String>,
+            #[arg(long, conflicts_with = "sign")]
+            draft: bool,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_argument_constraints() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<ReleaseCommands>::new()
+            .with_tool_attributes(ReleaseCommands::tool_attributes())
+            .with_handler(Box::new(|_| Ok("published".to_string())))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        let schema = &tools[0].input_schema;
+        assert_eq!(
+            schema["allOf"],
+            json!([
+                { "oneOf": [{ "required": ["tag"] }, { "required": ["branch"] }] },
+                { "not": { "required": ["draft", "sign"] } }
+            ])
+        );
+        // Listed under the argument's renamed name
+        assert_eq!(
+            schema["dependentRequired"],
+            json!({ "sign": ["signing_key"] })
+        );
+
+        for (arguments, error) in [
+            (
+                json!({}),
+                "One of the arguments `tag`, `branch` is required",
+            ),
+            (
+                json!({ "tag": "v1", "branch": "main" }),
+                "Arguments `tag` and `branch` cannot be used together",
+            ),
+            (
+                json!({ "tag": "v1", "sign": true }),
+                "Missing argument `signing_key`, required when `sign` is given",
+            ),
+            (
+                json!({ "tag": "v1", "sign": true, "signing_key": "k", "draft": true }),
+                "Arguments `draft` and `sign` cannot be used together",
+            ),
+        ] {
+            let err = client
+                .call_tool("publish", Some(arguments))
+                .await
+                .expect_err("constraint should be enforced");
+            assert!(err.to_string().contains(error), "unexpected error: {err}");
+        }
+
+        let result = client
+            .call_tool(
+                "publish",
+                Some(json!({ "tag": "v1", "sign": true, "signing_key": "k", "draft": false })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("published")
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_last_result_resources() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_last_results()
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        assert!(client.list_resources().await.unwrap().is_empty());
+
+        for (a, b) in [(1, 2), (3, 4)] {
+            client
+                .call_tool("add", Some(json!({ "a": a, "b": b })))
+                .await
+                .unwrap();
+        }
+        client
+            .call_tool("divide", Some(json!({ "dividend": 1, "divisor": 0 })))
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+
+        // A later session can still refer back to the output
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let resources = client.list_resources().await.unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0].uri, "last_result://add");
+        let contents = client.read_resource("last_result://add").await.unwrap();
+        assert!(matches!(
+            &contents[..],
+            [ResourceContents::TextResourceContents { text, .. }] if text == "3 + 4 = 7"
+        ));
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_last_results_are_private() {
+        use crate::client::McpClient;
+
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_last_results()
+            .with_auth(auth::Principals(|token: &str| {
+                token.strip_prefix("user-").map(str::to_string)
+            }))
+            .spawn_http("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let url = format!("http://{}/sse", server.local_addr().unwrap());
+
+        let alice = McpClient::connect_url_with_token(&url, "user-alice")
+            .await
+            .unwrap();
+        alice
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        assert_eq!(alice.list_resources().await.unwrap().len(), 1);
+
+        let bob = McpClient::connect_url_with_token(&url, "user-bob")
+            .await
+            .unwrap();
+        assert!(bob.list_resources().await.unwrap().is_empty());
+        assert!(bob.read_resource("last_result://add").await.is_err());
+
+        alice.shutdown().await.unwrap();
+        bob.shutdown().await.unwrap();
+        server.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_last_results_are_private_within_a_session_token() {
+        use crate::client::McpClient;
+        use crate::vars::{MemorySessionStore, SESSION_TOKEN_META_KEY};
+        use rmcp::model::{
+            CallToolRequest, ClientRequest, GetMeta, ListResourcesRequest, ServerResult,
+        };
+
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_last_results()
+            .with_session_store(MemorySessionStore::new())
+            .with_auth(auth::Principals(|token: &str| {
+                token.strip_prefix("user-").map(str::to_string)
+            }))
+            .spawn_http("127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        let url = format!("http://{}/sse", server.local_addr().unwrap());
+
+        // Both name the same session token
+        let send = |client: &McpClient, mut request: ClientRequest| {
+            request
+                .get_meta_mut()
+                .insert(SESSION_TOKEN_META_KEY.to_string(), json!("shared"));
+            let peer = client.peer().clone();
+            async move { peer.send_request(request).await.unwrap() }
+        };
+        let list = || ClientRequest::ListResourcesRequest(ListResourcesRequest::default());
+        let listed = |result: ServerResult| match result {
+            ServerResult::ListResourcesResult(result) => result.resources.len(),
+            other => panic!("unexpected result {:?}", other),
+        };
+
+        let alice = McpClient::connect_url_with_token(&url, "user-alice")
+            .await
+            .unwrap();
+        let add = CallToolRequest::new(CallToolRequestParam {
+            name: "add".into(),
+            arguments: json!({ "a": 1, "b": 2 }).as_object().cloned(),
+        });
+        send(&alice, ClientRequest::CallToolRequest(add)).await;
+        assert_eq!(listed(send(&alice, list()).await), 1);
+
+        let bob = McpClient::connect_url_with_token(&url, "user-bob")
+            .await
+            .unwrap();
+        assert_eq!(listed(send(&bob, list()).await), 0);
+
+        alice.shutdown().await.unwrap();
+        bob.shutdown().await.unwrap();
+        server.cancel();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools)]
+    enum NapCommands {
+        /// Sleep for a while
+        Nap {
+            #[arg(long)]
+            ms: u64,
+        },
+        /// Sleep for a while, with more time allowed
+        #[mcp(timeout_secs = 5)]
+        Doze {
+            #[arg(long)]
+            ms: u64,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_tool_timeouts_and_cancellation() {
+        use crate::test_client::McpTestClient;
+        use rmcp::service::PeerRequestOptions;
+        use std::time::Instant;
+
+        let attributes = NapCommands::tool_attributes();
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].name, "doze");
+        assert_eq!(attributes[0].timeout, Some(Duration::from_secs(5)));
+
+        let (calls, mut ended) = tokio::sync::mpsc::unbounded_channel();
+        let handler = McpServer::<NapCommands>::new()
+            .with_tool_attributes(attributes)
+            .with_timeout(Duration::from_millis(200))
+            .with_meter(
+                move |call: &metering::ToolCall, outcome: &metering::Outcome| {
+                    let _ = calls.send((call.tool.clone(), *outcome, call.duration));
+                },
+            )
+            .with_handler(Box::new(|cmd| {
+                let (NapCommands::Nap { ms } | NapCommands::Doze { ms }) = cmd;
+                std::thread::sleep(Duration::from_millis(ms));
+                Ok("rested".to_string())
+            }))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let result = client
+            .call_tool("nap", Some(json!({ "ms": 10 })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("rested")
+        );
+
+        let started = Instant::now();
+        let result = client
+            .call_tool("nap", Some(json!({ "ms": 1500 })))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("Tool `nap` timed out after 200ms")
+        );
+
+        // The tool's own timeout replaces the server-wide one
+        let result = client
+            .call_tool("doze", Some(json!({ "ms": 400 })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("rested")
+        );
+
+        // A cancelled call is answered without waiting for the handler
+        while ended.try_recv().is_ok() {}
+        let request = ClientRequest::CallToolRequest(Request::new(CallToolRequestParam {
+            name: "doze".into(),
+            arguments: json!({ "ms": 1500 }).as_object().cloned(),
+        }));
+        let handle = client
+            .peer()
+            .send_cancellable_request(request, PeerRequestOptions::no_options())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        handle.cancel(None).await.unwrap();
+        let (tool, outcome, duration) = tokio::time::timeout(Duration::from_secs(2), ended.recv())
+            .await
+            .expect("cancelled call should end promptly")
+            .unwrap();
+        assert_eq!(tool, "doze");
+        assert_eq!(outcome, metering::Outcome::Failed);
+        assert!(duration < Duration::from_secs(1));
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_embedded_handler() {
+        use crate::test_client::McpTestClient;
+
+        // Mounted directly, without going through `McpServer::serve_*`
+        let handler = ClapMcpHandler::<TestCommands>::new(Box::new(execute_test_command));
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .expect("Failed to call add");
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "1 + 2 = 3");
+        client.shutdown().await.unwrap();
+        ct.cancel();
+
+        // Built from the server builder, keeping its settings
+        let handler = McpServer::<TestCommands>::new()
+            .with_metadata(ServerMetadata {
+                title: Some("Embedded".to_string()),
+                ..Default::default()
+            })
+            .into_handler()
+            .await
+            .unwrap();
+        let info = handler.get_info();
+        assert_eq!(
+            info.capabilities.experimental.unwrap()["serverMetadata"]["title"],
+            "Embedded"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_capability_hooks() {
+        let mut draft = JsonObject::new();
+        draft.insert("version".to_string(), json!("draft"));
+        let handler = McpServer::<TestCommands>::new()
+            .with_capabilities(|caps| {
+                caps.tools.get_or_insert_with(Default::default).list_changed = Some(true);
+            })
+            .with_experimental_capability("draftFeature", draft)
+            .into_handler()
+            .await
+            .unwrap();
+
+        let caps = handler.get_info().capabilities;
+        assert_eq!(caps.tools.unwrap().list_changed, Some(true));
+        assert_eq!(
+            caps.experimental.unwrap()["draftFeature"]["version"],
+            "draft"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_correlation_id_reaches_handler_and_errors() {
+        use crate::test_client::McpTestClient;
+
+        let (ct, port) = start_in_process_server::<TestCommands>(Box::new(|_| {
+            context::correlation_id().ok_or_else(|| "no correlation id".to_string())
+        }))
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let first = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        let second = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        let first = McpTestClient::extract_text(&first).unwrap();
+        assert!(!first.is_empty());
+        assert_ne!(first, McpTestClient::extract_text(&second).unwrap());
+
+        let err = client
+            .call_tool("add", Some(json!({})))
+            .await
+            .expect_err("missing arguments should fail");
+        match err.downcast_ref::<rmcp::service::ServiceError>() {
+            Some(rmcp::service::ServiceError::McpError(e)) => {
+                assert!(e.data.as_ref().unwrap()["correlationId"].is_string());
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_client_features_reach_handler() {
+        use rmcp::ServiceExt;
+
+        async fn call_with(capabilities: ClientCapabilities) -> String {
+            let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+            let handler = ClapMcpHandler::<TestCommands>::new(Box::new(|_| {
+                let features = context::client_features();
+                Ok(if features.sampling {
+                    "summarized".to_string()
+                } else {
+                    "raw".to_string()
+                })
+            }));
+            tokio::spawn(async move {
+                if let Ok(server) = handler.serve(server_io).await {
+                    let _ = server.waiting().await;
+                }
+            });
+            let info = ClientInfo {
+                capabilities,
+                ..Default::default()
+            };
+            let client = info.serve(client_io).await.unwrap();
+            let result = client
+                .call_tool(CallToolRequestParam {
+                    name: "add".into(),
+                    arguments: json!({ "a": 1, "b": 2 }).as_object().cloned(),
+                })
+                .await
+                .unwrap();
+            client.cancel().await.unwrap();
+            crate::test_client::McpTestClient::extract_text(&result).unwrap()
+        }
+
+        assert_eq!(call_with(ClientCapabilities::default()).await, "raw");
+        let sampling = ClientCapabilities {
+            sampling: Some(Default::default()),
+            ..Default::default()
+        };
+        assert_eq!(call_with(sampling).await, "summarized");
+    }
+
+    #[derive(Subcommand, Clone, crate::McpSchema)]
+    enum DefaultedCommands {
+        /// Roll out a release
+        Deploy {
+            /// Environment to deploy to
+            #[arg(long, default_value = "staging")]
+            target: String,
+            /// Number of instances
+            #[arg(long, default_value = "3")]
+            replicas: u16,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_clap_defaults_advertised_and_applied() {
+        use crate::test_client::McpTestClient;
+
+        let (ct, port) = start_in_process_server::<DefaultedCommands>(Box::new(|cmd| match cmd {
+            DefaultedCommands::Deploy { target, replicas } => {
+                Ok(format!("{} x{}", target, replicas))
+            }
+        }))
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        let properties = &tools[0].input_schema["properties"];
+        assert_eq!(properties["target"]["default"], "staging");
+        assert_eq!(properties["replicas"]["default"], 3);
+        let generated = DefaultedCommands::TOOLS[0].to_tool();
+        assert_eq!(
+            generated.input_schema["properties"]["target"]["default"],
+            "staging"
+        );
+        assert_eq!(
+            generated.input_schema["properties"]["replicas"]["default"],
+            3
+        );
+
+        // Omitted and null arguments both fall back to the defaults
+        for arguments in [json!({}), json!({ "target": null, "replicas": null })] {
+            let result = client.call_tool("deploy", Some(arguments)).await.unwrap();
+            assert_eq!(
+                McpTestClient::extract_text(&result).as_deref(),
+                Some("staging x3")
+            );
+        }
+        let result = client
+            .call_tool("deploy", Some(json!({ "replicas": 5 })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("staging x5")
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpSchema)]
+    enum BatchCommands {
+        /// Archive files
+        Archive {
+            /// Labels to attach
+            #[arg(long)]
+            label: Vec<String>,
+            /// Ports to open
+            #[arg(long, num_args = 1.., action = clap::ArgAction::Set)]
+            ports: Vec<u16>,
+            /// Files to archive
+            files: Vec<String>,
+        },
+    }
+
+    #[tokio::test]
+    async fn test_multi_value_arguments() {
+        use crate::test_client::McpTestClient;
+
+        let (ct, port) = start_in_process_server::<BatchCommands>(Box::new(|cmd| match cmd {
+            BatchCommands::Archive {
+                label,
+                ports,
+                files,
+            } => Ok(format!("{:?} {:?} {:?}", label, ports, files)),
+        }))
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        let generated = BatchCommands::TOOLS[0].to_tool();
+        for schema in [&tools[0].input_schema, &generated.input_schema] {
+            for (id, items) in [
+                ("label", "string"),
+                ("ports", "integer"),
+                ("files", "string"),
+            ] {
+                let property = &schema["properties"][id];
+                assert_eq!(property["type"], "array", "type of {}", id);
+                assert_eq!(property["items"]["type"], items, "items of {}", id);
+            }
+        }
+
+        let result = client
+            .call_tool(
+                "archive",
+                Some(json!({
+                    "label": ["nightly", "x86"],
+                    "ports": [80, 443],
+                    "files": ["a.txt", "-b.txt"],
+                })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some(r#"["nightly", "x86"] [80, 443] ["a.txt", "-b.txt"]"#)
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[test]
+    fn test_static_schemas_match_runtime() {
+        let handler = ClapMcpHandler::<TestCommands>::with_options(None, ServerOptions::default());
+        let runtime = handler.tools();
+        let generated: Vec<Tool> = TestCommands::TOOLS.iter().map(|t| t.to_tool()).collect();
+        assert_eq!(generated.len(), runtime.len());
+
+        for (generated, runtime) in generated.iter().zip(runtime) {
+            assert_eq!(generated.name, runtime.name);
+            assert_eq!(generated.description, runtime.description);
+            let mut required = generated.input_schema["required"].clone();
+            required
+                .as_array_mut()
+                .unwrap()
+                .sort_by_key(|v| v.to_string());
+            let mut expected = runtime.input_schema["required"].clone();
+            expected
+                .as_array_mut()
+                .unwrap()
+                .sort_by_key(|v| v.to_string());
+            assert_eq!(required, expected, "required of {}", runtime.name);
+
+            let properties = generated.input_schema["properties"].as_object().unwrap();
+            let expected = runtime.input_schema["properties"].as_object().unwrap();
+            assert_eq!(properties.len(), expected.len());
+            for (id, schema) in expected {
+                assert_eq!(properties[id]["description"], schema["description"]);
+                assert_eq!(properties[id]["type"], schema["type"], "type of {}", id);
+            }
+        }
+
+        // Types come from the field types rather than clap introspection
+        let add = &generated_tool("add").input_schema["properties"];
+        assert_eq!(add["a"]["type"], "integer");
+        let hello = &generated_tool("hello").input_schema["properties"];
+        assert_eq!(hello["name"]["type"], "string");
+        assert_eq!(hello["excited"]["type"], "boolean");
+    }
+
+    fn generated_tool(name: &str) -> Tool {
+        TestCommands::TOOLS
+            .iter()
+            .find(|t| t.name == name)
+            .unwrap()
+            .to_tool()
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn test_json_schema_from_value_type() {
+        #[derive(Clone, clap::ValueEnum, schemars::JsonSchema)]
+        #[serde(rename_all = "kebab-case")]
+        enum Region {
+            UsEast,
+            EuWest,
+        }
+
+        #[derive(Subcommand, Clone, crate::McpTools)]
+        enum DeployCommands {
+            Deploy {
+                /// Target region
+                #[arg(long)]
+                #[mcp(json_schema)]
+                region: Option<Region>,
+                #[arg(long)]
+                replicas: u16,
+            },
+        }
+
+        let handler = McpServer::<DeployCommands>::new()
+            .with_tool_attributes(DeployCommands::tool_attributes())
+            .with_arg_schema::<u16>("deploy", "replicas");
+        let handler = ClapMcpHandler::<DeployCommands>::with_options(None, handler.options);
+        let tools = handler.tools();
+        let region = &tools[0].input_schema["properties"]["region"];
+        assert_eq!(region["enum"], json!(["us-east", "eu-west"]));
+        assert_eq!(region["title"], "Region");
+        assert_eq!(region["description"], "Target region");
+        let replicas = &tools[0].input_schema["properties"]["replicas"];
+        assert_eq!(replicas["type"], "integer");
+        assert_eq!(replicas["format"], "uint16");
+    }
+
+    #[tokio::test]
+    async fn test_usage_stats() {
+        use crate::stats::{UsageStats, UsageSummary};
+        use crate::test_client::McpTestClient;
+        use std::sync::Mutex;
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let stats = UsageStats::new(move |summary: &UsageSummary| {
+            sink.lock().unwrap().push(summary.clone());
+        });
+        let options = ServerOptions {
+            usage_stats: Some(stats.clone()),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(execute_test_command),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let add = json!({ "a": 1, "b": 2 });
+        client.call_tool("add", Some(add.clone())).await.unwrap();
+        client.call_tool("add", Some(add)).await.unwrap();
+        let divide = json!({ "dividend": 1, "divisor": 0 });
+        client.call_tool("divide", Some(divide)).await.unwrap();
+        assert!(client.call_tool("nope", None).await.is_err());
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+
+        stats.flush();
+        let reports = reports.lock().unwrap();
+        let tools = &reports[0].tools;
+        assert_eq!((tools["add"].calls, tools["add"].failures), (2, 0));
+        assert_eq!((tools["divide"].calls, tools["divide"].failures), (1, 1));
+        assert_eq!(tools[stats::UNKNOWN_TOOL].calls, 1);
+        assert!(!tools.contains_key("nope"));
+    }
+
+    #[tokio::test]
+    async fn test_support_matrix_tool() {
+        use crate::test_client::McpTestClient;
+
+        let options = ServerOptions {
+            support_matrix: true,
+            static_tools: Some(TestCommands::TOOLS.iter().map(|t| t.to_tool()).collect()),
+            ..Default::default()
+        };
+        let (ct, port) = start_in_process_server_with_options::<TestCommands>(
+            Box::new(execute_test_command),
+            options,
+        )
+        .await
+        .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        let tool = tools
+            .iter()
+            .find(|tool| tool.name == support::SUPPORT_TOOL)
+            .expect("capabilities tool listed");
+        assert_eq!(
+            tool.annotations.as_ref().and_then(|a| a.read_only_hint),
+            Some(true)
+        );
+
+        let result = client
+            .call_tool(support::SUPPORT_TOOL, Some(json!({})))
+            .await
+            .unwrap();
+        let matrix: serde_json::Value =
+            serde_json::from_str(&McpTestClient::extract_text(&result).unwrap()).unwrap();
+        assert_eq!(matrix["features"]["staticSchemas"], true);
+        assert_eq!(matrix["features"]["bridge"], false);
+        assert_eq!(matrix["features"]["jobs"], false);
+        assert_eq!(matrix["features"]["streaming"], false);
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+
+        // Not registered unless opted in
+        let (ct, port) = start_in_process_server::<TestCommands>(Box::new(execute_test_command))
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+        let tools = client.list_tools().await.unwrap();
+        assert!(tools.iter().all(|tool| tool.name != support::SUPPORT_TOOL));
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_stress_reports_latencies_and_errors() {
+        use crate::test_client::McpTestClient;
+
+        let (ct, port) = start_in_process_server::<TestCommands>(Box::new(execute_test_command))
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        // Every third call divides by zero
+        let report = client
+            .stress(
+                "divide",
+                |i| Some(json!({ "dividend": 6, "divisor": i % 3 })),
+                4,
+                Duration::from_millis(200),
+            )
+            .await;
+        assert!(report.calls > 0);
+        assert_eq!(report.errors, 0);
+        assert!(report.tool_errors > 0 && report.tool_errors < report.calls);
+        assert!(report.p50 <= report.p90 && report.p90 <= report.p99 && report.p99 <= report.max);
+        assert!(report.throughput() > 0.0);
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_conformance_matrix() {
+        use crate::conformance::Conformance;
+
+        let handler = ClapMcpHandler::<TestCommands>::new(Box::new(execute_test_command));
+        let report = Conformance::new(handler)
+            .with_call("add", json!({ "a": 2, "b": 3 }))
+            .run()
+            .await;
+        // rmcp 0.2 closes the connection on unknown methods instead of
+        // answering -32601; this fails loudly once an upgrade fixes it
+        let failed: Vec<&str> = report.failures().map(|case| case.name.as_str()).collect();
+        assert_eq!(failed, ["errors/method-not-found"], "{report}");
+        assert!(report
+            .cases
+            .iter()
+            .any(|case| case.name == "cancel/in-flight"));
+        assert!(report.cases.iter().all(|case| !case.transcript.is_empty()));
+    }
+
+    #[cfg(feature = "chaos")]
+    #[tokio::test]
+    async fn test_chaos_transport_faults() {
+        use crate::chaos::{self, ChaosConfig};
+        use rmcp::ServiceExt;
+
+        async fn connect(
+            config: ChaosConfig,
+        ) -> Result<rmcp::service::RunningService<rmcp::RoleClient, ()>, Box<dyn std::error::Error>>
+        {
+            let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+            let handler = ClapMcpHandler::<TestCommands>::new(Box::new(execute_test_command));
+            tokio::spawn(async move {
+                if let Ok(server) = handler.serve(chaos::wrap(server_io, config)).await {
+                    let _ = server.waiting().await;
+                }
+            });
+            let client = tokio::time::timeout(Duration::from_secs(2), ().serve(client_io))
+                .await
+                .map_err(|_| "initialize timed out")??;
+            Ok(client)
+        }
+
+        // Delays slow the exchange down but everything arrives
+        let client = connect(ChaosConfig::new(1).delay(1.0, Duration::from_millis(5)))
+            .await
+            .expect("delayed server should still initialize");
+        let result = client
+            .call_tool(CallToolRequestParam {
+                name: "add".into(),
+                arguments: json!({ "a": 2, "b": 2 }).as_object().cloned(),
+            })
+            .await
+            .unwrap();
+        assert!(!result.is_error.unwrap_or(false));
+        client.cancel().await.unwrap();
+
+        // A reset on the first message makes the handshake fail
+        assert!(connect(ChaosConfig::new(1).reset(1.0)).await.is_err());
+    }
+
+    #[test]
+    fn test_install_config_opt_in() {
+        // The test harness is not invoked as `mcp install-config`
+        assert!(MetadataCli::handle_mcp_install_config().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mcp_call() {
+        use crate::call::{call, CallArgs};
+
+        // The test harness is not invoked as `mcp call`
+        assert!(MetadataCli::handle_mcp_call().is_none());
+
+        let (ct, port) = start_in_process_server(Box::new(execute_test_command))
+            .await
+            .expect("Failed to start server");
+        let args = |tool: Option<&str>, args: &[&str]| CallArgs {
+            server: format!("127.0.0.1:{}", port),
+            tool: tool.map(str::to_string),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            ..Default::default()
+        };
+
+        let tools = call(&args(None, &[])).await.unwrap();
+        assert!(tools
+            .lines()
+            .any(|line| line.starts_with("add") && line.ends_with("Add two numbers")));
+        let sum = call(&args(Some("add"), &["a=2", "b=3"])).await.unwrap();
+        assert_eq!(sum, "2 + 3 = 5");
+        let raw = call(&CallArgs {
+            raw: true,
+            ..args(Some("add"), &[r#"{"a": 1, "b": 1}"#])
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&raw).unwrap()["content"][0]["text"],
+            "1 + 1 = 2"
+        );
+        assert!(call(&args(Some("add"), &["a=2"])).await.is_err());
+        ct.cancel();
+    }
+
+    #[test]
+    fn test_replay_opt_in() {
+        // The test harness is not invoked with `--mcp-replay`
+        assert!(MetadataCli::handle_mcp_replay(execute_test_command).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_export_schema() {
+        use crate::export::SchemaFormat;
+
+        // The test harness is not invoked with `--mcp-schema`
+        assert!(MetadataCli::handle_mcp_schema().is_none());
+
+        let server = || {
+            McpServer::<TestCommands>::new()
+                .with_name("calc")
+                .with_handler(Box::new(execute_test_command))
+        };
+        let doc = server()
+            .export_schema(SchemaFormat::JsonSchema)
+            .await
+            .unwrap();
+        let add = &doc["$defs"]["add"];
+        assert_eq!(add["description"], "Add two numbers");
+        assert_eq!(add["properties"]["a"]["type"], "integer");
+        assert!(doc["oneOf"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|call| call["properties"]["name"]["const"] == "hello"));
+
+        let doc = server().export_schema(SchemaFormat::OpenApi).await.unwrap();
+        assert_eq!(doc["info"]["title"], "calc");
+        assert_eq!(doc["paths"]["/tools/add"]["post"]["operationId"], "add");
+    }
+
+    #[tokio::test]
+    async fn test_replay_recorded_call() {
+        use crate::replay::AuditEntry;
+
+        let entry = AuditEntry::from_json(
+            r#"{"name": "login", "arguments": {"token": "REGISTRY_TOKEN"}, "correlationId": "req-7"}"#,
+        )
+        .unwrap();
+        let server = || {
+            McpServer::<TypedOutputCommands>::new()
+                .with_tool_attributes(TypedOutputCommands::tool_attributes())
+                .with_secret("REGISTRY_TOKEN", "tok-9f8e7d")
+                .with_handler(Box::new(|cmd| match cmd {
+                    TypedOutputCommands::Login { token } => Ok(format!(
+                        "{} as {}",
+                        token,
+                        crate::context::correlation_id().unwrap()
+                    )),
+                    _ => Ok("ok".to_string()),
+                }))
+        };
+
+        let replay = server().replay(&entry).await.unwrap();
+        assert_eq!(replay.tool, "login");
+        // The argv shows where the secret went without revealing it
+        assert_eq!(
+            replay.argv.as_deref(),
+            Some(&["mcp", "login", "--token=[secret:REGISTRY_TOKEN]"].map(String::from)[..])
+        );
+        let report = replay.to_string();
+        assert!(
+            report.contains("result: success\n[secret:REGISTRY_TOKEN] as req-7"),
+            "{}",
+            report
+        );
+        assert!(!report.contains("tok-9f8e7d"), "{}", report);
+
+        // Calls the server would reject are reported, not run
+        let rejected = server()
+            .replay(&AuditEntry {
+                name: "login".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(rejected.argv.is_none());
+        assert!(rejected
+            .to_string()
+            .contains("rejected: Missing required argument `token`"));
+    }
+
+    #[test]
+    fn test_registry_manifest_from_derive() {
+        let manifest = MetadataCli::mcp_registry_manifest()
+            .name("io.github.example/calculator")
+            .to_json();
+        assert_eq!(manifest["name"], "io.github.example/calculator");
+        assert_eq!(manifest["title"], "Test Calculator");
+        assert_eq!(
+            manifest["packages"][0]["packageArguments"][0]["name"],
+            "--mcp"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_runtime_tools_are_guarded() {
+        use rmcp::ServiceExt;
+
+        let server = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_timeout(Duration::from_secs(1));
+        let schema = Arc::new(object(json!({ "type": "object" })));
+        server.register_tool(Tool::new("slow", "Sleep", schema.clone()), |_| {
+            std::thread::sleep(Duration::from_secs(3));
+            Ok(output::ToolOutput::Text("done".to_string()))
+        });
+        server.register_tool(Tool::new("crash", "Panic", schema), |_| {
+            panic!("runtime tool panicked")
+        });
+        let handler = server.into_handler().await.unwrap();
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            if let Ok(server) = handler.serve(server_io).await {
+                let _ = server.waiting().await;
+            }
+        });
+        let client = ().serve(client_io).await.unwrap();
+        let call = |name: &'static str| CallToolRequestParam {
+            name: name.into(),
+            arguments: None,
+        };
+
+        let started = std::time::Instant::now();
+        let result = client.call_tool(call("slow")).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(result.is_error, Some(true));
+        let text = crate::test_client::McpTestClient::extract_text(&result).unwrap();
+        assert!(text.contains("timed out"), "{}", text);
+
+        let error = client.call_tool(call("crash")).await.unwrap_err();
+        assert!(
+            error.to_string().contains("tool `crash` panicked"),
+            "{}",
+            error
+        );
+        // The server survives it
+        assert!(client.list_all_tools().await.is_ok());
+        client.cancel().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_runtime_tools() {
+        use rmcp::service::{NotificationContext, RoleClient};
+        use rmcp::ServiceExt;
+
+        /// A client reporting `notifications/tools/list_changed`
+        struct ListChanged(tokio::sync::mpsc::UnboundedSender<()>);
+
+        impl rmcp::ClientHandler for ListChanged {
+            async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+                let _ = self.0.send(());
+            }
+        }
+
+        let server = McpServer::<TestCommands>::new().with_handler(Box::new(execute_test_command));
+        let registry = server.tool_registry();
+        let handler = server.into_handler().await.unwrap();
+        assert_eq!(
+            handler.get_info().capabilities.tools.unwrap().list_changed,
+            Some(true)
+        );
+
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            if let Ok(server) = handler.serve(server_io).await {
+                let _ = server.waiting().await;
+            }
+        });
+        let (changed, mut changes) = tokio::sync::mpsc::unbounded_channel();
+        let client = ListChanged(changed).serve(client_io).await.unwrap();
+        let names = |tools: Vec<Tool>| -> Vec<String> {
+            tools
+                .into_iter()
+                .map(|tool| tool.name.to_string())
+                .collect()
+        };
+        let before = names(client.list_all_tools().await.unwrap());
+
+        let schema = json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+            "required": ["name"]
+        });
+        let greet = Tool::new("greet", "Greet someone", Arc::new(object(schema)));
+        registry.register(greet, |arguments| {
+            Ok(output::ToolOutput::Text(format!(
+                "Hello, {}!",
+                arguments["name"].as_str().unwrap()
+            )))
+        });
+        tokio::time::timeout(Duration::from_secs(2), changes.recv())
+            .await
+            .expect("clients should be told the tools changed");
+        let after = names(client.list_all_tools().await.unwrap());
+        assert_eq!(after.len(), before.len() + 1);
+        assert!(after.contains(&"greet".to_string()));
+
+        let call = |arguments: serde_json::Value| CallToolRequestParam {
+            name: "greet".into(),
+            arguments: arguments.as_object().cloned(),
+        };
+        let result = client
+            .call_tool(call(json!({ "name": "Ada" })))
+            .await
+            .unwrap();
+        assert_eq!(
+            crate::test_client::McpTestClient::extract_text(&result).as_deref(),
+            Some("Hello, Ada!")
+        );
+        // Arguments are checked against the registered schema
+        let error = client.call_tool(call(json!({}))).await.unwrap_err();
+        assert!(error.to_string().contains("name"), "{}", error);
+
+        assert!(registry.unregister("greet"));
+        tokio::time::timeout(Duration::from_secs(2), changes.recv())
+            .await
+            .expect("clients should be told the tools changed");
+        assert_eq!(names(client.list_all_tools().await.unwrap()), before);
+        assert!(client
+            .call_tool(call(json!({ "name": "Ada" })))
+            .await
+            .is_err());
+        client.cancel().await.unwrap();
+    }
+
+    #[derive(Subcommand, Clone, crate::McpTools)]
+    enum AdminCommands {
+        /// Reset a user's password
+        #[mcp(destructive)]
+        Reset {
+            #[arg(long)]
+            user: String,
+            #[arg(long)]
+            force: bool,
+            /// Password to set instead of a generated one
+            #[arg(long)]
+            #[mcp(secret)]
+            password: Option<String>,
+        },
+        /// Add two numbers, shadowed by the main enum's tool
+        Add { a: i32 },
+        /// Delete every user
+        #[mcp(skip)]
+        Purge,
+    }
+
+    #[tokio::test]
+    async fn test_multiple_subcommand_enums() {
+        use crate::test_client::McpTestClient;
+
+        let handler = McpServer::<TestCommands>::new()
+            .with_handler(Box::new(execute_test_command))
+            .with_subcommands::<AdminCommands>(
+                "admin_",
+                Box::new(|cmd| match cmd {
+                    AdminCommands::Reset {
+                        password: Some(password),
+                        ..
+                    } => Err(format!("password `{}` is too short", password)),
+                    AdminCommands::Reset { user, force, .. } => {
+                        Ok(format!("reset {} (force: {})", user, force))
+                    }
+                    AdminCommands::Add { a } => Ok(format!("admin add {}", a)),
+                    AdminCommands::Purge => panic!("skipped tools must never run"),
+                }),
+            )
+            .with_subcommands::<AdminCommands>("", Box::new(|_| Ok("unprefixed".to_string())))
+            .into_handler()
+            .await
+            .unwrap();
+        let (ct, port) = start_in_process_handler(handler)
+            .await
+            .expect("Failed to start server");
+        let client = McpTestClient::connect(&format!("127.0.0.1:{}", port))
+            .await
+            .expect("Failed to connect to server");
+
+        let tools = client.list_tools().await.unwrap();
+        let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+        assert!(names.contains(&"admin_reset") && names.contains(&"admin_add"));
+        assert!(names.contains(&"reset"));
+        assert_eq!(names.iter().filter(|name| **name == "add").count(), 1);
+        let reset = tools
+            .iter()
+            .find(|tool| tool.name == "admin_reset")
+            .unwrap();
+        assert_eq!(
+            reset.description.as_deref(),
+            Some("Reset a user's password")
+        );
+        assert_eq!(reset.input_schema["required"], json!(["user"]));
+        assert_eq!(
+            reset.annotations.as_ref().unwrap().destructive_hint,
+            Some(true)
+        );
+        assert!(!names.contains(&"admin_purge") && !names.contains(&"purge"));
+        assert!(client.call_tool("admin_purge", None).await.is_err());
+
+        let result = client
+            .call_tool("admin_reset", Some(json!({ "user": "ada", "force": true })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("reset ada (force: true)")
+        );
+        let result = client
+            .call_tool("admin_add", Some(json!({ "a": 4 })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("admin add 4")
+        );
+        // The main enum wins a name collision
+        let result = client
+            .call_tool("add", Some(json!({ "a": 1, "b": 2 })))
+            .await
+            .unwrap();
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("1 + 2 = 3")
+        );
+        assert!(client
+            .call_tool("admin_reset", Some(json!({})))
+            .await
+            .is_err());
+        let result = client
+            .call_tool(
+                "admin_reset",
+                Some(json!({ "user": "ada", "password": "hunter2" })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(
+            McpTestClient::extract_text(&result).as_deref(),
+            Some("password `[redacted:password]` is too short")
+        );
+
+        client.shutdown().await.unwrap();
+        ct.cancel();
+    }
+
+    /// Settings a generic CLI is built with, not parsed from its arguments
+    trait Flavor: Clone + Default + Send + Sync + 'static {
+        fn name(&self) -> &'static str;
+    }
+
+    #[derive(Clone, Default)]
+    struct Plain;
+
+    impl Flavor for Plain {
+        fn name(&self) -> &'static str {
+            "plain"
+        }
+    }
+
+    #[derive(clap::Parser, crate::McpMode)]
+    #[mcp(
+        title = "Generic",
+        install_config,
+        call,
+        replay,
+        export_schema,
+        transport_flag = "mcp_port"
+    )]
+    struct GenericCli<C, F>
+    where
+        C: Subcommand + Clone,
+        F: Flavor,
+    {
+        #[command(subcommand)]
+        command: Option<C>,
+
+        #[arg(long)]
+        #[mcp(mode_flag)]
+        mcp: bool,
+
+        #[arg(long)]
+        mcp_port: Option<u16>,
+
+        #[arg(skip)]
+        flavor: F,
+    }
+
+    #[derive(clap::Parser, crate::McpMode)]
+    struct GlobalsCli<C: Subcommand, G: clap::Args> {
+        #[command(subcommand)]
+        command: C,
+
+        #[arg(long)]
+        #[mcp(mode_flag)]
+        mcp: bool,
+
+        #[command(flatten)]
+        #[mcp(global)]
+        globals: G,
+    }
+
+    #[test]
+    fn test_generic_parser_structs() {
+        use clap::Parser;
+
+        type Cli = GenericCli<TestCommands, Plain>;
+        assert_eq!(Cli::mcp_server_metadata().title.as_deref(), Some("Generic"));
+        let manifest = Cli::mcp_registry_manifest().to_json();
+        assert_eq!(
+            manifest["packages"][0]["packageArguments"][0]["name"],
+            "--mcp"
+        );
+        assert!(Cli::handle_mcp_install_config().is_none());
+        assert!(Cli::handle_mcp_call().is_none());
+        assert!(Cli::handle_mcp_replay(execute_test_command).is_none());
+        assert!(Cli::handle_mcp_schema().is_none());
+
+        let cli = Cli::parse_from(["cli", "add", "--a", "1", "--b", "2"]);
+        assert_eq!(cli.flavor.name(), "plain");
+        assert!(matches!(
+            cli.command,
+            Some(TestCommands::Add { a: 1, b: 2 })
+        ));
+        let error = cli.run_mcp(execute_test_command).unwrap_err();
+        assert_eq!(error.to_string(), "MCP mode not enabled");
+
+        let cli =
+            GlobalsCli::<TestCommands, TestGlobals>::parse_from(["cli", "hello", "--name", "x"]);
+        assert!(cli.run_mcp_server().is_err());
+        assert!(
+            GlobalsCli::<TestCommands, TestGlobals>::mcp_server_metadata()
+                .title
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generic_parser_struct_keeps_tool_attributes() {
+        let schema = GenericCli::<TypedOutputCommands, Plain>::mcp_server()
+            .export_schema(export::SchemaFormat::JsonSchema)
+            .await
+            .unwrap();
+        let schema = schema.to_string();
+        assert!(schema.contains("\"greet\""));
+        assert!(!schema.contains("wipe-db"));
+    }
 }
@@ -0,0 +1,184 @@
+//! Handler log events sent to the client as MCP log messages
+//!
+//! With `McpServer::with_logging()` the server offers the `logging`
+//! capability, and [`McpLogLayer`] forwards the `tracing` events emitted
+//! while a handler runs to the client making the call, as
+//! `notifications/message`:
+//!
+//! ```ignore
+//! use tracing_subscriber::prelude::*;
+//!
+//! tracing_subscriber::registry()
+//!     .with(clap_mcp::logging::layer())
+//!     .init();
+//! McpServer::<Commands>::new().with_logging().with_handler(Box::new(execute));
+//!
+//! fn execute(cmd: Commands) -> Result<String, String> {
+//!     tracing::warn!(path = %cmd.path(), "config file missing, using defaults");
+//!     // ...
+//! }
+//! ```
+//!
+//! Each message carries the event's target as its `logger` and its fields
+//! as `data`, e.g. `{"message": "config file missing, using defaults",
+//! "path": "~/.tool.toml"}`. Events below `info` are left out until the
+//! client asks for them with `logging/setLevel`; the level applies to its
+//! session only. A call's messages are sent before its result. Events
+//! outside handlers, including the server's own, are not forwarded. Needs
+//! the `logging` feature.
+
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam};
+use rmcp::service::{Peer, RoleServer};
+use std::cell::RefCell;
+use tokio::sync::{mpsc, oneshot};
+
+/// A message for the task forwarding a call's log events
+///
+/// Without the `logging` feature there is no layer, so only flushes are sent.
+#[cfg_attr(not(feature = "logging"), allow(dead_code))]
+enum Forward {
+    Log(LoggingMessageNotificationParam),
+    /// Answered once the messages sent before it have been forwarded
+    Flush(oneshot::Sender<()>),
+}
+
+/// Where the call being handled sends its log events
+#[derive(Clone)]
+pub(crate) struct LogSink {
+    messages: mpsc::UnboundedSender<Forward>,
+    /// Least severe level forwarded
+    #[cfg_attr(not(feature = "logging"), allow(dead_code))]
+    level: LoggingLevel,
+}
+
+impl LogSink {
+    /// A sink forwarding events at `level` or above to `peer`, in order
+    pub(crate) fn new(peer: Peer<RoleServer>, level: LoggingLevel) -> Self {
+        let (messages, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(forward) = received.recv().await {
+                match forward {
+                    Forward::Log(message) => {
+                        if let Err(e) = peer.notify_logging_message(message).await {
+                            tracing::debug!("failed to send log message: {}", e);
+                        }
+                    }
+                    Forward::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Self { messages, level }
+    }
+
+    /// Wait until the events sent so far have been forwarded
+    pub(crate) async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.messages.send(Forward::Flush(done)).is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+thread_local! {
+    static SINK: RefCell<Option<LogSink>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `sink` receiving the log events of this thread
+pub(crate) fn scope<R>(sink: Option<LogSink>, f: impl FnOnce() -> R) -> R {
+    let previous = SINK.with(|current| current.replace(sink));
+    let result = f();
+    SINK.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// How severe `level` is, for comparing levels
+#[cfg(feature = "logging")]
+fn severity(level: LoggingLevel) -> u8 {
+    level as u8
+}
+
+/// The MCP level of a `tracing` level
+#[cfg(feature = "logging")]
+fn mcp_level(level: &tracing::Level) -> LoggingLevel {
+    match *level {
+        tracing::Level::ERROR => LoggingLevel::Error,
+        tracing::Level::WARN => LoggingLevel::Warning,
+        tracing::Level::INFO => LoggingLevel::Info,
+        _ => LoggingLevel::Debug,
+    }
+}
+
+/// A `tracing` layer sending the events emitted inside handlers to the
+/// client making the call
+#[cfg(feature = "logging")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct McpLogLayer;
+
+/// The layer to add to the subscriber, see [`McpLogLayer`]
+#[cfg(feature = "logging")]
+pub fn layer() -> McpLogLayer {
+    McpLogLayer
+}
+
+#[cfg(feature = "logging")]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for McpLogLayer {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        SINK.with(|sink| {
+            let sink = sink.borrow();
+            let Some(sink) = sink.as_ref() else {
+                return;
+            };
+            let level = mcp_level(event.metadata().level());
+            if severity(level) < severity(sink.level) {
+                return;
+            }
+            let mut fields = FieldVisitor::default();
+            event.record(&mut fields);
+            let message = LoggingMessageNotificationParam {
+                level,
+                logger: Some(event.metadata().target().to_string()),
+                data: serde_json::Value::Object(fields.0),
+            };
+            let _ = sink.messages.send(Forward::Log(message));
+        });
+    }
+}
+
+/// Collects an event's fields as JSON
+#[cfg(feature = "logging")]
+#[derive(Default)]
+struct FieldVisitor(serde_json::Map<String, serde_json::Value>);
+
+#[cfg(feature = "logging")]
+impl tracing::field::Visit for FieldVisitor {
+    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
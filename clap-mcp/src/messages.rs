@@ -0,0 +1,601 @@
+//! Server-generated text in a configurable language
+//!
+//! Validation errors and notices produced by clap-mcp itself (not by the CLI)
+//! end up in agent chats, so they can be localized. Pick a built-in
+//! [`Language`] or supply a [`Translator`] for anything else; messages it
+//! does not translate fall back to English:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new().with_language(Language::German);
+//!
+//! McpServer::<Commands>::new().with_translator(|message: &Message| match message {
+//!     Message::NoHandler => Some("Kein Handler".to_string()),
+//!     _ => None,
+//! });
+//! ```
+//!
+//! Details quoted from clap's own errors stay in English.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A string generated by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Message {
+    /// An abbreviated tool name matches several subcommands
+    AmbiguousTool {
+        tool: String,
+        candidates: Vec<String>,
+    },
+    /// The tool is not offered by this server
+    UnknownTool { tool: String },
+    /// A [`ToolPolicy`](crate::policy::ToolPolicy) closed the tool for now
+    ToolUnavailable {
+        tool: String,
+        reason: String,
+        retry_after_secs: u64,
+    },
+    /// The principal used up its quota for `period` ("daily" or "monthly")
+    QuotaExceeded {
+        principal: String,
+        period: String,
+        limit: u64,
+        retry_after_secs: u64,
+    },
+    /// clap rejected the arguments; `details` is clap's error
+    InvalidArguments { details: String },
+    /// A required argument is missing from the call
+    MissingArgument { field: String },
+    /// An argument does not match its schema; `field` is a path such as `tags/1`
+    InvalidArgument { field: String, details: String },
+    /// Two arguments clap does not accept together were both given
+    ConflictingArguments { first: String, second: String },
+    /// `field` is missing, but clap requires it alongside `required_by`
+    MissingDependentArgument { field: String, required_by: String },
+    /// None of the arguments of a required group was given
+    MissingOneOf { fields: Vec<String> },
+    /// The arguments parsed but did not form a subcommand
+    ParseFailed { details: String },
+    /// The server was built without a command handler
+    NoHandler,
+    /// `_vars` is not an object of scalar values
+    InvalidVariables,
+    /// `${name}` names no session variable
+    UndefinedVariable { name: String },
+    /// `${` without a closing brace
+    UnterminatedVariable { template: String },
+    /// A `#[mcp(secret_ref)]` argument names no registered secret
+    UnknownSecret { handle: String, argument: String },
+    /// A `#[mcp(secret_ref)]` argument is not a secret name
+    InvalidSecretRef { argument: String },
+    /// The program set with `McpServer::with_exec` could not be started
+    ExecFailed { program: String, details: String },
+    /// The program set with `McpServer::with_exec` ran past its timeout
+    ExecTimedOut { timeout_secs: u64 },
+    /// A tool call ran past its timeout (see `McpServer::with_timeout`)
+    ToolTimedOut { tool: String, timeout: Duration },
+    /// The client cancelled a tool call before it finished
+    ToolCancelled { tool: String },
+    /// A call was refused because `limit` calls were already running
+    ServerBusy { limit: usize },
+    /// A call used the former name `old` of the tool `new`
+    ToolRenamed { old: String, new: String },
+    /// A [`ToolAuthorizer`](crate::authz::ToolAuthorizer) refused the
+    /// principal access to the tool
+    AccessDenied { principal: String, tool: String },
+    /// A list request passed a cursor no earlier page returned
+    InvalidCursor { cursor: String },
+    /// A destructive call was held back until the user approves `command`
+    /// (see [`crate::confirm`])
+    ConfirmationRequired {
+        tool: String,
+        command: String,
+        token: String,
+    },
+    /// The confirmation handler did not approve `command`
+    /// (see [`crate::confirm`])
+    ConfirmationDenied { tool: String, command: String },
+}
+
+impl Message {
+    /// The message in English, the default language
+    pub fn english(&self) -> String {
+        match self {
+            Self::AmbiguousTool { tool, candidates } => format!(
+                "Ambiguous tool name `{}`: could be {}",
+                tool,
+                candidates.join(", ")
+            ),
+            Self::UnknownTool { tool } => format!("Unknown tool `{}`", tool),
+            Self::ToolUnavailable {
+                tool,
+                reason,
+                retry_after_secs,
+            } => format!(
+                "Tool `{}` is unavailable ({}); retry in {} seconds",
+                tool, reason, retry_after_secs
+            ),
+            Self::QuotaExceeded {
+                principal,
+                period,
+                limit,
+                retry_after_secs,
+            } => format!(
+                "`{}` used up its {} quota of {}; retry in {} seconds",
+                principal, period, limit, retry_after_secs
+            ),
+            Self::InvalidArguments { details } => format!("Invalid arguments: {}", details),
+            Self::MissingArgument { field } => format!("Missing required argument `{}`", field),
+            Self::InvalidArgument { field, details } => {
+                format!("Invalid argument `{}`: {}", field, details)
+            }
+            Self::ConflictingArguments { first, second } => {
+                format!("Arguments `{}` and `{}` cannot be used together", first, second)
+            }
+            Self::MissingDependentArgument { field, required_by } => format!(
+                "Missing argument `{}`, required when `{}` is given",
+                field, required_by
+            ),
+            Self::MissingOneOf { fields } => {
+                format!("One of the arguments {} is required", quoted(fields))
+            }
+            Self::ParseFailed { details } => format!("Failed to parse subcommand: {}", details),
+            Self::NoHandler => "No command handler provided. The CLI must provide a handler function to execute commands in MCP mode.".to_string(),
+            Self::InvalidVariables => {
+                "`_vars` must be an object of strings, numbers or booleans".to_string()
+            }
+            Self::UndefinedVariable { name } => format!("undefined variable `{}`", name),
+            Self::UnterminatedVariable { template } => {
+                format!("unterminated variable reference in `{}`", template)
+            }
+            Self::UnknownSecret { handle, argument } => {
+                format!("unknown secret `{}` for `{}`", handle, argument)
+            }
+            Self::InvalidSecretRef { argument } => format!("`{}` must name a secret", argument),
+            Self::ExecFailed { program, details } => {
+                format!("Failed to run `{}`: {}", program, details)
+            }
+            Self::ExecTimedOut { timeout_secs } => format!(
+                "Command timed out after {} seconds and was killed",
+                timeout_secs
+            ),
+            Self::ToolTimedOut { tool, timeout } => {
+                format!("Tool `{}` timed out after {:?}", tool, timeout)
+            }
+            Self::ToolCancelled { tool } => format!("Tool `{}` was cancelled", tool),
+            Self::ServerBusy { limit } => format!(
+                "Server busy: {} tool calls already running, try again later",
+                limit
+            ),
+            Self::ToolRenamed { old, new } => format!(
+                "Warning: tool `{}` has been renamed to `{}`; the old name is deprecated and will be removed in a future release",
+                old, new
+            ),
+            Self::AccessDenied { principal, tool } => {
+                format!("`{}` is not allowed to call tool `{}`", principal, tool)
+            }
+            Self::InvalidCursor { cursor } => format!("Invalid pagination cursor `{}`", cursor),
+            Self::ConfirmationRequired { tool, command, token } => format!(
+                "Tool `{}` is destructive and was not run. Ask the user to approve `{}`, then repeat the call with the same arguments and `\"_confirm\": \"{}\"`",
+                tool, command, token
+            ),
+            Self::ConfirmationDenied { tool, command } => format!(
+                "Tool `{}` is destructive and was not run: the user did not approve `{}`",
+                tool, command
+            ),
+        }
+    }
+}
+
+/// `names` as a list of code spans, e.g. `` `a`, `b` ``
+fn quoted(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.english())
+    }
+}
+
+/// Translates server messages; closures taking `&Message` implement it
+///
+/// Returning `None` falls back to English.
+pub trait Translator: Send + Sync {
+    fn translate(&self, message: &Message) -> Option<String>;
+}
+
+impl<F: Fn(&Message) -> Option<String> + Send + Sync> Translator for F {
+    fn translate(&self, message: &Message) -> Option<String> {
+        self(message)
+    }
+}
+
+/// Languages with built-in translations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Language {
+    #[default]
+    English,
+    German,
+    French,
+    Spanish,
+}
+
+impl Translator for Language {
+    fn translate(&self, message: &Message) -> Option<String> {
+        use Message::*;
+        let text = match (self, message) {
+            (Self::English, _) => return None,
+
+            (Self::German, AmbiguousTool { tool, candidates }) => format!(
+                "Mehrdeutiger Toolname `{}`: möglich sind {}",
+                tool,
+                candidates.join(", ")
+            ),
+            (Self::German, UnknownTool { tool }) => format!("Unbekanntes Tool `{}`", tool),
+            (
+                Self::German,
+                ToolUnavailable {
+                    tool,
+                    reason,
+                    retry_after_secs,
+                },
+            ) => format!(
+                "Tool `{}` ist nicht verfügbar ({}); erneut versuchen in {} Sekunden",
+                tool, reason, retry_after_secs
+            ),
+            (
+                Self::German,
+                QuotaExceeded {
+                    principal,
+                    period,
+                    limit,
+                    retry_after_secs,
+                },
+            ) => format!(
+                "`{}` hat das Kontingent ({}) von {} aufgebraucht; erneut versuchen in {} Sekunden",
+                principal, period, limit, retry_after_secs
+            ),
+            (Self::German, InvalidArguments { details }) => {
+                format!("Ungültige Argumente: {}", details)
+            }
+            (Self::German, MissingArgument { field }) => format!("Erforderliches Argument `{}` fehlt", field),
+            (Self::German, InvalidArgument { field, details }) => {
+                format!("Ungültiges Argument `{}`: {}", field, details)
+            }
+            (Self::German, ConflictingArguments { first, second }) => format!(
+                "Die Argumente `{}` und `{}` können nicht zusammen verwendet werden",
+                first, second
+            ),
+            (Self::German, MissingDependentArgument { field, required_by }) => format!(
+                "Argument `{}` fehlt, es ist erforderlich, wenn `{}` angegeben ist",
+                field, required_by
+            ),
+            (Self::German, MissingOneOf { fields }) => {
+                format!("Eines der Argumente {} ist erforderlich", quoted(fields))
+            }
+            (Self::German, ParseFailed { details }) => {
+                format!("Unterbefehl konnte nicht gelesen werden: {}", details)
+            }
+            (Self::German, NoHandler) => "Kein Befehls-Handler angegeben. Die CLI muss eine Handler-Funktion bereitstellen, um Befehle im MCP-Modus auszuführen.".to_string(),
+            (Self::German, InvalidVariables) => {
+                "`_vars` muss ein Objekt aus Zeichenketten, Zahlen oder Wahrheitswerten sein"
+                    .to_string()
+            }
+            (Self::German, UndefinedVariable { name }) => {
+                format!("Variable `{}` ist nicht definiert", name)
+            }
+            (Self::German, UnterminatedVariable { template }) => {
+                format!("Nicht abgeschlossener Variablenverweis in `{}`", template)
+            }
+            (Self::German, UnknownSecret { handle, argument }) => {
+                format!("Unbekanntes Geheimnis `{}` für `{}`", handle, argument)
+            }
+            (Self::German, InvalidSecretRef { argument }) => {
+                format!("`{}` muss ein Geheimnis benennen", argument)
+            }
+            (Self::German, ExecFailed { program, details }) => {
+                format!("`{}` konnte nicht ausgeführt werden: {}", program, details)
+            }
+            (Self::German, ExecTimedOut { timeout_secs }) => format!(
+                "Befehl nach {} Sekunden abgebrochen und beendet",
+                timeout_secs
+            ),
+            (Self::German, ToolTimedOut { tool, timeout }) => format!(
+                "Zeitüberschreitung von Werkzeug `{}` nach {:?}",
+                tool, timeout
+            ),
+            (Self::German, ToolCancelled { tool }) => {
+                format!("Werkzeug `{}` wurde abgebrochen", tool)
+            }
+            (Self::German, ServerBusy { limit }) => format!(
+                "Server ausgelastet: {} Werkzeugaufrufe laufen bereits, bitte später erneut versuchen",
+                limit
+            ),
+            (Self::German, ToolRenamed { old, new }) => format!(
+                "Warnung: Werkzeug `{}` wurde in `{}` umbenannt; der alte Name ist veraltet und wird in einer künftigen Version entfernt",
+                old, new
+            ),
+            (Self::German, AccessDenied { principal, tool }) => format!(
+                "`{}` darf das Werkzeug `{}` nicht aufrufen",
+                principal, tool
+            ),
+            (Self::German, InvalidCursor { cursor }) => {
+                format!("Ungültiger Seiten-Cursor `{}`", cursor)
+            }
+            (Self::German, ConfirmationRequired { tool, command, token }) => format!(
+                "Werkzeug `{}` ist destruktiv und wurde nicht ausgeführt. Lassen Sie `{}` vom Benutzer bestätigen und wiederholen Sie den Aufruf dann mit denselben Argumenten und `\"_confirm\": \"{}\"`",
+                tool, command, token
+            ),
+            (Self::German, ConfirmationDenied { tool, command }) => format!(
+                "Werkzeug `{}` ist destruktiv und wurde nicht ausgeführt: der Benutzer hat `{}` nicht bestätigt",
+                tool, command
+            ),
+
+            (Self::French, AmbiguousTool { tool, candidates }) => format!(
+                "Nom d'outil ambigu `{}` : correspond à {}",
+                tool,
+                candidates.join(", ")
+            ),
+            (Self::French, UnknownTool { tool }) => format!("Outil inconnu `{}`", tool),
+            (
+                Self::French,
+                ToolUnavailable {
+                    tool,
+                    reason,
+                    retry_after_secs,
+                },
+            ) => format!(
+                "L'outil `{}` est indisponible ({}) ; réessayez dans {} secondes",
+                tool, reason, retry_after_secs
+            ),
+            (
+                Self::French,
+                QuotaExceeded {
+                    principal,
+                    period,
+                    limit,
+                    retry_after_secs,
+                },
+            ) => format!(
+                "`{}` a épuisé son quota ({}) de {} ; réessayez dans {} secondes",
+                principal, period, limit, retry_after_secs
+            ),
+            (Self::French, InvalidArguments { details }) => {
+                format!("Arguments invalides : {}", details)
+            }
+            (Self::French, MissingArgument { field }) => format!("Argument obligatoire `{}` manquant", field),
+            (Self::French, InvalidArgument { field, details }) => {
+                format!("Argument `{}` invalide : {}", field, details)
+            }
+            (Self::French, ConflictingArguments { first, second }) => format!(
+                "Les arguments `{}` et `{}` ne peuvent pas être utilisés ensemble",
+                first, second
+            ),
+            (Self::French, MissingDependentArgument { field, required_by }) => format!(
+                "Argument `{}` manquant, obligatoire lorsque `{}` est donné",
+                field, required_by
+            ),
+            (Self::French, MissingOneOf { fields }) => {
+                format!("L'un des arguments {} est obligatoire", quoted(fields))
+            }
+            (Self::French, ParseFailed { details }) => {
+                format!("Impossible d'analyser la sous-commande : {}", details)
+            }
+            (Self::French, NoHandler) => "Aucun gestionnaire de commandes fourni. La CLI doit fournir une fonction de traitement pour exécuter les commandes en mode MCP.".to_string(),
+            (Self::French, InvalidVariables) => {
+                "`_vars` doit être un objet de chaînes, de nombres ou de booléens".to_string()
+            }
+            (Self::French, UndefinedVariable { name }) => {
+                format!("variable `{}` non définie", name)
+            }
+            (Self::French, UnterminatedVariable { template }) => {
+                format!("référence de variable non terminée dans `{}`", template)
+            }
+            (Self::French, UnknownSecret { handle, argument }) => {
+                format!("secret `{}` inconnu pour `{}`", handle, argument)
+            }
+            (Self::French, InvalidSecretRef { argument }) => {
+                format!("`{}` doit désigner un secret", argument)
+            }
+            (Self::French, ExecFailed { program, details }) => {
+                format!("Impossible d'exécuter `{}` : {}", program, details)
+            }
+            (Self::French, ExecTimedOut { timeout_secs }) => format!(
+                "La commande a dépassé le délai de {} secondes et a été arrêtée",
+                timeout_secs
+            ),
+            (Self::French, ToolTimedOut { tool, timeout }) => format!(
+                "L'outil `{}` a dépassé le délai de {:?}",
+                tool, timeout
+            ),
+            (Self::French, ToolCancelled { tool }) => format!("L'outil `{}` a été annulé", tool),
+            (Self::French, ServerBusy { limit }) => format!(
+                "Serveur occupé : {} appels d'outils déjà en cours, réessayez plus tard",
+                limit
+            ),
+            (Self::French, ToolRenamed { old, new }) => format!(
+                "Avertissement : l'outil `{}` a été renommé en `{}` ; l'ancien nom est obsolète et sera supprimé dans une version future",
+                old, new
+            ),
+            (Self::French, AccessDenied { principal, tool }) => format!(
+                "`{}` n'est pas autorisé à appeler l'outil `{}`",
+                principal, tool
+            ),
+            (Self::French, InvalidCursor { cursor }) => {
+                format!("Curseur de pagination invalide `{}`", cursor)
+            }
+            (Self::French, ConfirmationRequired { tool, command, token }) => format!(
+                "L'outil `{}` est destructif et n'a pas été exécuté. Demandez à l'utilisateur d'approuver `{}`, puis répétez l'appel avec les mêmes arguments et `\"_confirm\": \"{}\"`",
+                tool, command, token
+            ),
+            (Self::French, ConfirmationDenied { tool, command }) => format!(
+                "L'outil `{}` est destructif et n'a pas été exécuté : l'utilisateur n'a pas approuvé `{}`",
+                tool, command
+            ),
+
+            (Self::Spanish, AmbiguousTool { tool, candidates }) => format!(
+                "Nombre de herramienta ambiguo `{}`: podría ser {}",
+                tool,
+                candidates.join(", ")
+            ),
+            (Self::Spanish, UnknownTool { tool }) => {
+                format!("Herramienta desconocida `{}`", tool)
+            }
+            (
+                Self::Spanish,
+                ToolUnavailable {
+                    tool,
+                    reason,
+                    retry_after_secs,
+                },
+            ) => format!(
+                "La herramienta `{}` no está disponible ({}); reintente en {} segundos",
+                tool, reason, retry_after_secs
+            ),
+            (
+                Self::Spanish,
+                QuotaExceeded {
+                    principal,
+                    period,
+                    limit,
+                    retry_after_secs,
+                },
+            ) => format!(
+                "`{}` agotó su cuota ({}) de {}; reintente en {} segundos",
+                principal, period, limit, retry_after_secs
+            ),
+            (Self::Spanish, InvalidArguments { details }) => {
+                format!("Argumentos no válidos: {}", details)
+            }
+            (Self::Spanish, MissingArgument { field }) => format!("Falta el argumento obligatorio `{}`", field),
+            (Self::Spanish, InvalidArgument { field, details }) => {
+                format!("Argumento `{}` no válido: {}", field, details)
+            }
+            (Self::Spanish, ConflictingArguments { first, second }) => format!(
+                "Los argumentos `{}` y `{}` no se pueden usar juntos",
+                first, second
+            ),
+            (Self::Spanish, MissingDependentArgument { field, required_by }) => format!(
+                "Falta el argumento `{}`, obligatorio cuando se da `{}`",
+                field, required_by
+            ),
+            (Self::Spanish, MissingOneOf { fields }) => {
+                format!("Se requiere uno de los argumentos {}", quoted(fields))
+            }
+            (Self::Spanish, ParseFailed { details }) => {
+                format!("No se pudo analizar el subcomando: {}", details)
+            }
+            (Self::Spanish, NoHandler) => "No se proporcionó un controlador de comandos. La CLI debe proporcionar una función controladora para ejecutar comandos en modo MCP.".to_string(),
+            (Self::Spanish, InvalidVariables) => {
+                "`_vars` debe ser un objeto de cadenas, números o booleanos".to_string()
+            }
+            (Self::Spanish, UndefinedVariable { name }) => {
+                format!("variable `{}` no definida", name)
+            }
+            (Self::Spanish, UnterminatedVariable { template }) => {
+                format!("referencia de variable sin cerrar en `{}`", template)
+            }
+            (Self::Spanish, UnknownSecret { handle, argument }) => {
+                format!("secreto `{}` desconocido para `{}`", handle, argument)
+            }
+            (Self::Spanish, InvalidSecretRef { argument }) => {
+                format!("`{}` debe nombrar un secreto", argument)
+            }
+            (Self::Spanish, ExecFailed { program, details }) => {
+                format!("No se pudo ejecutar `{}`: {}", program, details)
+            }
+            (Self::Spanish, ExecTimedOut { timeout_secs }) => format!(
+                "El comando superó el tiempo límite de {} segundos y se detuvo",
+                timeout_secs
+            ),
+            (Self::Spanish, ToolTimedOut { tool, timeout }) => format!(
+                "La herramienta `{}` superó el tiempo límite de {:?}",
+                tool, timeout
+            ),
+            (Self::Spanish, ToolCancelled { tool }) => {
+                format!("La herramienta `{}` fue cancelada", tool)
+            }
+            (Self::Spanish, ServerBusy { limit }) => format!(
+                "Servidor ocupado: ya hay {} llamadas de herramientas en curso, inténtelo más tarde",
+                limit
+            ),
+            (Self::Spanish, ToolRenamed { old, new }) => format!(
+                "Advertencia: la herramienta `{}` se renombró a `{}`; el nombre anterior está obsoleto y se eliminará en una versión futura",
+                old, new
+            ),
+            (Self::Spanish, AccessDenied { principal, tool }) => format!(
+                "`{}` no tiene permiso para llamar a la herramienta `{}`",
+                principal, tool
+            ),
+            (Self::Spanish, InvalidCursor { cursor }) => {
+                format!("Cursor de paginación no válido `{}`", cursor)
+            }
+            (Self::Spanish, ConfirmationRequired { tool, command, token }) => format!(
+                "La herramienta `{}` es destructiva y no se ejecutó. Pida al usuario que apruebe `{}` y repita la llamada con los mismos argumentos y `\"_confirm\": \"{}\"`",
+                tool, command, token
+            ),
+            (Self::Spanish, ConfirmationDenied { tool, command }) => format!(
+                "La herramienta `{}` es destructiva y no se ejecutó: el usuario no aprobó `{}`",
+                tool, command
+            ),
+        };
+        Some(text)
+    }
+}
+
+/// The translator configured for one server
+#[derive(Clone, Default)]
+pub(crate) struct Messages {
+    translator: Option<Arc<dyn Translator>>,
+}
+
+impl Messages {
+    pub(crate) fn new(translator: impl Translator + 'static) -> Self {
+        Self {
+            translator: Some(Arc::new(translator)),
+        }
+    }
+
+    /// `message` in the configured language, or in English
+    pub(crate) fn render(&self, message: &Message) -> String {
+        self.translator
+            .as_ref()
+            .and_then(|translator| translator.translate(message))
+            .unwrap_or_else(|| message.english())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_with_fallback() {
+        let message = Message::UndefinedVariable {
+            name: "project".to_string(),
+        };
+        assert_eq!(
+            Messages::default().render(&message),
+            "undefined variable `project`"
+        );
+        assert_eq!(
+            Messages::new(Language::German).render(&message),
+            "Variable `project` ist nicht definiert"
+        );
+
+        let partial = Messages::new(|message: &Message| match message {
+            Message::NoHandler => Some("sin controlador".to_string()),
+            _ => None,
+        });
+        assert_eq!(partial.render(&Message::NoHandler), "sin controlador");
+        assert_eq!(partial.render(&message), message.english());
+    }
+}
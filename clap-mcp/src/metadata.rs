@@ -0,0 +1,412 @@
+//! Per-tool metadata declared with `#[derive(McpTools)]`
+//!
+//! clap has no place to store MCP-specific settings, so the `McpTools` derive
+//! collects `#[mcp(...)]` attributes from the variants of a `Subcommand` enum
+//! and exposes them through the [`McpTools`] trait:
+//!
+//! ```ignore
+//! #[derive(Subcommand, Clone, McpTools)]
+//! enum Commands {
+//!     /// Print the current config
+//!     #[mcp(output_mime = "application/json", read_only)]
+//!     Config,
+//!     /// Remove a stale cache entry
+//!     #[mcp(destructive, idempotent)]
+//!     Evict { key: String },
+//!     /// Drop every table; never offered to agents
+//!     #[mcp(skip)]
+//!     WipeDb,
+//!     /// Create a branch
+//!     Branch {
+//!         #[mcp(schema = r#"{"type": "string", "pattern": "^[a-z-]+$"}"#)]
+//!         name: String,
+//!     },
+//! }
+//! ```
+//!
+//! Arguments kept in an `Args` struct of their own, wrapped by a tuple
+//! variant, take their `#[mcp(...)]` settings on the struct, which derives
+//! `McpTools` as well:
+//!
+//! ```ignore
+//! #[derive(Args, Clone, McpTools)]
+//! struct BuildArgs {
+//!     #[arg(long)]
+//!     #[mcp(sticky)]
+//!     target: String,
+//! }
+//!
+//! #[derive(Subcommand, Clone, McpTools)]
+//! enum Commands {
+//!     Build(BuildArgs),
+//! }
+//! ```
+
+use rmcp::model::{JsonObject, ToolAnnotations};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::marker::PhantomData;
+use std::time::Duration;
+
+/// Settings for a single tool, keyed by the clap subcommand name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolAttributes {
+    /// Name of the clap subcommand these attributes apply to
+    pub name: String,
+    /// MIME type of the tool's successful text output
+    pub output_mime: Option<String>,
+    /// Whether `${var}` references in string arguments are replaced with
+    /// session variables (see [`crate::vars`])
+    pub interpolate: bool,
+    /// Whether the subcommand is hidden from MCP clients, e.g. because it is
+    /// destructive; it is not listed and calls to it are rejected
+    pub skip: bool,
+    /// Whether the tool only reads, never modifying its environment
+    pub read_only: bool,
+    /// Whether the tool may destroy or overwrite data
+    pub destructive: bool,
+    /// Whether repeating a call with the same arguments has no further effect
+    pub idempotent: bool,
+    /// JSON schema overrides keyed by argument id
+    ///
+    /// Each object is merged over the schema generated for that argument, so
+    /// the keys it sets (e.g. `type`) replace the inferred ones.
+    pub arg_schemas: BTreeMap<String, serde_json::Value>,
+    /// Arguments whose values name server-side secrets (see [`crate::secrets`])
+    pub secret_refs: BTreeSet<String>,
+    /// How long a call may run, overriding `McpServer::with_timeout`
+    pub timeout: Option<Duration>,
+    /// Arguments that default to the value last given in the session (see
+    /// [`crate::vars`])
+    pub sticky_args: BTreeSet<String>,
+    /// Former names of the tool, still accepted but not listed; calls using
+    /// them get a deprecation warning
+    pub renamed_from: BTreeSet<String>,
+    /// Arguments whose values are redacted from error messages, e.g. a
+    /// `--token` clap would quote when rejecting it
+    pub secret_args: BTreeSet<String>,
+    /// Names arguments are listed and called by instead of their ids, keyed
+    /// by argument id
+    pub arg_renames: BTreeMap<String, String>,
+    /// Further names calls may use for arguments, mapped to the argument id
+    pub arg_aliases: BTreeMap<String, String>,
+    /// Arguments each argument requires (`#[arg(requires = "...")]`), keyed
+    /// by argument id; clap does not expose them at runtime
+    pub arg_requires: BTreeMap<String, BTreeSet<String>>,
+    /// Argument whose value calls run serially by: calls giving it the same
+    /// value wait for each other, also across tools serializing by the same
+    /// argument
+    pub serialize_by: Option<String>,
+}
+
+impl ToolAttributes {
+    /// Annotation hints listed with the tool, if any are set
+    pub fn annotations(&self) -> Option<ToolAnnotations> {
+        let hint = |set: bool| set.then_some(true);
+        let annotations = ToolAnnotations {
+            read_only_hint: hint(self.read_only),
+            destructive_hint: hint(self.destructive),
+            idempotent_hint: hint(self.idempotent),
+            ..Default::default()
+        };
+        (annotations != ToolAnnotations::default()).then_some(annotations)
+    }
+
+    /// Add the argument settings of an `Args` struct the tool's variant wraps
+    pub fn with_arg_attributes(mut self, args: ToolAttributes) -> Self {
+        self.arg_schemas.extend(args.arg_schemas);
+        self.secret_refs.extend(args.secret_refs);
+        self.sticky_args.extend(args.sticky_args);
+        self.secret_args.extend(args.secret_args);
+        self.arg_renames.extend(args.arg_renames);
+        self.arg_aliases.extend(args.arg_aliases);
+        self.arg_requires.extend(args.arg_requires);
+        self
+    }
+
+    /// Name the argument `id` is listed under
+    pub fn arg_name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.arg_renames.get(id).map_or(id, String::as_str)
+    }
+
+    /// Id of the argument a call names `name`, by its listed name, an alias
+    /// or its id
+    pub fn arg_id<'a>(&'a self, name: &'a str) -> &'a str {
+        if let Some(id) = self.arg_aliases.get(name) {
+            return id;
+        }
+        self.arg_renames
+            .iter()
+            .find(|(_, renamed)| *renamed == name)
+            .map_or(name, |(id, _)| id.as_str())
+    }
+
+    /// Key `arguments` by argument id instead of listed names and aliases
+    pub(crate) fn resolve_arg_names(&self, arguments: &mut JsonObject) {
+        if self.arg_renames.is_empty() && self.arg_aliases.is_empty() {
+            return;
+        }
+        let names: Vec<String> = arguments.keys().cloned().collect();
+        for name in names {
+            let id = self.arg_id(&name);
+            if id != name {
+                let id = id.to_string();
+                let value = arguments.remove(&name).expect("listed key");
+                arguments.insert(id, value);
+            }
+        }
+    }
+
+    /// Key `arguments` by listed name instead of argument id
+    pub(crate) fn listed_arg_names(&self, arguments: JsonObject) -> JsonObject {
+        if self.arg_renames.is_empty() {
+            return arguments;
+        }
+        arguments
+            .into_iter()
+            .map(|(id, value)| (self.arg_name(&id).to_string(), value))
+            .collect()
+    }
+
+    /// List the properties of `schema` under their renamed names, with their
+    /// aliases under `x-aliases`, and name them so in its constraints
+    pub(crate) fn rename_properties(&self, schema: &mut JsonObject) {
+        if self.arg_renames.is_empty() && self.arg_aliases.is_empty() {
+            return;
+        }
+        if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+            for (alias, id) in &self.arg_aliases {
+                if let Some(Value::Object(property)) = properties.get_mut(id) {
+                    let aliases = property
+                        .entry("x-aliases")
+                        .or_insert_with(|| Value::Array(Vec::new()));
+                    if let Value::Array(aliases) = aliases {
+                        aliases.push(Value::from(alias.as_str()));
+                    }
+                }
+            }
+            for (id, name) in &self.arg_renames {
+                if let Some(property) = properties.remove(id) {
+                    properties.insert(name.clone(), property);
+                }
+            }
+        }
+        self.rename_required(schema);
+        if let Some(Value::Object(dependent)) = schema.get_mut("dependentRequired") {
+            *dependent = std::mem::take(dependent)
+                .into_iter()
+                .map(|(id, mut required)| {
+                    self.rename_ids(&mut required);
+                    (self.arg_name(&id).to_string(), required)
+                })
+                .collect();
+        }
+    }
+
+    /// Rename the ids `schema` lists as `required`, also in the constraints
+    /// it combines with `allOf`, `anyOf`, `oneOf` and `not`
+    fn rename_required(&self, schema: &mut JsonObject) {
+        if let Some(required) = schema.get_mut("required") {
+            self.rename_ids(required);
+        }
+        for key in ["allOf", "anyOf", "oneOf"] {
+            if let Some(Value::Array(schemas)) = schema.get_mut(key) {
+                for schema in schemas {
+                    if let Value::Object(schema) = schema {
+                        self.rename_required(schema);
+                    }
+                }
+            }
+        }
+        if let Some(Value::Object(not)) = schema.get_mut("not") {
+            self.rename_required(not);
+        }
+    }
+
+    fn rename_ids(&self, ids: &mut Value) {
+        if let Value::Array(ids) = ids {
+            for id in ids.iter_mut() {
+                if let Some(name) = id.as_str().and_then(|id| self.arg_renames.get(id)) {
+                    *id = Value::from(name.as_str());
+                }
+            }
+        }
+    }
+}
+
+/// Descriptive server information shown by clients in their server listings
+///
+/// Set from struct-level attributes on the `McpMode` parser:
+///
+/// ```ignore
+/// #[derive(Parser, McpMode)]
+/// #[mcp(title = "Calculator", website = "https://example.com", icon = "https://example.com/icon.png")]
+/// struct Cli { /* ... */ }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerMetadata {
+    /// Human-readable display name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub website_url: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub icons: Vec<Icon>,
+    /// Contact for support, e.g. an email address or issue tracker URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+}
+
+impl ServerMetadata {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// An icon a client can display for the server
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Icon {
+    /// URL or `data:` URI of the image
+    pub src: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+impl Icon {
+    pub fn new(src: impl Into<String>) -> Self {
+        Self {
+            src: src.into(),
+            mime_type: None,
+        }
+    }
+}
+
+/// Implemented by `#[derive(McpTools)]` for subcommand enums
+pub trait McpTools {
+    /// Attributes for every variant that declared at least one `#[mcp(...)]` setting
+    fn tool_attributes() -> Vec<ToolAttributes>;
+
+    /// Schemas of the enum's `#[derive(McpSchema)]`, if it has one
+    ///
+    /// Lets parser structs generic over the enum find them, which the probe
+    /// they otherwise use cannot.
+    fn static_tools() -> Option<&'static [crate::schema::StaticTool]> {
+        None
+    }
+}
+
+/// Lookup of tool attributes used by the derive-generated server constructors
+///
+/// The derive on the parser struct cannot know whether the subcommand enum also
+/// derives `McpTools`, so it resolves attributes through this probe: the
+/// `ViaMcpTools` impl is picked when the bound holds, otherwise method
+/// resolution falls back to `ViaDefault` and no attributes are applied.
+///
+/// The bound is only decided for concrete types. A parser struct generic over
+/// its subcommand type requires `McpTools` of it instead.
+#[doc(hidden)]
+pub struct AttributesProbe<T>(PhantomData<T>);
+
+impl<T> AttributesProbe<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaMcpTools {
+    fn tool_attributes(&self) -> Vec<ToolAttributes>;
+}
+
+impl<T: McpTools> ViaMcpTools for AttributesProbe<T> {
+    fn tool_attributes(&self) -> Vec<ToolAttributes> {
+        T::tool_attributes()
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaDefault {
+    fn tool_attributes(&self) -> Vec<ToolAttributes>;
+}
+
+impl<T> ViaDefault for &AttributesProbe<T> {
+    fn tool_attributes(&self) -> Vec<ToolAttributes> {
+        Vec::new()
+    }
+}
+
+/// Implemented by `#[derive(McpTools)]` for `Args` structs
+pub trait McpArgs {
+    /// Settings of the struct's arguments; the tool-level fields are unset
+    fn arg_attributes() -> ToolAttributes;
+}
+
+/// Lookup of an `Args` struct's settings used by the `McpTools` derive for
+/// tuple variants, resolved like [`AttributesProbe`]
+#[doc(hidden)]
+pub struct ArgsProbe<T>(PhantomData<T>);
+
+impl<T> ArgsProbe<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaMcpArgs {
+    fn arg_attributes(&self) -> ToolAttributes;
+}
+
+impl<T: McpArgs> ViaMcpArgs for ArgsProbe<T> {
+    fn arg_attributes(&self) -> ToolAttributes {
+        T::arg_attributes()
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaPlainArgs {
+    fn arg_attributes(&self) -> ToolAttributes;
+}
+
+impl<T> ViaPlainArgs for &ArgsProbe<T> {
+    fn arg_attributes(&self) -> ToolAttributes {
+        ToolAttributes::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct WithTools;
+    struct WithoutTools;
+
+    impl McpTools for WithTools {
+        fn tool_attributes() -> Vec<ToolAttributes> {
+            vec![ToolAttributes {
+                name: "config".to_string(),
+                output_mime: Some("application/json".to_string()),
+                ..Default::default()
+            }]
+        }
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn test_probe_resolves_derived_attributes() {
+        #[allow(unused_imports)]
+        use super::{ViaDefault as _, ViaMcpTools as _};
+
+        let with = (&AttributesProbe::<WithTools>::new()).tool_attributes();
+        assert_eq!(with.len(), 1);
+        assert_eq!(with[0].name, "config");
+
+        let without = (&AttributesProbe::<WithoutTools>::new()).tool_attributes();
+        assert!(without.is_empty());
+    }
+}
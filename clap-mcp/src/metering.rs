@@ -0,0 +1,181 @@
+//! Per-call metering for billing and quotas on hosted deployments
+//!
+//! A [`Meter`] is told about every completed tool call: who made it, what it
+//! cost and how it ended. Costs default to 1 per call and can be weighted per
+//! tool. [`Ledger`] is a meter that totals usage per principal:
+//!
+//! ```ignore
+//! let ledger = Ledger::new();
+//! McpServer::<Commands>::new()
+//!     .with_meter(ledger.clone())
+//!     .with_tool_cost("deploy", 50);
+//! // ... later, e.g. from a billing job
+//! for (principal, usage) in ledger.take() {
+//!     billing::charge(&principal, usage.cost);
+//! }
+//! ```
+//!
+//! With authentication configured (see [`crate::auth`]), the principal is the
+//! one the call's bearer token authenticates and `_meta.principal` is
+//! ignored. Otherwise it is the request's `_meta.principal`, falling back to
+//! the client's declared name, neither of which clap-mcp can verify.
+
+use rmcp::model::{Implementation, Meta};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// `_meta` key naming the principal a call is billed to
+pub const PRINCIPAL_META_KEY: &str = "principal";
+
+/// Principal of calls that name none
+pub const ANONYMOUS: &str = "anonymous";
+
+/// A completed tool call
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    /// Tool name, pooled like in usage statistics for unknown tools
+    pub tool: String,
+    pub correlation_id: String,
+    /// Who the call is billed to
+    pub principal: String,
+    /// Weight of the tool, 1 unless set with `McpServer::with_tool_cost`
+    pub cost: u64,
+    /// Time from receiving the call to answering it
+    pub duration: Duration,
+}
+
+/// How a metered call ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The command ran and succeeded
+    Success,
+    /// The command ran and returned an error result
+    Failed,
+    /// The call was refused before running (invalid arguments, policy, ...)
+    Rejected,
+}
+
+/// Receives every completed call; closures taking `(&ToolCall, &Outcome)`
+/// implement it
+pub trait Meter: Send + Sync {
+    fn record(&self, call: &ToolCall, outcome: &Outcome);
+}
+
+impl<F: Fn(&ToolCall, &Outcome) + Send + Sync> Meter for F {
+    fn record(&self, call: &ToolCall, outcome: &Outcome) {
+        self(call, outcome)
+    }
+}
+
+/// Usage totals of one principal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrincipalUsage {
+    /// Calls made, including failed and rejected ones
+    pub calls: u64,
+    /// Summed cost of the calls that ran
+    pub cost: u64,
+}
+
+/// A [`Meter`] totalling usage per principal; clones share the totals
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    usage: Arc<Mutex<BTreeMap<String, PrincipalUsage>>>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Totals of `principal` so far
+    pub fn usage(&self, principal: &str) -> PrincipalUsage {
+        self.usage
+            .lock()
+            .unwrap()
+            .get(principal)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// All totals, resetting them, e.g. at the end of a billing period
+    pub fn take(&self) -> BTreeMap<String, PrincipalUsage> {
+        std::mem::take(&mut *self.usage.lock().unwrap())
+    }
+}
+
+impl Meter for Ledger {
+    fn record(&self, call: &ToolCall, outcome: &Outcome) {
+        let mut usage = self.usage.lock().unwrap();
+        let totals = usage.entry(call.principal.clone()).or_default();
+        totals.calls += 1;
+        // Rejected calls did no work, so they count toward quotas but are free
+        if *outcome != Outcome::Rejected {
+            totals.cost += call.cost;
+        }
+    }
+}
+
+/// The principal named in `meta`, else the client's name
+///
+/// `meta` is `None` when clients are authenticated, and may not name one.
+pub(crate) fn principal(meta: Option<&Meta>, client: Option<&Implementation>) -> String {
+    match meta.and_then(|meta| meta.0.get(PRINCIPAL_META_KEY)) {
+        Some(serde_json::Value::String(principal)) if !principal.is_empty() => principal.clone(),
+        _ => client
+            .map(|client| client.name.clone())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| ANONYMOUS.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn call(principal: &str, cost: u64) -> ToolCall {
+        ToolCall {
+            tool: "deploy".to_string(),
+            correlation_id: "1".to_string(),
+            principal: principal.to_string(),
+            cost,
+            duration: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_ledger_totals_per_principal() {
+        let ledger = Ledger::new();
+        let meter: &dyn Meter = &ledger.clone();
+        meter.record(&call("alice", 50), &Outcome::Success);
+        meter.record(&call("alice", 50), &Outcome::Failed);
+        meter.record(&call("alice", 50), &Outcome::Rejected);
+        meter.record(&call("bob", 1), &Outcome::Success);
+
+        assert_eq!(
+            ledger.usage("alice"),
+            PrincipalUsage {
+                calls: 3,
+                cost: 100
+            }
+        );
+        assert_eq!(ledger.take().len(), 2);
+        assert_eq!(ledger.usage("bob"), PrincipalUsage::default());
+    }
+
+    #[test]
+    fn test_principal_from_meta_or_client() {
+        let client = Implementation {
+            name: "desktop".to_string(),
+            version: "1".to_string(),
+        };
+        let mut meta = Meta::new();
+        assert_eq!(principal(Some(&meta), None), ANONYMOUS);
+        assert_eq!(principal(Some(&meta), Some(&client)), "desktop");
+        meta.0
+            .insert(PRINCIPAL_META_KEY.to_string(), json!("team-a"));
+        assert_eq!(principal(Some(&meta), Some(&client)), "team-a");
+        assert_eq!(principal(None, Some(&client)), "desktop");
+    }
+}
@@ -0,0 +1,344 @@
+//! MCP over NATS request/reply
+//!
+//! Serves the tools on a NATS subject instead of an HTTP port, so CLIs can
+//! hang off existing messaging infrastructure:
+//!
+//! ```ignore
+//! let transport = NatsTransport::new("nats://broker:4222", "tools.deploy").queue_group("deployers");
+//! McpServer::<Commands>::new().serve(transport).await?;
+//! ```
+//!
+//! Clients send each JSON-RPC message as a NATS request to the subject, with
+//! an `Mcp-Session-Id` header naming their session. Requests are answered
+//! with the JSON-RPC response; notifications and responses are acknowledged
+//! with an empty reply. Messages the server initiates (progress, logging,
+//! sampling requests) are published to `<subject>.<session>`, so session IDs
+//! must be valid subject tokens.
+//!
+//! A session starts with its first `initialize` request. With a queue group,
+//! several servers share the subject, but NATS does not keep a session on one
+//! member, so each session should be pinned by deploying one server per
+//! subject or by using sessions that make a single call. Needs the `nats`
+//! feature.
+
+use crate::transport::McpTransportProvider;
+use futures::StreamExt;
+use rmcp::handler::server::ServerHandler;
+use rmcp::model::{
+    ClientJsonRpcMessage, ErrorCode, ErrorData, JsonRpcError, JsonRpcMessage, JsonRpcVersion2_0,
+    NumberOrString, RequestId, ServerJsonRpcMessage,
+};
+use rmcp::service::RoleServer;
+use rmcp::transport::Transport;
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Header carrying the client's session ID
+pub const SESSION_HEADER: &str = "Mcp-Session-Id";
+
+/// Serves MCP sessions on a NATS subject
+#[derive(Debug, Clone)]
+pub struct NatsTransport {
+    url: String,
+    subject: String,
+    queue_group: Option<String>,
+}
+
+impl NatsTransport {
+    /// Listen on `subject` of the NATS server at `url`
+    pub fn new(url: impl Into<String>, subject: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            subject: subject.into(),
+            queue_group: None,
+        }
+    }
+
+    /// Subscribe as a member of `group`, sharing the subject with other servers
+    pub fn queue_group(mut self, group: impl Into<String>) -> Self {
+        self.queue_group = Some(group.into());
+        self
+    }
+}
+
+impl McpTransportProvider for NatsTransport {
+    async fn serve<H: ServerHandler + Clone>(self, handler: H) -> Result<(), Box<dyn Error>> {
+        let client = async_nats::connect(&self.url).await?;
+        let mut requests = match &self.queue_group {
+            Some(group) => {
+                client
+                    .queue_subscribe(self.subject.clone(), group.clone())
+                    .await?
+            }
+            None => client.subscribe(self.subject.clone()).await?,
+        };
+        tracing::info!(url = %self.url, subject = %self.subject, "serving MCP over NATS");
+
+        let (outbox, mut outgoing) = mpsc::unbounded_channel::<Outgoing>();
+        let publisher = client.clone();
+        tokio::spawn(async move {
+            while let Some(Outgoing { subject, payload }) = outgoing.recv().await {
+                if let Err(e) = publisher.publish(subject, payload.into()).await {
+                    tracing::warn!("failed to publish MCP message: {}", e);
+                }
+            }
+        });
+
+        let mut router = Router::new(handler, self.subject, outbox);
+        while let Some(message) = requests.next().await {
+            let session = message
+                .headers
+                .as_ref()
+                .and_then(|headers| headers.get(SESSION_HEADER))
+                .map(|value| value.as_str().to_string());
+            let reply = message.reply.map(|reply| reply.to_string());
+            router.route(session.as_deref(), reply, &message.payload);
+        }
+        Ok(())
+    }
+}
+
+/// A message to publish
+#[derive(Debug)]
+struct Outgoing {
+    subject: String,
+    payload: Vec<u8>,
+}
+
+/// Reply subjects of the requests a session has yet to answer
+type PendingReplies = Arc<Mutex<HashMap<RequestId, String>>>;
+
+/// A running session, fed by the router
+struct Session {
+    inbox: mpsc::UnboundedSender<ClientJsonRpcMessage>,
+    pending: PendingReplies,
+}
+
+/// Routes incoming messages to per-session rmcp services
+struct Router<H> {
+    handler: H,
+    subject: String,
+    outbox: mpsc::UnboundedSender<Outgoing>,
+    sessions: HashMap<String, Session>,
+}
+
+impl<H: ServerHandler + Clone> Router<H> {
+    fn new(handler: H, subject: String, outbox: mpsc::UnboundedSender<Outgoing>) -> Self {
+        Self {
+            handler,
+            subject,
+            outbox,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Hand a message received on the subject to its session
+    fn route(&mut self, session_id: Option<&str>, reply: Option<String>, payload: &[u8]) {
+        let Some(session_id) = session_id else {
+            let message = format!("missing {} header", SESSION_HEADER);
+            return self.reject(reply, ErrorCode::INVALID_REQUEST, message);
+        };
+        let message = match serde_json::from_slice::<ClientJsonRpcMessage>(payload) {
+            Ok(JsonRpcMessage::BatchRequest(_) | JsonRpcMessage::BatchResponse(_)) => {
+                let message = "batches are not supported".to_string();
+                return self.reject(reply, ErrorCode::INVALID_REQUEST, message);
+            }
+            Ok(message) => message,
+            Err(e) => return self.reject(reply, ErrorCode::PARSE_ERROR, e.to_string()),
+        };
+
+        if self
+            .sessions
+            .get(session_id)
+            .is_some_and(|session| session.inbox.is_closed())
+        {
+            // The session ended; start a new one
+            self.sessions.remove(session_id);
+        }
+        let session = self.session(session_id);
+        let (inbox, pending) = (session.inbox.clone(), session.pending.clone());
+        match (&message, reply) {
+            (JsonRpcMessage::Request(request), Some(reply)) => {
+                pending.lock().unwrap().insert(request.id.clone(), reply);
+            }
+            // Notifications and responses only need an acknowledgement
+            (_, Some(reply)) => self.publish(reply, Vec::new()),
+            (_, None) => {}
+        }
+        let _ = inbox.send(message);
+    }
+
+    /// The session `id`, started on first use
+    fn session(&mut self, id: &str) -> &Session {
+        if !self.sessions.contains_key(id) {
+            let (inbox, received) = mpsc::unbounded_channel();
+            let pending = PendingReplies::default();
+            let transport = SessionTransport {
+                received,
+                pending: pending.clone(),
+                events: format!("{}.{}", self.subject, id),
+                outbox: self.outbox.clone(),
+            };
+            let handler = self.handler.clone();
+            let session_id = id.to_string();
+            tokio::spawn(async move {
+                match rmcp::serve_server(handler, transport).await {
+                    Ok(service) => {
+                        let _ = service.waiting().await;
+                    }
+                    Err(e) => tracing::warn!(session = %session_id, "MCP session failed: {}", e),
+                }
+            });
+            self.sessions
+                .insert(id.to_string(), Session { inbox, pending });
+        }
+        &self.sessions[id]
+    }
+
+    fn reject(&self, reply: Option<String>, code: ErrorCode, message: String) {
+        let Some(reply) = reply else {
+            return;
+        };
+        let error: ServerJsonRpcMessage = JsonRpcMessage::Error(JsonRpcError {
+            jsonrpc: JsonRpcVersion2_0,
+            id: NumberOrString::Number(0),
+            error: ErrorData::new(code, message, None),
+        });
+        self.publish(reply, serde_json::to_vec(&error).unwrap_or_default());
+    }
+
+    fn publish(&self, subject: String, payload: Vec<u8>) {
+        let _ = self.outbox.send(Outgoing { subject, payload });
+    }
+}
+
+/// rmcp transport of one session: messages in from the router, replies out
+/// to the request they answer
+struct SessionTransport {
+    received: mpsc::UnboundedReceiver<ClientJsonRpcMessage>,
+    pending: PendingReplies,
+    /// Subject of messages that answer no request
+    events: String,
+    outbox: mpsc::UnboundedSender<Outgoing>,
+}
+
+impl Transport<RoleServer> for SessionTransport {
+    type Error = std::io::Error;
+
+    fn send(
+        &mut self,
+        item: ServerJsonRpcMessage,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let id = match &item {
+            JsonRpcMessage::Response(response) => Some(&response.id),
+            JsonRpcMessage::Error(error) => Some(&error.id),
+            _ => None,
+        };
+        let subject = id
+            .and_then(|id| self.pending.lock().unwrap().remove(id))
+            .unwrap_or_else(|| self.events.clone());
+        let sent = serde_json::to_vec(&item)
+            .map_err(std::io::Error::from)
+            .and_then(|payload| {
+                self.outbox
+                    .send(Outgoing { subject, payload })
+                    .map_err(|_| std::io::Error::other("NATS connection closed"))
+            });
+        std::future::ready(sent)
+    }
+
+    async fn receive(&mut self) -> Option<ClientJsonRpcMessage> {
+        self.received.recv().await
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        self.received.close();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClapMcpHandler;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    #[derive(clap::Subcommand, Clone)]
+    enum Commands {
+        /// Say hello
+        Greet {
+            #[arg(long)]
+            name: String,
+        },
+    }
+
+    /// Route `message` and wait for what gets published
+    async fn exchange(
+        router: &mut Router<ClapMcpHandler<Commands>>,
+        outgoing: &mut mpsc::UnboundedReceiver<Outgoing>,
+        session: Option<&str>,
+        message: Value,
+    ) -> (String, Value) {
+        router.route(
+            session,
+            Some("_INBOX.1".to_string()),
+            message.to_string().as_bytes(),
+        );
+        let Outgoing { subject, payload } =
+            tokio::time::timeout(Duration::from_secs(5), outgoing.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        let payload = match payload.is_empty() {
+            true => Value::Null,
+            false => serde_json::from_slice(&payload).unwrap(),
+        };
+        (subject, payload)
+    }
+
+    #[tokio::test]
+    async fn test_session_over_request_reply() {
+        let handler = ClapMcpHandler::<Commands>::new(Box::new(|cmd| match cmd {
+            Commands::Greet { name } => Ok(format!("Hello, {}!", name)),
+        }));
+        let (outbox, mut outgoing) = mpsc::unbounded_channel();
+        let mut router = Router::new(handler, "tools.greeter".to_string(), outbox);
+
+        let (subject, reply) = exchange(&mut router, &mut outgoing, None, json!({})).await;
+        assert_eq!(subject, "_INBOX.1");
+        assert_eq!(reply["error"]["code"], -32600);
+
+        let initialize = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "1" }
+            }
+        });
+        let (_, reply) = exchange(&mut router, &mut outgoing, Some("s1"), initialize).await;
+        assert_eq!(reply["id"], 1);
+        assert!(reply["result"]["capabilities"]["tools"].is_object());
+
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        let (_, ack) = exchange(&mut router, &mut outgoing, Some("s1"), initialized).await;
+        assert_eq!(ack, Value::Null);
+
+        let call = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": { "name": "greet", "arguments": { "name": "NATS" } }
+        });
+        let (subject, reply) = exchange(&mut router, &mut outgoing, Some("s1"), call).await;
+        assert_eq!(subject, "_INBOX.1");
+        assert_eq!(reply["id"], 2);
+        assert_eq!(reply["result"]["content"][0]["text"], "Hello, NATS!");
+    }
+}
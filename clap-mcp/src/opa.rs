@@ -0,0 +1,180 @@
+//! Tool call authorization by an Open Policy Agent server
+//!
+//! [`OpaAuthorizer`] asks OPA's Data API about every call, so which CLI
+//! operations agents may perform is decided by a central Rego policy instead
+//! of each server:
+//!
+//! ```ignore
+//! let opa = OpaAuthorizer::new("http://localhost:8181/v1/data/clapmcp/allow");
+//! McpServer::<Commands>::new().with_authorizer(opa);
+//! ```
+//!
+//! The call is posted as the policy's input, with the tool's annotation hints:
+//!
+//! ```json
+//! {
+//!   "input": {
+//!     "principal": "alice",
+//!     "tool": "deploy",
+//!     "arguments": { "env": "prod" },
+//!     "annotations": { "destructiveHint": true }
+//!   }
+//! }
+//! ```
+//!
+//! ```rego
+//! package clapmcp
+//!
+//! default allow := false
+//! allow if input.annotations.readOnlyHint
+//! allow if {
+//!     input.principal == "ops"
+//!     input.tool == "deploy"
+//! }
+//! ```
+//!
+//! Policies can only trust `principal` with authentication configured (see
+//! [`crate::auth`]), where it is the one the call's bearer token
+//! authenticates. Otherwise clients name it themselves.
+//!
+//! Only a `true` result allows the call. An undefined result, any other
+//! value, or an OPA server that cannot be reached denies it. Needs the `opa`
+//! feature.
+
+use crate::authz::{AuthorizationRequest, Decision, ToolAuthorizer};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::time::Duration;
+
+/// Asks an OPA server whether to allow tool calls
+#[derive(Debug, Clone)]
+pub struct OpaAuthorizer {
+    /// Data API URL of the rule deciding, e.g. `.../v1/data/clapmcp/allow`
+    url: String,
+    token: Option<String>,
+    timeout: Duration,
+    http: reqwest::Client,
+}
+
+impl OpaAuthorizer {
+    /// Evaluate the rule at the Data API `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            token: None,
+            timeout: Duration::from_secs(5),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Authenticate to OPA with a bearer token
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// How long to wait for a decision before denying the call; 5 seconds
+    /// by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn evaluate(
+        &self,
+        request: &AuthorizationRequest<'_>,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let input = json!({
+            "input": {
+                "principal": request.principal,
+                "tool": request.tool,
+                "arguments": request.arguments,
+                "annotations": request.annotations,
+            }
+        });
+        let mut post = self
+            .http
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(input.to_string())
+            .timeout(self.timeout);
+        if let Some(token) = &self.token {
+            post = post.bearer_auth(token);
+        }
+        let response = post.send().await?.error_for_status()?;
+        let body: Value = serde_json::from_slice(&response.bytes().await?)?;
+        Ok(body["result"] == Value::Bool(true))
+    }
+}
+
+impl ToolAuthorizer for OpaAuthorizer {
+    fn authorize<'a>(&'a self, request: &'a AuthorizationRequest<'a>) -> Decision<'a> {
+        Box::pin(async move {
+            match self.evaluate(request).await {
+                Ok(allowed) => allowed,
+                Err(e) => {
+                    tracing::warn!(url = %self.url, tool = %request.tool, "OPA evaluation failed, denying: {}", e);
+                    false
+                }
+            }
+        })
+    }
+}
+
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use super::*;
+    use rmcp::model::{JsonObject, ToolAnnotations};
+
+    /// An OPA stand-in allowing read-only tools and `ops` deploying to prod
+    async fn start_opa() -> String {
+        let policy = axum::routing::post(|axum::Json(body): axum::Json<Value>| async move {
+            let input = &body["input"];
+            let allowed = input["annotations"]["readOnlyHint"] == true
+                || (input["principal"] == "ops"
+                    && input["tool"] == "deploy"
+                    && input["arguments"]["env"] == "prod");
+            // OPA leaves the result out when the rule is undefined
+            match allowed {
+                true => axum::Json(json!({ "result": true })),
+                false => axum::Json(json!({})),
+            }
+        });
+        let router = axum::Router::new().route("/v1/data/clapmcp/allow", policy);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        format!("http://{}/v1/data/clapmcp/allow", addr)
+    }
+
+    #[tokio::test]
+    async fn test_opa_decisions() {
+        let opa = OpaAuthorizer::new(start_opa().await);
+        let prod = json!({ "env": "prod" }).as_object().cloned().unwrap();
+        let read_only = ToolAnnotations::new().read_only(true);
+        let request = |principal, tool, annotations| AuthorizationRequest {
+            principal,
+            tool,
+            arguments: &prod,
+            annotations,
+        };
+
+        assert!(opa.authorize(&request("ops", "deploy", None)).await);
+        assert!(!opa.authorize(&request("alice", "deploy", None)).await);
+        assert!(
+            opa.authorize(&request("alice", "status", Some(&read_only)))
+                .await
+        );
+
+        let staging = JsonObject::from_iter([("env".to_string(), json!("staging"))]);
+        let request = AuthorizationRequest {
+            arguments: &staging,
+            ..request("ops", "deploy", None)
+        };
+        assert!(!opa.authorize(&request).await);
+
+        // Unreachable servers deny
+        let down = OpaAuthorizer::new("http://127.0.0.1:9/v1/data/clapmcp/allow");
+        assert!(!down.authorize(&request).await);
+    }
+}
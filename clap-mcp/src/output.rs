@@ -0,0 +1,497 @@
+//! Output helpers for tool results
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rmcp::model::Content;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// Kind of change recorded in a [`ChangeSet`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// A single item affected by a mutating command
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Identifier of the affected item (name, path, primary key, ...)
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// Diff-style summary of what a mutating command changed
+///
+/// Renders as markdown for humans (via [`fmt::Display`] or
+/// [`ChangeSet::to_markdown`]) and as JSON for agents (via
+/// [`ChangeSet::to_json`]), so every mutating tool reports its effects the same
+/// way.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChangeSet {
+    pub changes: Vec<Change>,
+}
+
+impl ChangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a newly created item
+    pub fn created(mut self, id: impl Into<String>, after: impl Serialize) -> Self {
+        self.changes.push(Change {
+            kind: ChangeKind::Created,
+            id: id.into(),
+            before: None,
+            after: Some(to_value(after)),
+        });
+        self
+    }
+
+    /// Record an item that was modified in place
+    pub fn updated(
+        mut self,
+        id: impl Into<String>,
+        before: impl Serialize,
+        after: impl Serialize,
+    ) -> Self {
+        self.changes.push(Change {
+            kind: ChangeKind::Updated,
+            id: id.into(),
+            before: Some(to_value(before)),
+            after: Some(to_value(after)),
+        });
+        self
+    }
+
+    /// Record a removed item
+    pub fn deleted(mut self, id: impl Into<String>, before: impl Serialize) -> Self {
+        self.changes.push(Change {
+            kind: ChangeKind::Deleted,
+            id: id.into(),
+            before: Some(to_value(before)),
+            after: None,
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    fn count(&self, kind: ChangeKind) -> usize {
+        self.changes.iter().filter(|c| c.kind == kind).count()
+    }
+
+    /// Structured representation with per-kind counts
+    pub fn to_json(&self) -> Value {
+        json!({
+            "summary": {
+                "created": self.count(ChangeKind::Created),
+                "updated": self.count(ChangeKind::Updated),
+                "deleted": self.count(ChangeKind::Deleted),
+            },
+            "changes": self.changes,
+        })
+    }
+
+    /// Markdown rendering grouped by change kind
+    pub fn to_markdown(&self) -> String {
+        if self.is_empty() {
+            return "No changes.".to_string();
+        }
+
+        let mut out = format!(
+            "## Changes: {} created, {} updated, {} deleted\n",
+            self.count(ChangeKind::Created),
+            self.count(ChangeKind::Updated),
+            self.count(ChangeKind::Deleted)
+        );
+
+        for (kind, heading) in [
+            (ChangeKind::Created, "Created"),
+            (ChangeKind::Updated, "Updated"),
+            (ChangeKind::Deleted, "Deleted"),
+        ] {
+            if self.count(kind) == 0 {
+                continue;
+            }
+            out.push_str(&format!("\n### {}\n", heading));
+            for change in self.changes.iter().filter(|c| c.kind == kind) {
+                out.push_str(&format!("- `{}`\n", change.id));
+                if kind == ChangeKind::Updated {
+                    for line in field_diff(change.before.as_ref(), change.after.as_ref()) {
+                        out.push_str(&format!("  - {}\n", line));
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for ChangeSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_markdown())
+    }
+}
+
+/// Result of a tool call, for handlers set with
+/// [`McpServer::with_structured_handler`](crate::McpServer::with_structured_handler)
+///
+/// Each variant becomes its own content block: images and resources reach
+/// the client typed instead of flattened into text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolOutput {
+    /// Plain text, sanitized like the output of a string handler
+    Text(String),
+    /// A JSON document
+    ///
+    /// rmcp 0.2 cannot send `structuredContent` yet, so it travels serialized
+    /// in a text block, as the spec asks servers to do for older clients.
+    Json(Value),
+    /// Base64-encoded image data
+    Image { data: String, mime_type: String },
+    /// A text resource identified by `uri`
+    Resource {
+        uri: String,
+        mime_type: Option<String>,
+        text: String,
+    },
+    /// A binary resource identified by `uri`, with base64-encoded data
+    Blob {
+        uri: String,
+        mime_type: Option<String>,
+        data: String,
+    },
+    /// Content blocks built with rmcp, passed to the client as they are
+    /// except for text, which is sanitized like [`ToolOutput::Text`]
+    Content(Vec<Content>),
+    /// Several outputs, in order
+    Mixed(Vec<ToolOutput>),
+}
+
+impl ToolOutput {
+    /// JSON output from any serializable value
+    pub fn json(value: impl Serialize) -> Result<Self, serde_json::Error> {
+        serde_json::to_value(value).map(Self::Json)
+    }
+
+    /// A list of items, e.g. what a `list-*` subcommand found: a markdown
+    /// list for humans, then the items as a JSON array for agents
+    ///
+    /// Objects are listed as `key: value` pairs in key order, one item per line.
+    pub fn json_list<T: Serialize>(
+        items: impl IntoIterator<Item = T>,
+    ) -> Result<Self, serde_json::Error> {
+        let items = items
+            .into_iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        let markdown = match items.is_empty() {
+            true => "No items.".to_string(),
+            false => items
+                .iter()
+                .map(|item| format!("- {}", list_item(item)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        };
+        Ok(Self::Mixed(vec![
+            Self::Text(markdown),
+            Self::Json(Value::Array(items)),
+        ]))
+    }
+
+    /// An image from its raw bytes, e.g. a rendered PNG chart
+    pub fn image(bytes: impl AsRef<[u8]>, mime_type: impl Into<String>) -> Self {
+        Self::Image {
+            data: STANDARD.encode(bytes),
+            mime_type: mime_type.into(),
+        }
+    }
+
+    /// A binary resource from its raw bytes, e.g. a generated PDF
+    pub fn blob(uri: impl Into<String>, mime_type: Option<&str>, bytes: impl AsRef<[u8]>) -> Self {
+        Self::Blob {
+            uri: uri.into(),
+            mime_type: mime_type.map(str::to_string),
+            data: STANDARD.encode(bytes),
+        }
+    }
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for ToolOutput {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl From<Value> for ToolOutput {
+    fn from(value: Value) -> Self {
+        Self::Json(value)
+    }
+}
+
+impl From<Content> for ToolOutput {
+    fn from(content: Content) -> Self {
+        Self::Content(vec![content])
+    }
+}
+
+impl From<Vec<Content>> for ToolOutput {
+    fn from(contents: Vec<Content>) -> Self {
+        Self::Content(contents)
+    }
+}
+
+/// The markdown summary for humans, then the JSON for agents
+impl From<ChangeSet> for ToolOutput {
+    fn from(changes: ChangeSet) -> Self {
+        Self::Mixed(vec![
+            Self::Text(changes.to_markdown()),
+            Self::Json(changes.to_json()),
+        ])
+    }
+}
+
+/// Failure of a structured handler
+///
+/// By default it is the caller's fault (a missing file, a rejected value) and
+/// reaches the client as an error result, which the agent can act on. When
+/// it carries a `code` or `data`, they follow the output as a JSON block:
+///
+/// ```ignore
+/// Err(ToolError::new("no such environment: staging")
+///     .with_code("not_found")
+///     .with_data(json!({ "known": ["prod", "dev"] })))
+/// ```
+///
+/// [`ToolError::internal`] marks a failure of the server itself, answered
+/// with a JSON-RPC internal error whose data holds the code and data instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolError {
+    pub output: ToolOutput,
+    code: Option<String>,
+    // Boxed to keep handler results small
+    data: Option<Box<Value>>,
+    internal: bool,
+}
+
+impl ToolError {
+    /// An error carrying any output, e.g. JSON describing what failed
+    pub fn new(output: impl Into<ToolOutput>) -> Self {
+        Self {
+            output: output.into(),
+            code: None,
+            data: None,
+            internal: false,
+        }
+    }
+
+    /// An error of the server rather than the call, e.g. a lost database
+    /// connection
+    pub fn internal(output: impl Into<ToolOutput>) -> Self {
+        Self {
+            internal: true,
+            ..Self::new(output)
+        }
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_data(mut self, data: impl Into<Value>) -> Self {
+        self.data = Some(Box::new(data.into()));
+        self
+    }
+
+    /// Machine-readable kind of failure, e.g. `not_found`
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
+    /// Details for programs handling the error
+    pub fn data(&self) -> Option<&Value> {
+        self.data.as_deref()
+    }
+
+    /// Whether the server failed rather than the call
+    pub fn is_internal(&self) -> bool {
+        self.internal
+    }
+
+    /// The code and data as a JSON object, if either is set
+    pub(crate) fn details(&self) -> Option<Value> {
+        if self.code.is_none() && self.data.is_none() {
+            return None;
+        }
+        let mut details = serde_json::Map::new();
+        if let Some(code) = &self.code {
+            details.insert("code".to_string(), Value::String(code.clone()));
+        }
+        if let Some(data) = &self.data {
+            details.insert("data".to_string(), Value::clone(data));
+        }
+        Some(Value::Object(details))
+    }
+}
+
+impl From<String> for ToolError {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<&str> for ToolError {
+    fn from(message: &str) -> Self {
+        Self::new(message)
+    }
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.output {
+            ToolOutput::Text(text) => f.write_str(text),
+            ToolOutput::Json(value) => value.fmt(f),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+fn to_value(value: impl Serialize) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// One line of a [`ToolOutput::json_list`], strings unquoted
+fn list_item(item: &Value) -> String {
+    match item {
+        Value::String(text) => text.clone(),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, list_item(value)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        other => other.to_string(),
+    }
+}
+
+/// Describe the differences between two values, field by field for objects
+fn field_diff(before: Option<&Value>, after: Option<&Value>) -> Vec<String> {
+    match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => {
+            let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter()
+                .filter_map(|key| match (before.get(key), after.get(key)) {
+                    (Some(b), Some(a)) if a == b => None,
+                    (Some(b), Some(a)) => Some(format!("`{}`: `{}` → `{}`", key, b, a)),
+                    (Some(b), None) => Some(format!("`{}`: `{}` → (removed)", key, b)),
+                    (None, Some(a)) => Some(format!("`{}`: (added) → `{}`", key, a)),
+                    (None, None) => None,
+                })
+                .collect()
+        }
+        (Some(b), Some(a)) if a != b => vec![format!("`{}` → `{}`", b, a)],
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changeset_renders_markdown_and_json() {
+        let changes = ChangeSet::new()
+            .created("bucket/logs", json!({ "region": "us-east-1" }))
+            .updated(
+                "user/alice",
+                json!({ "role": "reader", "active": true }),
+                json!({ "role": "writer", "active": true }),
+            )
+            .deleted("token/abc", json!({ "scope": "read" }));
+
+        let markdown = changes.to_markdown();
+        assert!(markdown.starts_with("## Changes: 1 created, 1 updated, 1 deleted"));
+        assert!(markdown.contains("### Created\n- `bucket/logs`"));
+        assert!(markdown.contains("`role`: `\"reader\"` → `\"writer\"`"));
+        assert!(!markdown.contains("`active`"));
+        assert_eq!(markdown, changes.to_string());
+
+        let json = changes.to_json();
+        assert_eq!(json["summary"]["updated"], 1);
+        assert_eq!(json["changes"][0]["kind"], "created");
+        assert_eq!(json["changes"][2]["before"]["scope"], "read");
+        assert!(json["changes"][2].get("after").is_none());
+
+        assert_eq!(
+            ToolOutput::from(changes),
+            ToolOutput::Mixed(vec![ToolOutput::Text(markdown), ToolOutput::Json(json)])
+        );
+    }
+
+    #[test]
+    fn test_json_list() {
+        #[derive(Serialize)]
+        struct Branch {
+            name: &'static str,
+            ahead: u32,
+        }
+
+        let output = ToolOutput::json_list([
+            Branch {
+                name: "main",
+                ahead: 0,
+            },
+            Branch {
+                name: "feature/x",
+                ahead: 3,
+            },
+        ])
+        .unwrap();
+        assert_eq!(
+            output,
+            ToolOutput::Mixed(vec![
+                ToolOutput::Text("- ahead: 0, name: main\n- ahead: 3, name: feature/x".into()),
+                ToolOutput::Json(json!([
+                    { "name": "main", "ahead": 0 },
+                    { "name": "feature/x", "ahead": 3 },
+                ])),
+            ])
+        );
+
+        let output = ToolOutput::json_list(["a", "b"]).unwrap();
+        assert!(
+            matches!(&output, ToolOutput::Mixed(outputs) if outputs[0] == ToolOutput::Text("- a\n- b".into()))
+        );
+        let output = ToolOutput::json_list(Vec::<u32>::new()).unwrap();
+        assert!(
+            matches!(&output, ToolOutput::Mixed(outputs) if outputs[0] == ToolOutput::Text("No items.".into()))
+        );
+    }
+
+    #[test]
+    fn test_empty_changeset() {
+        let changes = ChangeSet::new();
+        assert!(changes.is_empty());
+        assert_eq!(changes.to_markdown(), "No changes.");
+        assert_eq!(changes.to_json()["summary"]["created"], 0);
+    }
+}
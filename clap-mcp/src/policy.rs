@@ -0,0 +1,407 @@
+//! Time-based gating of tool calls
+//!
+//! A [`ToolPolicy`] sees every call before it runs and may reject it with a
+//! retry hint or hold it until later. [`MaintenanceWindows`] implements one
+//! from a JSON config file, e.g. no `deploy` during business hours:
+//!
+//! ```json
+//! {
+//!   "windows": [
+//!     {
+//!       "name": "business hours",
+//!       "tools": ["deploy"],
+//!       "days": ["mon", "tue", "wed", "thu", "fri"],
+//!       "start": "09:00",
+//!       "end": "17:00",
+//!       "utcOffsetMinutes": -300,
+//!       "action": "reject"
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! ```ignore
+//! McpServer::<Commands>::new().with_policy(MaintenanceWindows::from_file("policy.json")?);
+//! ```
+//!
+//! Rejected calls fail with an error whose data carries `retryAfter`, the
+//! seconds until the window closes.
+//!
+//! Independently of any policy, `McpServer::with_max_concurrency(n)` caps how
+//! many calls run at once; calls beyond it wait for a free slot or, with
+//! [`BusyAction::Reject`], fail right away. Tools marked
+//! `#[mcp(serialize_by = "project")]` additionally run one call at a time
+//! per value of their `project` argument. Those locks are held in the
+//! process; replicas sharing a backend share them through a
+//! [`LockProvider`], e.g. `RedisLocks` with the `redis` feature:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new().with_lock_provider(RedisLocks::new("redis://locks:6379")?);
+//! ```
+
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// What to do with a tool call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyDecision {
+    Allow,
+    /// Fail the call; the client may retry after `retry_after`
+    Reject {
+        reason: String,
+        retry_after: Duration,
+    },
+    /// Hold the call for `wait`, then ask the policy again
+    Queue {
+        wait: Duration,
+    },
+}
+
+/// Decides whether tool calls may run; closures taking the tool name and the
+/// current time implement it
+pub trait ToolPolicy: Send + Sync {
+    fn check(&self, tool: &str, now: SystemTime) -> PolicyDecision;
+}
+
+impl<F: Fn(&str, SystemTime) -> PolicyDecision + Send + Sync> ToolPolicy for F {
+    fn check(&self, tool: &str, now: SystemTime) -> PolicyDecision {
+        self(tool, now)
+    }
+}
+
+/// How calls beyond the concurrency limit are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BusyAction {
+    /// Wait until a running call finishes
+    #[default]
+    Queue,
+    /// Fail with a busy error
+    Reject,
+}
+
+/// Slots for the calls allowed to run at once
+#[derive(Debug, Clone)]
+pub(crate) struct ConcurrencyLimit {
+    slots: Arc<Semaphore>,
+    pub(crate) limit: usize,
+}
+
+impl ConcurrencyLimit {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(limit)),
+            limit,
+        }
+    }
+
+    /// A free slot, waiting for one unless `action` is to reject; `None`
+    /// when busy
+    pub(crate) async fn acquire(&self, action: BusyAction) -> Option<OwnedSemaphorePermit> {
+        match action {
+            BusyAction::Queue => self.slots.clone().acquire_owned().await.ok(),
+            BusyAction::Reject => self.slots.clone().try_acquire_owned().ok(),
+        }
+    }
+}
+
+/// A lock held by a call, released when dropped
+pub struct LockGuard {
+    _held: Box<dyn Send>,
+}
+
+impl LockGuard {
+    /// Hold a lock until `held` is dropped
+    pub fn new(held: impl Send + 'static) -> Self {
+        Self {
+            _held: Box::new(held),
+        }
+    }
+}
+
+/// The held lock, once acquired
+pub type LockFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<LockGuard, Box<dyn Error + Send + Sync>>> + Send + 'a>>;
+
+/// Hands out the locks `serialize_by` tools run under
+///
+/// Keys are the argument id and the JSON value, e.g. `project="acme"`.
+pub trait LockProvider: Send + Sync {
+    /// Wait until no other call holds `key`, then hold it
+    fn lock<'a>(&'a self, key: &'a str) -> LockFuture<'a>;
+}
+
+/// Locks held in the process, used without a [`LockProvider`]; clones share
+/// the locks
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SerialLocks {
+    locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl LockProvider for SerialLocks {
+    fn lock<'a>(&'a self, key: &'a str) -> LockFuture<'a> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            // Forget the keys no call holds or waits for
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(key.to_string()).or_default().clone()
+        };
+        Box::pin(async move { Ok(LockGuard::new(lock.lock_owned().await)) })
+    }
+}
+
+/// Day of the week a window applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Sun,
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+}
+
+/// How calls during a window are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowAction {
+    /// Fail with a retry hint
+    #[default]
+    Reject,
+    /// Hold the call until the window closes
+    Queue,
+}
+
+/// A recurring period during which some tools may not run
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceWindow {
+    /// Shown to the client when a call is rejected
+    pub name: String,
+    /// Tools the window applies to
+    pub tools: Vec<String>,
+    /// Days the window opens on; every day when empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// Opening time as minutes after midnight, written `"HH:MM"`
+    #[serde(deserialize_with = "minute_of_day")]
+    pub start: u32,
+    /// Closing time; before `start` for windows spanning midnight
+    #[serde(deserialize_with = "minute_of_day")]
+    pub end: u32,
+    /// Offset of the times above from UTC
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    #[serde(default)]
+    pub action: WindowAction,
+}
+
+impl MaintenanceWindow {
+    /// Time until the window closes, if it is open at `now`
+    fn remaining(&self, now: SystemTime) -> Option<Duration> {
+        let secs = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64
+            + i64::from(self.utc_offset_minutes) * 60;
+        let day = secs.div_euclid(86_400);
+        let second = secs.rem_euclid(86_400) as u32;
+        let (start, end) = (self.start * 60, self.end * 60);
+
+        // A window spanning midnight opened the day before in its early hours
+        let (opened_on, remaining) = if start < end {
+            (start..end)
+                .contains(&second)
+                .then(|| (day, end - second))?
+        } else if second >= start {
+            (day, 86_400 - second + end)
+        } else if second < end {
+            (day - 1, end - second)
+        } else {
+            return None;
+        };
+
+        let weekday = WEEKDAYS[(opened_on + 4).rem_euclid(7) as usize];
+        (self.days.is_empty() || self.days.contains(&weekday))
+            .then(|| Duration::from_secs(u64::from(remaining)))
+    }
+}
+
+/// Weekdays from Sunday; 1970-01-01, the first day of the epoch, is a Thursday
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Sun,
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+];
+
+fn minute_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.split_once(':')
+        .and_then(|(hours, minutes)| {
+            Some((hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?))
+        })
+        .filter(|&(hours, minutes)| hours < 24 && minutes < 60)
+        .map(|(hours, minutes)| hours * 60 + minutes)
+        .ok_or_else(|| serde::de::Error::custom(format!("invalid time `{}`, expected HH:MM", text)))
+}
+
+/// A [`ToolPolicy`] closing tools during configured windows
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct MaintenanceWindows {
+    pub windows: Vec<MaintenanceWindow>,
+}
+
+impl MaintenanceWindows {
+    /// Parse a policy config in the JSON format shown in the module docs
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Load a policy config file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+}
+
+impl ToolPolicy for MaintenanceWindows {
+    fn check(&self, tool: &str, now: SystemTime) -> PolicyDecision {
+        let open = self
+            .windows
+            .iter()
+            .filter(|window| window.tools.iter().any(|t| t == tool))
+            .filter_map(|window| window.remaining(now).map(|remaining| (window, remaining)));
+        // Rejecting wins over queueing; the retry hint covers the longest window
+        let mut decision = PolicyDecision::Allow;
+        for (window, remaining) in open {
+            decision = match (decision, window.action) {
+                (
+                    PolicyDecision::Reject {
+                        reason,
+                        retry_after,
+                    },
+                    _,
+                ) if retry_after >= remaining => PolicyDecision::Reject {
+                    reason,
+                    retry_after,
+                },
+                (PolicyDecision::Reject { .. }, _) | (_, WindowAction::Reject) => {
+                    PolicyDecision::Reject {
+                        reason: window.name.clone(),
+                        retry_after: remaining,
+                    }
+                }
+                (PolicyDecision::Queue { wait }, WindowAction::Queue) => PolicyDecision::Queue {
+                    wait: wait.max(remaining),
+                },
+                (_, WindowAction::Queue) => PolicyDecision::Queue { wait: remaining },
+            };
+        }
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"{
+        "windows": [
+            { "name": "business hours", "tools": ["deploy"], "days": ["mon", "tue", "wed", "thu", "fri"],
+              "start": "09:00", "end": "17:00" },
+            { "name": "nightly backup", "tools": ["migrate"], "start": "23:30", "end": "01:00",
+              "action": "queue" }
+        ]
+    }"#;
+
+    /// 2024-01-01 (a Monday) at `hh:mm` UTC, plus `days`
+    fn at(days: u64, hh: u64, mm: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH
+            + Duration::from_secs(1_704_067_200 + days * 86_400 + hh * 3600 + mm * 60)
+    }
+
+    #[test]
+    fn test_business_hours_reject() {
+        let policy = MaintenanceWindows::from_json(POLICY).unwrap();
+        assert_eq!(
+            policy.check("deploy", at(0, 16, 0)),
+            PolicyDecision::Reject {
+                reason: "business hours".to_string(),
+                retry_after: Duration::from_secs(3600),
+            }
+        );
+        assert_eq!(policy.check("deploy", at(0, 17, 0)), PolicyDecision::Allow);
+        assert_eq!(policy.check("status", at(0, 16, 0)), PolicyDecision::Allow);
+        // Saturday
+        assert_eq!(policy.check("deploy", at(5, 12, 0)), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn test_window_spanning_midnight_queues() {
+        let policy = MaintenanceWindows::from_json(POLICY).unwrap();
+        assert_eq!(
+            policy.check("migrate", at(0, 23, 45)),
+            PolicyDecision::Queue {
+                wait: Duration::from_secs(75 * 60)
+            }
+        );
+        assert_eq!(
+            policy.check("migrate", at(1, 0, 30)),
+            PolicyDecision::Queue {
+                wait: Duration::from_secs(30 * 60)
+            }
+        );
+        assert_eq!(policy.check("migrate", at(1, 1, 0)), PolicyDecision::Allow);
+    }
+
+    #[test]
+    fn test_utc_offset_and_invalid_times() {
+        let policy = MaintenanceWindows::from_json(
+            r#"{"windows": [{"name": "ny", "tools": ["deploy"], "start": "09:00", "end": "17:00",
+                "utcOffsetMinutes": -300}]}"#,
+        )
+        .unwrap();
+        // 13:00 UTC is 08:00 in New York
+        assert_eq!(policy.check("deploy", at(0, 13, 0)), PolicyDecision::Allow);
+        assert!(matches!(
+            policy.check("deploy", at(0, 15, 0)),
+            PolicyDecision::Reject { .. }
+        ));
+
+        let err = MaintenanceWindows::from_json(
+            r#"{"windows": [{"name": "x", "tools": [], "start": "25:00", "end": "01:00"}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid time `25:00`"));
+    }
+
+    #[tokio::test]
+    async fn test_serial_locks() {
+        let locks = SerialLocks::default();
+        let acme = locks.lock("project=\"acme\"").await.unwrap();
+        // Other keys are free, the held one waits
+        let zeta = locks.lock("project=\"zeta\"").await.unwrap();
+        let waiting = locks.lock("project=\"acme\"");
+        let timeout = Duration::from_millis(50);
+        assert!(tokio::time::timeout(timeout, waiting).await.is_err());
+
+        drop((acme, zeta));
+        assert!(locks.lock("project=\"acme\"").await.is_ok());
+        // Only the key just locked was kept
+        assert_eq!(locks.locks.lock().unwrap().len(), 1);
+    }
+}
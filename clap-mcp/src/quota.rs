@@ -0,0 +1,327 @@
+//! Daily and monthly call quotas per principal
+//!
+//! Builds on [`crate::metering`]: calls are attributed to the same principal
+//! and consume their tool's cost weight (1 by default). Periods follow UTC
+//! calendar days and months. Enforce them with authentication configured
+//! (see [`crate::auth`]): calls then count against the principal their bearer
+//! token authenticates, and clients cannot name a fresh one to start over.
+//!
+//! ```ignore
+//! let quotas = Quotas::new(Quota::default().daily(1_000).monthly(20_000))
+//!     .for_principal("team-a", Quota::default().monthly(100_000));
+//! McpServer::<Commands>::new().with_quotas(quotas);
+//! ```
+//!
+//! A call over quota fails before running with an error whose data carries
+//! the exhausted `period`, its `limit`, `retryAfter`, the seconds until the
+//! period resets, and the units `remaining` in each period. Calls rejected
+//! before running for other reasons (invalid arguments, policy) give their
+//! units back; calls that ran keep them, even when they fail.
+//!
+//! rmcp 0.2 results cannot carry `_meta` yet. With
+//! `McpServer::with_quota_report`, results end with a JSON block such as
+//! `{"quota":{"daily":997}}` instead; deployments can also read the quota
+//! left with [`Quotas::remaining`].
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Limits of one principal; `None` means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub daily: Option<u64>,
+    pub monthly: Option<u64>,
+}
+
+impl Quota {
+    /// Allow `limit` units per UTC day
+    pub fn daily(mut self, limit: u64) -> Self {
+        self.daily = Some(limit);
+        self
+    }
+
+    /// Allow `limit` units per UTC calendar month
+    pub fn monthly(mut self, limit: u64) -> Self {
+        self.monthly = Some(limit);
+        self
+    }
+}
+
+/// Period a quota applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Month,
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Day => "daily",
+            Self::Month => "monthly",
+        })
+    }
+}
+
+/// Units left in each limited period after a call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Remaining {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monthly: Option<u64>,
+}
+
+/// Why a call was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exceeded {
+    pub period: Period,
+    pub limit: u64,
+    /// Seconds until the period resets
+    pub retry_after_secs: u64,
+}
+
+/// Units used by one principal in the current periods
+#[derive(Debug, Default)]
+struct Usage {
+    day: i64,
+    day_used: u64,
+    month: i64,
+    month_used: u64,
+}
+
+/// Quotas for all principals; clones share the counters
+#[derive(Debug, Clone)]
+pub struct Quotas {
+    default: Quota,
+    principals: HashMap<String, Quota>,
+    usage: Arc<Mutex<HashMap<String, Usage>>>,
+}
+
+impl Quotas {
+    /// `default` applies to every principal without its own quota
+    pub fn new(default: Quota) -> Self {
+        Self {
+            default,
+            principals: HashMap::new(),
+            usage: Arc::default(),
+        }
+    }
+
+    /// Give `principal` its own quota instead of the default
+    pub fn for_principal(mut self, principal: impl Into<String>, quota: Quota) -> Self {
+        self.principals.insert(principal.into(), quota);
+        self
+    }
+
+    fn quota(&self, principal: &str) -> Quota {
+        self.principals
+            .get(principal)
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Take `units` from `principal`'s quota, or refuse if that would exceed it
+    pub(crate) fn consume(
+        &self,
+        principal: &str,
+        units: u64,
+        now: SystemTime,
+    ) -> Result<Remaining, Exceeded> {
+        let quota = self.quota(principal);
+        let (day, month) = periods(now);
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(principal.to_string()).or_default();
+        if usage.day != day {
+            usage.day = day;
+            usage.day_used = 0;
+        }
+        if usage.month != month {
+            usage.month = month;
+            usage.month_used = 0;
+        }
+
+        let checks = [
+            (Period::Day, quota.daily, usage.day_used),
+            (Period::Month, quota.monthly, usage.month_used),
+        ];
+        for (period, limit, used) in checks {
+            if let Some(limit) = limit {
+                if used + units > limit {
+                    return Err(Exceeded {
+                        period,
+                        limit,
+                        retry_after_secs: until_reset(period, now),
+                    });
+                }
+            }
+        }
+
+        usage.day_used += units;
+        usage.month_used += units;
+        Ok(Remaining {
+            daily: quota.daily.map(|limit| limit - usage.day_used),
+            monthly: quota.monthly.map(|limit| limit - usage.month_used),
+        })
+    }
+
+    /// Give back units consumed by a call that did not run
+    pub(crate) fn refund(&self, principal: &str, units: u64, now: SystemTime) {
+        let (day, month) = periods(now);
+        if let Some(usage) = self.usage.lock().unwrap().get_mut(principal) {
+            if usage.day == day {
+                usage.day_used = usage.day_used.saturating_sub(units);
+            }
+            if usage.month == month {
+                usage.month_used = usage.month_used.saturating_sub(units);
+            }
+        }
+    }
+
+    /// Units `principal` has left in the current periods
+    pub fn remaining(&self, principal: &str) -> Remaining {
+        let quota = self.quota(principal);
+        let (day, month) = periods(SystemTime::now());
+        let usage = self.usage.lock().unwrap();
+        let (day_used, month_used) = match usage.get(principal) {
+            Some(usage) => (
+                if usage.day == day { usage.day_used } else { 0 },
+                if usage.month == month {
+                    usage.month_used
+                } else {
+                    0
+                },
+            ),
+            None => (0, 0),
+        };
+        Remaining {
+            daily: quota.daily.map(|limit| limit.saturating_sub(day_used)),
+            monthly: quota.monthly.map(|limit| limit.saturating_sub(month_used)),
+        }
+    }
+}
+
+fn epoch_secs(now: SystemTime) -> i64 {
+    now.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Days since the epoch and months since the epoch of `now`, in UTC
+fn periods(now: SystemTime) -> (i64, i64) {
+    let day = epoch_secs(now).div_euclid(86_400);
+    let (year, month, _) = civil_from_days(day);
+    (day, (year - 1970) * 12 + i64::from(month) - 1)
+}
+
+/// Seconds from `now` until the next UTC day or month starts
+fn until_reset(period: Period, now: SystemTime) -> u64 {
+    let secs = epoch_secs(now);
+    let day = secs.div_euclid(86_400);
+    let next = match period {
+        Period::Day => day + 1,
+        Period::Month => {
+            let (year, month, _) = civil_from_days(day);
+            match month {
+                12 => days_from_civil(year + 1, 1, 1),
+                _ => days_from_civil(year, month + 1, 1),
+            }
+        }
+    };
+    (next * 86_400 - secs) as u64
+}
+
+/// (year, month, day) of a day count since 1970-01-01, proleptic Gregorian
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Inverse of [`civil_from_days`]
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = i64::from(month);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    // 2024-01-31 23:00 UTC
+    const JAN_31: u64 = 1_706_742_000;
+
+    #[test]
+    fn test_calendar() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_783), (2024, 3, 1));
+        assert_eq!(days_from_civil(2024, 3, 1), 19_783);
+        assert_eq!(until_reset(Period::Day, at(JAN_31)), 3600);
+        assert_eq!(until_reset(Period::Month, at(JAN_31)), 3600);
+        assert_eq!(until_reset(Period::Month, at(JAN_31 + 3600)), 29 * 86_400);
+    }
+
+    #[test]
+    fn test_consume_and_reset() {
+        let quotas = Quotas::new(Quota::default().daily(3).monthly(4))
+            .for_principal("vip", Quota::default());
+
+        let remaining = quotas.consume("alice", 2, at(JAN_31)).unwrap();
+        assert_eq!(
+            remaining,
+            Remaining {
+                daily: Some(1),
+                monthly: Some(2)
+            }
+        );
+        assert_eq!(
+            quotas.consume("alice", 2, at(JAN_31)),
+            Err(Exceeded {
+                period: Period::Day,
+                limit: 3,
+                retry_after_secs: 3600,
+            })
+        );
+        quotas.consume("alice", 1, at(JAN_31)).unwrap();
+        quotas.refund("alice", 1, at(JAN_31));
+        assert_eq!(
+            quotas.consume("alice", 1, at(JAN_31)).unwrap().daily,
+            Some(0)
+        );
+
+        // A new day and month start at midnight
+        let remaining = quotas.consume("alice", 3, at(JAN_31 + 3600)).unwrap();
+        assert_eq!(
+            remaining,
+            Remaining {
+                daily: Some(0),
+                monthly: Some(1)
+            }
+        );
+
+        for _ in 0..100 {
+            quotas.consume("vip", 1, at(JAN_31)).unwrap();
+        }
+        assert_eq!(quotas.remaining("vip"), Remaining::default());
+    }
+}
@@ -0,0 +1,167 @@
+//! `serialize_by` locks shared by several servers through Redis
+//!
+//! Replicas of an HTTP server fronting the same backend each hold their own
+//! `#[mcp(serialize_by = "...")]` locks, so two of them may run conflicting
+//! calls at once. [`RedisLocks`] keeps the locks in Redis instead, so they
+//! hold cluster-wide:
+//!
+//! ```ignore
+//! let locks = RedisLocks::new("redis://locks:6379")?.prefix("deployer:");
+//! McpServer::<Commands>::new().with_lock_provider(locks);
+//! ```
+//!
+//! A lock is a key set with `SET NX PX`, holding a token unique to the call.
+//! It expires after its TTL, so the lock of a crashed server frees itself,
+//! and is extended while the call runs. Waiting calls poll for it. Releasing
+//! only deletes the key if it still holds the call's token. Needs the
+//! `redis` feature.
+
+use crate::policy::{LockFuture, LockGuard, LockProvider};
+use redis::aio::MultiplexedConnection;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::OnceCell;
+use tokio_util::sync::CancellationToken;
+
+/// Extends a lock if it still holds the caller's token
+const EXTEND: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Deletes a lock if it still holds the caller's token
+const RELEASE: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Locks kept in Redis
+#[derive(Debug)]
+pub struct RedisLocks {
+    client: redis::Client,
+    /// Opened on the first lock
+    connection: OnceCell<MultiplexedConnection>,
+    prefix: String,
+    ttl: Duration,
+    retry_interval: Duration,
+}
+
+impl RedisLocks {
+    /// Keep the locks on the Redis server at `url`, e.g.
+    /// `redis://locks:6379`; the connection is opened on first use
+    pub fn new(url: &str) -> Result<Self, redis::RedisError> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            connection: OnceCell::new(),
+            prefix: "clap-mcp:lock:".to_string(),
+            ttl: Duration::from_secs(30),
+            retry_interval: Duration::from_millis(100),
+        })
+    }
+
+    /// Prefix of the Redis keys; `clap-mcp:lock:` by default
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// How long a lock outlives a server that stopped extending it; 30
+    /// seconds by default
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// How often a waiting call checks whether the lock is free; every 100
+    /// milliseconds by default
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    async fn acquire(&self, key: &str) -> Result<LockGuard, Box<dyn Error + Send + Sync>> {
+        let mut connection = self
+            .connection
+            .get_or_try_init(|| self.client.get_multiplexed_async_connection())
+            .await?
+            .clone();
+        let key = format!("{}{}", self.prefix, key);
+        let token = token();
+        let ttl = self.ttl.as_millis() as u64;
+        loop {
+            let set: Option<String> = redis::cmd("SET")
+                .arg(&key)
+                .arg(&token)
+                .arg("NX")
+                .arg("PX")
+                .arg(ttl)
+                .query_async(&mut connection)
+                .await?;
+            if set.is_some() {
+                break;
+            }
+            tokio::time::sleep(self.retry_interval).await;
+        }
+
+        let released = CancellationToken::new();
+        let guard = released.clone().drop_guard();
+        let renew_every = self.ttl / 3;
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(renew_every) => {}
+                    _ = released.cancelled() => break,
+                }
+                let extended: Result<i64, _> = redis::Script::new(EXTEND)
+                    .key(&key)
+                    .arg(&token)
+                    .arg(ttl)
+                    .invoke_async(&mut connection)
+                    .await;
+                match extended {
+                    Ok(1) => {}
+                    Ok(_) => {
+                        tracing::warn!(%key, "lost Redis lock before the call finished");
+                        return;
+                    }
+                    Err(e) => tracing::warn!(%key, "failed to extend Redis lock: {}", e),
+                }
+            }
+            let released: Result<i64, _> = redis::Script::new(RELEASE)
+                .key(&key)
+                .arg(&token)
+                .invoke_async(&mut connection)
+                .await;
+            if let Err(e) = released {
+                tracing::warn!(%key, "failed to release Redis lock, it expires on its own: {}", e);
+            }
+        });
+        Ok(LockGuard::new(guard))
+    }
+}
+
+impl LockProvider for RedisLocks {
+    fn lock<'a>(&'a self, key: &'a str) -> LockFuture<'a> {
+        Box::pin(self.acquire(key))
+    }
+}
+
+/// A value no other lock holder uses
+fn token() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!(
+        "{}-{}-{}",
+        std::process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
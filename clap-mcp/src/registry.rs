@@ -0,0 +1,270 @@
+//! Export of MCP registry `server.json` metadata
+//!
+//! Builds the manifest MCP registries consume from the clap `Command` (name,
+//! version, description) and the derive's [`ServerMetadata`], so publishing a
+//! CLI's MCP mode doesn't require hand-authoring JSON:
+//!
+//! ```ignore
+//! let manifest = Cli::mcp_registry_manifest().name("io.github.me/calculator");
+//! std::fs::write("server.json", manifest.to_json_pretty())?;
+//! ```
+
+use crate::metadata::ServerMetadata;
+use clap::Command;
+use serde_json::{json, Map, Value};
+
+/// JSON schema the generated `server.json` declares
+pub const SERVER_SCHEMA_URL: &str =
+    "https://static.modelcontextprotocol.io/schemas/2025-07-09/server.schema.json";
+
+/// Builder for a registry `server.json` document
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerManifest {
+    name: String,
+    description: Option<String>,
+    version: Option<String>,
+    metadata: ServerMetadata,
+    repository: Option<String>,
+    registry_type: String,
+    identifier: String,
+    run_args: Vec<String>,
+    remotes: Vec<String>,
+}
+
+impl ServerManifest {
+    /// Start a manifest from the clap command's name, version and about text
+    ///
+    /// The package defaults to a crates.io package named after the command.
+    pub fn from_command(cmd: &Command) -> Self {
+        let name = cmd.get_name().to_string();
+        Self {
+            identifier: name.clone(),
+            name,
+            description: cmd.get_about().map(|about| about.to_string()),
+            version: cmd.get_version().map(str::to_string),
+            metadata: ServerMetadata::default(),
+            repository: None,
+            registry_type: "cargo".to_string(),
+            run_args: Vec::new(),
+            remotes: Vec::new(),
+        }
+    }
+
+    /// Registry name, usually namespaced like `io.github.owner/server`
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Title, website and icons declared on the parser
+    pub fn metadata(mut self, metadata: ServerMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Source repository URL
+    pub fn repository(mut self, url: impl Into<String>) -> Self {
+        self.repository = Some(url.into());
+        self
+    }
+
+    /// Package registry (`cargo`, `npm`, `oci`, ...) and package identifier
+    pub fn package(
+        mut self,
+        registry_type: impl Into<String>,
+        identifier: impl Into<String>,
+    ) -> Self {
+        self.registry_type = registry_type.into();
+        self.identifier = identifier.into();
+        self
+    }
+
+    /// Arguments that start the MCP server, e.g. `["--mcp"]`
+    pub fn run_args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.run_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Advertise a hosted deployment reachable over SSE at `url`
+    pub fn remote(mut self, url: impl Into<String>) -> Self {
+        self.remotes.push(url.into());
+        self
+    }
+
+    /// Render the manifest as a `server.json` document
+    pub fn to_json(&self) -> Value {
+        let mut doc = Map::new();
+        doc.insert("$schema".to_string(), json!(SERVER_SCHEMA_URL));
+        doc.insert("name".to_string(), json!(self.name));
+        if let Some(description) = &self.description {
+            doc.insert("description".to_string(), json!(description));
+        }
+        if let Some(version) = &self.version {
+            doc.insert("version".to_string(), json!(version));
+        }
+        if let Some(title) = &self.metadata.title {
+            doc.insert("title".to_string(), json!(title));
+        }
+        if let Some(website) = &self.metadata.website_url {
+            doc.insert("websiteUrl".to_string(), json!(website));
+        }
+        if !self.metadata.icons.is_empty() {
+            doc.insert("icons".to_string(), json!(self.metadata.icons));
+        }
+        if let Some(url) = &self.repository {
+            doc.insert("repository".to_string(), repository_json(url));
+        }
+
+        let mut package = json!({
+            "registryType": self.registry_type,
+            "identifier": self.identifier,
+            "transport": { "type": "stdio" },
+        });
+        if let Some(version) = &self.version {
+            package["version"] = json!(version);
+        }
+        if !self.run_args.is_empty() {
+            package["packageArguments"] = self
+                .run_args
+                .iter()
+                .map(|arg| package_argument(arg))
+                .collect();
+        }
+        doc.insert("packages".to_string(), json!([package]));
+
+        if !self.remotes.is_empty() {
+            let remotes: Vec<Value> = self
+                .remotes
+                .iter()
+                .map(|url| json!({ "type": "sse", "url": url }))
+                .collect();
+            doc.insert("remotes".to_string(), json!(remotes));
+        }
+
+        Value::Object(doc)
+    }
+
+    pub fn to_json_pretty(&self) -> String {
+        serde_json::to_string_pretty(&self.to_json()).unwrap_or_default()
+    }
+
+    pub(crate) fn registry_name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn package_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub(crate) fn package_version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    pub(crate) fn server_metadata(&self) -> &ServerMetadata {
+        &self.metadata
+    }
+
+    pub(crate) fn run_arguments(&self) -> &[String] {
+        &self.run_args
+    }
+}
+
+/// Long (or short) flag that turns on MCP mode for the arg with id `arg_id`
+pub fn mode_flag_args(cmd: &Command, arg_id: &str) -> Vec<String> {
+    cmd.get_arguments()
+        .find(|arg| arg.get_id().as_str() == arg_id)
+        .and_then(|arg| {
+            arg.get_long()
+                .map(|long| format!("--{}", long))
+                .or_else(|| arg.get_short().map(|short| format!("-{}", short)))
+        })
+        .into_iter()
+        .collect()
+}
+
+fn package_argument(arg: &str) -> Value {
+    match arg.split_once('=') {
+        Some((name, value)) if arg.starts_with('-') => {
+            json!({ "type": "named", "name": name, "value": value })
+        }
+        _ if arg.starts_with('-') => json!({ "type": "named", "name": arg }),
+        _ => json!({ "type": "positional", "value": arg }),
+    }
+}
+
+fn repository_json(url: &str) -> Value {
+    let source = ["github", "gitlab", "bitbucket"]
+        .into_iter()
+        .find(|host| url.contains(&format!("{}.", host)));
+    match source {
+        Some(source) => json!({ "url": url, "source": source }),
+        None => json!({ "url": url }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Icon;
+    use clap::Arg;
+
+    fn command() -> Command {
+        Command::new("calculator")
+            .version("1.2.0")
+            .about("A simple calculator")
+            .arg(Arg::new("mcp").long("mcp").action(clap::ArgAction::SetTrue))
+    }
+
+    #[test]
+    fn test_manifest_from_command() {
+        let cmd = command();
+        let manifest = ServerManifest::from_command(&cmd)
+            .name("io.github.example/calculator")
+            .metadata(ServerMetadata {
+                title: Some("Calculator".to_string()),
+                icons: vec![Icon::new("https://example.com/icon.png")],
+                ..Default::default()
+            })
+            .repository("https://github.com/example/calculator")
+            .run_args(mode_flag_args(&cmd, "mcp"))
+            .to_json();
+
+        assert_eq!(manifest["name"], "io.github.example/calculator");
+        assert_eq!(manifest["description"], "A simple calculator");
+        assert_eq!(manifest["version"], "1.2.0");
+        assert_eq!(manifest["title"], "Calculator");
+        assert_eq!(manifest["icons"][0]["src"], "https://example.com/icon.png");
+        assert_eq!(manifest["repository"]["source"], "github");
+
+        let package = &manifest["packages"][0];
+        assert_eq!(package["registryType"], "cargo");
+        assert_eq!(package["identifier"], "calculator");
+        assert_eq!(package["transport"]["type"], "stdio");
+        assert_eq!(
+            package["packageArguments"],
+            json!([{ "type": "named", "name": "--mcp" }])
+        );
+        assert!(manifest.get("remotes").is_none());
+    }
+
+    #[test]
+    fn test_manifest_remotes() {
+        let manifest = ServerManifest::from_command(&command())
+            .remote("https://calc.example.com/sse")
+            .to_json();
+        assert_eq!(
+            manifest["remotes"],
+            json!([{ "type": "sse", "url": "https://calc.example.com/sse" }])
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Local replay of a recorded tool call, for debugging
+//!
+//! An [`AuditEntry`] is the `params` of a `tools/call` request as a server
+//! received it, optionally with the correlation ID it was logged under:
+//!
+//! ```json
+//! {"name": "deploy", "arguments": {"target": "prod", "replicas": 3}, "correlationId": "18df-1"}
+//! ```
+//!
+//! `McpServer::replay` runs it through the same pipeline as a live call
+//! (variables, secrets, schema validation, clap, the handler) and reports the
+//! command line clap parsed alongside the result. Maintenance windows and
+//! quotas are not applied, and `_vars` set by earlier calls of the original
+//! session are not known, so entries should carry the variables they use.
+//!
+//! `McpMode` parsers marked `#[mcp(replay)]` get a `handle_mcp_replay`
+//! function that does this for `--mcp-replay <file>`:
+//!
+//! ```ignore
+//! if let Some(result) = Cli::handle_mcp_replay(execute) {
+//!     return result;
+//! }
+//! ```
+
+use rmcp::model::{CallToolResult, JsonObject, RawContent, ResourceContents};
+use rmcp::Error as McpError;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::Path;
+
+/// Flag naming the file of the entry to replay
+pub const REPLAY_FLAG: &str = "--mcp-replay";
+
+/// A recorded tool call
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Tool name as the client sent it
+    #[serde(alias = "tool")]
+    pub name: String,
+    #[serde(default)]
+    pub arguments: JsonObject,
+    /// Correlation ID of the original call, seen by the handler again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Load an entry saved as a JSON file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        Self::from_json(&json)
+    }
+}
+
+/// Outcome of a replayed call
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// Tool the call resolved to
+    pub tool: String,
+    /// Command line handed to clap, with secrets redacted; `None` when the
+    /// call was rejected before one was built
+    pub argv: Option<Vec<String>>,
+    /// What the client would have received
+    pub result: Result<CallToolResult, McpError>,
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "tool: {}", self.tool)?;
+        match &self.argv {
+            Some(argv) => writeln!(f, "argv: {}", crate::argv::format_command_line(argv))?,
+            None => writeln!(f, "argv: (not built)")?,
+        }
+        match &self.result {
+            Ok(result) => {
+                let status = if result.is_error.unwrap_or(false) {
+                    "error"
+                } else {
+                    "success"
+                };
+                writeln!(f, "result: {}", status)?;
+                for content in &result.content {
+                    match &content.raw {
+                        RawContent::Text(text) => writeln!(f, "{}", text.text)?,
+                        RawContent::Resource(embedded) => match &embedded.resource {
+                            ResourceContents::TextResourceContents { text, .. } => {
+                                writeln!(f, "{}", text)?
+                            }
+                            ResourceContents::BlobResourceContents { uri, .. } => {
+                                writeln!(f, "[blob {}]", uri)?
+                            }
+                        },
+                        RawContent::Image(image) => writeln!(f, "[image {}]", image.mime_type)?,
+                        _ => writeln!(f, "[unsupported content]")?,
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => writeln!(f, "rejected: {}", e.message),
+        }
+    }
+}
+
+/// Replay the entry named by `--mcp-replay <file>` if the flag is present
+///
+/// `args` are the full process arguments including the binary name. Returns
+/// `None` when the flag is absent, so regular parsing can go on.
+pub fn handle_from_args(
+    args: impl IntoIterator<Item = impl Into<OsString>>,
+    replay: impl FnOnce(AuditEntry) -> Result<Replay, Box<dyn std::error::Error>>,
+) -> Option<Result<(), Box<dyn std::error::Error>>> {
+    let path = replay_path(args.into_iter().map(Into::into))?;
+    let run = || -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.ok_or_else(|| format!("{} requires a file", REPLAY_FLAG))?;
+        print!("{}", replay(AuditEntry::from_file(path)?)?);
+        Ok(())
+    };
+    Some(run())
+}
+
+/// `Some(file)` after the replay flag, `Some(None)` if it lacks one
+fn replay_path(args: impl Iterator<Item = OsString>) -> Option<Option<OsString>> {
+    let mut args = args.skip(1);
+    while let Some(arg) = args.next() {
+        if arg == REPLAY_FLAG {
+            return Some(args.next());
+        }
+        if let Some(path) = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix(REPLAY_FLAG))
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(Some(path.into()));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path_of(args: &[&str]) -> Option<Option<OsString>> {
+        replay_path(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn test_replay_flag() {
+        assert_eq!(path_of(&["cli", "add", "--a", "1"]), None);
+        assert_eq!(
+            path_of(&["cli", "--mcp-replay", "call.json"]),
+            Some(Some("call.json".into()))
+        );
+        assert_eq!(
+            path_of(&["cli", "--mcp-replay=call.json"]),
+            Some(Some("call.json".into()))
+        );
+        assert_eq!(path_of(&["cli", "--mcp-replay"]), Some(None));
+        // The binary name is never the flag
+        assert_eq!(path_of(&["--mcp-replay", "x"]), None);
+    }
+
+    #[test]
+    fn test_parse_entry() {
+        let entry = AuditEntry::from_json(
+            r#"{"tool": "add", "arguments": {"a": 1}, "correlationId": "abc"}"#,
+        )
+        .unwrap();
+        assert_eq!(entry.name, "add");
+        assert_eq!(entry.arguments["a"], 1);
+        assert_eq!(entry.correlation_id.as_deref(), Some("abc"));
+        assert!(AuditEntry::from_json(r#"{"arguments": {}}"#).is_err());
+    }
+}
@@ -0,0 +1,374 @@
+//! MCP resources for files a CLI produces
+//!
+//! Reports, logs and build artifacts are better served as resources than
+//! pasted into a tool result: the client lists them and reads the ones it
+//! needs. A [`ResourceProvider`] supplies them; [`FileResources`] is one that
+//! handlers register output files with:
+//!
+//! ```ignore
+//! let files = FileResources::new();
+//! let registry = files.clone();
+//! McpServer::<Commands>::new()
+//!     .with_resources(files)
+//!     .with_handler(Box::new(move |cmd| {
+//!         let path = build_report(cmd)?;
+//!         let uri = registry.register(&path, Some("text/csv"));
+//!         Ok(format!("Report written to {}", uri))
+//!     }));
+//! ```
+//!
+//! Registered files are read when the client asks for them, so they always
+//! reflect what is on disk. Valid UTF-8 is served as text, anything else as a
+//! base64 blob.
+//!
+//! [`LastResults`] keeps the text of each tool's most recent successful result
+//! under `last_result://<tool>`, so a client can refer back to earlier output
+//! without running an expensive command again. Each caller only sees its own
+//! results.
+
+use base64::Engine;
+use rmcp::model::{
+    AnnotateAble, CallToolResult, RawContent, RawResource, Resource, ResourceContents,
+};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Supplies the resources a server lists and reads
+pub trait ResourceProvider: Send + Sync {
+    /// Every resource currently available
+    fn list(&self) -> Vec<Resource>;
+
+    /// Contents of `uri`, or `None` if this provider does not serve it
+    fn read(&self, uri: &str) -> Option<Result<ResourceContents, String>>;
+}
+
+/// A registered file
+#[derive(Debug, Clone)]
+struct Entry {
+    path: PathBuf,
+    mime_type: Option<String>,
+    description: Option<String>,
+}
+
+/// A [`ResourceProvider`] serving files registered at runtime; clones share
+/// the registrations
+#[derive(Debug, Clone, Default)]
+pub struct FileResources {
+    entries: Arc<Mutex<BTreeMap<String, Entry>>>,
+}
+
+impl FileResources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publish the file at `path` and return its `file://` URI
+    ///
+    /// Without a `mime_type`, one is guessed from the extension.
+    pub fn register(&self, path: impl AsRef<Path>, mime_type: Option<&str>) -> String {
+        self.insert(path.as_ref(), mime_type, None)
+    }
+
+    /// Like [`register`](Self::register), with a description listed to clients
+    pub fn register_described(
+        &self,
+        path: impl AsRef<Path>,
+        mime_type: Option<&str>,
+        description: impl Into<String>,
+    ) -> String {
+        self.insert(path.as_ref(), mime_type, Some(description.into()))
+    }
+
+    fn insert(&self, path: &Path, mime_type: Option<&str>, description: Option<String>) -> String {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let uri = file_uri(&path);
+        let entry = Entry {
+            mime_type: mime_type
+                .map(str::to_string)
+                .or_else(|| guess_mime_type(&path).map(str::to_string)),
+            path,
+            description,
+        };
+        self.entries.lock().unwrap().insert(uri.clone(), entry);
+        uri
+    }
+
+    /// Stop serving `uri`; returns whether it was registered
+    pub fn unregister(&self, uri: &str) -> bool {
+        self.entries.lock().unwrap().remove(uri).is_some()
+    }
+}
+
+impl ResourceProvider for FileResources {
+    fn list(&self) -> Vec<Resource> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(uri, entry)| {
+                let name = entry
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| uri.clone());
+                RawResource {
+                    uri: uri.clone(),
+                    name,
+                    description: entry.description.clone(),
+                    mime_type: entry.mime_type.clone(),
+                    size: std::fs::metadata(&entry.path)
+                        .ok()
+                        .and_then(|metadata| u32::try_from(metadata.len()).ok()),
+                }
+                .no_annotation()
+            })
+            .collect()
+    }
+
+    fn read(&self, uri: &str) -> Option<Result<ResourceContents, String>> {
+        let entry = self.entries.lock().unwrap().get(uri).cloned()?;
+        Some(read_file(uri, &entry.path, entry.mime_type))
+    }
+}
+
+/// Contents of the file at `path` served as `uri`: text when it is valid
+/// UTF-8, a base64 blob otherwise
+pub(crate) fn read_file(
+    uri: &str,
+    path: &Path,
+    mime_type: Option<String>,
+) -> Result<ResourceContents, String> {
+    std::fs::read(path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))
+        .map(|bytes| match String::from_utf8(bytes) {
+            Ok(text) => ResourceContents::TextResourceContents {
+                uri: uri.to_string(),
+                mime_type,
+                text,
+            },
+            Err(e) => ResourceContents::BlobResourceContents {
+                uri: uri.to_string(),
+                mime_type,
+                blob: base64::engine::general_purpose::STANDARD.encode(e.as_bytes()),
+            },
+        })
+}
+
+/// URI scheme of [`LastResults`]
+pub const LAST_RESULT_SCHEME: &str = "last_result://";
+
+/// The most recent successful result of each tool, per owner, served as
+/// `last_result://<tool>`; clones share the results
+///
+/// The server keys results by the caller's session-store token, or by its
+/// principal without one, and keeps them for its lifetime: a client resuming
+/// in a new session still finds its own results, never anyone else's.
+#[derive(Debug, Clone, Default)]
+pub struct LastResults {
+    /// Text by owner, then tool
+    results: Arc<Mutex<BTreeMap<String, BTreeMap<String, String>>>>,
+}
+
+impl LastResults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remember the text of `result` as the last one of `tool` for `owner`
+    ///
+    /// Error results and results without text leave the previous one in place.
+    pub fn record(&self, owner: &str, tool: &str, result: &CallToolResult) {
+        if result.is_error.unwrap_or(false) {
+            return;
+        }
+        let texts: Vec<&str> = result
+            .content
+            .iter()
+            .filter_map(|content| match &content.raw {
+                RawContent::Text(text) => Some(text.text.as_str()),
+                RawContent::Resource(embedded) => match &embedded.resource {
+                    ResourceContents::TextResourceContents { text, .. } => Some(text.as_str()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        if !texts.is_empty() {
+            let mut results = self.results.lock().unwrap();
+            let owned = results.entry(owner.to_string()).or_default();
+            owned.insert(tool.to_string(), texts.join("\n"));
+        }
+    }
+
+    /// The results `owner` can read
+    pub fn list(&self, owner: &str) -> Vec<Resource> {
+        let results = self.results.lock().unwrap();
+        let Some(owned) = results.get(owner) else {
+            return Vec::new();
+        };
+        owned
+            .iter()
+            .map(|(tool, text)| {
+                RawResource {
+                    uri: format!("{}{}", LAST_RESULT_SCHEME, tool),
+                    name: format!("Last result of {}", tool),
+                    description: Some(format!("Most recent successful result of `{}`", tool)),
+                    mime_type: Some("text/plain".to_string()),
+                    size: u32::try_from(text.len()).ok(),
+                }
+                .no_annotation()
+            })
+            .collect()
+    }
+
+    /// Contents of `uri` for `owner`, or `None` if it is not one of its
+    /// results
+    pub fn read(&self, owner: &str, uri: &str) -> Option<ResourceContents> {
+        let tool = uri.strip_prefix(LAST_RESULT_SCHEME)?;
+        let results = self.results.lock().unwrap();
+        let text = results.get(owner)?.get(tool)?.clone();
+        Some(ResourceContents::TextResourceContents {
+            uri: uri.to_string(),
+            mime_type: Some("text/plain".to_string()),
+            text,
+        })
+    }
+}
+
+/// `file://` URI of an absolute path, percent-encoding what URIs cannot hold
+pub(crate) fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        // Windows drive paths: file:///C:/...
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// MIME type of common output files by extension
+pub(crate) fn guess_mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/yaml",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "svg" => "image/svg+xml",
+        "zip" => "application/zip",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::model::Content;
+
+    #[test]
+    fn test_file_uri() {
+        assert_eq!(
+            file_uri(Path::new("/tmp/build report.csv")),
+            "file:///tmp/build%20report.csv"
+        );
+        assert_eq!(
+            file_uri(Path::new(r"C:\out\log.txt")),
+            "file:///C:/out/log.txt"
+        );
+    }
+
+    #[test]
+    fn test_register_list_and_read() {
+        let dir = std::env::temp_dir().join(format!("clap-mcp-resources-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let report = dir.join("report.csv");
+        let artifact = dir.join("artifact.bin");
+        std::fs::write(&report, "a,b\n1,2\n").unwrap();
+        std::fs::write(&artifact, [0xff, 0x00, 0x01]).unwrap();
+
+        let files = FileResources::new();
+        let report_uri = files.register_described(&report, None, "Build report");
+        let artifact_uri = files
+            .clone()
+            .register(&artifact, Some("application/octet-stream"));
+
+        let listed = files.list();
+        assert_eq!(listed.len(), 2);
+        let listed_report = listed.iter().find(|r| r.uri == report_uri).unwrap();
+        assert_eq!(listed_report.name, "report.csv");
+        assert_eq!(listed_report.mime_type.as_deref(), Some("text/csv"));
+        assert_eq!(listed_report.size, Some(8));
+
+        assert_eq!(
+            files.read(&report_uri),
+            Some(Ok(ResourceContents::TextResourceContents {
+                uri: report_uri.clone(),
+                mime_type: Some("text/csv".to_string()),
+                text: "a,b\n1,2\n".to_string(),
+            }))
+        );
+        assert!(matches!(
+            files.read(&artifact_uri),
+            Some(Ok(ResourceContents::BlobResourceContents { blob, .. })) if blob == "/wAB"
+        ));
+        assert_eq!(files.read("file:///elsewhere"), None);
+
+        std::fs::remove_file(&report).unwrap();
+        assert!(matches!(files.read(&report_uri), Some(Err(_))));
+        assert!(files.unregister(&report_uri));
+        assert_eq!(files.read(&report_uri), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_last_results() {
+        let results = LastResults::new();
+        assert!(results.list("alice").is_empty());
+
+        let success = |text: &str| CallToolResult::success(vec![Content::text(text)]);
+        results.record("alice", "build", &success("v1"));
+        results.record("alice", "build", &success("v2"));
+        results.record(
+            "alice",
+            "build",
+            &CallToolResult::error(vec![Content::text("broken")]),
+        );
+        results.record("alice", "test", &CallToolResult::success(vec![]));
+        results.record("bob", "deploy", &success("deployed"));
+
+        let listed = results.list("alice");
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].uri, "last_result://build");
+        assert_eq!(
+            results.read("alice", "last_result://build"),
+            Some(ResourceContents::TextResourceContents {
+                uri: "last_result://build".to_string(),
+                mime_type: Some("text/plain".to_string()),
+                text: "v2".to_string(),
+            })
+        );
+        assert_eq!(results.read("alice", "last_result://test"), None);
+        assert_eq!(results.read("alice", "file:///build"), None);
+
+        // Owners do not see each other's results
+        assert_eq!(results.read("bob", "last_result://build"), None);
+        assert_eq!(results.read("alice", "last_result://deploy"), None);
+        assert!(results.list("carol").is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+//! Terminal-format sanitation of text results
+//!
+//! CLI code often writes ANSI colors, progress bars redrawn with `\r`, and
+//! box-drawing tables. MCP clients render text verbatim, so these are cleaned
+//! up before results are returned.
+
+/// Cleanup applied to every text result before it is returned to the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputSanitizer {
+    /// Remove ANSI escape sequences (colors, cursor movement, hyperlinks)
+    pub strip_ansi: bool,
+    /// Convert CRLF to LF and collapse `\r`-redrawn lines to their final state
+    pub normalize_newlines: bool,
+    /// Convert simple box-drawing or ASCII tables into markdown tables
+    pub tables_to_markdown: bool,
+}
+
+impl Default for OutputSanitizer {
+    fn default() -> Self {
+        Self {
+            strip_ansi: true,
+            normalize_newlines: true,
+            tables_to_markdown: false,
+        }
+    }
+}
+
+impl OutputSanitizer {
+    /// A sanitizer that returns output untouched
+    pub fn disabled() -> Self {
+        Self {
+            strip_ansi: false,
+            normalize_newlines: false,
+            tables_to_markdown: false,
+        }
+    }
+
+    pub fn with_tables_to_markdown(mut self, enabled: bool) -> Self {
+        self.tables_to_markdown = enabled;
+        self
+    }
+
+    pub fn sanitize(&self, text: &str) -> String {
+        let mut out = if self.strip_ansi {
+            strip_ansi(text)
+        } else {
+            text.to_string()
+        };
+        if self.normalize_newlines {
+            out = normalize_newlines(&out);
+        }
+        if self.tables_to_markdown {
+            out = tables_to_markdown(&out);
+        }
+        out
+    }
+}
+
+/// Remove ANSI escape sequences (CSI, OSC and two-byte escapes)
+pub fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters and intermediates until a final byte in @..~
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: terminated by BEL or ST (ESC \)
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    out
+}
+
+/// Convert CRLF to LF and keep only the last redraw of `\r`-rewritten lines
+pub fn normalize_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .split('\n')
+        .map(|line| line.rsplit('\r').find(|s| !s.is_empty()).unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+const BORDER_CHARS: &[char] = &[
+    '─', '━', '═', '┌', '┐', '└', '┘', '├', '┤', '┬', '┴', '┼', '╭', '╮', '╯', '╰', '╔', '╗', '╚',
+    '╝', '╠', '╣', '╦', '╩', '╬', '╞', '╡', '╪', '+', '-', '=', ':', '│', '┃', '║', '|',
+];
+const CELL_SEPARATORS: &[char] = &['│', '┃', '║', '|'];
+
+fn is_border_line(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty()
+        && trimmed
+            .chars()
+            .all(|c| BORDER_CHARS.contains(&c) || c == ' ')
+        && trimmed
+            .chars()
+            .any(|c| !CELL_SEPARATORS.contains(&c) && c != ' ')
+}
+
+fn is_row_line(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.starts_with(CELL_SEPARATORS) && trimmed.ends_with(CELL_SEPARATORS) && trimmed.len() > 1
+}
+
+/// Convert blocks of box-drawing or ASCII tables into markdown tables
+pub fn tables_to_markdown(text: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut i = 0;
+
+    while i < lines.len() {
+        let start = i;
+        while i < lines.len() && (is_border_line(lines[i]) || is_row_line(lines[i])) {
+            i += 1;
+        }
+
+        let block = &lines[start..i];
+        let has_border = block.iter().any(|l| is_border_line(l));
+        let rows: Vec<Vec<String>> = block
+            .iter()
+            .filter(|l| !is_border_line(l))
+            .map(|l| {
+                l.trim()
+                    .trim_matches(CELL_SEPARATORS)
+                    .split(CELL_SEPARATORS)
+                    .map(|cell| cell.trim().to_string())
+                    .collect()
+            })
+            .collect();
+
+        if has_border && !rows.is_empty() {
+            for (n, row) in rows.iter().enumerate() {
+                out.push(format!("| {} |", row.join(" | ")));
+                if n == 0 {
+                    out.push(format!("|{}", " --- |".repeat(row.len())));
+                }
+            }
+        } else {
+            out.extend(block.iter().map(|l| l.to_string()));
+        }
+
+        if i == start {
+            out.push(lines[i].to_string());
+            i += 1;
+        }
+    }
+
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(strip_ansi("\x1b[1;31merror\x1b[0m: bad"), "error: bad");
+        assert_eq!(
+            strip_ansi("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x07 done"),
+            "link done"
+        );
+        assert_eq!(strip_ansi("plain text"), "plain text");
+    }
+
+    #[test]
+    fn test_normalize_newlines() {
+        assert_eq!(normalize_newlines("a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(
+            normalize_newlines("10%\r50%\r100%\ndone"),
+            "100%\ndone".to_string()
+        );
+    }
+
+    #[test]
+    fn test_tables_to_markdown() {
+        let table = "Results:\n┌──────┬───────┐\n│ name │ value │\n├──────┼───────┤\n│ a    │ 1     │\n│ b    │ 2     │\n└──────┴───────┘\nend";
+        assert_eq!(
+            tables_to_markdown(table),
+            "Results:\n| name | value |\n| --- | --- |\n| a | 1 |\n| b | 2 |\nend"
+        );
+
+        let ascii = "+----+----+\n| id | ok |\n+----+----+\n| 1  | y  |\n+----+----+";
+        assert_eq!(
+            tables_to_markdown(ascii),
+            "| id | ok |\n| --- | --- |\n| 1 | y |"
+        );
+
+        // A lone separator line or pipe-delimited text is left alone
+        assert_eq!(tables_to_markdown("----\ntext"), "----\ntext");
+        assert_eq!(tables_to_markdown("|a|"), "|a|");
+    }
+
+    #[test]
+    fn test_default_sanitizer() {
+        let sanitizer = OutputSanitizer::default();
+        assert_eq!(
+            sanitizer.sanitize("\x1b[32mok\x1b[0m\r\nnext"),
+            "ok\nnext".to_string()
+        );
+        assert_eq!(
+            OutputSanitizer::disabled().sanitize("\x1b[32mok\x1b[0m"),
+            "\x1b[32mok\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Handler skeletons for existing `Subcommand` enums
+//!
+//! Adopting the crate on a large enum starts with a `match` covering every
+//! variant. [`handler_skeleton`] writes it from the enum's clap definition,
+//! with each subcommand's about text and fields:
+//!
+//! ```ignore
+//! println!("{}", clap_mcp::scaffold::handler_skeleton::<Commands>());
+//! ```
+//!
+//! ```ignore
+//! fn execute_command(cmd: Commands) -> Result<String, String> {
+//!     match cmd {
+//!         // Add two numbers
+//!         Commands::Add { a, b } => todo!("add"),
+//!         Commands::ShowConfig { .. } => todo!("show-config"),
+//!     }
+//! }
+//! ```
+//!
+//! Variant names are derived from subcommand names, and fields from argument
+//! ids, so subcommands renamed with `#[command(name = "...")]`, arguments
+//! with a custom `id` and tuple variants wrapping an `Args` struct need their
+//! arms adjusted by hand.
+
+use clap::{ArgAction, Subcommand};
+use std::path::Path;
+
+/// A handler function matching every subcommand of `T`, each arm a `todo!()`
+pub fn handler_skeleton<T: Subcommand>() -> String {
+    let enum_name = std::any::type_name::<T>()
+        .rsplit("::")
+        .next()
+        .unwrap_or("Commands");
+    let cmd = T::augment_subcommands(clap::Command::new("mcp"));
+
+    let mut out = format!(
+        "fn execute_command(cmd: {}) -> Result<String, String> {{\n    match cmd {{\n",
+        enum_name
+    );
+    for sub in cmd.get_subcommands() {
+        if let Some(about) = sub.get_about() {
+            for line in about.to_string().lines() {
+                out.push_str(format!("        // {}", line).trim_end());
+                out.push('\n');
+            }
+        }
+        out.push_str(&format!(
+            "        {}::{} {} => todo!(\"{}\"),\n",
+            enum_name,
+            variant_name(sub.get_name()),
+            fields_pattern(sub),
+            sub.get_name()
+        ));
+    }
+    out.push_str("    }\n}\n");
+    out
+}
+
+/// Write [`handler_skeleton`] to `path`
+pub fn write_handler_skeleton<T: Subcommand>(path: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::write(path, handler_skeleton::<T>())
+}
+
+/// `ShowConfig` for the subcommand `show-config`, undoing clap's kebab-case
+fn variant_name(subcommand: &str) -> String {
+    subcommand
+        .split(['-', '_'])
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// `{ a, b }` binding each argument, `{ .. }` without any
+fn fields_pattern(sub: &clap::Command) -> String {
+    let mut fields = Vec::new();
+    let mut rest = false;
+    for arg in sub.get_arguments() {
+        if matches!(arg.get_action(), ArgAction::Help | ArgAction::Version) {
+            continue;
+        }
+        let id = arg.get_id().as_str();
+        if is_identifier(id) {
+            fields.push(id);
+        } else {
+            rest = true;
+        }
+    }
+    if rest || fields.is_empty() {
+        fields.push("..");
+    }
+    format!("{{ {} }}", fields.join(", "))
+}
+
+fn is_identifier(id: &str) -> bool {
+    let mut chars = id.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Subcommand, Clone)]
+    enum Commands {
+        /// Add two numbers
+        Add {
+            #[arg(long)]
+            a: i32,
+            b: i32,
+        },
+        /// Show the configuration
+        ShowConfig,
+        Tag {
+            #[arg(long, id = "tag-name")]
+            name: String,
+            #[arg(long)]
+            force: bool,
+        },
+    }
+
+    #[test]
+    fn test_handler_skeleton() {
+        assert_eq!(
+            handler_skeleton::<Commands>(),
+            r#"fn execute_command(cmd: Commands) -> Result<String, String> {
+    match cmd {
+        // Add two numbers
+        Commands::Add { a, b } => todo!("add"),
+        // Show the configuration
+        Commands::ShowConfig { .. } => todo!("show-config"),
+        Commands::Tag { force, .. } => todo!("tag"),
+    }
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,690 @@
+//! Tool schemas generated at compile time by `#[derive(McpSchema)]`
+//!
+//! By default the server builds each tool's input schema by introspecting the
+//! clap `Command` the first time tools are listed or called, and reuses them
+//! afterwards; see [`arg_schema`] for how argument types are inferred.
+//! Deriving `McpSchema` on the subcommand enum computes them at build time
+//! instead, as static JSON:
+//!
+//! ```ignore
+//! #[derive(Subcommand, Clone, McpSchema)]
+//! enum Commands {
+//!     /// Add two numbers
+//!     Add { #[arg(long)] a: i64, #[arg(long)] b: i64 },
+//! }
+//!
+//! McpServer::<Commands>::new().with_static_schemas(Commands::TOOLS)
+//! ```
+//!
+//! A tuple variant like `Build(BuildArgs)` takes its schema from the `Args`
+//! struct, which then derives `McpSchema` as well.
+//!
+//! `McpMode` servers use them automatically when the enum derives `McpSchema`.
+//!
+//! Arguments with an environment variable fallback (`#[arg(env = "TOKEN")]`)
+//! name it under `x-env` and in their description. They are only required
+//! while the variable is unset in the server's environment, since clap falls
+//! back to it when the call omits them.
+//!
+//! Groups, conflicts and `requires` between arguments are added to both kinds
+//! of schemas from clap, as `allOf` and `dependentRequired` constraints (see
+//! `command_constraints`).
+//!
+//! With the `schemars` feature, an argument's schema can instead come from the
+//! `JsonSchema` impl of its value type, for titles, formats, enums and nested
+//! definitions the clap-derived schema cannot describe:
+//!
+//! ```ignore
+//! #[derive(Subcommand, Clone, McpTools)]
+//! enum Commands {
+//!     Deploy {
+//!         #[arg(long)]
+//!         #[mcp(json_schema)] // uses <Region as JsonSchema>
+//!         region: Region,
+//!     },
+//! }
+//! ```
+
+use crate::metadata::ToolAttributes;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use rmcp::model::{object, JsonObject, Tool};
+use serde_json::{json, Value};
+use std::any::TypeId;
+use std::collections::{BTreeSet, HashMap};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// JSON schema of an argument, inferred from its clap definition
+///
+/// Flags are `boolean` and counted flags `integer`. Otherwise the type comes
+/// from the value parser's output type (`integer`, `number`, `boolean`, or
+/// `string` for anything else), with `enum` listing the visible possible
+/// values. Arguments accepting several values are `array`s of that type.
+/// Default values (`default_value`, `default_value_t`) are given as `default`.
+///
+/// `arg` must come from a built `Command` (see `Command::build`), or the
+/// number of values it takes is not known yet.
+pub(crate) fn arg_schema(arg: &Arg) -> Value {
+    match arg.get_action() {
+        ArgAction::SetTrue | ArgAction::SetFalse => return json!({ "type": "boolean" }),
+        ArgAction::Count => return json!({ "type": "integer", "minimum": 0 }),
+        _ => {}
+    }
+
+    let mut item = json!({ "type": value_type(arg.get_value_parser().type_id()) });
+    let possible: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if !possible.is_empty() {
+        item = json!({ "type": "string", "enum": possible });
+    }
+
+    let multiple = takes_multiple(arg);
+    let ty = item["type"].as_str().unwrap_or("string");
+    let mut defaults: Vec<Value> = arg
+        .get_default_values()
+        .iter()
+        .map(|value| typed_value(&value.to_string_lossy(), ty))
+        .collect();
+    let default = match defaults.len() {
+        0 => None,
+        _ if multiple => Some(Value::Array(defaults)),
+        _ => Some(defaults.swap_remove(0)),
+    };
+
+    let mut schema = if multiple {
+        json!({ "type": "array", "items": item })
+    } else {
+        item
+    };
+    if let Some(default) = default {
+        schema["default"] = default;
+    }
+    schema
+}
+
+/// Whether `arg` accepts several values, making its schema an `array`
+fn takes_multiple(arg: &Arg) -> bool {
+    matches!(arg.get_action(), ArgAction::Append)
+        || arg
+            .get_num_args()
+            .is_some_and(|range| range.max_values() > 1)
+}
+
+/// The values clap parsed for the visible arguments of a built `command`,
+/// typed as [`arg_schema`] describes them
+///
+/// Defaults are included; arguments left without a value are not.
+pub(crate) fn parsed_values(command: &Command, matches: &ArgMatches) -> JsonObject {
+    let mut values = JsonObject::new();
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if arg.is_hide_set() || id == "help" || id == "version" {
+            continue;
+        }
+        let value = match arg.get_action() {
+            ArgAction::SetTrue | ArgAction::SetFalse => Value::from(matches.get_flag(id)),
+            ArgAction::Count => Value::from(matches.get_count(id)),
+            _ => {
+                let Some(raw) = matches.get_raw(id) else {
+                    continue;
+                };
+                let ty = value_type(arg.get_value_parser().type_id());
+                let mut typed: Vec<Value> = raw
+                    .map(|value| typed_value(&value.to_string_lossy(), ty))
+                    .collect();
+                match typed.len() {
+                    0 => continue,
+                    _ if takes_multiple(arg) => Value::Array(typed),
+                    _ => typed.swap_remove(0),
+                }
+            }
+        };
+        values.insert(id.to_string(), value);
+    }
+    values
+}
+
+/// `raw` as a JSON value of type `ty`, or a string if it does not parse as one
+fn typed_value(raw: &str, ty: &str) -> Value {
+    let parsed = match ty {
+        "integer" => raw
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| raw.parse::<u64>().map(Value::from))
+            .ok(),
+        "number" => raw.parse::<f64>().ok().map(Value::from),
+        "boolean" => raw.parse::<bool>().ok().map(Value::from),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| Value::from(raw))
+}
+
+/// Schemas of the visible arguments of a built `command`, and the ids of the
+/// required ones
+///
+/// `attrs` adds the `#[mcp(...)]` settings of the tool the command backs.
+pub(crate) fn command_properties(
+    command: &Command,
+    attrs: Option<&ToolAttributes>,
+) -> (HashMap<String, Value>, Vec<String>) {
+    let mut properties = HashMap::new();
+    let mut required = Vec::new();
+
+    let mut positional_count = 0;
+    for arg in command.get_arguments() {
+        if !is_listed(arg) {
+            continue;
+        }
+
+        let arg_name = arg.get_id().to_string();
+        let is_positional = arg.get_long().is_none() && arg.get_short().is_none();
+
+        let mut schema = arg_schema(arg);
+
+        if let Some(help) = arg.get_help() {
+            schema["description"] = json!(help.to_string());
+        }
+        if let Some(env) = arg.get_env() {
+            let env = env.to_string_lossy();
+            let note = format!("[env: {}]", env);
+            schema["description"] = json!(match schema["description"].as_str() {
+                Some(help) => format!("{} {}", help, note),
+                None => note,
+            });
+            schema[ENV_KEY] = json!(env);
+        }
+        if attrs.is_some_and(|attrs| attrs.secret_refs.contains(&arg_name)) {
+            schema["x-secret-ref"] = json!(true);
+        }
+        if attrs.is_some_and(|attrs| attrs.sticky_args.contains(&arg_name)) {
+            schema["x-sticky"] = json!(true);
+        }
+
+        let schema_override = attrs.and_then(|attrs| attrs.arg_schemas.get(&arg_name));
+        if let Some(Value::Object(fields)) = schema_override {
+            for (key, value) in fields {
+                schema[key] = value.clone();
+            }
+        }
+
+        // Add metadata to indicate positional arguments
+        if is_positional {
+            schema["x-positional"] = json!(true);
+            // clap's indexes start at 1 once built; positions start at 0
+            let position = arg.get_index().map(|index| index - 1).unwrap_or_else(|| {
+                let pos = positional_count;
+                positional_count += 1;
+                pos
+            });
+            schema["x-position"] = json!(position);
+        }
+
+        properties.insert(arg_name.clone(), schema);
+
+        if arg.is_required_set() && !from_env(arg) {
+            required.push(arg_name);
+        }
+    }
+
+    (properties, required)
+}
+
+/// Whether `arg` is listed in its tool's schema
+fn is_listed(arg: &Arg) -> bool {
+    !arg.is_hide_set() && arg.get_id() != "help" && arg.get_id() != "version"
+}
+
+/// Whether the server's own environment supplies `arg`, which clap then
+/// falls back to when a call omits it
+fn from_env(arg: &Arg) -> bool {
+    arg.get_env()
+        .is_some_and(|env| env_is_set(&env.to_string_lossy()))
+}
+
+/// Constraints between the visible arguments of a built `command`, as the
+/// `allOf` and `dependentRequired` keywords of its input schema
+///
+/// Arguments that cannot be given together (`conflicts_with`, `exclusive`,
+/// or two arguments of a group without `multiple`) become
+/// `{"not": {"required": [a, b]}}`. A required group becomes an `anyOf` of
+/// its arguments, or a `oneOf` when only one may be given. Arguments an
+/// argument `requires` are listed under `dependentRequired`; clap only
+/// exposes them through `attrs`, which `#[derive(McpTools)]` fills in.
+pub(crate) fn command_constraints(command: &Command, attrs: Option<&ToolAttributes>) -> JsonObject {
+    let listed: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|arg| is_listed(arg))
+        .collect();
+    let is_arg = |id: &str| listed.iter().any(|arg| arg.get_id() == id);
+
+    let mut conflicts = BTreeSet::new();
+    let mut conflict = |a: &str, b: &str| {
+        if a != b {
+            conflicts.insert((a.min(b).to_string(), a.max(b).to_string()));
+        }
+    };
+    for arg in &listed {
+        let others = if arg.is_exclusive_set() {
+            listed.clone()
+        } else {
+            command.get_arg_conflicts_with(arg)
+        };
+        for other in others {
+            if is_arg(other.get_id().as_str()) {
+                conflict(arg.get_id().as_str(), other.get_id().as_str());
+            }
+        }
+    }
+
+    let mut all_of = Vec::new();
+    for group in command.get_groups() {
+        let args: Vec<&str> = group
+            .get_args()
+            .map(|id| id.as_str())
+            .filter(|id| is_arg(id))
+            .collect();
+        if args.is_empty() {
+            continue;
+        }
+        let multiple = group.clone().is_multiple();
+        let supplied = listed
+            .iter()
+            .any(|arg| args.contains(&arg.get_id().as_str()) && from_env(arg));
+        if group.is_required_set() && !supplied {
+            let choices: Vec<Value> = args.iter().map(|id| json!({ "required": [id] })).collect();
+            if multiple {
+                all_of.push(json!({ "anyOf": choices }));
+            } else {
+                // Also rules out giving several of them
+                all_of.push(json!({ "oneOf": choices }));
+                continue;
+            }
+        }
+        if !multiple {
+            for (i, a) in args.iter().enumerate() {
+                for b in &args[i + 1..] {
+                    conflict(a, b);
+                }
+            }
+        }
+    }
+    all_of.extend(
+        conflicts
+            .into_iter()
+            .map(|(a, b)| json!({ "not": { "required": [a, b] } })),
+    );
+
+    let mut dependent = JsonObject::new();
+    for (id, requires) in attrs.iter().flat_map(|attrs| &attrs.arg_requires) {
+        if !is_arg(id) {
+            continue;
+        }
+        let requires: Vec<&String> = requires
+            .iter()
+            .filter(|required| {
+                listed
+                    .iter()
+                    .any(|arg| arg.get_id() == required.as_str() && !from_env(arg))
+            })
+            .collect();
+        if !requires.is_empty() {
+            dependent.insert(id.clone(), json!(requires));
+        }
+    }
+
+    let mut constraints = JsonObject::new();
+    if !all_of.is_empty() {
+        constraints.insert("allOf".to_string(), Value::Array(all_of));
+    }
+    if !dependent.is_empty() {
+        constraints.insert("dependentRequired".to_string(), Value::Object(dependent));
+    }
+    constraints
+}
+
+/// Schema key naming the environment variable an argument falls back to
+pub const ENV_KEY: &str = "x-env";
+
+fn env_is_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+/// Drop required arguments whose environment variable (`x-env`) is set in
+/// the server's environment, for schemas generated at compile time
+pub(crate) fn apply_env_fallbacks(input_schema: &mut JsonObject) {
+    let from_env: Vec<String> = match input_schema.get("properties") {
+        Some(Value::Object(properties)) => properties
+            .iter()
+            .filter(|(_, schema)| schema[ENV_KEY].as_str().is_some_and(env_is_set))
+            .map(|(id, _)| id.clone())
+            .collect(),
+        _ => return,
+    };
+    if let Some(Value::Array(required)) = input_schema.get_mut("required") {
+        required.retain(|id| {
+            !id.as_str()
+                .is_some_and(|id| from_env.iter().any(|env| env == id))
+        });
+    }
+}
+
+/// JSON type of values parsed into the type identified by `id`
+fn value_type(id: impl PartialEq<TypeId>) -> &'static str {
+    macro_rules! any_of {
+        ($($ty:ty),*) => { false $(|| id == TypeId::of::<$ty>())* };
+    }
+    if any_of!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize) {
+        "integer"
+    } else if any_of!(f32, f64) {
+        "number"
+    } else if any_of!(bool) {
+        "boolean"
+    } else {
+        "string"
+    }
+}
+
+/// One tool as generated at compile time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StaticTool {
+    /// Tool (clap subcommand) name
+    pub name: &'static str,
+    /// Short help of the subcommand
+    pub description: &'static str,
+    /// JSON schema of the tool's arguments
+    pub input_schema: &'static str,
+}
+
+impl StaticTool {
+    /// The tool as listed to clients
+    pub fn to_tool(&self) -> Tool {
+        let schema = serde_json::from_str(self.input_schema)
+            .expect("input schema generated by #[derive(McpSchema)]");
+        Tool {
+            name: self.name.into(),
+            description: Some(self.description.into()),
+            input_schema: Arc::new(object(schema)),
+            annotations: None,
+        }
+    }
+}
+
+/// Implemented by `#[derive(McpSchema)]` for subcommand enums
+pub trait McpSchema {
+    /// Every subcommand's tool, in declaration order
+    const TOOLS: &'static [StaticTool];
+}
+
+/// Implemented by `#[derive(McpSchema)]` for `Args` structs, whose schema
+/// tuple variants wrapping them use
+pub trait McpArgsSchema {
+    /// JSON schema of the struct's arguments
+    const INPUT_SCHEMA: &'static str;
+}
+
+/// JSON schema of `V` from its `schemars::JsonSchema` impl
+///
+/// Subschemas are inlined where possible; recursive types keep their
+/// `definitions`. The result is merged over the clap-derived argument schema.
+#[cfg(feature = "schemars")]
+pub fn schema_for<V: schemars::JsonSchema>() -> serde_json::Value {
+    let generator = schemars::gen::SchemaSettings::draft07()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator();
+    serde_json::to_value(generator.into_root_schema_for::<V>())
+        .expect("schemars schemas serialize to JSON")
+}
+
+/// Lookup of static schemas used by the derive-generated server constructors
+///
+/// Works like [`AttributesProbe`](crate::metadata::AttributesProbe): the
+/// `ViaMcpSchema` impl applies when the enum derives `McpSchema`, otherwise
+/// `ViaRuntimeSchema` leaves schema generation to clap introspection.
+#[doc(hidden)]
+pub struct SchemaProbe<T>(PhantomData<T>);
+
+impl<T> SchemaProbe<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaMcpSchema {
+    fn static_tools(&self) -> Option<&'static [StaticTool]>;
+}
+
+impl<T: McpSchema> ViaMcpSchema for SchemaProbe<T> {
+    fn static_tools(&self) -> Option<&'static [StaticTool]> {
+        Some(T::TOOLS)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaRuntimeSchema {
+    fn static_tools(&self) -> Option<&'static [StaticTool]>;
+}
+
+impl<T> ViaRuntimeSchema for &SchemaProbe<T> {
+    fn static_tools(&self) -> Option<&'static [StaticTool]> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{value_parser, ArgGroup, Command};
+
+    #[test]
+    fn test_arg_schema_types() {
+        let mut cmd = Command::new("deploy")
+            .arg(
+                Arg::new("replicas")
+                    .long("replicas")
+                    .value_parser(value_parser!(u16)),
+            )
+            .arg(
+                Arg::new("ratio")
+                    .long("ratio")
+                    .value_parser(value_parser!(f64)),
+            )
+            .arg(Arg::new("force").long("force").action(ArgAction::SetTrue))
+            .arg(Arg::new("verbose").short('v').action(ArgAction::Count))
+            .arg(
+                Arg::new("region")
+                    .long("region")
+                    .value_parser(["us-east", "eu-west"]),
+            )
+            .arg(Arg::new("tag").long("tag").action(ArgAction::Append))
+            .arg(Arg::new("name").long("name"))
+            .arg(
+                Arg::new("timeout")
+                    .long("timeout")
+                    .value_parser(value_parser!(u32))
+                    .default_value("30"),
+            )
+            .arg(
+                Arg::new("label")
+                    .long("label")
+                    .action(ArgAction::Append)
+                    .default_values(["app", "web"]),
+            );
+        cmd.build();
+        let schema = |id: &str| arg_schema(cmd.get_arguments().find(|a| a.get_id() == id).unwrap());
+
+        assert_eq!(schema("replicas"), json!({ "type": "integer" }));
+        assert_eq!(schema("ratio"), json!({ "type": "number" }));
+        assert_eq!(schema("force"), json!({ "type": "boolean" }));
+        assert_eq!(
+            schema("verbose"),
+            json!({ "type": "integer", "minimum": 0 })
+        );
+        assert_eq!(
+            schema("region"),
+            json!({ "type": "string", "enum": ["us-east", "eu-west"] })
+        );
+        assert_eq!(
+            schema("tag"),
+            json!({ "type": "array", "items": { "type": "string" } })
+        );
+        assert_eq!(schema("name"), json!({ "type": "string" }));
+        assert_eq!(
+            schema("timeout"),
+            json!({ "type": "integer", "default": 30 })
+        );
+        assert_eq!(
+            schema("label"),
+            json!({ "type": "array", "items": { "type": "string" }, "default": ["app", "web"] })
+        );
+    }
+
+    #[test]
+    fn test_parsed_values() {
+        let mut cmd = Command::new("deploy")
+            .arg(
+                Arg::new("replicas")
+                    .long("replicas")
+                    .value_parser(value_parser!(u16)),
+            )
+            .arg(Arg::new("force").long("force").action(ArgAction::SetTrue))
+            .arg(Arg::new("verbose").short('v').action(ArgAction::Count))
+            .arg(Arg::new("tag").long("tag").action(ArgAction::Append))
+            .arg(Arg::new("name").long("name"))
+            .arg(
+                Arg::new("timeout")
+                    .long("timeout")
+                    .value_parser(value_parser!(u32))
+                    .default_value("30"),
+            );
+        cmd.build();
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(["deploy", "--replicas=3", "-vv", "--tag=a", "--tag=b"])
+            .unwrap();
+        assert_eq!(
+            Value::Object(parsed_values(&cmd, &matches)),
+            json!({
+                "replicas": 3,
+                "force": false,
+                "verbose": 2,
+                "tag": ["a", "b"],
+                "timeout": 30,
+            })
+        );
+    }
+
+    #[test]
+    fn test_env_fallbacks() {
+        std::env::set_var("CLAP_MCP_TEST_SCHEMA_TOKEN", "t0k");
+        let mut cmd = Command::new("publish")
+            .arg(
+                Arg::new("token")
+                    .long("token")
+                    .help("Registry token")
+                    .env("CLAP_MCP_TEST_SCHEMA_TOKEN")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("registry")
+                    .long("registry")
+                    .env("CLAP_MCP_TEST_SCHEMA_UNSET")
+                    .required(true),
+            );
+        cmd.build();
+        let (properties, required) = command_properties(&cmd, None);
+        assert_eq!(
+            properties["token"],
+            json!({
+                "type": "string",
+                "description": "Registry token [env: CLAP_MCP_TEST_SCHEMA_TOKEN]",
+                "x-env": "CLAP_MCP_TEST_SCHEMA_TOKEN",
+            })
+        );
+        assert_eq!(
+            properties["registry"]["description"],
+            "[env: CLAP_MCP_TEST_SCHEMA_UNSET]"
+        );
+        assert_eq!(required, ["registry"]);
+
+        let mut input_schema = object(json!({
+            "type": "object",
+            "properties": properties,
+            "required": ["token", "registry"],
+        }));
+        apply_env_fallbacks(&mut input_schema);
+        assert_eq!(input_schema["required"], json!(["registry"]));
+    }
+
+    #[test]
+    fn test_command_constraints() {
+        let flag = |id: &'static str| Arg::new(id).long(id).action(ArgAction::SetTrue);
+        let mut cmd = Command::new("publish")
+            .arg(Arg::new("tag").long("tag"))
+            .arg(Arg::new("branch").long("branch"))
+            .arg(Arg::new("commit").long("commit"))
+            .arg(flag("sign"))
+            .arg(Arg::new("key").long("key"))
+            .arg(flag("draft").conflicts_with("sign"))
+            .arg(flag("dump").exclusive(true).hide(true))
+            .arg(flag("list").exclusive(true))
+            .arg(flag("quiet"))
+            .arg(flag("verbose"))
+            .group(ArgGroup::new("ref").args(["branch", "commit"]))
+            .group(
+                ArgGroup::new("source")
+                    .args(["tag", "branch", "commit"])
+                    .required(true),
+            )
+            .group(
+                ArgGroup::new("output")
+                    .args(["quiet", "verbose"])
+                    .multiple(true),
+            );
+        cmd.build();
+        let attrs = ToolAttributes {
+            arg_requires: [
+                ("sign".to_string(), ["key".to_string()].into()),
+                ("dump".to_string(), ["key".to_string()].into()),
+            ]
+            .into(),
+            ..Default::default()
+        };
+
+        let constraints = command_constraints(&cmd, Some(&attrs));
+        let not_both = |a: &str, b: &str| json!({ "not": { "required": [a, b] } });
+        let mut all_of = vec![json!({ "oneOf": [
+            { "required": ["tag"] },
+            { "required": ["branch"] },
+            { "required": ["commit"] },
+        ] })];
+        // Exclusive arguments conflict with every other listed one, hidden
+        // ones are left out
+        all_of.extend([
+            not_both("branch", "commit"),
+            not_both("branch", "list"),
+            not_both("commit", "list"),
+            not_both("draft", "list"),
+            not_both("draft", "sign"),
+            not_both("key", "list"),
+            not_both("list", "quiet"),
+            not_both("list", "sign"),
+            not_both("list", "tag"),
+            not_both("list", "verbose"),
+        ]);
+        assert_eq!(constraints["allOf"], Value::Array(all_of));
+        assert_eq!(constraints["dependentRequired"], json!({ "sign": ["key"] }));
+
+        let mut cmd = Command::new("plain").arg(Arg::new("name").long("name"));
+        cmd.build();
+        assert!(command_constraints(&cmd, None).is_empty());
+    }
+}
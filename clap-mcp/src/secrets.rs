@@ -0,0 +1,243 @@
+//! Secrets referenced by handle instead of by value
+//!
+//! Credentials registered with `McpServer::with_secret` stay on the server.
+//! Arguments marked `#[mcp(secret_ref)]` take the name of a secret, which is
+//! replaced with its value just before the command runs, so the raw value
+//! never transits the agent's conversation:
+//!
+//! ```ignore
+//! #[derive(Subcommand, Clone, McpTools)]
+//! enum Commands {
+//!     Deploy {
+//!         #[arg(long)]
+//!         #[mcp(secret_ref)]
+//!         token: String, // the agent sends "DEPLOY_TOKEN"
+//!     },
+//! }
+//!
+//! McpServer::<Commands>::new().with_secret("DEPLOY_TOKEN", std::env::var("DEPLOY_TOKEN")?);
+//! ```
+//!
+//! Secret values are also redacted from tool output and error messages, in
+//! case a command echoes them back.
+//!
+//! Arguments the agent passes by value but that are still sensitive, such as
+//! a `--token`, can be marked `#[mcp(secret)]` instead. Their values are
+//! replaced with `[redacted:<argument>]` in every error returned for the
+//! call: clap's "invalid value" messages, schema validation errors and the
+//! handler's own error results.
+
+use crate::messages::Message;
+use rmcp::model::{CallToolResult, JsonObject, RawContent, ResourceContents};
+use rmcp::Error as McpError;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Secrets of one server, keyed by handle name
+#[derive(Clone, Default)]
+pub(crate) struct SecretStore {
+    secrets: BTreeMap<String, String>,
+}
+
+impl fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.secrets.keys()).finish()
+    }
+}
+
+impl SecretStore {
+    pub(crate) fn insert(&mut self, name: String, value: String) {
+        self.secrets.insert(name, value);
+    }
+
+    /// Replace the handle names in the `refs` arguments with secret values
+    pub(crate) fn resolve(
+        &self,
+        arguments: &mut JsonObject,
+        refs: &BTreeSet<String>,
+    ) -> Result<(), Message> {
+        for id in refs {
+            match arguments.get_mut(id) {
+                Some(Value::String(handle)) => *handle = self.lookup(id, handle)?,
+                Some(Value::Array(handles)) => {
+                    for handle in handles {
+                        let Value::String(name) = handle else {
+                            return Err(Message::InvalidSecretRef {
+                                argument: id.clone(),
+                            });
+                        };
+                        *name = self.lookup(id, name)?;
+                    }
+                }
+                Some(_) => {
+                    return Err(Message::InvalidSecretRef {
+                        argument: id.clone(),
+                    })
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, id: &str, handle: &str) -> Result<String, Message> {
+        self.secrets
+            .get(handle)
+            .cloned()
+            .ok_or_else(|| Message::UnknownSecret {
+                handle: handle.to_string(),
+                argument: id.to_string(),
+            })
+    }
+
+    /// Replace every secret value in `text` with `[secret:NAME]`
+    pub(crate) fn redact(&self, text: &str) -> String {
+        let mut secrets: Vec<(&String, &String)> = self
+            .secrets
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        // Longest first, so a secret containing another is redacted whole
+        secrets.sort_by_key(|(_, value)| std::cmp::Reverse(value.len()));
+        let mut out = text.to_string();
+        for (name, value) in secrets {
+            out = out.replace(value.as_str(), &format!("[secret:{}]", name));
+        }
+        out
+    }
+}
+
+/// Values of one call's `#[mcp(secret)]` arguments
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SecretArgs {
+    /// Argument id and value, longest value first
+    values: Vec<(String, String)>,
+}
+
+impl SecretArgs {
+    /// The values of the `ids` arguments, including array elements
+    pub(crate) fn collect(arguments: &JsonObject, ids: &BTreeSet<String>) -> Self {
+        let mut values = Vec::new();
+        for id in ids {
+            let items = match arguments.get(id) {
+                Some(Value::Array(items)) => items.iter().collect(),
+                Some(value) => vec![value],
+                None => Vec::new(),
+            };
+            for item in items {
+                let value = match item {
+                    Value::String(s) => s.clone(),
+                    Value::Number(n) => n.to_string(),
+                    _ => continue,
+                };
+                if !value.is_empty() {
+                    values.push((id.clone(), value));
+                }
+            }
+        }
+        // Longest first, so a value containing another is redacted whole
+        values.sort_by_key(|(_, value)| std::cmp::Reverse(value.len()));
+        Self { values }
+    }
+
+    /// Replace every value in `text` with `[redacted:<argument>]`
+    pub(crate) fn redact(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for (id, value) in &self.values {
+            out = out.replace(value.as_str(), &format!("[redacted:{}]", id));
+        }
+        out
+    }
+
+    pub(crate) fn redact_error(&self, mut error: McpError) -> McpError {
+        if !self.values.is_empty() {
+            error.message = self.redact(&error.message).into();
+        }
+        error
+    }
+
+    /// Redact the text of an error result; successful results pass through
+    pub(crate) fn redact_result(&self, mut result: CallToolResult) -> CallToolResult {
+        if self.values.is_empty() || result.is_error != Some(true) {
+            return result;
+        }
+        for content in &mut result.content {
+            match &mut content.raw {
+                RawContent::Text(text) => text.text = self.redact(&text.text),
+                RawContent::Resource(embedded) => {
+                    if let ResourceContents::TextResourceContents { text, .. } =
+                        &mut embedded.resource
+                    {
+                        *text = self.redact(text);
+                    }
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn store() -> SecretStore {
+        let mut store = SecretStore::default();
+        store.insert("API_KEY".to_string(), "sk-123".to_string());
+        store.insert("LONG_KEY".to_string(), "sk-123456".to_string());
+        store
+    }
+
+    #[test]
+    fn test_resolve_secret_refs() {
+        let refs = BTreeSet::from(["token".to_string()]);
+        let mut arguments = json!({ "token": "API_KEY", "name": "API_KEY" })
+            .as_object()
+            .cloned()
+            .unwrap();
+        store().resolve(&mut arguments, &refs).unwrap();
+        assert_eq!(arguments["token"], "sk-123");
+        assert_eq!(arguments["name"], "API_KEY");
+
+        let mut unknown = json!({ "token": "sk-raw" }).as_object().cloned().unwrap();
+        let err = store().resolve(&mut unknown, &refs).unwrap_err();
+        assert_eq!(err.to_string(), "unknown secret `sk-raw` for `token`");
+    }
+
+    #[test]
+    fn test_redact_and_debug() {
+        assert_eq!(
+            store().redact("key=sk-123456 short=sk-123"),
+            "key=[secret:LONG_KEY] short=[secret:API_KEY]"
+        );
+        assert_eq!(format!("{:?}", store()), r#"{"API_KEY", "LONG_KEY"}"#);
+    }
+
+    #[test]
+    fn test_secret_args() {
+        let ids = BTreeSet::from(["token".to_string(), "pins".to_string()]);
+        let arguments = json!({ "token": "sk-live-1", "pins": [1234, "sk"], "name": "sk-live" })
+            .as_object()
+            .cloned()
+            .unwrap();
+        let secrets = SecretArgs::collect(&arguments, &ids);
+        assert_eq!(
+            secrets.redact("invalid value 'sk-live-1' for '--token', pin 1234"),
+            "invalid value '[redacted:token]' for '--token', pin [redacted:pins]"
+        );
+
+        let error = secrets.redact_error(McpError::invalid_params("bad sk-live-1", None));
+        assert_eq!(error.message, "bad [redacted:token]");
+        let ok = CallToolResult::success(vec![rmcp::model::Content::text("sk-live-1")]);
+        assert_eq!(secrets.redact_result(ok.clone()), ok);
+        let failed = CallToolResult::error(vec![rmcp::model::Content::text("sk-live-1")]);
+        let failed = secrets.redact_result(failed);
+        assert_eq!(
+            failed.content[0].as_text().unwrap().text,
+            "[redacted:token]"
+        );
+    }
+}
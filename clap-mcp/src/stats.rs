@@ -0,0 +1,177 @@
+//! Opt-in anonymous usage statistics
+//!
+//! Counts calls and failures per tool and hands periodic summaries to a
+//! reporter the deployment provides, e.g. to forward to its analytics. Only
+//! tool names and counts are collected, never argument values or output.
+//!
+//! ```ignore
+//! let stats = UsageStats::new(|summary: &UsageSummary| {
+//!     for (tool, counts) in &summary.tools {
+//!         analytics::track(tool, counts.calls, counts.failures);
+//!     }
+//! })
+//! .every(Duration::from_secs(3600));
+//!
+//! McpServer::<Commands>::new().with_usage_stats(stats.clone());
+//! // ... on shutdown
+//! stats.flush();
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Name recorded for calls to tools the server does not have
+pub const UNKNOWN_TOOL: &str = "(unknown)";
+
+/// Call counts of one tool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolCounts {
+    /// Calls received
+    pub calls: u64,
+    /// Calls that returned a tool error or a protocol error
+    pub failures: u64,
+}
+
+impl ToolCounts {
+    /// Fraction of calls that failed, `0.0` when there were none
+    pub fn failure_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.calls as f64
+        }
+    }
+}
+
+/// Counts collected since the previous report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UsageSummary {
+    /// Counts per tool name
+    pub tools: BTreeMap<String, ToolCounts>,
+    /// Time covered by this summary
+    pub period: Duration,
+}
+
+/// Receives usage summaries; closures taking `&UsageSummary` implement it
+pub trait UsageReporter: Send + Sync {
+    fn report(&self, summary: &UsageSummary);
+}
+
+impl<F: Fn(&UsageSummary) + Send + Sync> UsageReporter for F {
+    fn report(&self, summary: &UsageSummary) {
+        self(summary)
+    }
+}
+
+/// Shared usage counters, registered with `McpServer::with_usage_stats`
+///
+/// Clones share the same counters. A summary is reported once the interval
+/// has elapsed when the next call completes, and on [`UsageStats::flush`].
+#[derive(Clone)]
+pub struct UsageStats {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    reporter: Box<dyn UsageReporter>,
+    interval: Mutex<Duration>,
+    state: Mutex<State>,
+}
+
+struct State {
+    tools: BTreeMap<String, ToolCounts>,
+    since: Instant,
+}
+
+impl UsageStats {
+    /// Counters reporting to `reporter`, hourly by default
+    pub fn new(reporter: impl UsageReporter + 'static) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                reporter: Box::new(reporter),
+                interval: Mutex::new(Duration::from_secs(3600)),
+                state: Mutex::new(State {
+                    tools: BTreeMap::new(),
+                    since: Instant::now(),
+                }),
+            }),
+        }
+    }
+
+    /// Report at most once per `interval`
+    pub fn every(self, interval: Duration) -> Self {
+        *self.inner.interval.lock().unwrap() = interval;
+        self
+    }
+
+    /// Count one completed call of `tool`
+    pub(crate) fn record(&self, tool: &str, failed: bool) {
+        let due = {
+            let mut state = self.inner.state.lock().unwrap();
+            let counts = state.tools.entry(tool.to_string()).or_default();
+            counts.calls += 1;
+            counts.failures += u64::from(failed);
+            state.since.elapsed() >= *self.inner.interval.lock().unwrap()
+        };
+        if due {
+            self.flush();
+        }
+    }
+
+    /// Report the counts collected so far, if any, and start a new period
+    pub fn flush(&self) {
+        let summary = {
+            let mut state = self.inner.state.lock().unwrap();
+            let summary = UsageSummary {
+                tools: std::mem::take(&mut state.tools),
+                period: state.since.elapsed(),
+            };
+            state.since = Instant::now();
+            summary
+        };
+        // Report outside the lock so a slow reporter does not block calls
+        if !summary.tools.is_empty() {
+            self.inner.reporter.report(&summary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_flush() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let stats = UsageStats::new(move |summary: &UsageSummary| {
+            sink.lock().unwrap().push(summary.clone());
+        });
+
+        stats.record("add", false);
+        stats.record("add", true);
+        stats.record("hello", false);
+        assert!(reports.lock().unwrap().is_empty());
+
+        stats.flush();
+        stats.flush(); // nothing new to report
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        let add = reports[0].tools["add"];
+        assert_eq!((add.calls, add.failures), (2, 1));
+        assert_eq!(add.failure_rate(), 0.5);
+        assert_eq!(reports[0].tools["hello"].failures, 0);
+    }
+
+    #[test]
+    fn test_reports_when_interval_elapsed() {
+        let reports = Arc::new(Mutex::new(0));
+        let sink = reports.clone();
+        let stats = UsageStats::new(move |_: &UsageSummary| *sink.lock().unwrap() += 1)
+            .every(Duration::ZERO);
+        stats.record("add", false);
+        stats.record("add", false);
+        assert_eq!(*reports.lock().unwrap(), 2);
+    }
+}
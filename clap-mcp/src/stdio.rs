@@ -0,0 +1,75 @@
+//! Stdio framing that works without tokio's `io-std`
+//!
+//! tokio's stdin/stdout hand blocking reads to a thread pool, which is not
+//! available on `wasm32-wasi`. WASM hosts run the server on a single thread
+//! anyway, so [`Blocking`] performs the std reads and writes inline and the
+//! MCP transport is layered on top of it.
+
+use std::io::{Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Async adapter for a blocking std reader or writer
+///
+/// Each poll completes immediately by performing the underlying std I/O call,
+/// so it must only be used where blocking the executor is acceptable.
+pub struct Blocking<T>(pub T);
+
+impl<R: Read + Unpin> AsyncRead for Blocking<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let n = self.0.read(buf.initialize_unfilled())?;
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: Write + Unpin> AsyncWrite for Blocking<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(self.0.write(buf))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(self.0.flush())
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Blocking stdin/stdout pair usable as an MCP transport
+pub fn blocking_stdio() -> (Blocking<std::io::Stdin>, Blocking<std::io::Stdout>) {
+    (Blocking(std::io::stdin()), Blocking(std::io::stdout()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    #[tokio::test]
+    async fn test_blocking_adapter_round_trip() {
+        let input = b"{\"jsonrpc\":\"2.0\"}\nsecond\n".to_vec();
+        let mut lines = BufReader::new(Blocking(std::io::Cursor::new(input))).lines();
+        assert_eq!(
+            lines.next_line().await.unwrap().as_deref(),
+            Some("{\"jsonrpc\":\"2.0\"}")
+        );
+        assert_eq!(lines.next_line().await.unwrap().as_deref(), Some("second"));
+        assert_eq!(lines.next_line().await.unwrap(), None);
+
+        let mut out = Blocking(Vec::new());
+        out.write_all(b"reply\n").await.unwrap();
+        out.flush().await.unwrap();
+        assert_eq!(out.0, b"reply\n");
+    }
+}
@@ -0,0 +1,192 @@
+//! Incremental output of long-running commands
+//!
+//! Commands like `tail -f` or `watch` produce output as they go rather than
+//! all at the end. A [`StreamingHandler`](crate::StreamingHandler) gets a
+//! [`Sink`] to push that output through while it runs:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new().with_streaming_handler(Box::new(|cmd, sink| {
+//!     for line in follow(cmd.path())? {
+//!         if !sink.send(format!("{}\n", line)) {
+//!             break;
+//!         }
+//!     }
+//!     Ok(String::new())
+//! }));
+//! ```
+//!
+//! When the call's request carries `_meta.progressToken`, each chunk is sent
+//! to the client right away as a `notifications/progress`, with the chunk as
+//! its `message` and the number of chunks so far as its `progress`. The
+//! result holds the chunks followed by what the handler returns, so clients
+//! not tracking progress still get the whole output. A call's notifications
+//! are sent before its result.
+//!
+//! [`Sink::send`] returns `false` once the call is over, e.g. cancelled by
+//! the client or timed out, so handlers following output forever know when
+//! to stop.
+
+use rmcp::model::{ProgressNotificationParam, ProgressToken};
+use rmcp::service::{Peer, RoleServer};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+/// A message for the task forwarding a call's chunks
+enum Forward {
+    Chunk(String),
+    /// Answered once the chunks sent before it have been forwarded
+    Flush(oneshot::Sender<()>),
+}
+
+/// Where a streaming handler pushes the output of its call
+#[derive(Clone, Default)]
+pub struct Sink {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Everything sent so far, for the call's result
+    output: Mutex<String>,
+    /// The forwarding task, when the client asked for progress
+    chunks: Option<mpsc::UnboundedSender<Forward>>,
+    closed: AtomicBool,
+}
+
+impl std::fmt::Debug for Sink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sink")
+            .field("forwarding", &self.inner.chunks.is_some())
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+impl Sink {
+    /// A sink sending each chunk to `peer` as progress on `token`, in order
+    pub(crate) fn new(peer: Peer<RoleServer>, token: ProgressToken) -> Self {
+        let (chunks, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut progress = 0;
+            while let Some(forward) = received.recv().await {
+                match forward {
+                    Forward::Chunk(chunk) => {
+                        progress += 1;
+                        let notification = ProgressNotificationParam {
+                            progress_token: token.clone(),
+                            progress,
+                            total: None,
+                            message: Some(chunk),
+                        };
+                        if let Err(e) = peer.notify_progress(notification).await {
+                            tracing::debug!("failed to send progress: {}", e);
+                        }
+                    }
+                    Forward::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Self {
+            inner: Arc::new(Inner {
+                chunks: Some(chunks),
+                ..Inner::default()
+            }),
+        }
+    }
+
+    /// Push a chunk of output
+    ///
+    /// Returns `false`, dropping the chunk, once the call is over.
+    pub fn send(&self, chunk: impl Into<String>) -> bool {
+        if self.is_closed() {
+            return false;
+        }
+        let chunk = chunk.into();
+        if chunk.is_empty() {
+            return true;
+        }
+        self.inner.output.lock().unwrap().push_str(&chunk);
+        if let Some(chunks) = &self.inner.chunks {
+            let _ = chunks.send(Forward::Chunk(chunk));
+        }
+        true
+    }
+
+    /// Whether the call is over, so further chunks are dropped
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed.load(Ordering::Relaxed)
+    }
+
+    /// End the call, refusing further chunks
+    pub(crate) fn close(&self) {
+        self.inner.closed.store(true, Ordering::Relaxed);
+    }
+
+    /// The output sent so far, leaving none behind
+    pub(crate) fn take_output(&self) -> String {
+        std::mem::take(&mut self.inner.output.lock().unwrap())
+    }
+
+    /// Wait until the chunks sent so far have been forwarded
+    pub(crate) async fn flush(&self) {
+        let Some(chunks) = &self.inner.chunks else {
+            return;
+        };
+        let (done, flushed) = oneshot::channel();
+        if chunks.send(Forward::Flush(done)).is_ok() {
+            let _ = flushed.await;
+        }
+    }
+}
+
+thread_local! {
+    static SINK: RefCell<Option<Sink>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with `sink` receiving the output of the call on this thread
+pub(crate) fn scope<R>(sink: Option<Sink>, f: impl FnOnce() -> R) -> R {
+    let previous = SINK.with(|current| current.replace(sink));
+    let result = f();
+    SINK.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// The sink of the call on this thread; outside a call, one only collecting
+/// the output
+pub(crate) fn current() -> Sink {
+    SINK.with(|current| current.borrow().clone().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sink_collects_until_closed() {
+        let sink = Sink::default();
+        assert!(sink.send("one\n"));
+        assert!(sink.send(String::from("two\n")));
+        assert_eq!(sink.take_output(), "one\ntwo\n");
+        assert!(sink.send("three\n"));
+        sink.close();
+        assert!(sink.is_closed());
+        assert!(!sink.send("four\n"));
+        assert_eq!(sink.take_output(), "three\n");
+    }
+
+    #[test]
+    fn test_scope() {
+        let sink = Sink::default();
+        scope(Some(sink.clone()), || {
+            assert!(current().send("inside"));
+        });
+        assert_eq!(sink.take_output(), "inside");
+        // Outside a call the output goes nowhere
+        assert!(current().send("outside"));
+        assert_eq!(sink.take_output(), "");
+    }
+}
@@ -0,0 +1,87 @@
+//! The opt-in `capabilities` tool describing this server's optional features
+//!
+//! Generic agent frameworks cannot know which clap-mcp features a server was
+//! built with. `McpServer::with_support_matrix` registers a `capabilities`
+//! tool returning a [`SupportMatrix`] as JSON, so they can adapt at runtime:
+//!
+//! ```json
+//! {"version":"0.1.0","features":{"batch":false,"bridge":true,"jobs":false,...}}
+//! ```
+
+use rmcp::model::{object, Tool, ToolAnnotations};
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+
+/// Name of the registered tool
+pub const SUPPORT_TOOL: &str = "capabilities";
+
+/// Optional clap-mcp features and whether this server instance enables them
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportedFeatures {
+    /// Long-running calls tracked as background jobs
+    ///
+    /// Always `false` for now: calls run to completion within the request, so
+    /// there is no job store yet, nor results to retain or collect.
+    pub jobs: bool,
+    /// Incremental output while a call runs
+    pub streaming: bool,
+    /// MCP resources besides tools
+    pub resources: bool,
+    /// Several tool invocations in one call
+    pub batch: bool,
+    /// Tools re-exported from remote servers
+    pub bridge: bool,
+    /// Schemas generated at compile time by `#[derive(McpSchema)]`
+    pub static_schemas: bool,
+    /// Per-tool usage statistics
+    pub usage_stats: bool,
+}
+
+/// What the `capabilities` tool returns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SupportMatrix {
+    /// clap-mcp version serving the tools
+    pub version: &'static str,
+    pub features: SupportedFeatures,
+}
+
+impl SupportMatrix {
+    pub(crate) fn new(features: SupportedFeatures) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            features,
+        }
+    }
+}
+
+/// Tool definition listed when the support matrix is enabled
+pub(crate) fn support_tool() -> Tool {
+    Tool {
+        name: SUPPORT_TOOL.into(),
+        description: Some("List the optional clap-mcp features this server supports".into()),
+        input_schema: Arc::new(object(json!({ "type": "object", "properties": {} }))),
+        annotations: Some(ToolAnnotations::new().read_only(true)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_support_matrix_json() {
+        let matrix = SupportMatrix::new(SupportedFeatures {
+            bridge: true,
+            static_schemas: true,
+            ..Default::default()
+        });
+        let json = serde_json::to_value(&matrix).unwrap();
+        assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["features"]["bridge"], true);
+        assert_eq!(json["features"]["staticSchemas"], true);
+        assert_eq!(json["features"]["jobs"], false);
+        assert_eq!(json["features"].as_object().unwrap().len(), 7);
+    }
+}
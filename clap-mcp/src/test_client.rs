@@ -1,100 +1,312 @@
 //! Test utilities for clap-mcp
 
-use rmcp::{model::*, transport::SseClientTransport, RoleClient, ServiceExt};
+use rmcp::model::CallToolRequestParam;
 use serde_json::Value;
+use std::ffi::OsStr;
+use std::ops::Deref;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
 
-/// An MCP test client for testing MCP servers
-pub struct McpTestClient {
-    client: rmcp::service::RunningService<RoleClient, ClientInfo>,
+/// The MCP client used by tests and examples
+///
+/// Kept under its original name; new code should use [`crate::client::McpClient`].
+pub use crate::client::McpClient as McpTestClient;
+
+/// Outcome of [`McpTestClient::stress`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StressReport {
+    /// Calls completed, successful or not
+    pub calls: u64,
+    /// Calls that returned a tool error result
+    pub tool_errors: u64,
+    /// Calls that failed at the protocol or transport level
+    pub errors: u64,
+    /// Median latency
+    pub p50: Duration,
+    /// 90th percentile latency
+    pub p90: Duration,
+    /// 99th percentile latency
+    pub p99: Duration,
+    /// Slowest call
+    pub max: Duration,
+    /// Wall-clock time of the run
+    pub elapsed: Duration,
+}
+
+impl StressReport {
+    /// Completed calls per second
+    pub fn throughput(&self) -> f64 {
+        self.calls as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
 }
 
 impl McpTestClient {
-    /// Connect to an MCP server at the given address
-    pub async fn connect(addr: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let sse_url = format!("http://{}/sse", addr);
-        let transport = SseClientTransport::start(sse_url).await?;
-
-        let client_info = ClientInfo {
-            protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ClientCapabilities::default(),
-            client_info: Implementation {
-                name: "test-client".to_string(),
-                version: "1.0".to_string(),
-            },
-        };
+    /// Call `tool` from `concurrency` concurrent workers for `duration`
+    ///
+    /// `args_gen` receives the call's sequence number and returns its
+    /// arguments, so runs can vary inputs. Calls still in flight when the time
+    /// is up are allowed to finish.
+    ///
+    /// ```ignore
+    /// let report = client
+    ///     .stress("add", |i| Some(json!({ "a": i, "b": 1 })), 16, Duration::from_secs(5))
+    ///     .await;
+    /// assert_eq!(report.errors, 0);
+    /// println!("p99 {:?} at {:.0} calls/s", report.p99, report.throughput());
+    /// ```
+    pub async fn stress<F>(
+        &self,
+        tool: &str,
+        args_gen: F,
+        concurrency: usize,
+        duration: Duration,
+    ) -> StressReport
+    where
+        F: Fn(u64) -> Option<Value> + Send + Sync + 'static,
+    {
+        let args_gen = Arc::new(args_gen);
+        let sequence = Arc::new(AtomicU64::new(0));
+        let start = Instant::now();
+        let deadline = start + duration;
+
+        let mut workers = tokio::task::JoinSet::new();
+        for _ in 0..concurrency.max(1) {
+            let peer = self.peer().clone();
+            let args_gen = args_gen.clone();
+            let sequence = sequence.clone();
+            let tool = tool.to_string();
+            workers.spawn(async move {
+                let mut samples = Vec::new();
+                while Instant::now() < deadline {
+                    let arguments = args_gen(sequence.fetch_add(1, Ordering::Relaxed))
+                        .and_then(|v| v.as_object().cloned());
+                    let call_start = Instant::now();
+                    let result = peer
+                        .call_tool(CallToolRequestParam {
+                            name: tool.clone().into(),
+                            arguments,
+                        })
+                        .await;
+                    let outcome = match result {
+                        Ok(result) if result.is_error.unwrap_or(false) => Outcome::ToolError,
+                        Ok(_) => Outcome::Success,
+                        Err(_) => Outcome::Error,
+                    };
+                    samples.push((call_start.elapsed(), outcome));
+                }
+                samples
+            });
+        }
 
-        let client = client_info.serve(transport).await?;
+        let mut latencies = Vec::new();
+        let mut report = StressReport::default();
+        while let Some(samples) = workers.join_next().await {
+            for (latency, outcome) in samples.unwrap_or_default() {
+                latencies.push(latency);
+                match outcome {
+                    Outcome::Success => {}
+                    Outcome::ToolError => report.tool_errors += 1,
+                    Outcome::Error => report.errors += 1,
+                }
+            }
+        }
+        report.elapsed = start.elapsed();
+        report.calls = latencies.len() as u64;
 
-        Ok(Self { client })
+        latencies.sort();
+        report.p50 = percentile(&latencies, 50.0);
+        report.p90 = percentile(&latencies, 90.0);
+        report.p99 = percentile(&latencies, 99.0);
+        report.max = latencies.last().copied().unwrap_or_default();
+        report
     }
+}
 
-    /// List all available tools
-    pub async fn list_tools(&self) -> Result<Vec<Tool>, Box<dyn std::error::Error>> {
-        let result = self.client.list_tools(None).await?;
-        Ok(result.tools)
+enum Outcome {
+    Success,
+    ToolError,
+    Error,
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
     }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
 
-    /// Call a tool with optional arguments
-    pub async fn call_tool(
-        &self,
-        name: &str,
-        arguments: Option<Value>,
-    ) -> Result<CallToolResult, Box<dyn std::error::Error>> {
-        let args = arguments.and_then(|v| v.as_object().cloned());
-        let result = self
-            .client
-            .call_tool(CallToolRequestParam {
-                name: name.to_string().into(),
-                arguments: args,
-            })
-            .await?;
-        Ok(result)
+/// A server binary spawned over stdio for integration tests
+///
+/// Spawning waits for the MCP initialize handshake and fails unless the
+/// server answers it offering tools. The client's list and call helpers are
+/// available through `Deref`, and [`shutdown`](Self::shutdown) closes stdin
+/// and expects the process to exit on its own:
+///
+/// ```ignore
+/// let server = StdioServerUnderTest::spawn(env!("CARGO_BIN_EXE_mycli"), &["--mcp"]).await?;
+/// assert_eq!(server.list_tools().await?.len(), 4);
+/// let result = server.call(&Commands::Add { a: 1, b: 2 }).await?;
+/// server.shutdown().await?;
+/// ```
+///
+/// A server left running is killed when the value is dropped.
+pub struct StdioServerUnderTest {
+    client: McpTestClient,
+    child: Child,
+    timeout: Duration,
+}
+
+impl StdioServerUnderTest {
+    /// Time allowed for the handshake, and for exiting after shutdown
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Spawn `program` with `args` and complete the handshake
+    pub async fn spawn(
+        program: impl AsRef<OsStr>,
+        args: &[&str],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::spawn_with_timeout(program, args, Self::DEFAULT_TIMEOUT).await
     }
 
-    /// Extract text content from a tool result
-    pub fn extract_text(result: &CallToolResult) -> Option<String> {
-        result.content.first().and_then(|content| {
-            if let RawContent::Text(text) = &content.raw {
-                Some(text.text.clone())
-            } else {
-                None
-            }
+    /// Like [`spawn`](Self::spawn), allowing `timeout` for the handshake
+    /// and for teardown
+    pub async fn spawn_with_timeout(
+        program: impl AsRef<OsStr>,
+        args: &[&str],
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let program = program.as_ref();
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err("server stdio was not captured".into());
+        };
+
+        let name = program.to_string_lossy();
+        let client =
+            match tokio::time::timeout(timeout, McpTestClient::connect_io(stdout, stdin)).await {
+                Ok(client) => client?,
+                Err(_) => {
+                    return Err(format!(
+                        "{} did not complete the handshake within {:?}",
+                        name, timeout
+                    )
+                    .into())
+                }
+            };
+        let offers_tools = client
+            .server_info()
+            .is_some_and(|info| info.capabilities.tools.is_some());
+        if !offers_tools {
+            return Err(format!("{} does not offer tools", name).into());
+        }
+
+        Ok(Self {
+            client,
+            child,
+            timeout,
         })
     }
 
-    /// Shutdown the client
-    pub async fn shutdown(self) -> Result<(), Box<dyn std::error::Error>> {
-        self.client.cancel().await?;
-        Ok(())
+    /// Process ID of the server
+    pub fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Close the connection and wait for the server to exit
+    ///
+    /// A server still running after the timeout is killed and reported as
+    /// an error.
+    pub async fn shutdown(mut self) -> Result<ExitStatus, Box<dyn std::error::Error>> {
+        self.client.shutdown().await?;
+        match tokio::time::timeout(self.timeout, self.child.wait()).await {
+            Ok(status) => Ok(status?),
+            Err(_) => {
+                self.child.kill().await?;
+                Err(format!("server did not exit within {:?} of shutdown", self.timeout).into())
+            }
+        }
     }
 }
 
-#[cfg(test)]
-pub mod test_utils {
-    use std::process::{Child, Command, Stdio};
-    use std::time::Duration;
+impl Deref for StdioServerUnderTest {
+    type Target = McpTestClient;
 
-    /// Start a server process for testing
-    pub fn start_test_server(exe_path: &str, args: &[&str]) -> std::io::Result<Child> {
-        Command::new(exe_path)
-            .args(args)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
+    fn deref(&self) -> &McpTestClient {
+        &self.client
     }
+}
 
-    /// Wait for a server to be ready
-    pub async fn wait_for_server(addr: &str, timeout_secs: u64) -> bool {
-        let start = std::time::Instant::now();
-        let timeout = Duration::from_secs(timeout_secs);
+#[cfg(all(test, unix, feature = "http"))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::{Path, PathBuf};
 
-        while start.elapsed() < timeout {
-            if let Ok(client) = super::McpTestClient::connect(addr).await {
-                let _ = client.shutdown().await;
-                return true;
+    /// The calculator example, built now unless `cargo test` already built it
+    fn calculator() -> PathBuf {
+        let deps = std::env::current_exe().unwrap();
+        let path = deps
+            .parent()
+            .and_then(Path::parent)
+            .unwrap()
+            .join("examples")
+            .join(format!("calculator{}", std::env::consts::EXE_SUFFIX));
+        if !path.exists() {
+            let mut cargo = std::process::Command::new(env!("CARGO"));
+            cargo.current_dir(env!("CARGO_MANIFEST_DIR")).args([
+                "build",
+                "--example",
+                "calculator",
+                "--features",
+                "http",
+            ]);
+            if !cfg!(debug_assertions) {
+                cargo.arg("--release");
             }
-            tokio::time::sleep(Duration::from_millis(100)).await;
+            let status = cargo.status().expect("failed to run cargo");
+            assert!(status.success(), "failed to build the calculator example");
         }
-        false
+        path
+    }
+
+    #[tokio::test]
+    async fn test_stdio_server_under_test() {
+        let calculator = calculator();
+        let server = StdioServerUnderTest::spawn(&calculator, &["--mcp"])
+            .await
+            .expect("Failed to start calculator");
+        assert!(server.id().is_some());
+        let tools = server.list_tools().await.unwrap();
+        assert!(tools.iter().any(|tool| tool.name == "add"));
+        let result = server
+            .call_tool("add", Some(json!({ "a": 2, "b": 3 })))
+            .await
+            .unwrap();
+        assert_eq!(McpTestClient::extract_text(&result).unwrap(), "2 + 3 = 5");
+        let status = server.shutdown().await.unwrap();
+        assert!(status.success());
+
+        // Programs that never answer the handshake time out
+        let err =
+            StdioServerUnderTest::spawn_with_timeout("sleep", &["10"], Duration::from_millis(200))
+                .await
+                .err()
+                .unwrap();
+        assert!(
+            err.to_string().contains("did not complete the handshake"),
+            "{}",
+            err
+        );
     }
 }
@@ -0,0 +1,272 @@
+//! Tools registered at runtime
+//!
+//! The subcommand enum fixes a server's tools at compile time. Tools that
+//! only exist once the server runs, e.g. from plugins loaded after startup,
+//! go in its [`ToolRegistry`] instead. Each takes its arguments as JSON:
+//!
+//! ```ignore
+//! let server = McpServer::<Commands>::new().with_handler(Box::new(execute));
+//! let registry = server.tool_registry();
+//! tokio::spawn(server.serve_stdio());
+//!
+//! // ... once the plugin is loaded
+//! registry.register(plugin.tool(), move |arguments| plugin.call(arguments));
+//! // ... and when it is unloaded
+//! registry.unregister(&plugin.tool().name);
+//! ```
+//!
+//! Every change sends `notifications/tools/list_changed` to the connected
+//! clients, so they list the tools again. Arguments are validated against
+//! the tool's input schema before its handler runs, and calls go through the
+//! same authorization, quotas and metering as the subcommands. A subcommand
+//! of the same name takes precedence over a registered tool.
+//!
+//! [`ToolRegistry::register_subcommands`] registers a tool for every
+//! subcommand of another enum, so a CLI composed of several (e.g.
+//! `AdminCommands` and `UserCommands`) serves them all, each with its own
+//! handler. Calls are parsed by clap into the enum as usual, and its
+//! `#[mcp(...)]` attributes apply: skipped subcommands are left out, secret
+//! arguments are redacted from errors, and annotation hints, argument schemas
+//! and renames are listed.
+
+use crate::output::{ToolError, ToolOutput};
+use crate::{McpTools, ToolAttributes};
+use clap::Subcommand;
+use rmcp::model::{JsonObject, Tool};
+use rmcp::service::{Peer, RoleServer};
+use serde_json::json;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Handler of a tool registered at runtime, taking the call's arguments
+pub type RuntimeToolHandler =
+    dyn Fn(JsonObject) -> Result<ToolOutput, ToolError> + Send + Sync + 'static;
+
+/// A tool registered at runtime
+#[derive(Clone)]
+pub(crate) struct RuntimeTool {
+    pub tool: Tool,
+    pub handler: Arc<RuntimeToolHandler>,
+    /// Arguments whose values are redacted from error results, by every
+    /// name a call may give them
+    pub secret_args: BTreeSet<String>,
+}
+
+/// Tools added and removed while a server runs; clones share the registry
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Arc<RwLock<Vec<RuntimeTool>>>,
+    /// Sessions to notify when the tools change
+    peers: Arc<Mutex<Vec<Peer<RoleServer>>>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `tool`, replacing a registered tool of the same name
+    pub fn register(
+        &self,
+        tool: Tool,
+        handler: impl Fn(JsonObject) -> Result<ToolOutput, ToolError> + Send + Sync + 'static,
+    ) {
+        self.insert(tool, Arc::new(handler), BTreeSet::new());
+        self.notify();
+    }
+
+    /// Add a tool for every subcommand of `U` not marked `#[mcp(skip)]`,
+    /// named `prefix` followed by the subcommand's name, whose calls clap
+    /// parses for `handler`
+    pub fn register_subcommands<U: Subcommand + McpTools + 'static>(
+        &self,
+        prefix: &str,
+        handler: impl Fn(U) -> Result<ToolOutput, ToolError> + Send + Sync + 'static,
+    ) {
+        let handler = Arc::new(handler);
+        let mut attributes: HashMap<String, ToolAttributes> = U::tool_attributes()
+            .into_iter()
+            .map(|attrs| (attrs.name.clone(), attrs))
+            .collect();
+        let root = U::augment_subcommands(clap::Command::new("mcp"));
+        for subcommand in root.get_subcommands() {
+            let attrs = attributes.remove(subcommand.get_name());
+            if attrs.as_ref().is_some_and(|attrs| attrs.skip) {
+                continue;
+            }
+            let mut subcommand = subcommand.clone();
+            subcommand.build();
+            let (properties, required) =
+                crate::schema::command_properties(&subcommand, attrs.as_ref());
+            let mut input_schema = rmcp::model::object(json!({
+                "type": "object",
+                "properties": properties,
+                "required": required
+            }));
+            input_schema.extend(crate::schema::command_constraints(
+                &subcommand,
+                attrs.as_ref(),
+            ));
+            if let Some(attrs) = &attrs {
+                attrs.rename_properties(&mut input_schema);
+            }
+            let mut tool = Tool::new(
+                format!("{}{}", prefix, subcommand.get_name()),
+                subcommand
+                    .get_about()
+                    .map(|about| about.to_string())
+                    .unwrap_or_default(),
+                Arc::new(input_schema),
+            );
+            tool.annotations = attrs.as_ref().and_then(ToolAttributes::annotations);
+            let secret_args = attrs.as_ref().map(secret_arg_names).unwrap_or_default();
+
+            let handler = handler.clone();
+            let root = clap::Command::new("mcp").subcommand(subcommand.clone());
+            let call = move |mut arguments| {
+                if let Some(attrs) = &attrs {
+                    attrs.resolve_arg_names(&mut arguments);
+                }
+                let mut argv = vec!["mcp".to_string(), subcommand.get_name().to_string()];
+                argv.extend(crate::argv::build_argv(Some(&subcommand), arguments, None));
+                let matches = root
+                    .clone()
+                    .try_get_matches_from(argv)
+                    .map_err(|e| ToolError::new(e.to_string()))?;
+                let command =
+                    U::from_arg_matches(&matches).map_err(|e| ToolError::new(e.to_string()))?;
+                handler(command)
+            };
+            self.insert(tool, Arc::new(call), secret_args);
+        }
+        self.notify();
+    }
+
+    fn insert(&self, tool: Tool, handler: Arc<RuntimeToolHandler>, secret_args: BTreeSet<String>) {
+        let mut tools = self.tools.write().unwrap();
+        tools.retain(|registered| registered.tool.name != tool.name);
+        tools.push(RuntimeTool {
+            tool,
+            handler,
+            secret_args,
+        });
+    }
+
+    /// Remove the tool called `name`, returning whether it was registered
+    pub fn unregister(&self, name: &str) -> bool {
+        let removed = {
+            let mut tools = self.tools.write().unwrap();
+            let before = tools.len();
+            tools.retain(|registered| registered.tool.name != name);
+            tools.len() != before
+        };
+        if removed {
+            self.notify();
+        }
+        removed
+    }
+
+    /// Whether a tool called `name` is registered
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The registered tools, in registration order
+    pub fn list(&self) -> Vec<Tool> {
+        let tools = self.tools.read().unwrap();
+        tools
+            .iter()
+            .map(|registered| registered.tool.clone())
+            .collect()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<RuntimeTool> {
+        let tools = self.tools.read().unwrap();
+        tools
+            .iter()
+            .find(|registered| registered.tool.name == name)
+            .cloned()
+    }
+
+    /// Notify the session served through `peer` of future changes
+    pub(crate) fn subscribe(&self, peer: Peer<RoleServer>) {
+        let mut peers = self.peers.lock().unwrap();
+        peers.retain(|peer| !peer.is_transport_closed());
+        peers.push(peer);
+    }
+
+    /// Send `notifications/tools/list_changed` to every open session
+    ///
+    /// Needs a Tokio runtime to send from; without one (or without sessions)
+    /// clients learn of the change the next time they list the tools.
+    fn notify(&self) {
+        let peers: Vec<_> = {
+            let mut peers = self.peers.lock().unwrap();
+            peers.retain(|peer| !peer.is_transport_closed());
+            peers.clone()
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        for peer in peers {
+            runtime.spawn(async move {
+                if let Err(e) = peer.notify_tool_list_changed().await {
+                    tracing::debug!("failed to notify tool list change: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Every name a call may give the tool's secret arguments: their ids,
+/// listed names and aliases
+fn secret_arg_names(attrs: &ToolAttributes) -> BTreeSet<String> {
+    let mut names = attrs.secret_args.clone();
+    for id in &attrs.secret_args {
+        names.insert(attrs.arg_name(id).to_string());
+    }
+    for (alias, id) in &attrs.arg_aliases {
+        if attrs.secret_args.contains(id) {
+            names.insert(alias.clone());
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str) -> Tool {
+        let schema = json!({ "type": "object", "properties": {} });
+        Tool::new(
+            name.to_string(),
+            "",
+            Arc::new(schema.as_object().unwrap().clone()),
+        )
+    }
+
+    #[test]
+    fn test_register_and_unregister() {
+        let registry = ToolRegistry::new();
+        registry.register(tool("greet"), |_| Ok(ToolOutput::Text("hi".into())));
+        registry.clone().register(tool("echo"), |arguments| {
+            Ok(ToolOutput::Json(arguments.into()))
+        });
+        let names: Vec<_> = registry.list().into_iter().map(|tool| tool.name).collect();
+        assert_eq!(names, ["greet", "echo"]);
+
+        // Registering a name again replaces the tool
+        registry.register(tool("greet"), |_| Ok(ToolOutput::Text("hello".into())));
+        assert_eq!(registry.list().len(), 2);
+        let greet = registry.get("greet").unwrap();
+        assert!(
+            matches!((greet.handler)(JsonObject::new()), Ok(ToolOutput::Text(text)) if text == "hello")
+        );
+
+        assert!(registry.unregister("greet"));
+        assert!(!registry.unregister("greet"));
+        assert!(!registry.contains("greet") && registry.contains("echo"));
+    }
+}
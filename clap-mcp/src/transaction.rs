@@ -0,0 +1,247 @@
+//! Compensation hooks for multi-step tool workflows
+//!
+//! A [`Transaction`] runs a sequence of steps in order. Each step may register a
+//! compensation action; when a later step fails, the compensations of the steps
+//! that already completed are run in reverse order so that half-finished work
+//! (e.g. a resource that was created but never configured) gets rolled back.
+
+use serde::Serialize;
+use serde_json::Value;
+
+type StepFn = Box<dyn FnOnce() -> Result<String, String> + Send>;
+
+struct Step {
+    name: String,
+    action: StepFn,
+    compensate: Option<StepFn>,
+}
+
+/// A sequence of steps with optional compensation actions
+#[derive(Default)]
+pub struct Transaction {
+    steps: Vec<Step>,
+}
+
+/// Final status of a single step in a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    /// The step ran successfully and was kept
+    Completed,
+    /// The step itself failed
+    Failed,
+    /// The step succeeded but was rolled back by its compensation action
+    Compensated,
+    /// The step succeeded but its compensation action failed
+    CompensationFailed,
+    /// The step succeeded but had no compensation action to roll it back
+    NotCompensated,
+    /// The step never ran because an earlier step failed
+    Skipped,
+}
+
+/// Report for a single step in a transaction
+#[derive(Debug, Clone, Serialize)]
+pub struct StepReport {
+    pub name: String,
+    pub status: StepStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Aggregate outcome of running a [`Transaction`]
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionOutcome {
+    /// Whether every step completed without triggering a rollback
+    pub committed: bool,
+    pub steps: Vec<StepReport>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a step that has nothing to roll back
+    pub fn step(
+        mut self,
+        name: impl Into<String>,
+        action: impl FnOnce() -> Result<String, String> + Send + 'static,
+    ) -> Self {
+        self.steps.push(Step {
+            name: name.into(),
+            action: Box::new(action),
+            compensate: None,
+        });
+        self
+    }
+
+    /// Add a step together with the action that undoes it
+    pub fn step_with_compensation(
+        mut self,
+        name: impl Into<String>,
+        action: impl FnOnce() -> Result<String, String> + Send + 'static,
+        compensate: impl FnOnce() -> Result<String, String> + Send + 'static,
+    ) -> Self {
+        self.steps.push(Step {
+            name: name.into(),
+            action: Box::new(action),
+            compensate: Some(Box::new(compensate)),
+        });
+        self
+    }
+
+    /// Run all steps, compensating completed steps in reverse order on failure
+    pub fn run(self) -> TransactionOutcome {
+        let mut reports = Vec::with_capacity(self.steps.len());
+        let mut completed: Vec<(usize, Option<StepFn>)> = Vec::new();
+        let mut failed = false;
+
+        for step in self.steps {
+            if failed {
+                reports.push(StepReport {
+                    name: step.name,
+                    status: StepStatus::Skipped,
+                    output: None,
+                    error: None,
+                });
+                continue;
+            }
+
+            match (step.action)() {
+                Ok(output) => {
+                    completed.push((reports.len(), step.compensate));
+                    reports.push(StepReport {
+                        name: step.name,
+                        status: StepStatus::Completed,
+                        output: Some(output),
+                        error: None,
+                    });
+                }
+                Err(e) => {
+                    failed = true;
+                    reports.push(StepReport {
+                        name: step.name,
+                        status: StepStatus::Failed,
+                        output: None,
+                        error: Some(e),
+                    });
+                }
+            }
+        }
+
+        if failed {
+            for (index, compensate) in completed.into_iter().rev() {
+                let report = &mut reports[index];
+                match compensate {
+                    Some(compensate) => match compensate() {
+                        Ok(_) => report.status = StepStatus::Compensated,
+                        Err(e) => {
+                            report.status = StepStatus::CompensationFailed;
+                            report.error = Some(e);
+                        }
+                    },
+                    None => report.status = StepStatus::NotCompensated,
+                }
+            }
+        }
+
+        TransactionOutcome {
+            committed: !failed,
+            steps: reports,
+        }
+    }
+}
+
+impl TransactionOutcome {
+    /// Structured representation of the outcome
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    /// Convert into a handler result carrying the outcome as JSON
+    ///
+    /// Rolled back transactions are reported as errors so clients see the tool
+    /// call as failed while still receiving the per-step breakdown.
+    pub fn into_result(self) -> Result<String, String> {
+        let committed = self.committed;
+        let json = serde_json::to_string_pretty(&self).map_err(|e| e.to_string())?;
+        if committed {
+            Ok(json)
+        } else {
+            Err(json)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_all_steps_commit() {
+        let outcome = Transaction::new()
+            .step("create", || Ok("created".to_string()))
+            .step_with_compensation(
+                "configure",
+                || Ok("configured".to_string()),
+                || Ok("unconfigured".to_string()),
+            )
+            .run();
+
+        assert!(outcome.committed);
+        assert!(outcome
+            .steps
+            .iter()
+            .all(|s| s.status == StepStatus::Completed));
+    }
+
+    #[test]
+    fn test_failure_compensates_in_reverse_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (l1, l2) = (log.clone(), log.clone());
+
+        let outcome = Transaction::new()
+            .step_with_compensation(
+                "create-bucket",
+                || Ok("bucket".to_string()),
+                move || {
+                    l1.lock().unwrap().push("delete-bucket");
+                    Ok(String::new())
+                },
+            )
+            .step_with_compensation(
+                "create-user",
+                || Ok("user".to_string()),
+                move || {
+                    l2.lock().unwrap().push("delete-user");
+                    Ok(String::new())
+                },
+            )
+            .step("grant", || Err("permission denied".to_string()))
+            .step("notify", || Ok("sent".to_string()))
+            .run();
+
+        assert!(!outcome.committed);
+        assert_eq!(*log.lock().unwrap(), vec!["delete-user", "delete-bucket"]);
+
+        let statuses: Vec<_> = outcome.steps.iter().map(|s| s.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                StepStatus::Compensated,
+                StepStatus::Compensated,
+                StepStatus::Failed,
+                StepStatus::Skipped,
+            ]
+        );
+
+        let json = outcome.to_json();
+        assert_eq!(json["committed"], false);
+        assert_eq!(json["steps"][2]["error"], "permission denied");
+        assert!(outcome.into_result().is_err());
+    }
+}
@@ -0,0 +1,427 @@
+//! Transports an MCP server can be served over
+//!
+//! [`McpServer::serve`](crate::McpServer::serve) accepts any
+//! [`McpTransportProvider`]. The built-in [`StdioTransport`] and
+//! [`SseTransport`] implement it, as does the [`McpTransport`] enum choosing
+//! between them at runtime. Other crates can add their own (QUIC, message
+//! queues, embedded brokers) by serving the handler over any rmcp transport:
+//!
+//! ```ignore
+//! struct UnixSocket(PathBuf);
+//!
+//! impl McpTransportProvider for UnixSocket {
+//!     async fn serve<H: ServerHandler + Clone>(self, handler: H) -> Result<(), Box<dyn Error>> {
+//!         let listener = tokio::net::UnixListener::bind(&self.0)?;
+//!         loop {
+//!             let (stream, _) = listener.accept().await?;
+//!             let handler = handler.clone();
+//!             tokio::spawn(async move {
+//!                 if let Ok(service) = rmcp::serve_server(handler, stream).await {
+//!                     let _ = service.waiting().await;
+//!                 }
+//!             });
+//!         }
+//!     }
+//! }
+//!
+//! McpServer::<Commands>::new().serve(UnixSocket("/run/cli.sock".into())).await?;
+//! ```
+//!
+//! Serving blocks until the transport shuts down; HTTP until Ctrl-C. To embed
+//! the server in a larger service, spawn it instead and stop it through the
+//! returned [`ServerHandle`]:
+//!
+//! ```ignore
+//! let server = McpServer::<Commands>::new().spawn_http(addr).await?;
+//! println!("MCP on {}", server.local_addr().unwrap());
+//! // ... on shutdown
+//! server.cancel();
+//! server.join().await?;
+//! ```
+
+use crate::auth::AuthProvider;
+use rmcp::handler::server::ServerHandler;
+use std::error::Error;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// A server running in the background
+#[derive(Debug)]
+pub struct ServerHandle {
+    ct: CancellationToken,
+    local_addr: Option<SocketAddr>,
+    task: JoinHandle<Result<(), Box<dyn Error + Send + Sync>>>,
+}
+
+impl ServerHandle {
+    /// Stop accepting sessions and end the open ones
+    ///
+    /// An HTTP server gives requests in flight a second to finish; SSE
+    /// clients still connected are not told, but their further requests fail.
+    pub fn cancel(&self) {
+        self.ct.cancel();
+    }
+
+    /// Address an HTTP server listens on, including the port picked when
+    /// binding port 0; `None` for stdio
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Wait for the server to stop, after [`cancel`](Self::cancel) or, for
+    /// stdio, once the client disconnects
+    pub async fn join(self) -> Result<(), Box<dyn Error>> {
+        match self.task.await {
+            Ok(result) => result.map_err(|e| -> Box<dyn Error> { e }),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Serves an MCP handler until the transport shuts down
+pub trait McpTransportProvider {
+    /// Serve `handler`; clone it for every session the transport accepts
+    fn serve<H: ServerHandler + Clone>(
+        self,
+        handler: H,
+    ) -> impl Future<Output = Result<(), Box<dyn Error>>>;
+
+    /// Serve `handler` to clients `auth` accepts
+    ///
+    /// Used when the server has authentication configured. Transports that
+    /// cannot check credentials keep the default, which refuses to serve.
+    fn serve_with_auth<H: ServerHandler + Clone>(
+        self,
+        handler: H,
+        auth: Arc<dyn AuthProvider>,
+    ) -> impl Future<Output = Result<(), Box<dyn Error>>>
+    where
+        Self: Sized,
+    {
+        let _ = (handler, auth);
+        std::future::ready(Err("this transport does not support authentication".into()))
+    }
+}
+
+/// Standard I/O (stdin/stdout), serving a single session
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdioTransport;
+
+#[cfg(not(target_family = "wasm"))]
+impl StdioTransport {
+    /// Serve `handler` in the background until the client disconnects or the
+    /// returned handle cancels it
+    pub async fn spawn<H: ServerHandler>(self, handler: H) -> Result<ServerHandle, Box<dyn Error>> {
+        let ct = CancellationToken::new();
+        let service_ct = ct.clone();
+        let task = tokio::spawn(async move {
+            let transport = rmcp::transport::stdio();
+            match rmcp::service::serve_server_with_ct(handler, transport, service_ct).await {
+                Ok(service) => {
+                    service.waiting().await?;
+                    Ok(())
+                }
+                Err(rmcp::service::ServerInitializeError::Cancelled) => Ok(()),
+                Err(e) => Err(e.into()),
+            }
+        });
+        Ok(ServerHandle {
+            ct,
+            local_addr: None,
+            task,
+        })
+    }
+}
+
+impl McpTransportProvider for StdioTransport {
+    async fn serve<H: ServerHandler + Clone>(self, handler: H) -> Result<(), Box<dyn Error>> {
+        #[cfg(not(target_family = "wasm"))]
+        let transport = rmcp::transport::stdio();
+        #[cfg(target_family = "wasm")]
+        let transport = crate::stdio::blocking_stdio();
+
+        // Keep serving until the client disconnects
+        rmcp::serve_server(handler, transport)
+            .await?
+            .waiting()
+            .await?;
+        Ok(())
+    }
+
+    /// Stdio is private to the parent process, so there is nothing to check
+    async fn serve_with_auth<H: ServerHandler + Clone>(
+        self,
+        handler: H,
+        _auth: Arc<dyn AuthProvider>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.serve(handler).await
+    }
+}
+
+/// Stdio framing over any byte stream, serving a single session
+///
+/// For channels other than the process's own stdin/stdout: an SSH exec
+/// channel accepted by an embedded SSH server, a pipe, or a socket a
+/// supervisor hands over. Like stdio, the channel is trusted to reach only
+/// the intended client, so server authentication is not checked.
+#[derive(Debug)]
+pub struct IoTransport<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R, W> IoTransport<R, W>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    pub fn new(reader: R, writer: W) -> Self {
+        Self { reader, writer }
+    }
+}
+
+impl<R, W> McpTransportProvider for IoTransport<R, W>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    async fn serve<H: ServerHandler + Clone>(self, handler: H) -> Result<(), Box<dyn Error>> {
+        rmcp::serve_server(handler, (self.reader, self.writer))
+            .await?
+            .waiting()
+            .await?;
+        Ok(())
+    }
+
+    async fn serve_with_auth<H: ServerHandler + Clone>(
+        self,
+        handler: H,
+        _auth: Arc<dyn AuthProvider>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.serve(handler).await
+    }
+}
+
+/// HTTP Server-Sent Events, serving until Ctrl-C
+///
+/// Clients connect to `/sse` and post messages to `/message`. Needs the
+/// `http` feature.
+#[derive(Clone)]
+pub struct SseTransport {
+    pub addr: SocketAddr,
+    auth: Option<Arc<dyn AuthProvider>>,
+}
+
+impl SseTransport {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr, auth: None }
+    }
+
+    /// Answer requests without a bearer token `auth` accepts with 401
+    pub fn with_auth(mut self, auth: impl AuthProvider + 'static) -> Self {
+        self.auth = Some(Arc::new(auth));
+        self
+    }
+}
+
+/// How long a cancelled HTTP server waits for requests in flight
+#[cfg(feature = "http")]
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[cfg(feature = "http")]
+impl SseTransport {
+    /// Serve `handler` in the background until the returned handle cancels it
+    pub async fn spawn<H: ServerHandler + Clone>(
+        self,
+        handler: H,
+    ) -> Result<ServerHandle, Box<dyn Error>> {
+        use rmcp::transport::sse_server::{SseServer, SseServerConfig};
+
+        let config = SseServerConfig {
+            bind: self.addr,
+            sse_path: "/sse".to_string(),
+            post_path: "/message".to_string(),
+            ct: CancellationToken::new(),
+            sse_keep_alive: None,
+        };
+
+        let (sse_server, router) = SseServer::new(config);
+        let router = match self.auth {
+            Some(auth) => crate::auth::require_auth(router, auth),
+            None => router,
+        };
+        #[cfg(feature = "compression")]
+        let router = crate::compression::compress_responses(router);
+
+        let listener = tokio::net::TcpListener::bind(sse_server.config.bind).await?;
+        let local_addr = listener.local_addr()?;
+        let shutdown = sse_server.config.ct.child_token();
+        let stopped = shutdown.clone();
+        let server =
+            axum::serve(listener, router.into_make_service()).with_graceful_shutdown(async move {
+                shutdown.cancelled().await;
+            });
+        let task = tokio::spawn(async move {
+            // Open SSE streams never end on their own, so requests in flight
+            // get a moment to finish before the server stops waiting for them
+            let grace = async {
+                stopped.cancelled().await;
+                tokio::time::sleep(SHUTDOWN_GRACE).await;
+            };
+            tokio::select! {
+                result = server => result.map_err(Into::into),
+                _ = grace => Ok(()),
+            }
+        });
+
+        let ct = sse_server.with_service(move || handler.clone());
+        Ok(ServerHandle {
+            ct,
+            local_addr: Some(local_addr),
+            task,
+        })
+    }
+}
+
+impl McpTransportProvider for SseTransport {
+    #[cfg(not(feature = "http"))]
+    async fn serve<H: ServerHandler + Clone>(self, _handler: H) -> Result<(), Box<dyn Error>> {
+        Err(format!(
+            "cannot serve on {}: clap-mcp was built without the `http` feature",
+            self.addr
+        )
+        .into())
+    }
+
+    #[cfg(feature = "http")]
+    async fn serve<H: ServerHandler + Clone>(self, handler: H) -> Result<(), Box<dyn Error>> {
+        let server = self.spawn(handler).await?;
+        let addr = server.local_addr().expect("HTTP servers have an address");
+        println!("MCP server listening on http://{}", addr);
+        println!("SSE endpoint: http://{}/sse", addr);
+        println!("Message endpoint: http://{}/message", addr);
+
+        tokio::signal::ctrl_c().await?;
+        println!("\nShutting down MCP server...");
+        server.cancel();
+        server.join().await
+    }
+
+    async fn serve_with_auth<H: ServerHandler + Clone>(
+        mut self,
+        handler: H,
+        auth: Arc<dyn AuthProvider>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.auth = Some(auth);
+        self.serve(handler).await
+    }
+}
+
+/// Configuration for MCP server transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McpTransport {
+    /// Standard I/O (stdin/stdout)
+    Stdio,
+    /// HTTP Server-Sent Events (SSE) on specified address
+    Http(SocketAddr),
+}
+
+impl McpTransportProvider for McpTransport {
+    async fn serve<H: ServerHandler + Clone>(self, handler: H) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::Stdio => StdioTransport.serve(handler).await,
+            Self::Http(addr) => SseTransport::new(addr).serve(handler).await,
+        }
+    }
+
+    async fn serve_with_auth<H: ServerHandler + Clone>(
+        self,
+        handler: H,
+        auth: Arc<dyn AuthProvider>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::Stdio => StdioTransport.serve_with_auth(handler, auth).await,
+            Self::Http(addr) => SseTransport::new(addr).serve_with_auth(handler, auth).await,
+        }
+    }
+}
+
+/// A CLI option choosing the transport, named by `#[mcp(transport_flag = "...")]`
+///
+/// A port serves HTTP on localhost, and an address (`SocketAddr` or a
+/// `host:port` string) serves HTTP on it. Leaving an `Option` unset serves
+/// stdio.
+pub trait TransportSetting {
+    fn mcp_transport(&self) -> Result<McpTransport, String>;
+}
+
+impl TransportSetting for u16 {
+    fn mcp_transport(&self) -> Result<McpTransport, String> {
+        Ok(McpTransport::Http(SocketAddr::from((
+            [127, 0, 0, 1],
+            *self,
+        ))))
+    }
+}
+
+impl TransportSetting for SocketAddr {
+    fn mcp_transport(&self) -> Result<McpTransport, String> {
+        Ok(McpTransport::Http(*self))
+    }
+}
+
+impl TransportSetting for String {
+    fn mcp_transport(&self) -> Result<McpTransport, String> {
+        if let Ok(port) = self.parse::<u16>() {
+            return port.mcp_transport();
+        }
+        std::net::ToSocketAddrs::to_socket_addrs(self.as_str())
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .map(McpTransport::Http)
+            .ok_or_else(|| {
+                format!(
+                    "invalid MCP address `{}`: expected a port or host:port",
+                    self
+                )
+            })
+    }
+}
+
+impl<T: TransportSetting> TransportSetting for Option<T> {
+    fn mcp_transport(&self) -> Result<McpTransport, String> {
+        match self {
+            Some(setting) => setting.mcp_transport(),
+            None => Ok(McpTransport::Stdio),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_settings() {
+        let local = |port| McpTransport::Http(SocketAddr::from(([127, 0, 0, 1], port)));
+        assert_eq!(None::<u16>.mcp_transport(), Ok(McpTransport::Stdio));
+        assert_eq!(Some(8080u16).mcp_transport(), Ok(local(8080)));
+        assert_eq!("9000".to_string().mcp_transport(), Ok(local(9000)));
+
+        let addr: SocketAddr = "0.0.0.0:8080".parse().unwrap();
+        assert_eq!(Some(addr).mcp_transport(), Ok(McpTransport::Http(addr)));
+        assert_eq!(
+            Some("0.0.0.0:8080".to_string()).mcp_transport(),
+            Ok(McpTransport::Http(addr))
+        );
+        assert!("not an address"
+            .to_string()
+            .mcp_transport()
+            .unwrap_err()
+            .contains("expected a port or host:port"));
+    }
+}
@@ -0,0 +1,339 @@
+//! Validation of tool arguments against their input schema
+//!
+//! Calls are checked against the schema listed for the tool (types, required
+//! arguments, enums, and any constraints added with `#[mcp(schema = ...)]`)
+//! before the arguments become a command line. Errors then name the argument
+//! at fault instead of surfacing as clap's messages about flags the client
+//! never saw.
+//!
+//! Each argument is validated against its own property schema, so `$ref`s
+//! into the `definitions` of a `#[mcp(json_schema)]` schema resolve. Arguments
+//! with a custom [`ArgEncoder`](crate::argv::ArgEncoder) are skipped, since
+//! the encoder defines the values it accepts, and so are arguments the schema
+//! does not list, which clap still rejects.
+//!
+//! Constraints between arguments listed under the schema's `allOf` and
+//! `dependentRequired` (see `schema::command_constraints`) are checked too,
+//! so calls giving conflicting arguments, none of a required group, or an
+//! argument without one it requires are told which arguments are at fault.
+//!
+//! `McpServer::with_validate_tool` also registers a `validate_call` tool that
+//! runs a call through this check and clap without executing it, so agents
+//! can pre-check risky invocations:
+//!
+//! ```json
+//! {"name": "validate_call", "arguments": {"tool": "deploy", "arguments": {"replicas": "three"}}}
+//! ```
+//!
+//! It answers `{"valid": true, "tool": "deploy"}` with the resolved tool name,
+//! or `{"valid": false, "error": "..."}` with the error the call would fail
+//! with. Only local tools can be validated, not bridged ones.
+//!
+//! `McpServer::with_parse_only` lets any call to a local tool go as far as
+//! clap instead, by passing `"_parse_only": true` among its arguments. The
+//! call is not run; it answers with the arguments clap parsed, defaults
+//! included and typed as in the tool's schema, and the command line they
+//! came from:
+//!
+//! ```json
+//! {"tool": "add", "arguments": {"a": 5, "b": 3, "verbose": false}, "argv": ["add", "--a=5", "--b=3"]}
+//! ```
+
+use crate::messages::Message;
+use rmcp::model::{object, JsonObject, Tool, ToolAnnotations};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Name of the registered tool
+pub const VALIDATE_TOOL: &str = "validate_call";
+
+/// Argument asking for a call to be parsed but not run
+pub const PARSE_ONLY_KEY: &str = "_parse_only";
+
+/// Schema of the `_parse_only` argument
+pub(crate) fn parse_only_schema() -> Value {
+    json!({
+        "type": "boolean",
+        "description": "Return the arguments as parsed by the CLI instead of running the command",
+    })
+}
+
+/// Tool definition listed when the validation tool is enabled
+pub(crate) fn validate_tool() -> Tool {
+    Tool {
+        name: VALIDATE_TOOL.into(),
+        description: Some(
+            "Check whether a call to another tool would be accepted, without running it".into(),
+        ),
+        input_schema: Arc::new(object(json!({
+            "type": "object",
+            "properties": {
+                "tool": { "type": "string", "description": "Name of the tool to check" },
+                "arguments": {
+                    "type": "object",
+                    "description": "Arguments the tool would be called with",
+                },
+            },
+            "required": ["tool"],
+        }))),
+        annotations: Some(ToolAnnotations::new().read_only(true).idempotent(true)),
+    }
+}
+
+/// Check `arguments` against the tool's `schema`
+///
+/// Arguments named in `skip` are not validated.
+pub(crate) fn validate_arguments(
+    schema: &JsonObject,
+    arguments: &JsonObject,
+    skip: &dyn Fn(&str) -> bool,
+) -> Result<(), Message> {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        let missing = required
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|name| !arguments.contains_key(*name) && !skip(name));
+        if let Some(name) = missing {
+            return Err(Message::MissingArgument {
+                field: name.to_string(),
+            });
+        }
+    }
+    check_constraints(schema, arguments)?;
+
+    let Some(Value::Object(properties)) = schema.get("properties") else {
+        return Ok(());
+    };
+    for (name, value) in arguments {
+        let Some(property) = properties.get(name) else {
+            continue;
+        };
+        if skip(name) {
+            continue;
+        }
+        let validator =
+            jsonschema::validator_for(property).map_err(|e| Message::InvalidArgument {
+                field: name.clone(),
+                details: format!("unusable schema: {}", e),
+            })?;
+        let error = validator
+            .iter_errors(value)
+            .next()
+            .map(|error| (error.instance_path.as_str().to_string(), error.to_string()));
+        if let Some((path, details)) = error {
+            // Point into arrays and objects, e.g. `tags/1`
+            return Err(Message::InvalidArgument {
+                field: format!("{}{}", name, path),
+                details,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check the constraints clap places between arguments, as listed in the
+/// schema's `allOf` and `dependentRequired` (see
+/// `schema::command_constraints`)
+fn check_constraints(schema: &JsonObject, arguments: &JsonObject) -> Result<(), Message> {
+    let given = |name: &&str| is_given(arguments, name);
+    if let Some(Value::Array(constraints)) = schema.get("allOf") {
+        for constraint in constraints {
+            if let Some(names) = constraint.get("not").and_then(required_names) {
+                if let [first, second, ..] = names[..] {
+                    if names.iter().all(given) {
+                        return Err(Message::ConflictingArguments {
+                            first: first.to_string(),
+                            second: second.to_string(),
+                        });
+                    }
+                }
+            }
+            for (key, only_one) in [("anyOf", false), ("oneOf", true)] {
+                let Some(Value::Array(choices)) = constraint.get(key) else {
+                    continue;
+                };
+                let names: Vec<&str> = choices
+                    .iter()
+                    .filter_map(required_names)
+                    .flatten()
+                    .collect();
+                match names.iter().copied().filter(given).collect::<Vec<_>>()[..] {
+                    [] => {
+                        return Err(Message::MissingOneOf {
+                            fields: names.iter().map(|name| name.to_string()).collect(),
+                        })
+                    }
+                    [first, second, ..] if only_one => {
+                        return Err(Message::ConflictingArguments {
+                            first: first.to_string(),
+                            second: second.to_string(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if let Some(Value::Object(dependent)) = schema.get("dependentRequired") {
+        for (name, required) in dependent {
+            if !is_given(arguments, name) {
+                continue;
+            }
+            let missing = required
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .find(|field| !given(field));
+            if let Some(field) = missing {
+                return Err(Message::MissingDependentArgument {
+                    field: field.to_string(),
+                    required_by: name.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a call gives the argument `name`; `null` and `false` leave it off
+/// the command line, as if it were not given
+fn is_given(arguments: &JsonObject, name: &str) -> bool {
+    arguments
+        .get(name)
+        .is_some_and(|value| !matches!(value, Value::Null | Value::Bool(false)))
+}
+
+/// The names a `{"required": [...]}` schema lists
+fn required_names(schema: &Value) -> Option<Vec<&str>> {
+    let required = schema.get("required")?.as_array()?;
+    Some(required.iter().filter_map(Value::as_str).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap()
+    }
+
+    fn schema() -> JsonObject {
+        object(json!({
+            "type": "object",
+            "properties": {
+                "replicas": { "type": "integer", "x-positional": true, "x-position": 0 },
+                "region": { "type": "string", "enum": ["us-east", "eu-west"] },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "filter": { "type": "array", "items": { "type": "string" } }
+            },
+            "required": ["replicas"]
+        }))
+    }
+
+    fn validate(arguments: Value) -> Result<(), Message> {
+        validate_arguments(&schema(), &object(arguments), &|name| name == "filter")
+    }
+
+    #[test]
+    fn test_valid_arguments() {
+        assert_eq!(
+            validate(json!({ "replicas": 3, "region": "eu-west", "tags": ["a"] })),
+            Ok(())
+        );
+        // Encoded and unlisted arguments are left to the encoder and clap
+        assert_eq!(
+            validate(json!({ "replicas": 3, "filter": { "env": "prod" }, "other": 1 })),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_field_precise_errors() {
+        assert_eq!(
+            validate(json!({ "region": "us-east" })),
+            Err(Message::MissingArgument {
+                field: "replicas".to_string()
+            })
+        );
+        let Err(Message::InvalidArgument { field, details }) =
+            validate(json!({ "replicas": "three" }))
+        else {
+            panic!("expected an invalid argument");
+        };
+        assert_eq!(field, "replicas");
+        assert!(details.contains("\"integer\""), "{}", details);
+
+        assert!(matches!(
+            validate(json!({ "replicas": 1, "region": "mars" })),
+            Err(Message::InvalidArgument { field, .. }) if field == "region"
+        ));
+        assert!(matches!(
+            validate(json!({ "replicas": 1, "tags": ["a", 2] })),
+            Err(Message::InvalidArgument { field, .. }) if field == "tags/1"
+        ));
+    }
+
+    #[test]
+    fn test_constraint_errors() {
+        let schema = object(json!({
+            "type": "object",
+            "properties": {},
+            "allOf": [
+                { "oneOf": [{ "required": ["tag"] }, { "required": ["branch"] }] },
+                { "anyOf": [{ "required": ["quiet"] }, { "required": ["verbose"] }] },
+                { "not": { "required": ["draft", "sign"] } }
+            ],
+            "dependentRequired": { "sign": ["key"] }
+        }));
+        let validate =
+            |arguments: Value| validate_arguments(&schema, &object(arguments), &|_| false);
+        let conflict = |first: &str, second: &str| {
+            Err(Message::ConflictingArguments {
+                first: first.to_string(),
+                second: second.to_string(),
+            })
+        };
+
+        assert_eq!(
+            validate(json!({ "tag": "v1", "quiet": true, "verbose": true })),
+            Ok(())
+        );
+        assert_eq!(
+            validate(json!({ "quiet": true })),
+            Err(Message::MissingOneOf {
+                fields: vec!["tag".to_string(), "branch".to_string()]
+            })
+        );
+        assert_eq!(
+            validate(json!({ "tag": "v1", "branch": "main", "quiet": true })),
+            conflict("tag", "branch")
+        );
+        // `false` and `null` leave an argument out
+        assert_eq!(
+            validate(json!({ "tag": "v1", "branch": null, "quiet": false })).unwrap_err(),
+            Message::MissingOneOf {
+                fields: vec!["quiet".to_string(), "verbose".to_string()]
+            }
+        );
+        assert_eq!(
+            validate(json!({ "tag": "v1", "quiet": true, "sign": true, "draft": true })),
+            conflict("draft", "sign")
+        );
+        assert_eq!(
+            validate(json!({ "tag": "v1", "quiet": true, "sign": true, "draft": false })),
+            Err(Message::MissingDependentArgument {
+                field: "key".to_string(),
+                required_by: "sign".to_string()
+            })
+        );
+        assert_eq!(
+            Message::MissingOneOf {
+                fields: vec!["tag".to_string(), "branch".to_string()]
+            }
+            .to_string(),
+            "One of the arguments `tag`, `branch` is required"
+        );
+    }
+}
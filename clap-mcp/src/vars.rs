@@ -0,0 +1,496 @@
+//! Session variables and `${var}` interpolation in tool arguments
+//!
+//! Any tool call may carry a `_vars` object. Its entries are remembered for
+//! the rest of the session (one client connection) and removed from the
+//! arguments. Tools marked `#[mcp(interpolate)]` then have `${name}` in their
+//! string arguments replaced, so agents can reference a project id or token
+//! handle without repeating it in every call:
+//!
+//! ```json
+//! {"name": "deploy", "arguments": {"_vars": {"project": "acme-prod"}, "target": "${project}"}}
+//! {"name": "status", "arguments": {"target": "${project}"}}
+//! ```
+//!
+//! `$$` produces a literal `$`. Referencing an undefined variable fails the
+//! call rather than passing the reference through.
+//!
+//! Arguments marked `#[mcp(sticky)]` carry forward on their own: once a call
+//! passes `project`, later calls of the session that omit it get the same
+//! value, until one passes another. Values are shared by every tool with a
+//! sticky argument of that id, and secrets are remembered by name only.
+//!
+//! Clients undo these defaults explicitly:
+//!
+//! - `"project": null` runs this call with clap's built-in default, keeping
+//!   the remembered value for later calls
+//! - `"project": {"$unset": true}` forgets the remembered value, and runs
+//!   this call with the built-in default too
+//! - `"_vars": {"project": null}` forgets the variable
+//!
+//! Both are lost when the client disconnects, unless the server has a
+//! [`SessionStore`]. Calls carrying a token in `_meta.sessionToken` then
+//! save the session's state under it, and the first call of a new session
+//! with the same token restores it, so an agent reconnecting after a crash
+//! resumes where it left off:
+//!
+//! ```ignore
+//! McpServer::<Commands>::new().with_session_store(FileSessionStore::new("/var/lib/mytool/sessions"));
+//! ```
+//!
+//! ```json
+//! {"name": "status", "arguments": {}, "_meta": {"sessionToken": "3f9c2a..."}}
+//! ```
+//!
+//! Whoever knows a token gets its session's variables, so clients should
+//! pick long random ones. Implement the trait for other backends, e.g. a
+//! database table or Redis shared by several servers.
+
+use crate::messages::Message;
+use rmcp::model::{JsonObject, Meta};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Argument key carrying variables to remember for the session
+pub const VARS_KEY: &str = "_vars";
+
+/// Key of the `{"$unset": true}` value clearing a sticky argument
+pub const UNSET_KEY: &str = "$unset";
+
+/// Whether an argument value stands for an omitted argument: `null`, or
+/// `{"$unset": true}`
+pub(crate) fn is_omitted(value: &Value) -> bool {
+    value.is_null() || is_unset(value)
+}
+
+fn is_unset(value: &Value) -> bool {
+    matches!(value, Value::Object(object) if object.len() == 1 && object.get(UNSET_KEY) == Some(&Value::Bool(true)))
+}
+
+/// `_meta` key of the token a session's state is saved under
+pub const SESSION_TOKEN_META_KEY: &str = "sessionToken";
+
+/// The session token named in `_meta`, if any
+pub(crate) fn session_token(meta: &Meta) -> Option<&str> {
+    match meta.0.get(SESSION_TOKEN_META_KEY) {
+        Some(Value::String(token)) if !token.is_empty() => Some(token),
+        _ => None,
+    }
+}
+
+/// What a session remembers: its variables and sticky argument values
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SessionState {
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+    /// Last value of each sticky argument, by argument id
+    #[serde(default)]
+    pub sticky: HashMap<String, Value>,
+}
+
+/// Keeps session state across reconnects, by client-provided token
+///
+/// With authentication, the server passes a key combining the token with the
+/// authenticated principal instead of the bare token.
+pub trait SessionStore: Send + Sync {
+    /// The state saved under `token`, if any
+    fn load(&self, token: &str) -> Option<SessionState>;
+    /// Save `state` under `token`, replacing what was saved before
+    fn save(&self, token: &str, state: &SessionState);
+}
+
+/// A [`SessionStore`] in memory, surviving reconnects but not restarts
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, SessionState>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self, token: &str) -> Option<SessionState> {
+        self.sessions.lock().unwrap().get(token).cloned()
+    }
+
+    fn save(&self, token: &str, state: &SessionState) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(token.to_string(), state.clone());
+    }
+}
+
+/// A [`SessionStore`] keeping one JSON file per token in a directory
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    dir: PathBuf,
+}
+
+impl FileSessionStore {
+    /// Store sessions in `dir`, created on the first save
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The token hex-encoded, so any token makes a safe file name
+    fn path(&self, token: &str) -> PathBuf {
+        let name: String = token.bytes().map(|b| format!("{:02x}", b)).collect();
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn write(&self, token: &str, state: &SessionState) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(token);
+        // Written aside and renamed, so a crash never leaves half a file
+        let partial = path.with_extension("json.tmp");
+        std::fs::write(&partial, serde_json::to_vec(state)?)?;
+        std::fs::rename(partial, path)
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self, token: &str) -> Option<SessionState> {
+        let path = self.path(token);
+        let bytes = std::fs::read(&path).ok()?;
+        match serde_json::from_slice(&bytes) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), "ignoring unreadable session state: {}", e);
+                None
+            }
+        }
+    }
+
+    fn save(&self, token: &str, state: &SessionState) {
+        if let Err(e) = self.write(token, state) {
+            tracing::warn!(dir = %self.dir.display(), "failed to save session state: {}", e);
+        }
+    }
+}
+
+/// Schema of the `_vars` argument listed for tools that interpolate
+pub(crate) fn vars_schema() -> Value {
+    serde_json::json!({
+        "type": "object",
+        "description": "Session variables to remember, referenced in string arguments as ${name}",
+        "additionalProperties": { "type": ["string", "number", "boolean", "null"] },
+    })
+}
+
+/// Variables set by the calls of one session
+#[derive(Debug, Default)]
+pub(crate) struct SessionVars {
+    vars: Mutex<HashMap<String, String>>,
+    /// Last value of each sticky argument, by argument id
+    sticky: Mutex<HashMap<String, Value>>,
+    /// Token the session's state is saved under
+    token: Mutex<Option<String>>,
+    /// Argument defaults from the client's workspace, once resolved
+    workspace: Mutex<Option<HashMap<String, Value>>>,
+}
+
+impl SessionVars {
+    /// Save the session's state under `token` from now on, returning whether
+    /// it was saved under another (or none) before
+    pub(crate) fn bind(&self, token: &str) -> bool {
+        let mut bound = self.token.lock().unwrap();
+        if bound.as_deref() == Some(token) {
+            return false;
+        }
+        *bound = Some(token.to_string());
+        true
+    }
+
+    /// The variables and sticky values, to save
+    pub(crate) fn state(&self) -> SessionState {
+        SessionState {
+            vars: self.vars.lock().unwrap().clone(),
+            sticky: self.sticky.lock().unwrap().clone(),
+        }
+    }
+
+    /// Take on a saved state; values the session set itself take precedence
+    pub(crate) fn restore(&self, state: SessionState) {
+        let mut vars = self.vars.lock().unwrap();
+        for (name, value) in state.vars {
+            vars.entry(name).or_insert(value);
+        }
+        let mut sticky = self.sticky.lock().unwrap();
+        for (id, value) in state.sticky {
+            sticky.entry(id).or_insert(value);
+        }
+    }
+
+    /// Remove `_vars` from `arguments` and remember its entries, forgetting
+    /// the null ones
+    pub(crate) fn take_from(&self, arguments: &mut JsonObject) -> Result<(), Message> {
+        let Some(vars) = arguments.remove(VARS_KEY) else {
+            return Ok(());
+        };
+        let Value::Object(vars) = vars else {
+            return Err(Message::InvalidVariables);
+        };
+        let mut parsed = Vec::with_capacity(vars.len());
+        for (name, value) in vars {
+            let value = match value {
+                Value::String(s) => Some(s),
+                Value::Number(n) => Some(n.to_string()),
+                Value::Bool(b) => Some(b.to_string()),
+                Value::Null => None,
+                _ => return Err(Message::InvalidVariables),
+            };
+            parsed.push((name, value));
+        }
+        let mut vars = self.vars.lock().unwrap();
+        for (name, value) in parsed {
+            match value {
+                Some(value) => vars.insert(name, value),
+                None => vars.remove(&name),
+            };
+        }
+        Ok(())
+    }
+
+    /// A copy of the current variables, for calls that must not change them
+    pub(crate) fn snapshot(&self) -> SessionVars {
+        SessionVars {
+            vars: Mutex::new(self.vars.lock().unwrap().clone()),
+            sticky: Mutex::new(self.sticky.lock().unwrap().clone()),
+            token: Mutex::default(),
+            workspace: Mutex::new(self.workspace.lock().unwrap().clone()),
+        }
+    }
+
+    /// Whether the workspace defaults have been resolved
+    pub(crate) fn has_workspace(&self) -> bool {
+        self.workspace.lock().unwrap().is_some()
+    }
+
+    pub(crate) fn set_workspace(&self, defaults: HashMap<String, Value>) {
+        *self.workspace.lock().unwrap() = Some(defaults);
+    }
+
+    /// Resolve the workspace defaults again on the next call, e.g. after the
+    /// client's roots changed
+    pub(crate) fn forget_workspace(&self) {
+        *self.workspace.lock().unwrap() = None;
+    }
+
+    /// Fill in the workspace defaults of the arguments a call omits, for
+    /// the ones `takes` accepts
+    pub(crate) fn apply_workspace(&self, arguments: &mut JsonObject, takes: impl Fn(&str) -> bool) {
+        let workspace = self.workspace.lock().unwrap();
+        for (id, value) in workspace.iter().flatten() {
+            if !arguments.contains_key(id) && takes(id) {
+                arguments.insert(id.clone(), value.clone());
+            }
+        }
+    }
+
+    /// Remember the `sticky` arguments a call passes and fill in the ones it
+    /// omits from earlier calls
+    ///
+    /// Null and `{"$unset": true}` values are left for the caller to drop.
+    pub(crate) fn apply_sticky(&self, arguments: &mut JsonObject, sticky: &BTreeSet<String>) {
+        let mut remembered = self.sticky.lock().unwrap();
+        for id in sticky {
+            match arguments.get(id) {
+                Some(Value::Null) => {}
+                Some(value) if is_unset(value) => {
+                    remembered.remove(id);
+                }
+                Some(value) => {
+                    remembered.insert(id.clone(), value.clone());
+                }
+                None => {
+                    if let Some(value) = remembered.get(id) {
+                        arguments.insert(id.clone(), value.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace `${name}` in string arguments, including strings in arrays
+    pub(crate) fn interpolate_arguments(&self, arguments: &mut JsonObject) -> Result<(), Message> {
+        let vars = self.vars.lock().unwrap();
+        for value in arguments.values_mut() {
+            interpolate_value(value, &vars)?;
+        }
+        Ok(())
+    }
+}
+
+fn interpolate_value(value: &mut Value, vars: &HashMap<String, String>) -> Result<(), Message> {
+    match value {
+        Value::String(s) => *s = interpolate(s, vars)?,
+        Value::Array(items) => {
+            for item in items {
+                interpolate_value(item, vars)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expand `${name}` references and `$$` escapes in `template`
+fn interpolate(template: &str, vars: &HashMap<String, String>) -> Result<String, Message> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(after) = after.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some(after) = after.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| Message::UnterminatedVariable {
+                    template: template.to_string(),
+                })?;
+            let name = &after[..end];
+            let value = vars.get(name).ok_or_else(|| Message::UndefinedVariable {
+                name: name.to_string(),
+            })?;
+            out.push_str(value);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let vars = HashMap::from([("project".to_string(), "acme".to_string())]);
+        assert_eq!(interpolate("${project}/app", &vars).unwrap(), "acme/app");
+        assert_eq!(
+            interpolate("cost: $5, $${project}", &vars).unwrap(),
+            "cost: $5, ${project}"
+        );
+        assert_eq!(interpolate("plain", &vars).unwrap(), "plain");
+        assert_eq!(
+            interpolate("${missing}", &vars),
+            Err(Message::UndefinedVariable {
+                name: "missing".to_string()
+            })
+        );
+        assert!(interpolate("${project", &vars).is_err());
+    }
+
+    #[test]
+    fn test_session_vars_persist_across_calls() {
+        let session = SessionVars::default();
+        let mut first = object(json!({ "_vars": { "project": "acme", "port": 8080 }, "a": 1 }));
+        session.take_from(&mut first).unwrap();
+        assert_eq!(first, object(json!({ "a": 1 })));
+
+        let mut second = object(json!({ "target": "${project}:${port}", "tags": ["${project}"] }));
+        session.take_from(&mut second).unwrap();
+        session.interpolate_arguments(&mut second).unwrap();
+        assert_eq!(
+            second,
+            object(json!({ "target": "acme:8080", "tags": ["acme"] }))
+        );
+
+        let mut invalid = object(json!({ "_vars": ["project"] }));
+        assert!(session.take_from(&mut invalid).is_err());
+
+        let mut cleared = object(json!({ "_vars": { "project": null }, "target": "${port}" }));
+        session.take_from(&mut cleared).unwrap();
+        session.interpolate_arguments(&mut cleared).unwrap();
+        let mut undefined = object(json!({ "target": "${project}" }));
+        assert!(session.interpolate_arguments(&mut undefined).is_err());
+    }
+
+    #[test]
+    fn test_sticky_arguments() {
+        let session = SessionVars::default();
+        let sticky = BTreeSet::from(["project".to_string()]);
+
+        let mut first = object(json!({ "a": 1 }));
+        session.apply_sticky(&mut first, &sticky);
+        assert_eq!(first, object(json!({ "a": 1 })));
+
+        let mut second = object(json!({ "project": "acme", "a": 2 }));
+        session.apply_sticky(&mut second, &sticky);
+        let mut third = object(json!({ "a": 3 }));
+        session.apply_sticky(&mut third, &sticky);
+        assert_eq!(third, object(json!({ "project": "acme", "a": 3 })));
+
+        // Only sticky arguments are carried forward
+        let mut other = object(json!({}));
+        session.apply_sticky(&mut other, &BTreeSet::new());
+        assert_eq!(other, object(json!({})));
+
+        let mut fourth = object(json!({ "project": "globex" }));
+        session.apply_sticky(&mut fourth, &sticky);
+        let mut fifth = object(json!({}));
+        session.snapshot().apply_sticky(&mut fifth, &sticky);
+        assert_eq!(fifth, object(json!({ "project": "globex" })));
+
+        // Null skips the remembered value for one call, $unset forgets it
+        let mut default = object(json!({ "project": null }));
+        session.apply_sticky(&mut default, &sticky);
+        assert_eq!(default, object(json!({ "project": null })));
+        let mut remembered = object(json!({}));
+        session.apply_sticky(&mut remembered, &sticky);
+        assert_eq!(remembered, object(json!({ "project": "globex" })));
+
+        let mut unset = object(json!({ "project": { "$unset": true } }));
+        session.apply_sticky(&mut unset, &sticky);
+        assert!(is_omitted(&unset["project"]));
+        let mut forgotten = object(json!({}));
+        session.apply_sticky(&mut forgotten, &sticky);
+        assert_eq!(forgotten, object(json!({})));
+        assert!(!is_omitted(&json!({ "$unset": false })));
+    }
+
+    #[test]
+    fn test_restore_saved_state() {
+        let session = SessionVars::default();
+        let mut arguments = object(json!({ "_vars": { "project": "acme" }, "region": "eu" }));
+        session.take_from(&mut arguments).unwrap();
+        session.apply_sticky(&mut arguments, &BTreeSet::from(["region".to_string()]));
+        assert!(session.bind("t1") && !session.bind("t1") && session.bind("t2"));
+
+        let dir = std::env::temp_dir().join(format!("clap-mcp-sessions-{}", std::process::id()));
+        let store = FileSessionStore::new(&dir);
+        assert_eq!(store.load("a/b"), None);
+        store.save("a/b", &session.state());
+        let saved = store.load("a/b").unwrap();
+        assert_eq!(saved, session.state());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Values the new session already set win over the saved ones
+        let resumed = SessionVars::default();
+        let mut arguments = object(json!({ "_vars": { "project": "globex" } }));
+        resumed.take_from(&mut arguments).unwrap();
+        resumed.restore(saved);
+        let mut arguments = object(json!({ "target": "${project}" }));
+        resumed.apply_sticky(&mut arguments, &BTreeSet::from(["region".to_string()]));
+        resumed.interpolate_arguments(&mut arguments).unwrap();
+        assert_eq!(
+            arguments,
+            object(json!({ "target": "globex", "region": "eu" }))
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Argument defaults from the client's workspace
+//!
+//! Tools scoped to a project usually take its location, e.g. `--path` or
+//! `--manifest`. [`WorkspaceDefaults`] fills such arguments in when a call
+//! omits them, from the first root the client lists (`roots/list`) or a
+//! project file found in it:
+//!
+//! ```ignore
+//! let defaults = WorkspaceDefaults::new()
+//!     .root("path")
+//!     .project_file("manifest", ["Cargo.toml", "package.json"]);
+//! McpServer::<Commands>::new().with_workspace_defaults(defaults);
+//! ```
+//!
+//! Arguments are named by their clap id and filled in for every subcommand
+//! taking them. Values a call passes or carries forward as sticky ones take
+//! precedence, and `null` still runs the call with clap's default.
+//!
+//! Roots are asked for on a session's first call and again after the client
+//! reports they changed. Clients without the `roots` capability, or listing
+//! no `file://` root, get no defaults.
+
+use rmcp::service::{Peer, RoleServer};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long to wait for the client to list its roots
+const ROOTS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where an argument's default comes from
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// The root directory itself
+    Root,
+    /// The first of these files present in the root
+    ProjectFile(Vec<String>),
+}
+
+/// Arguments defaulting to paths in the client's workspace
+#[derive(Debug, Clone, Default)]
+pub struct WorkspaceDefaults {
+    rules: Vec<(String, Source)>,
+}
+
+impl WorkspaceDefaults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Default the argument `arg` to the client's first root directory
+    pub fn root(mut self, arg: impl Into<String>) -> Self {
+        self.rules.push((arg.into(), Source::Root));
+        self
+    }
+
+    /// Default the argument `arg` to the first of `names` found in the
+    /// client's first root, e.g. `Cargo.toml`; left out when none is there
+    pub fn project_file(
+        mut self,
+        arg: impl Into<String>,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let names = names.into_iter().map(Into::into).collect();
+        self.rules.push((arg.into(), Source::ProjectFile(names)));
+        self
+    }
+
+    /// The default value of each argument for the workspace at `root`
+    pub(crate) fn resolve(&self, root: Option<&Path>) -> HashMap<String, Value> {
+        let Some(root) = root else {
+            return HashMap::new();
+        };
+        let mut defaults = HashMap::new();
+        for (arg, source) in &self.rules {
+            let path = match source {
+                Source::Root => Some(root.to_path_buf()),
+                Source::ProjectFile(names) => names
+                    .iter()
+                    .map(|name| root.join(name))
+                    .find(|path| path.is_file()),
+            };
+            if let Some(path) = path {
+                let path = path.to_string_lossy().into_owned();
+                defaults.entry(arg.clone()).or_insert(Value::String(path));
+            }
+        }
+        defaults
+    }
+}
+
+/// The directory of the first `file://` root `peer`'s client lists
+pub(crate) async fn first_root(peer: &Peer<RoleServer>) -> Option<PathBuf> {
+    let listed = tokio::time::timeout(ROOTS_TIMEOUT, peer.list_roots()).await;
+    let roots = match listed {
+        Ok(Ok(result)) => result.roots,
+        Ok(Err(e)) => {
+            tracing::debug!("failed to list the client's roots: {}", e);
+            return None;
+        }
+        Err(_) => {
+            tracing::debug!("client did not list its roots in time");
+            return None;
+        }
+    };
+    roots.iter().find_map(|root| root_path(&root.uri))
+}
+
+/// The path of a `file://` URI, percent-decoded
+fn root_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // Skip the host, empty for local files
+    let path = &rest[rest.find('/')?..];
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // `file:///C:/work` names a Windows drive
+    let path = match path.get(2..3) {
+        Some(":") if cfg!(windows) => &path[1..],
+        _ => &path,
+    };
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_path() {
+        assert_eq!(
+            root_path("file:///home/me/my%20project"),
+            Some(PathBuf::from("/home/me/my project"))
+        );
+        assert_eq!(
+            root_path("file://localhost/srv/app"),
+            Some(PathBuf::from("/srv/app"))
+        );
+        assert_eq!(root_path("https://example.com/repo"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = std::env::temp_dir().join(format!("clap-mcp-workspace-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("package.json"), "{}").unwrap();
+        let defaults = WorkspaceDefaults::new()
+            .root("path")
+            .project_file("manifest", ["Cargo.toml", "package.json"])
+            .project_file("config", ["mytool.toml"]);
+
+        let resolved = defaults.resolve(Some(&root));
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(resolved["path"], root.to_string_lossy().as_ref());
+        assert_eq!(
+            resolved["manifest"],
+            root.join("package.json").to_string_lossy().as_ref()
+        );
+        assert!(!resolved.contains_key("config"));
+        assert!(defaults.resolve(None).is_empty());
+    }
+}
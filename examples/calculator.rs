@@ -2,6 +2,7 @@ use clap::{Parser, Subcommand};
 use clap_mcp::McpMode;
 
 #[derive(Parser, McpMode)]
+#[mcp(transport_flag = "mcp_port")]
 #[command(name = "calculator")]
 #[command(about = "A simple calculator CLI that can also run as an MCP server")]
 #[command(version = "1.0")]
@@ -138,13 +139,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
     if cli.mcp {
-        // Run as MCP server
-        if let Some(port) = cli.mcp_port {
-            let addr = format!("127.0.0.1:{}", port).parse()?;
-            cli.run_mcp_server_http_with_handler(addr, execute_command)?;
-        } else {
-            cli.run_mcp_server_with_handler(execute_command)?;
-        }
+        // Run as MCP server, over HTTP if --mcp-port is given
+        cli.run_mcp(execute_command)?;
     } else {
         // Run as normal CLI
         match execute_command(
@@ -1,4 +1,4 @@
-use clap_mcp::test_client::McpTestClient;
+use clap_mcp::client::McpClient;
 use serde_json::json;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -15,7 +15,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Connecting to MCP server at http://127.0.0.1:8080...");
 
     // Connect to server
-    let client = McpTestClient::connect("127.0.0.1:8080").await?;
+    let client = McpClient::connect("127.0.0.1:8080").await?;
 
     // List available tools
     println!("\nListing available tools...");
@@ -82,7 +82,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if result.is_error.unwrap_or(false) {
         println!("Error in result");
-    } else if let Some(text) = McpTestClient::extract_text(&result) {
+    } else if let Some(text) = McpClient::extract_text(&result) {
         println!("Result: {}", text);
     }
 
@@ -94,7 +94,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if result.is_error.unwrap_or(false) {
         println!("Error in result");
-    } else if let Some(text) = McpTestClient::extract_text(&result) {
+    } else if let Some(text) = McpClient::extract_text(&result) {
         println!("Result: {}", text);
     }
 
@@ -109,7 +109,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if result.is_error.unwrap_or(false) {
         println!("Error in result");
-    } else if let Some(text) = McpTestClient::extract_text(&result) {
+    } else if let Some(text) = McpClient::extract_text(&result) {
         println!("Result: {}", text);
     }
 